@@ -5,6 +5,8 @@ use crate::objects::ItemWithData;
 
 pub trait Attribute {
     type Object;
+    /** How much work it is to get this attribute, see `Cost`. */
+    fn cost(&self) -> Cost { Cost::default() }
 }
 pub trait Getter<'a>: Attribute {
     type IntoItem;
@@ -34,6 +36,24 @@ pub trait CollectionGetter<'a, T, I>: Attribute<Object=T> + OptionGetter<'a, Int
 }
 impl<'a, G, T, I> CollectionGetter<'a, T, I> for G where G: Attribute<Object=T> + OptionGetter<'a, IntoItem=Vec<I>> {}
 
+/** The values an object is joined on by `join_on`, `semi_join`, and `anti_join`: either a single
+    value, like `user::Id`, or a list of them, like `project::AuthorIds`. */
+pub trait JoinKeys {
+    type Key: Hash + Eq;
+    fn into_join_keys(self) -> Vec<Self::Key>;
+}
+
+/** Something worked out for a whole group of objects at once, eg. its key, its size, or the mean
+    of an attribute over its objects. Groups are made by `group_by`. */
+pub trait GroupGetter<'a, K, T> {
+    type IntoItem;
+    fn get_group(&self, key: &K, members: &Vec<ItemWithData<'a, T>>) -> Option<Self::IntoItem>;
+}
+
+pub trait GroupFilter<'a, K, T> {
+    fn accept_group(&self, key: &K, members: &Vec<ItemWithData<'a, T>>) -> bool;
+}
+
 pub trait Countable<'a>: Attribute { // TODO Option? // FIXME needed?
     fn count(&self, object: &ItemWithData<'a, Self::Object>) -> usize;
 }
@@ -55,6 +75,9 @@ impl<'a, T, I, A> Select<'a, T, I> for A where A: Attribute<Object=T> + Getter<'
 pub mod sort {
     #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Debug)]
     pub enum Direction { Ascending, Descending }
+    /** Where objects that have no value for a sort key go, regardless of direction. */
+    #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Debug)]
+    pub enum Missing { First, Last }
 }
 
 pub trait Sort<'a, T,I: Ord>: Attribute<Object=T> + Getter<'a, IntoItem=I> {
@@ -67,6 +90,63 @@ pub trait Sort<'a, T,I: Ord>: Attribute<Object=T> + Getter<'a, IntoItem=I> {
 }
 impl<'a, A, I, T> Sort<'a, T, I> for A where A: Getter<'a, IntoItem=I> + Attribute<Object=T>, I: Ord {}
 
+/** One or more keys to sort by, each with its own direction, eg. a tuple of `By`, `Ascending`, or
+    `Descending`. Keys are worked out once for every object, and compared in order. */
+pub trait SortKey<'a, T> {
+    type Key: Ord;
+    fn key(&self, object: &ItemWithData<'a, T>) -> Self::Key;
+}
+macro_rules! impl_sort_key_tuple {
+    ($($key:ident -> $n:tt),+) => {
+        impl<'a, T, $($key,)+> SortKey<'a, T> for ($($key,)+) where $($key: SortKey<'a, T>,)+ {
+            type Key = ($($key::Key,)+);
+            fn key(&self, object: &ItemWithData<'a, T>) -> Self::Key { ($(self.$n.key(object),)+) }
+        }
+    }
+}
+impl_sort_key_tuple!(Ka -> 0);
+impl_sort_key_tuple!(Ka -> 0, Kb -> 1);
+impl_sort_key_tuple!(Ka -> 0, Kb -> 1, Kc -> 2);
+impl_sort_key_tuple!(Ka -> 0, Kb -> 1, Kc -> 2, Kd -> 3);
+impl_sort_key_tuple!(Ka -> 0, Kb -> 1, Kc -> 2, Kd -> 3, Ke -> 4);
+impl_sort_key_tuple!(Ka -> 0, Kb -> 1, Kc -> 2, Kd -> 3, Ke -> 4, Kf -> 5);
+/* Keys only known at run time, eg. in a textual query. */
+impl<'a, T, K> SortKey<'a, T> for Vec<K> where K: SortKey<'a, T> {
+    type Key = Vec<K::Key>;
+    fn key(&self, object: &ItemWithData<'a, T>) -> Self::Key { self.iter().map(|key| key.key(object)).collect() }
+}
+
+/** The value of a single sort key, which orders itself according to its direction and to where
+    missing values go. */
+#[derive(Clone, Debug)]
+pub struct SortValue<I> { value: Option<I>, direction: sort::Direction, missing: sort::Missing }
+impl<I> SortValue<I> {
+    pub fn new(value: Option<I>, direction: sort::Direction, missing: sort::Missing) -> Self {
+        SortValue { value, direction, missing }
+    }
+}
+impl<I> PartialEq for SortValue<I> where I: Ord {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == std::cmp::Ordering::Equal }
+}
+impl<I> Eq for SortValue<I> where I: Ord {}
+impl<I> PartialOrd for SortValue<I> where I: Ord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) }
+}
+impl<I> Ord for SortValue<I> where I: Ord {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering::*;
+        match (&self.value, &other.value, self.missing) {
+            (None, None, _) => Equal,
+            (None, Some(_), sort::Missing::First) | (Some(_), None, sort::Missing::Last) => Less,
+            (None, Some(_), sort::Missing::Last) | (Some(_), None, sort::Missing::First) => Greater,
+            (Some(mine), Some(theirs), _) => match self.direction {
+                sort::Direction::Ascending => mine.cmp(theirs),
+                sort::Direction::Descending => theirs.cmp(mine),
+            }
+        }
+    }
+}
+
 pub trait Sampler<'a, T> {
 //    type Item;
     fn sample<I>(&self, iter: I) -> Vec<ItemWithData<'a, T>>
@@ -76,9 +156,29 @@ pub trait Sampler<'a, T> {
     }
 }
 
+/** A rough idea of how much work an attribute or a filter is, so that `And`, `Or`, `AllOf`, `AnyOf`,
+    and `NoneOf` can try cheaper filters first. Attributes read straight from the source or from
+    its metadata are `Cheap`, attributes computed into derived caches are `Expensive`, and filters
+    and attributes built out of other attributes cost as much as the dearest of them. A filter can
+    also be given a hint with `WithCost`. */
+#[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Debug)]
+pub enum Cost { Cheap, Moderate, Expensive }
+impl Default for Cost {
+    fn default() -> Self { Cost::Cheap }
+}
+
 pub trait Filter<'a> {
     type Item;
     fn accept(&self, item_with_data: &ItemWithData<'a, Self::Item>) -> bool;
+    fn cost(&self) -> Cost { Cost::default() }
+}
+
+impl<'a, T> Filter<'a> for Box<dyn Filter<'a, Item=T> + 'a> {
+    type Item = T;
+    fn accept(&self, item_with_data: &ItemWithData<'a, Self::Item>) -> bool {
+        self.as_ref().accept(item_with_data)
+    }
+    fn cost(&self) -> Cost { self.as_ref().cost() }
 }
 
 pub struct AttributeFilterIter<I, A> {
@@ -23,7 +23,7 @@ fn main() {
     projects_by_original_files(&config, &log, &database).into_csv(path!("projects_by_original_files")).unwrap();
     projects_by_impact(&config, &log, &database).into_csv(path!("projects_by_impact")).unwrap();
     projects_by_files(&config, &log, &database).into_csv(path!("projects_by_files")).unwrap();
-    //projects_by_major_language_ratio(&config, &log, &database).into_csv(path!("projects_by_major_language_ratio")).unwrap();
+    projects_by_major_language_ratio(&config, &log, &database).into_csv(path!("projects_by_major_language_ratio")).unwrap();
     projects_by_major_language_changes(&config, &log, &database).into_csv(path!("projects_by_major_language_changes")).unwrap();    
     projects_by_all_forks(&config, &log, &database).into_csv(path!("projects_by_all_forks")).unwrap();
     projects_by_loc(&config, &log, &database).into_csv(path!("projects_by_loc")).unwrap();
@@ -76,14 +76,13 @@ fn projects_by_files<'a>(_config: &Configuration, _log: &Log, database: &'a Data
         .sample(Top(50))
 }
 
-/*
+#[allow(dead_code)]
 fn projects_by_major_language_ratio<'a>(_config: &Configuration, _log: &Log, database: &'a Database) -> impl Iterator<Item=ItemWithData<'a, Project>> {
     database
         .projects()
         .sort_by(project::MajorLanguageRatio)
         .sample(Top(50))
 }
-*/
 
 #[allow(dead_code)]
 fn projects_by_major_language_changes<'a>(_config: &Configuration, _log: &Log, database: &'a Database) -> impl Iterator<Item=ItemWithData<'a, Project>> {
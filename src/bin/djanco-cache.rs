@@ -0,0 +1,103 @@
+use clap::{Clap, crate_version, crate_authors};
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use djanco::*;
+use djanco::log::*;
+use djanco::database::warmup::{all_caches, plan_waves};
+
+#[derive(Clap)]
+#[clap(version = crate_version!(), author = crate_authors!(), name = "djanco-cache")]
+struct Options {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Builds caches ahead of time (all of them, unless some are named)
+    Build(BuildOptions),
+    /// Lists the caches that can be built, in the order they would be built
+    List,
+}
+
+#[derive(Clap)]
+struct BuildOptions {
+    #[clap(flatten)]
+    config: Configuration,
+
+    /// Savepoint the caches are built for, as a date: YYYY-MM-DD
+    #[clap(long = "savepoint", default_value = "2020-12-01")]
+    savepoint: String,
+
+    /// Substores the caches are built for (all of them by default)
+    #[clap(long = "store")]
+    stores: Vec<String>,
+
+    /// Update caches from the most recent earlier savepoint that has them, where possible
+    #[clap(long = "update")]
+    update: bool,
+
+    /// Update caches from this earlier savepoint, where possible: YYYY-MM-DD
+    #[clap(long = "update-from")]
+    update_from: Option<String>,
+
+    /// Number of caches built at the same time (one per CPU by default)
+    #[clap(long = "jobs", short = 'j')]
+    jobs: Option<usize>,
+
+    /// Caches to build, along with all the caches they are derived from
+    caches: Vec<String>,
+}
+
+fn list() {
+    let caches = all_caches();
+    let waves = plan_waves(&vec![]).expect("Error planning caches.");
+    for (index, wave) in waves.iter().enumerate() {
+        println!("wave {}:", index + 1);
+        for name in wave {
+            let cache = caches.iter().find(|cache| &cache.name == name).unwrap();
+            if cache.prerequisites.is_empty() {
+                println!("    {} (from {:?})", name, cache.origin);
+            } else {
+                println!("    {} (from {})", name, cache.prerequisites.join(", "));
+            }
+        }
+    }
+}
+
+fn parse_savepoint(date: &str) -> Timestamp {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| Utc.from_utc_date(&date).and_hms(0, 0, 0).timestamp())
+        .expect("Savepoint should be a date in the format YYYY-MM-DD.")
+}
+
+fn build(options: BuildOptions) {
+    if let Some(jobs) = options.jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()
+            .expect("Error configuring thread pool.");
+    }
+
+    let savepoint = parse_savepoint(&options.savepoint);
+    let stores: Vec<Store> = options.stores.iter().map(|store| Store::from(store.as_str())).collect();
+
+    let log = Log::new(options.config.verbosity);
+    let database = Djanco::from_config(&options.config, savepoint, stores, log)
+        .expect("Error initializing datastore.");
+
+    if options.update || options.update_from.is_some() {
+        let earlier_savepoint = options.update_from.as_ref().map(|date| parse_savepoint(date));
+        let updated = database.update_caches(earlier_savepoint).expect("Error updating caches.");
+        println!("Updated {} caches{}", updated.len(),
+                 if updated.is_empty() { ".".to_owned() } else { format!(": {}", updated.join(", ")) });
+    }
+
+    let built = database.build_caches(&options.caches).expect("Error building caches.");
+    println!("Built {} caches.", built);
+}
+
+fn main() {
+    match Options::parse().command {
+        Command::Build(options) => build(options),
+        Command::List => list(),
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use clap::{Clap, crate_version, crate_authors};
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use djanco::*;
+use djanco::log::*;
+use djanco::query::Query;
+
+#[derive(Clap)]
+#[clap(version = crate_version!(), author = crate_authors!(), name = "djanco-query")]
+struct Options {
+    #[clap(flatten)]
+    config: Configuration,
+
+    /// Savepoint the queries run at, as a date: YYYY-MM-DD
+    #[clap(long = "savepoint", default_value = "2020-12-01")]
+    savepoint: String,
+
+    /// Substores the queries run on (all of them by default)
+    #[clap(long = "store")]
+    stores: Vec<String>,
+
+    /// Only check that the queries are correct, without running them
+    #[clap(long = "check")]
+    check: bool,
+
+    /// Files with one query each; the results of each go to a CSV file named after it in the output directory
+    #[clap(required = true, parse(from_os_str))]
+    queries: Vec<PathBuf>,
+}
+
+fn parse_savepoint(date: &str) -> Timestamp {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| Utc.from_utc_date(&date).and_hms(0, 0, 0).timestamp())
+        .expect("Savepoint should be a date in the format YYYY-MM-DD.")
+}
+
+// `cargo run --bin djanco-query --release -- -o ~/output -d /mnt/data/dataset -c /mnt/data/cache popular_rust.djq`
+fn main() {
+    let options = Options::parse();
+
+    /* Parse and check all the queries up front, so that a typo does not cost a database load. */
+    let queries: Vec<(String, Query)> = options.queries.iter().map(|file| {
+        let source = std::fs::read_to_string(file)
+            .unwrap_or_else(|e| panic!("Error reading query file {:?}: {}", file, e));
+        let query = Query::parse(&source).and_then(|query| query.check().map(|_| query))
+            .unwrap_or_else(|e| panic!("Error in query file {:?}: {}", file, e));
+        let name = file.file_stem().map_or("query".to_owned(), |stem| stem.to_string_lossy().to_string());
+        (name, query)
+    }).collect();
+
+    if options.check {
+        println!("{} queries are correct.", queries.len());
+        return
+    }
+
+    let savepoint = parse_savepoint(&options.savepoint);
+    let stores: Vec<Store> = options.stores.iter().map(|store| Store::from(store.as_str())).collect();
+
+    let log = Log::new(options.config.verbosity);
+    let database = Djanco::from_config(&options.config, savepoint, stores, log)
+        .expect("Error initializing datastore.");
+
+    for (name, query) in queries {
+        let location = options.config.output_path.join(format!("{}.csv", name));
+        query.execute_to_csv(&database, location.to_string_lossy().to_string())
+            .unwrap_or_else(|e| panic!("Error running query {}: {}", name, e));
+    }
+}
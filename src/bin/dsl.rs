@@ -4,6 +4,7 @@ use djanco::*;
 use djanco::objects::*;
 use djanco::csv::*;
 use djanco::log::*;
+use djanco::time::Duration;
 
 // `cargo run --bin dsl --release -- -o ~/output -d /mnt/data/dataset -c /mnt/data/cache --data-dump=~/output/dump`
 fn main() {
@@ -13,6 +14,7 @@ fn main() {
 
     // TODO a priori size estimate for logging
     database.projects().sort_by(project::Stars).into_csv_in_dir(&config.output_path,  "sort_by_stars").unwrap();
+    database.projects().sort_by_keys((Ascending(project::Language), Descending(project::Stars))).into_csv_in_dir(&config.output_path,  "sort_by_language_and_stars").unwrap();
     database.projects().map_into(project::Stars).into_csv_in_dir(&config.output_path,  "select_by_stars").unwrap();
     database.projects().group_by(project::Stars).ungroup().into_csv_in_dir(&config.output_path,  "group_by_stars").unwrap();
     database.projects().filter_by(Equal(project::Language, objects::Language::C)).into_csv_in_dir(&config.output_path,  "filter_by_language_c").unwrap();
@@ -26,11 +28,18 @@ fn main() {
     database.projects().sort_by(Count(project::Commits)).into_csv_in_dir(&config.output_path,  "sort_by_commit_count").unwrap();
     database.projects().map_into(Mean(FromEach(project::Commits, commit::MessageLength))).into_csv_in_dir(&config.output_path,  "select_mean_commit_messages_length").unwrap();
     database.projects().map_into(Median(FromEach(project::Commits, commit::MessageLength))).into_csv_in_dir(&config.output_path,  "select_median_commit_messages_length").unwrap();
+    database.projects().map_into(Percentile(FromEach(project::Commits, commit::MessageLength), 90)).into_csv_in_dir(&config.output_path,  "select_p90_commit_messages_length").unwrap();
+    database.projects().map_into(Select!(project::Id, Describe(FromEach(project::Commits, commit::MessageLength)))).into_csv_in_dir(&config.output_path,  "describe_commit_messages_length").unwrap();
     database.projects().map_into(Count(FromEachIf(project::Commits, Equal(commit::MessageLength, 0)))).into_csv_in_dir(&config.output_path,  "select_projects_with_empty_commits").unwrap();
     database.users().sort_by(user::Experience).sample(Top(100)).into_csv_in_dir(&config.output_path,  "sample_top_100_experienced_users").unwrap();
     database.paths().filter_by(Equal(path::Language, Language::Haskell)).into_csv_in_dir(&config.output_path,  "filter_haskell_paths").unwrap();
     database.paths().filter_by(Equal(path::Language, Language::JavaScript)).into_csv_in_dir(&config.output_path,  "filter_javascript_paths").unwrap(); // recheck
     database.commits().sample(Random(100, Seed(42))).into_csv_in_dir(&config.output_path,  "sample_100_commits").unwrap();
+    database.commits().sample(Reservoir(100, Seed(42))).into_csv_in_dir(&config.output_path,  "sample_100_commits_in_order").unwrap();
+    database.commits().top_by(commit::MessageLength, 100).into_csv_in_dir(&config.output_path,  "top_100_commits_by_message_length").unwrap();
+    database.projects().sample(Stratified(project::Language, 10, Seed(42))).into_csv_in_dir(&config.output_path,  "sample_10_projects_per_language").unwrap();
+    database.projects().sample(Weighted(Count(project::Commits), 100, Seed(42))).into_csv_in_dir(&config.output_path,  "sample_100_projects_weighted_by_commits").unwrap();
+    database.projects().group_by(project::Language).sample(Stratified(Bucket(project::Stars, Logarithmic(10)), 5, Seed(42))).ungroup().into_csv_in_dir(&config.output_path,  "sample_5_projects_per_language_and_stars").unwrap();
     database.projects().map_into(Ratio(project::Authors, project::Users)).into_csv_in_dir(&config.output_path,  "select_project_ratio_of_authors_to_users").unwrap();
     database.projects().map_into(Select!(project::Id, project::URL)).into_csv_in_dir(&config.output_path,  "select_project_ids_and_urls").unwrap();
     database.commits().map_into(commit::Author)/*TODO .unique().map_into_attrib(user::IdTODO Experience*/.into_csv_in_dir(&config.output_path,  "commit_author_experience").unwrap();
@@ -38,11 +47,22 @@ fn main() {
     database.commits().map_into(commit::Parents).into_csv_in_dir(&config.output_path,  "commit_parents").unwrap();
     database.projects().map_into(FromEach(project::Commits, commit::MessageLength)).into_csv_in_dir(&config.output_path,  "project_commit_message_length").unwrap();
     database.users().sort_by(user::Experience).map_into(user::Experience).into_csv_in_dir(&config.output_path,  "user_experience").unwrap();
+    database.projects().sort_by(Div(project::Stars, Years(project::Age))).map_into(Select!(project::Id, Div(project::Stars, Years(project::Age)), Logarithm(Coalesce(project::Stars, 0), 10.0))).into_csv_in_dir(&config.output_path,  "sort_by_stars_per_year").unwrap();
+    database.projects().filter_by(AtLeast(project::MajorLanguageRatio, 0.5)).sort_by(project::MajorLanguageRatio).into_csv_in_dir(&config.output_path,  "sort_by_major_language_ratio").unwrap();
+    database.projects().group_by(Round(project::MajorLanguageRatio, 1)).sort_keys().aggregate(Select!(group::Key, group::Size, MeanF64(project::Stars))).into_csv_in_dir(&config.output_path,  "major_language_ratio/summary").unwrap();
+    database.projects().filter_by(Derived::new(|project: &ItemWithData<Project>| project.star_count().map(|stars| stars > 100 && stars % 2 == 0))).sort_by(Derived::new(|project: &ItemWithData<Project>| project.commit_count().map(|commits| commits / 10))).into_csv_in_dir(&config.output_path,  "derived_attributes").unwrap();
+    database.projects().filter_by(AllOf((Equal(project::IsFork, false), AtLeast(Count(project::Commits), 100), AtLeast(project::Stars, 10)))).into_csv_in_dir(&config.output_path,  "popular_non_forks_with_100_commits").unwrap();
+    database.projects().semi_join(project::AuthorIds, database.users().filter_by(AtLeast(user::Experience, Duration::from_years(5))), user::Id).into_csv_in_dir(&config.output_path,  "projects_with_experienced_authors").unwrap();
+    database.projects().sample(Top(10)).join_on(project::AuthorIds, database.users(), user::Id).map(|(project, user)| (project.item.id(), user.item.id())).into_csv_in_dir(&config.output_path,  "project_authors").unwrap();
+    database.projects().group_by(project::Language).having(AtLeast(group::Size, 10)).sort_keys().aggregate(Select!(group::Key, group::Size, Mean(project::Stars), Median(Count(project::Commits)))).into_csv_in_dir(&config.output_path,  "language/summary").unwrap();
     database.projects().group_by(project::Language).map_into(FromEach(project::Commits, commit::MessageLength)).into_csv_in_dir(&config.output_path,  "language/project_commit_message_length").unwrap();
     database.projects().filter_by(Member(project::Homepage, vec!["http://manasource.org/"].iter().map(|e| e.to_string()).collect::<Vec<String>>()));
     database.projects().filter_by(AnyIn(FromEach(project::Commits, commit::Id), vec![objects::CommitId::from(42u64), objects::CommitId::from(666u64)]));
     database.projects().filter_by(AllIn(FromEach(project::Commits, commit::Id), vec![objects::CommitId::from(42u64), objects::CommitId::from(666u64)]));
     database.projects().filter_by(Within(FromEach(project::Commits, commit::Id), objects::CommitId::from(666u64)));
     database.snapshots_with_data().sample(Random(10, Seed(42))).into_files_in_dir(&config.output_path).unwrap();
-    // database.projects().map_into_attrib(Bucket(Count(project::Commits), Interval(1000))).into_csv_in_dir(&config.output_path,  "bucket_1000").unwrap();
+    database.projects().map_into(Bucket(Count(project::Commits), Interval(1000))).into_csv_in_dir(&config.output_path,  "bucket_1000").unwrap();
+    database.projects().group_by(Bucket(Count(project::Commits), Logarithmic(10))).map(|(bin, projects)| (bin, projects.len())).into_csv_in_dir(&config.output_path,  "histogram_commits_log10").unwrap();
+    let quartiles = Quantiles::of(database.projects(), Count(project::Commits), 4);
+    database.projects().group_by(Bucket(Count(project::Commits), quartiles)).map(|(bin, projects)| (bin, projects.len())).into_csv_in_dir(&config.output_path,  "histogram_commits_quartiles").unwrap();
 }
\ No newline at end of file
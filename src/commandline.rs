@@ -3,6 +3,7 @@ use std::{path::PathBuf, str::FromStr};
 use clap::{Clap, crate_version, crate_authors};
 
 use crate::log::Verbosity;
+use crate::database::persistent::CacheFormat;
 
 pub type CommandLineOptions = Configuration;
 
@@ -35,6 +36,18 @@ pub struct Configuration {
 
     #[clap(long = "preclean-merged-substores", alias = "preclean-merged")]
     pub preclean_merged_substores: bool,   
+
+    #[clap(long = "memory-budget-mb")]
+    pub memory_budget: Option<usize>,
+
+    #[clap(long = "cache-format", default_value = "cbor")]
+    pub cache_format: CacheFormat,
+
+    #[clap(long = "languages", parse(from_os_str))]
+    pub languages_path: Option<PathBuf>,
+
+    #[clap(long = "local-repository", parse(from_os_str))]
+    pub local_repositories: Vec<PathBuf>,
 }
 
 impl FromStr for Verbosity {
@@ -50,6 +63,18 @@ impl FromStr for Verbosity {
     }
 }
 
+impl FromStr for CacheFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cbor" => Ok(CacheFormat::CBOR),
+            "indexed" | "index" => Ok(CacheFormat::Indexed),
+            format => bail!(format!("Invalid cache format: {}", format)),
+        }
+    }
+}
+
 impl Configuration {
     pub fn dataset_path_as_str(&self) -> &str {
         self.dataset_path.as_os_str().to_str().unwrap()
@@ -57,6 +82,9 @@ impl Configuration {
     pub fn cache_path_as_str(&self) -> &str {
         self.cache_path.as_ref().map_or(".cache", |p| p.as_os_str().to_str().unwrap())
     }
+    pub fn memory_budget_in_bytes(&self) -> Option<usize> {
+        self.memory_budget.map(|megabytes| megabytes * 1024 * 1024)
+    }
     pub fn output_path_as_str(&self) -> &str {
         self.output_path.as_os_str().to_str().unwrap()
     }
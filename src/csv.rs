@@ -14,9 +14,12 @@ use crate::objects::*;
 use crate::fraction::*;
 use crate::product::*;
 use crate::time::Duration;
+use crate::ordf64::OrdF64;
 use crate::Store;
+use crate::{Bin, Description};
 
 use crate::database::metadata::ProjectMetadata;
+use crate::database::memory::InMemorySource;
 
 macro_rules! create_file {
     ($location:expr) => {{
@@ -405,12 +408,21 @@ impl_csv_item_to_string!(i8,   "n");
 
 impl_csv_item_to_string!(f64, "n");
 impl_csv_item_to_string!(f32, "n");
+impl_csv_item_to_string!(OrdF64, "n");
 
 impl_csv_item_quoted!(String, "string");
 
 impl_csv_item_to_string!(Language, "language");
 impl_csv_item_to_string!(Store, "store");
 impl_csv_item_to_string!(Duration, "duration");
+impl_csv_item_to_string!(Bin, "bin");
+impl CSVItem for Description {
+    fn column_headers() -> Vec<&'static str> { vec!["count", "mean", "std", "min", "q1", "median", "q3", "max"] }
+    fn row(&self) -> Vec<String> {
+        vec![self.count.to_string(), self.mean.to_string(), self.std.to_string_or_empty(), self.min.to_string(),
+             self.q1.to_string(), self.median.to_string(), self.q3.to_string(), self.max.to_string()]
+    }
+}
 impl_csv_item!(Fraction<N> where N: Fractionable -> "n", |selfie: &Fraction<N>| vec![selfie.as_fraction_string()]);
 
 //--- parasite CSV items ---------------------------------------------------------------------------
@@ -436,6 +448,7 @@ impl_csv_item_with_data_inner!(i8);
 
 impl_csv_item_with_data_inner!(f64);
 impl_csv_item_with_data_inner!(f32);
+impl_csv_item_with_data_inner!(OrdF64);
 
 impl_csv_item_with_data_inner!(String);
 
@@ -700,6 +713,18 @@ impl<'a> CSVItem for ItemWithData<'a, User> {
 }
 
 impl CSVItem for Path {
+    fn column_headers() -> Vec<&'static str> {
+        vec![ "path_id", "path" ]
+    }
+    fn row(&self) -> Vec<String>  {
+        vec![
+            self.id().to_string(),
+            self.location().to_string()
+        ]
+    }
+}
+
+impl<'a> CSVItem for ItemWithData<'a, Path> {
     fn column_headers() -> Vec<&'static str> {
         vec![ "path_id", "path", "language" ]
     }
@@ -711,7 +736,6 @@ impl CSVItem for Path {
         ]
     }
 }
-impl_csv_item_with_data_inner!(Path);
 
 impl CSVItem for Change {
     fn column_headers() -> Vec<&'static str> { vec!["path_id", "snapshot_id"] }
@@ -1209,6 +1233,23 @@ impl<'a, I> Dump for I where I: Iterator<Item=ItemWithData<'a, Project>> {
     }
 }
 
+// ---- export -------------------------------------------------------------------------------------
+
+/** Unlike `Dump`, writes a dataset that can be loaded back (see `Database::export_projects`). */
+pub trait Export {
+    fn export_dataset_to<S>(self, location: S) -> anyhow::Result<()> where S: Into<String>;
+}
+
+impl<'a, I> Export for I where I: Iterator<Item=ItemWithData<'a, Project>> {
+    fn export_dataset_to<S>(self, location: S) -> anyhow::Result<()> where S: Into<String> {
+        let mut projects = self.peekable();
+        match projects.peek().map(|project| project.data) {
+            Some(database) => database.export_projects(projects.map(|project| project.id()), location.into()),
+            None => InMemorySource::new().write_dir(location.into()),
+        }
+    }
+}
+
 pub trait FileWritable {
     fn contents_as_bytes(&self) -> Option<&Vec<u8>>;
 }
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use crate::log::{Log, Verbosity};
+use crate::weights_and_measures::Weights;
+
+/*
+ * Keeps track of which persistent collections in Data are currently loaded,
+ * how much memory they take up, and when they were last used.
+ *
+ * Collections are identified by the name of the field they are stored in.
+ * When a memory limit is set, collections are unloaded in least-recently-used
+ * order until the total falls under the limit again. The most recently used
+ * collection is never unloaded, even if it is larger than the limit on its
+ * own, since it is the one the query is currently working with.
+ *
+ * Unloading a collection is only possible if it can be brought back, ie. if
+ * it has a cache file or is never cached at all (then it is extracted anew).
+ * That is not decided here, but by whoever actually unloads the collection
+ * (see `PersistentCollection::unload`).
+ */
+pub(crate) struct MemoryBudget {
+    limit: Option<usize>,
+    clock: u64,
+    residents: BTreeMap<&'static str, Resident>,
+    log: Log,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Resident {
+    weight: usize,
+    last_used: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(log: Log) -> Self {
+        MemoryBudget { limit: None, clock: 0, residents: BTreeMap::new(), log }
+    }
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+    pub fn is_limited(&self) -> bool {
+        self.limit.is_some()
+    }
+    pub fn total_weight(&self) -> usize {
+        self.residents.values().map(|resident| resident.weight).sum()
+    }
+    pub fn is_over_limit(&self) -> bool {
+        self.limit.map_or(false, |limit| self.total_weight() > limit)
+    }
+
+    /** Records that a collection was loaded and how much it weighs. */
+    pub fn loaded(&mut self, name: &'static str, weight: usize) {
+        self.clock += 1;
+        self.residents.insert(name, Resident { weight, last_used: self.clock });
+    }
+    /** Records that a loaded collection was just used. */
+    pub fn touch(&mut self, name: &'static str) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(resident) = self.residents.get_mut(name) {
+            resident.last_used = clock;
+        }
+    }
+    /** Records that a collection was unloaded. */
+    pub fn unloaded(&mut self, name: &'static str) {
+        if let Some(resident) = self.residents.remove(name) {
+            let event = self.log.start(Verbosity::Debug,
+                format!("unloading {} to free {}", name, Weights::bytes_as_human_readable_string(resident.weight)));
+            self.log.end(event);
+        }
+    }
+
+    /** Lists the collections that should be unloaded to get under the memory limit, least-recently used first.
+
+        The list is a suggestion: the caller may not be able to unload some of them, in which case it should
+        go on to the next one. The most recently used collection is never suggested.
+     */
+    pub fn eviction_candidates(&self) -> Vec<&'static str> {
+        if !self.is_over_limit() {
+            return vec![]
+        }
+        let mut residents: Vec<(&'static str, Resident)> =
+            self.residents.iter().map(|(name, resident)| (*name, *resident)).collect();
+        residents.sort_by_key(|(_, resident)| resident.last_used);
+        residents.pop(); // most recently used
+        residents.into_iter().map(|(name, _)| name).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::log::{Log, Verbosity};
+    use super::MemoryBudget;
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut budget = MemoryBudget::new(Log::new(Verbosity::Warning));
+        budget.loaded("a", 10);
+        budget.loaded("b", 10);
+        budget.loaded("c", 10);
+        assert_eq!(budget.eviction_candidates(), Vec::<&str>::new());
+
+        budget.set_limit(Some(15));
+        budget.touch("a");
+        assert_eq!(budget.eviction_candidates(), vec!["b", "c"]);
+
+        budget.unloaded("b");
+        budget.touch("c");
+        assert!(budget.is_over_limit());
+        assert_eq!(budget.eviction_candidates(), vec!["a"]);
+
+        budget.unloaded("a");
+        assert!(!budget.is_over_limit());
+        assert_eq!(budget.eviction_candidates(), Vec::<&str>::new());
+    }
+}
@@ -64,6 +64,7 @@ pub static CACHE_FILE_USER_AUTHORED_COMMIT_COUNT:     &'static str = "user_autho
 pub static CACHE_FILE_USER_COMMITTED_COMMIT_COUNT:    &'static str = "user_committed_commit_count";
 pub static CACHE_FILE_DEVELOPER_EXPERIENCE:           &'static str = "developer_experience";
 pub static CACHE_FILE_PATHS:                          &'static str = "paths";
+pub static CACHE_FILE_PATH_LANGUAGES:                 &'static str = "path_languages";
 pub static CACHE_FILE_COMMITS:                        &'static str = "commits";
 pub static CACHE_FILE_COMMIT_HASHES:                  &'static str = "commit_hashes";
 pub static CACHE_FILE_COMMIT_MESSAGES:                &'static str = "commit_messages";
@@ -71,6 +72,8 @@ pub static CACHE_FILE_COMMIT_AUTHOR_TIMESTAMPS:       &'static str = "commit_aut
 pub static CACHE_FILE_COMMIT_COMMITTER_TIMESTAMPS:    &'static str = "commit_committer_timestamps";
 pub static CACHE_FILE_COMMIT_CHANGES:                 &'static str = "commit_changes";
 pub static CACHE_FILE_COMMIT_CHANGES_WITH_CONTENTS:   &'static str = "commit_changes_with_contents";
+pub static CACHE_FILE_COMMIT_PREVIOUS_SNAPSHOTS:      &'static str = "commit_previous_snapshots";
+pub static CACHE_FILE_COMMIT_LINE_CHANGES:           &'static str = "commit_line_changes";
 pub static CACHE_FILE_COMMIT_CHANGE_COUNT:            &'static str = "commit_change_count";
 pub static CACHE_FILE_COMMIT_CHANGE_WITH_CONTENTS_COUNT: &'static str = "commit_change_with_contents_count";
 pub static CACHE_FILE_COMMIT_PROJECTS:                &'static str = "commit_projects";
@@ -86,6 +89,8 @@ pub static CACHE_FILE_IS_ABANDONED:                   &'static str = "is_abandon
 pub static CACHE_FILE_SNAPSHOT_LOCS:                  &'static str = "snapshot_locs";  
 pub static CACHE_FILE_SNAPSHOT_HAS_CONTENTS:          &'static str = "snapshot_has_contents";  
 pub static CACHE_FILE_PROJECT_LOCS:                   &'static str = "project_locs";  
+pub static CACHE_FILE_SNAPSHOT_LINE_COUNTS:           &'static str = "snapshot_line_counts";
+pub static CACHE_FILE_PROJECT_LINE_COUNTS:            &'static str = "project_line_counts";
 pub static CACHE_FILE_DUPLICATED_CODE:                &'static str = "duplicated_code";  
 pub static CACHE_FILE_PROJECT_LOGS:                   &'static str = "project_logs";
 pub static CACHE_FILE_PROJECT_IS_VALID:               &'static str = "project_is_valid";
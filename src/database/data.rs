@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 
 use anyhow::*;
+use itertools::Itertools;
 
 use crate::objects::*;
 use crate::piracy::*;
@@ -12,9 +14,18 @@ use super::cache::*;
 use super::persistent::*;
 use super::metadata::*;
 use super::extractors::*;
+use super::budget::MemoryBudget;
+use super::header::CacheHeader;
 use super::source::Source;
+use crate::languages::LanguageRegistry;
+use crate::lines::LineCounts;
 
 pub(crate) struct Data {
+    budget:                      Mutex<MemoryBudget>,
+    cache_format:                CacheFormat,
+    verified_caches:             Mutex<BTreeMap<String, Option<u64>>>,
+    language_registry:           u64,
+    log:                         Log,
     project_metadata:            ProjectMetadataSource,
     project_substores:           PersistentMap<ProjectSubstoreExtractor>,
     project_urls:                PersistentMap<ProjectUrlExtractor>,
@@ -89,6 +100,7 @@ pub(crate) struct Data {
     user_committed_commit_count: PersistentMap<CountPerKeyExtractor<UserId, CommitId>>,
 
     paths:                       PersistentMap<PathExtractor>,
+    path_languages:              PersistentMap<PathLanguagesExtractor>,
     //snapshots:                   PersistentMap<SnapshotExtractor>,
 
     commits:                     PersistentMap<CommitExtractor>,
@@ -98,6 +110,8 @@ pub(crate) struct Data {
     commit_committer_timestamps: PersistentMap<CommitterTimestampExtractor>,
     commit_changes:              PersistentMap<CommitChangesExtractor>,
     commit_changes_with_contents:PersistentMap<CommitChangesWithContentsExtractor>,
+    commit_previous_snapshots:   PersistentMap<CommitPreviousSnapshotsExtractor>,
+    commit_line_changes:         PersistentMap<CommitLineChangesExtractor>,
 
     commit_change_count:         PersistentMap<CountPerKeyExtractor<CommitId, ChangeTuple>>,
     commit_change_with_contents_count: PersistentMap<CountPerKeyExtractor<CommitId, ChangeTuple>>,
@@ -120,6 +134,8 @@ pub(crate) struct Data {
     is_abandoned:                 PersistentMap<IsAbandonedExtractor>,
     snapshot_locs:                PersistentMap<SnapshotLocsExtractor>,
     project_locs:                 PersistentMap<ProjectLocsExtractor>,
+    snapshot_line_counts:         PersistentMap<SnapshotLineCountsExtractor>,
+    project_line_counts:          PersistentMap<ProjectLineCountsExtractor>,
     duplicated_code:              PersistentMap<DuplicatedCodeExtractor>,
     project_is_valid:             PersistentMap<ProjectIsValidExtractor>,
     project_logs:                 PersistentMap<ProjectLogsExtractor>,
@@ -130,7 +146,12 @@ pub(crate) struct Data {
 impl Data {
     pub fn new(/*source: DataSource,*/ cache_dir: CacheDir, log: Log) -> Data {
         let dir = cache_dir.as_string();
-        Data {
+        let mut data = Data {
+            budget:                         Mutex::new(MemoryBudget::new(log.clone())),
+            cache_format:                   CacheFormat::default(),
+            verified_caches:                Mutex::new(BTreeMap::new()),
+            language_registry:              LanguageRegistry::with_default(|registry| registry.fingerprint()),
+            log:                            log.clone(),
             project_metadata:               ProjectMetadataSource::new(log.clone(),dir.clone()),
    
             project_urls:                   PersistentMap::new(CACHE_FILE_PROJECT_URL,                    log.clone(),dir.clone()).without_cache(),
@@ -199,6 +220,7 @@ impl Data {
             user_committed_commit_count:    PersistentMap::new(CACHE_FILE_USER_COMMITTED_COMMIT_COUNT,    log.clone(),dir.clone()),
             developer_experience:           PersistentMap::new(CACHE_FILE_DEVELOPER_EXPERIENCE,           log.clone(),dir.clone()),
             paths:                          PersistentMap::new(CACHE_FILE_PATHS,                          log.clone(),dir.clone()).without_cache(),
+            path_languages:                 PersistentMap::new(CACHE_FILE_PATH_LANGUAGES,                 log.clone(),dir.clone()),
             commits:                        PersistentMap::new(CACHE_FILE_COMMITS,                        log.clone(),dir.clone()),
             commit_hashes:                  PersistentMap::new(CACHE_FILE_COMMIT_HASHES,                  log.clone(),dir.clone()).without_cache(),
             commit_messages:                PersistentMap::new(CACHE_FILE_COMMIT_MESSAGES,                log.clone(),dir.clone()).without_cache(),
@@ -207,6 +229,8 @@ impl Data {
             commit_changes:                 PersistentMap::new(CACHE_FILE_COMMIT_CHANGES,                 log.clone(),dir.clone()).without_cache(),
             // note changes with contents are cached since we do not have then readily available in parasite's dataset
             commit_changes_with_contents:   PersistentMap::new(CACHE_FILE_COMMIT_CHANGES_WITH_CONTENTS,   log.clone(),dir.clone()),
+            commit_previous_snapshots:      PersistentMap::new(CACHE_FILE_COMMIT_PREVIOUS_SNAPSHOTS,      log.clone(),dir.clone()),
+            commit_line_changes:            PersistentMap::new(CACHE_FILE_COMMIT_LINE_CHANGES,            log.clone(),dir.clone()),
             commit_change_count:            PersistentMap::new(CACHE_FILE_COMMIT_CHANGE_COUNT,            log.clone(),dir.clone()),
             commit_change_with_contents_count: PersistentMap::new(CACHE_FILE_COMMIT_CHANGE_WITH_CONTENTS_COUNT, log.clone(),dir.clone()),
             commit_projects:                PersistentMap::new(CACHE_FILE_COMMIT_PROJECTS,                log.clone(),dir.clone()),
@@ -222,148 +246,537 @@ impl Data {
             snapshot_locs:                  PersistentMap::new(CACHE_FILE_SNAPSHOT_LOCS, log.clone(), dir.clone()),
             snapshot_has_contents:          PersistentMap::new(CACHE_FILE_SNAPSHOT_HAS_CONTENTS, log.clone(), dir.clone()),
             project_locs:                   PersistentMap::new(CACHE_FILE_PROJECT_LOCS, log.clone(), dir.clone()),
+            snapshot_line_counts:           PersistentMap::new(CACHE_FILE_SNAPSHOT_LINE_COUNTS, log.clone(), dir.clone()),
+            project_line_counts:            PersistentMap::new(CACHE_FILE_PROJECT_LINE_COUNTS, log.clone(), dir.clone()),
             duplicated_code:                PersistentMap::new(CACHE_FILE_DUPLICATED_CODE, log.clone(), dir.clone()),
             project_is_valid:               PersistentMap::new(CACHE_FILE_PROJECT_IS_VALID, log.clone(), dir.clone()),
             project_logs:                   PersistentMap::new(CACHE_FILE_PROJECT_LOGS, log.clone(), dir.clone()),
             project_max_experience:         PersistentMap::new(CACHE_FILE_PROJECT_MAX_EXPERIENCE, log.clone(), dir.clone()),
             project_experience:             PersistentMap::new(CACHE_FILE_PROJECT_EXPERIENCE, log.clone(), dir.clone())
+        };
+        data.set_cache_context(&cache_dir);
+        data
+    }
+}
+
+/*
+ * All of the persistent collections in Data: the function that loads each
+ * one, where it is extracted from (straight from the source, from project
+ * metadata, or derived from other collections), and the collections it is
+ * derived from, in the order they are passed to its extractor.
+ *
+ * This has to agree with the smart_load functions, which check it in debug
+ * builds. The table is passed to a macro together with the given arguments.
+ */
+macro_rules! with_cache_table {
+    ($callback:ident, $($arguments:tt)*) => {
+        $callback!(($($arguments)*)
+            project_substores                       => smart_load_project_substore                        source   [],
+            project_urls                            => smart_load_project_urls                            source   [],
+            project_heads                           => smart_load_project_heads                           source   [],
+            project_paths                           => smart_load_project_paths                           derived  [project_commits, commit_changes],
+            project_snapshots                       => smart_load_project_snapshots                       derived  [project_commits, commit_changes, snapshot_has_contents],
+            project_users                           => smart_load_project_users                           derived  [project_authors, project_committers],
+            project_authors                         => smart_load_project_authors                         derived  [project_commits, commits],
+            project_committers                      => smart_load_project_committers                      derived  [project_commits, commits],
+            project_commits                         => smart_load_project_commits                         derived  [project_heads, commits],
+            project_lifetimes                       => smart_load_project_lifetimes                       derived  [project_commits, commit_author_timestamps, commit_committer_timestamps],
+            project_path_count                      => smart_load_project_path_count                      derived  [project_paths],
+            project_snapshot_count                  => smart_load_project_snapshot_count                  derived  [project_snapshots],
+            project_user_count                      => smart_load_project_user_count                      derived  [project_users],
+            project_author_count                    => smart_load_project_author_count                    derived  [project_authors],
+            project_committer_count                 => smart_load_project_committer_count                 derived  [project_committers],
+            project_commit_count                    => smart_load_project_commit_count                    derived  [project_commits],
+            project_change_contributions            => smart_load_project_change_contributions            derived  [project_commits, commits, commit_changes],
+            project_commit_contributions            => smart_load_project_commit_contributions            derived  [project_commits, commits],
+            project_cumulative_change_contributions => smart_load_project_cumulative_change_contributions derived  [project_change_contributions],
+            project_cumulative_commit_contributions => smart_load_project_cumulative_commit_contributions derived  [project_commit_contributions],
+            project_unique_files                    => smart_load_project_unique_files                    derived  [project_commits, commit_changes_with_contents, snapshot_projects],
+            project_original_files                  => smart_load_project_original_files                  derived  [project_commits, commit_changes_with_contents, snapshot_projects],
+            project_impact                          => smart_load_project_impact                          derived  [project_commits, commit_changes_with_contents, snapshot_projects],
+            project_files                           => smart_load_project_files                           derived  [project_commits, commit_changes],
+            project_languages                       => smart_load_project_languages                       derived  [project_commits, commit_changes, path_languages],
+            project_languages_count                 => smart_load_project_languages_count                 derived  [project_languages],
+            project_major_language                  => smart_load_project_major_language                  derived  [project_languages],
+            project_major_language_ratio            => smart_load_project_major_language_ratio            derived  [project_languages],
+            project_major_language_changes          => smart_load_project_major_language_changes          derived  [project_languages],
+            project_all_forks                       => smart_load_project_all_forks                       derived  [project_commits, commit_projects, project_created],
+            project_all_forks_count                 => smart_load_project_all_forks_count                 derived  [project_all_forks],
+            project_head_trees                      => smart_load_project_head_trees                      derived  [project_heads, commits, commit_changes],
+            project_head_trees_count                => smart_load_project_head_trees_count                derived  [project_head_trees],
+            project_buggy_issue_count               => smart_load_project_buggy_issues                    metadata [],
+            project_issue_count                     => smart_load_project_issues                          metadata [],
+            project_is_fork                         => smart_load_project_is_fork                         metadata [],
+            project_is_archived                     => smart_load_project_is_archived                     metadata [],
+            project_is_disabled                     => smart_load_project_is_disabled                     metadata [],
+            project_star_gazer_count                => smart_load_project_star_gazer_count                metadata [],
+            project_watcher_count                   => smart_load_project_watcher_count                   metadata [],
+            project_project_size                    => smart_load_project_size                            metadata [],
+            project_open_issue_count                => smart_load_project_open_issue_count                metadata [],
+            project_fork_count                      => smart_load_project_fork_count                      metadata [],
+            project_subscriber_count                => smart_load_project_subscriber_count                metadata [],
+            project_license                         => smart_load_project_license                         metadata [],
+            project_language                        => smart_load_project_language                        metadata [],
+            project_description                     => smart_load_project_description                     metadata [],
+            project_homepage                        => smart_load_project_homepage                        metadata [],
+            project_has_issues                      => smart_load_project_has_issues                      metadata [],
+            project_has_downloads                   => smart_load_project_has_downloads                   metadata [],
+            project_has_wiki                        => smart_load_project_has_wiki                        metadata [],
+            project_has_pages                       => smart_load_project_has_pages                       metadata [],
+            project_created                         => smart_load_project_created                         metadata [],
+            project_updated                         => smart_load_project_updated                         metadata [],
+            project_pushed                          => smart_load_project_pushed                          metadata [],
+            project_default_branch                  => smart_load_project_default_branch                  metadata [],
+            users                                   => smart_load_users                                   source   [],
+            user_authored_commits                   => smart_load_user_authored_commits                   derived  [commits],
+            user_committed_commits                  => smart_load_user_committed_commits                  derived  [commits],
+            user_author_experience                  => smart_load_user_author_experience                  derived  [user_authored_commits, commit_author_timestamps],
+            user_committer_experience               => smart_load_user_committer_experience               derived  [user_committed_commits, commit_committer_timestamps],
+            user_experience                         => smart_load_user_experience                         derived  [user_committed_commits, commit_author_timestamps, commit_committer_timestamps],
+            developer_experience                    => smart_load_developer_experience                    derived  [user_authored_commits, commit_author_timestamps],
+            user_authored_commit_count              => smart_load_user_authored_commit_count              derived  [user_authored_commits],
+            user_committed_commit_count             => smart_load_user_committed_commit_count             derived  [user_committed_commits],
+            paths                                   => smart_load_paths                                   source   [],
+            path_languages                          => smart_load_path_languages                          derived  [commit_changes, paths],
+            commits                                 => smart_load_commits                                 source   [],
+            commit_hashes                           => smart_load_commit_hashes                           source   [],
+            commit_messages                         => smart_load_commit_messages                         source   [],
+            commit_author_timestamps                => smart_load_commit_author_timestamps                source   [],
+            commit_committer_timestamps             => smart_load_commit_committer_timestamps             source   [],
+            commit_changes                          => smart_load_commit_changes                          source   [],
+            commit_changes_with_contents            => smart_load_commit_changes_with_contents            derived  [commit_changes, snapshot_has_contents],
+            commit_previous_snapshots               => smart_load_commit_previous_snapshots               derived  [commits, commit_changes],
+            commit_line_changes                     => smart_load_commit_line_changes                     derived  [commit_changes, commit_previous_snapshots],
+            commit_change_count                     => smart_load_commit_change_count                     derived  [commit_changes],
+            commit_change_with_contents_count       => smart_load_commit_change_with_contents_count       derived  [commit_changes_with_contents],
+            commit_projects                         => smart_load_commit_projects                         derived  [project_commits],
+            commit_projects_count                   => smart_load_commit_projects_count                   derived  [commit_projects],
+            commit_languages                        => smart_load_commit_languages                        derived  [commit_changes, path_languages],
+            commit_languages_count                  => smart_load_commit_languages_count                  derived  [commit_languages],
+            snapshot_projects                       => smart_load_snapshot_projects                       derived  [commit_changes, commit_projects, commit_author_timestamps, project_created],
+            snapshot_has_contents                   => smart_load_snapshot_has_contents                   source   [],
+            project_max_commit_delta                => smart_load_project_max_commit_delta                derived  [project_commits, commit_committer_timestamps],
+            avg_commit_delta                        => smart_load_project_avg_commit_delta                derived  [project_commits, commit_committer_timestamps],
+            project_time_since_last_commit          => smart_load_project_time_since_last_commit          derived  [project_commits, commit_committer_timestamps, project_logs],
+            project_time_since_first_commit         => smart_load_project_time_since_first_commit         derived  [project_commits, commit_committer_timestamps, project_logs],
+            is_abandoned                            => smart_load_project_is_abandoned                    derived  [project_max_commit_delta, project_time_since_last_commit],
+            snapshot_locs                           => smart_load_snapshot_locs                           source   [],
+            project_locs                            => smart_load_project_locs                            derived  [project_head_trees, project_default_branch, snapshot_locs],
+            snapshot_line_counts                    => smart_load_snapshot_line_counts                    derived  [commit_changes, path_languages],
+            project_line_counts                     => smart_load_project_line_counts                     derived  [project_head_trees, project_default_branch, snapshot_line_counts],
+            duplicated_code                         => smart_load_project_duplicated_code                 derived  [project_commits, commit_changes_with_contents, snapshot_projects],
+            project_is_valid                        => smart_load_project_is_valid                        source   [],
+            project_logs                            => smart_load_project_logs                            derived  [project_is_valid],
+            project_max_experience                  => smart_load_project_max_experience                  derived  [project_authors, developer_experience],
+            project_experience                      => smart_load_project_experience                      derived  [developer_experience, project_commits, commits])
+    }
+}
+
+/*
+ * Calls a macro with the given arguments followed by the names of all of the
+ * persistent collections in Data, for operations that apply to every one of
+ * them by name.
+ */
+macro_rules! for_each_collection {
+    ($callback:ident, $($arguments:tt)*) => {
+        with_cache_table!(collection_names, $callback, $($arguments)*)
+    }
+}
+
+macro_rules! collection_names {
+    (($callback:ident, $($arguments:tt)*) $($collection:ident => $load:ident $kind:ident [$($prerequisite:ident),*]),*) => {
+        $callback!($($arguments)*, $($collection),*)
+    }
+}
+
+macro_rules! list_collections {
+    (() $($collection:ident => $load:ident $kind:ident [$($prerequisite:ident),*]),*) => {
+        vec![$( (stringify!($collection), stringify!($kind), vec![$(stringify!($prerequisite)),*]) ),*]
+    }
+}
+
+macro_rules! build_by_name {
+    (($self:ident, $name:expr, $source:expr) $($collection:ident => $load:ident $kind:ident [$($prerequisite:ident),*]),*) => {{
+        $( if $name == stringify!($collection) { $self.$load($source); } )*
+    }}
+}
+
+macro_rules! unload_by_name {
+    ($self:ident, $name:expr, $($collection:ident),*) => {{
+        $( if $name == stringify!($collection) { return $self.$collection.unload() } )*
+        false
+    }}
+}
+
+macro_rules! loaded_names {
+    ($self:ident, $($collection:ident),*) => {{
+        let mut names: Vec<&'static str> = vec![];
+        $( if $self.$collection.is_loaded() { names.push(stringify!($collection)) } )*
+        names
+    }}
+}
+
+macro_rules! set_format_of_all {
+    ($self:ident, $format:expr, $($collection:ident),*) => {{
+        $( $self.$collection.set_format($format); )*
+    }}
+}
+
+macro_rules! set_context_of_all {
+    ($self:ident, $savepoint:expr, $substores:expr, $($collection:ident),*) => {{
+        $( $self.$collection.set_context($savepoint, $substores); )*
+    }}
+}
+
+macro_rules! cache_headers_by_name {
+    ($self:ident, $name:expr, $($collection:ident),*) => {{
+        $( if $name == stringify!($collection) {
+            let expected = $self.$collection.header();
+            // Collections that are loaded or never cached are always up to date.
+            let stored = if $self.$collection.is_loaded() || $self.$collection.skip_caching() {
+                Some(expected.clone())
+            } else {
+                $self.$collection.stored_header()
+            };
+            return Some((expected, stored))
+        } )*
+        None
+    }}
+}
+
+/*
+ * Retrieves a single value from a collection, straight from its cache file if
+ * the cache format allows it, and by loading the whole collection otherwise.
+ */
+macro_rules! lookup {
+    ($self:ident, $collection:ident, $smart_load:ident, $source:expr, $id:expr) => {{
+        let value = if $self.verified_fingerprint(stringify!($collection)).is_some() {
+            $self.$collection.lookup($id)
+        } else {
+            None
+        };
+        match value {
+            Some(value) => value,
+            None => $self.$smart_load($source).get($id).pirate(),
         }
+    }}
+}
+
+impl Data { // Memory management
+    pub fn set_memory_budget(&self, bytes: Option<usize>) {
+        self.budget.lock().unwrap().set_limit(bytes);
+        self.enforce_memory_budget();
+    }
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.budget.lock().unwrap().limit()
+    }
+
+    /* Unloads least recently used collections until the loaded collections fit into the budget again.
+
+       Collections are handed out as shared pointers, so unloading one does not take it away from
+       whoever is using it at the moment, eg. to extract another collection from it. It only
+       means that the next one to need it loads it again. Database calls this after each
+       delegated call returns.
+     */
+    pub fn enforce_memory_budget(&self) {
+        let mut budget = self.budget.lock().unwrap();
+        if !budget.is_over_limit() {
+            return
+        }
+        for name in budget.eviction_candidates() {
+            if self.unload(name) {
+                budget.unloaded(name);
+            }
+            if !budget.is_over_limit() {
+                break
+            }
+        }
+    }
+
+    fn unload(&self, name: &str) -> bool {
+        for_each_collection!(unload_by_name, self, name)
+    }
+
+    /* The names of the collections that are currently in memory. */
+    pub fn loaded_collections(&self) -> Vec<&'static str> {
+        for_each_collection!(loaded_names, self)
+    }
+}
+
+impl Data { // Cache format
+    /* Switches the on-disk format of all persistent maps. Maps that are already loaded stay
+       loaded, but from now on they are cached to and loaded from files in the new format.
+     */
+    pub fn set_cache_format(&mut self, format: CacheFormat) {
+        self.cache_format = format;
+        self.verified_caches.get_mut().unwrap().clear();
+        for_each_collection!(set_format_of_all, self, format)
+    }
+    pub fn cache_format(&self) -> CacheFormat {
+        self.cache_format
+    }
+}
+
+/* Caches that depend on the language registry list it among their prerequisites under this name,
+   with the fingerprint of the registry, as if it were a collection. */
+const LANGUAGE_REGISTRY: &str = "language registry";
+
+impl Data { // Language detection
+    /* Switches to a different language registry, whose fingerprint is given. Caches of languages
+       made with another registry are stale from now on. */
+    pub fn set_language_registry(&mut self, fingerprint: u64) {
+        if self.language_registry != fingerprint {
+            self.language_registry = fingerprint;
+            self.verified_caches.get_mut().unwrap().clear();
+        }
+    }
+}
+
+impl Data { // Cache validation
+    fn set_cache_context(&mut self, cache_dir: &CacheDir) {
+        let savepoint = cache_dir.savepoint();
+        let substores: Vec<String> = cache_dir.substores().iter().map(|store| store.to_string()).collect();
+        for_each_collection!(set_context_of_all, self, savepoint, &substores)
+    }
+
+    fn cache_headers(&self, name: &str) -> Option<(CacheHeader, Option<CacheHeader>)> {
+        for_each_collection!(cache_headers_by_name, self, name)
+    }
+
+    /* Checks whether the cache of a collection is up to date without loading it or anything it is
+       derived from, and returns its fingerprint if it is.
+
+       Loading a collection checks this by itself, because its prerequisites are loaded first, so
+       their fingerprints are known. This is for reading from a cache file directly: the header of
+       the file lists the collections it was derived from, and each of those is checked in turn.
+     */
+    fn verified_fingerprint(&self, name: &str) -> Option<u64> {
+        if name == LANGUAGE_REGISTRY {
+            return Some(self.language_registry)
+        }
+        if let Some(fingerprint) = self.verified_caches.lock().unwrap().get(name) {
+            return *fingerprint
+        }
+        // Not holding the lock while verifying, since that verifies the prerequisites as well.
+        let fingerprint = self.verify_cache(name);
+        self.verified_caches.lock().unwrap().insert(name.to_owned(), fingerprint);
+        fingerprint
+    }
+
+    fn verify_cache(&self, name: &str) -> Option<u64> {
+        let (expected, stored) = self.cache_headers(name)?;
+        let stored = stored?;
+
+        let expected = expected.with_prerequisites(stored.prerequisites.clone());
+        if let Some(reason) = stored.mismatch(&expected) {
+            let event = self.log.start(Verbosity::Debug, format!("cache of {} is stale because {}", name, reason));
+            self.log.end(event);
+            return None
+        }
+        for (prerequisite, fingerprint) in stored.prerequisites.iter() {
+            if self.verified_fingerprint(prerequisite) != Some(*fingerprint) {
+                let event = self.log.start(Verbosity::Debug, format!("cache of {} is stale because {} changed", name, prerequisite));
+                self.log.end(event);
+                return None
+            }
+        }
+        Some(stored.fingerprint())
+    }
+}
+
+/* Where a collection is extracted from. */
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum CollectionOrigin {
+    Source,
+    Metadata,
+    Derived,
+}
+
+impl CollectionOrigin {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "source" => CollectionOrigin::Source,
+            "metadata" => CollectionOrigin::Metadata,
+            "derived" => CollectionOrigin::Derived,
+            other => unreachable!("unknown collection origin {}", other),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CollectionSpec {
+    pub name: &'static str,
+    pub origin: CollectionOrigin,
+    pub prerequisites: Vec<&'static str>,
+}
+
+impl Data { // Building caches ahead of time
+    pub fn collections() -> Vec<CollectionSpec> {
+        with_cache_table!(list_collections,).into_iter()
+            .map(|(name, origin, prerequisites)| {
+                CollectionSpec { name, origin: CollectionOrigin::from_name(origin), prerequisites }
+            }).collect()
+    }
+    pub fn collection(name: &str) -> Option<CollectionSpec> {
+        Self::collections().into_iter().find(|collection| collection.name == name)
+    }
+
+    /* Extracts the metadata of all projects into its own cache files in one go, unless that was
+       done already. The collections that come from metadata are extracted from those files, so
+       this should be done before they are built in parallel, lest each of them do it at once. */
+    pub fn prepare_metadata(&self, source: &Source) -> Result<()> {
+        self.project_metadata.precache_all(source).map_err(|errors| {
+            anyhow!("cannot cache project metadata: {}", errors.iter().map(|e| e.to_string()).join("; "))
+        })
+    }
+
+    /* Loads a collection, so that it is extracted and stored in its cache file if that file is
+       missing or stale. Returns false without loading anything if the cache is up to date. */
+    pub fn build_cache(&self, name: &str, source: &Source) -> Result<bool> {
+        if Self::collection(name).is_none() {
+            bail!("there is no cache named {}", name)
+        }
+        if self.verified_fingerprint(name).is_some() {
+            return Ok(false)
+        }
+        with_cache_table!(build_by_name, self, name, source);
+        Ok(true)
     }
 }
 
 impl Data { // Prequincunx, sort of
-    pub fn all_project_ids(&mut self, source: &Source) -> Vec<ProjectId> {
+    pub fn all_project_ids(&self, source: &Source) -> Vec<ProjectId> {
         self.smart_load_project_urls(source).keys().collect::<Vec<&ProjectId>>().pirate()
     }
-    pub fn all_user_ids(&mut self, source: &Source) -> Vec<UserId> {
+    pub fn all_user_ids(&self, source: &Source) -> Vec<UserId> {
         self.smart_load_users(source).keys().collect::<Vec<&UserId>>().pirate()
     }
-    pub fn all_path_ids(&mut self, source: &Source) -> Vec<PathId> {
+    pub fn all_path_ids(&self, source: &Source) -> Vec<PathId> {
         self.smart_load_paths(source).keys().collect::<Vec<&PathId>>().pirate()
     }
-    pub fn all_commit_ids(&mut self, source: &Source) -> Vec<CommitId> {
+    pub fn all_commit_ids(&self, source: &Source) -> Vec<CommitId> {
         self.smart_load_commits(source).keys().collect::<Vec<&CommitId>>().pirate()
     }
 }
 
 impl Data { // Quincunx, sort of
-    #[allow(dead_code)] pub fn projects<'a>(&'a mut self, source: &Source) -> impl Iterator<Item=Project> + 'a {
-        self.smart_load_project_urls(source).iter().map(|(id, url)| Project::new(id.clone(), url.clone()))
+    #[allow(dead_code)] pub fn projects(&self, source: &Source) -> Vec<Project> {
+        self.smart_load_project_urls(source).iter().map(|(id, url)| Project::new(id.clone(), url.clone())).collect()
     }
 
-    #[allow(dead_code)] pub fn users<'a>(&'a mut self, source: &Source) -> impl Iterator<Item=&'a User> + 'a {
-        self.smart_load_users(source).iter().map(|(_, user)| user)
+    #[allow(dead_code)] pub fn users(&self, source: &Source) -> Vec<User> {
+        self.smart_load_users(source).values().cloned().collect()
     }
 
-    #[allow(dead_code)] pub fn paths<'a>(&'a mut self, source: &Source) -> impl Iterator<Item=&'a Path> + 'a {
-        self.smart_load_paths(source).iter().map(|(_, path)| path)
+    #[allow(dead_code)] pub fn paths(&self, source: &Source) -> Vec<Path> {
+        self.smart_load_paths(source).values().cloned().collect()
     }
 
-    #[allow(dead_code)] pub fn commits<'a>(&'a mut self, source: &Source) -> impl Iterator<Item=&'a Commit> + 'a {
-        self.smart_load_commits(source).iter().map(|(_, commit)| commit)
+    #[allow(dead_code)] pub fn commits(&self, source: &Source) -> Vec<Commit> {
+        self.smart_load_commits(source).values().cloned().collect()
     }
 }
 
 impl Data {
-    pub fn project(&mut self, id: &ProjectId, source: &Source) -> Option<Project> {
+    pub fn project(&self, id: &ProjectId, source: &Source) -> Option<Project> {
         self.smart_load_project_urls(source).get(id)
             .map(|url| Project::new(id.clone(), url.clone()))
     }
-    pub fn project_issues(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_issues(source).get(id).pirate()
+    pub fn project_issues(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_issue_count, smart_load_project_issues, source, id)
     }
-    pub fn project_buggy_issues(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_buggy_issues(source).get(id).pirate()
+    pub fn project_buggy_issues(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_buggy_issue_count, smart_load_project_buggy_issues, source, id)
     }
-    pub fn project_is_fork(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_is_fork(source).get(id).pirate()
+    pub fn project_is_fork(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_is_fork, smart_load_project_is_fork, source, id)
     }
-    pub fn project_is_archived(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_is_archived(source).get(id).pirate()
+    pub fn project_is_archived(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_is_archived, smart_load_project_is_archived, source, id)
     }
-    pub fn project_is_disabled(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_is_disabled(source).get(id).pirate()
+    pub fn project_is_disabled(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_is_disabled, smart_load_project_is_disabled, source, id)
     }
-    pub fn project_star_gazer_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_star_gazer_count(source).get(id).pirate()
+    pub fn project_star_gazer_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_star_gazer_count, smart_load_project_star_gazer_count, source, id)
     }
 
-    pub fn project_watcher_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_watcher_count(source).get(id).pirate()
+    pub fn project_watcher_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_watcher_count, smart_load_project_watcher_count, source, id)
     }
-    pub fn project_size(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_size(source).get(id).pirate()
+    pub fn project_size(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_project_size, smart_load_project_size, source, id)
     }
-    pub fn project_open_issue_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_open_issue_count(source).get(id).pirate()
+    pub fn project_open_issue_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_open_issue_count, smart_load_project_open_issue_count, source, id)
     }
-    pub fn project_fork_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_fork_count(source).get(id).pirate()
+    pub fn project_fork_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_fork_count, smart_load_project_fork_count, source, id)
     }
-    pub fn project_subscriber_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_subscriber_count(source).get(id).pirate()
+    pub fn project_subscriber_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_subscriber_count, smart_load_project_subscriber_count, source, id)
     }
-    pub fn project_license(&mut self, id: &ProjectId, source: &Source) -> Option<String> {
-        self.smart_load_project_license(source).get(id).pirate()
+    pub fn project_license(&self, id: &ProjectId, source: &Source) -> Option<String> {
+        lookup!(self, project_license, smart_load_project_license, source, id)
     }
-    pub fn project_language(&mut self, id: &ProjectId, source: &Source) -> Option<Language> {
-        self.smart_load_project_language(source).get(id).pirate()
+    pub fn project_language(&self, id: &ProjectId, source: &Source) -> Option<Language> {
+        lookup!(self, project_language, smart_load_project_language, source, id)
     }
-    pub fn project_description(&mut self, id: &ProjectId, source: &Source) -> Option<String> {
-        self.smart_load_project_description(source).get(id).pirate()
+    pub fn project_description(&self, id: &ProjectId, source: &Source) -> Option<String> {
+        lookup!(self, project_description, smart_load_project_description, source, id)
     }
-    pub fn project_homepage(&mut self, id: &ProjectId, source: &Source) -> Option<String> {
-        self.smart_load_project_homepage(source).get(id).pirate()
+    pub fn project_homepage(&self, id: &ProjectId, source: &Source) -> Option<String> {
+        lookup!(self, project_homepage, smart_load_project_homepage, source, id)
     }
-    pub fn project_has_issues(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_has_issues(source).get(id).pirate()
+    pub fn project_has_issues(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_has_issues, smart_load_project_has_issues, source, id)
     }
-    pub fn project_has_downloads(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_has_downloads(source).get(id).pirate()
+    pub fn project_has_downloads(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_has_downloads, smart_load_project_has_downloads, source, id)
     }
-    pub fn project_has_wiki(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_has_wiki(source).get(id).pirate()
+    pub fn project_has_wiki(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_has_wiki, smart_load_project_has_wiki, source, id)
     }
-    pub fn project_has_pages(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_has_pages(source).get(id).pirate()
+    pub fn project_has_pages(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, project_has_pages, smart_load_project_has_pages, source, id)
     }
-    pub fn project_created(&mut self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
-        self.smart_load_project_created(source).get(id).pirate()        
+    pub fn project_created(&self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
+        lookup!(self, project_created, smart_load_project_created, source, id)        
     }
-    pub fn project_updated(&mut self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
-        self.smart_load_project_updated(source).get(id).pirate()
+    pub fn project_updated(&self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
+        lookup!(self, project_updated, smart_load_project_updated, source, id)
     }
-    pub fn project_pushed(&mut self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
-        self.smart_load_project_pushed(source).get(id).pirate()
+    pub fn project_pushed(&self, id: &ProjectId, source: &Source) -> Option<Timestamp> {
+        lookup!(self, project_pushed, smart_load_project_pushed, source, id)
     }
-    pub fn project_default_branch(&mut self, id: &ProjectId, source: &Source) -> Option<String> {
-        self.smart_load_project_default_branch(source).get(id).pirate()
+    pub fn project_default_branch(&self, id: &ProjectId, source: &Source) -> Option<String> {
+        lookup!(self, project_default_branch, smart_load_project_default_branch, source, id)
     }
-    pub fn project_commit_contribution_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<(UserId, usize)>> {
-        self.smart_load_project_commit_contributions(source).get(id).pirate()
+    pub fn project_commit_contribution_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<(UserId, usize)>> {
+        lookup!(self, project_commit_contributions, smart_load_project_commit_contributions, source, id)
     }
-    pub fn project_commit_contributions(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<(User, usize)>> {
-        self.smart_load_project_commit_contributions(source).get(id).pirate().map(|contributions| {
+    pub fn project_commit_contributions(&self, id: &ProjectId, source: &Source) -> Option<Vec<(User, usize)>> {
+        lookup!(self, project_commit_contributions, smart_load_project_commit_contributions, source, id).map(|contributions| {
             contributions.iter().flat_map(|(user_id, n)| {
                 self.user(user_id, source).map(|user| (user.clone(), *n))
             }).collect()
         })
     }
-    pub fn project_cumulative_commit_contributions(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<Percentage>> {
-        self.smart_load_project_cumulative_commit_contributions(source).get(id).pirate()
+    pub fn project_cumulative_commit_contributions(&self, id: &ProjectId, source: &Source) -> Option<Vec<Percentage>> {
+        lookup!(self, project_cumulative_commit_contributions, smart_load_project_cumulative_commit_contributions, source, id)
     }
-    pub fn project_change_contribution_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<(UserId, usize)>> {
-        self.smart_load_project_change_contributions(source).get(id).pirate()
+    pub fn project_change_contribution_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<(UserId, usize)>> {
+        lookup!(self, project_change_contributions, smart_load_project_change_contributions, source, id)
     }
-    pub fn project_change_contributions(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<(User, usize)>> {
-        self.smart_load_project_change_contributions(source).get(id).pirate().map(|contributions| {
+    pub fn project_change_contributions(&self, id: &ProjectId, source: &Source) -> Option<Vec<(User, usize)>> {
+        lookup!(self, project_change_contributions, smart_load_project_change_contributions, source, id).map(|contributions| {
             contributions.iter().flat_map(|(user_id, n)| {
                 self.user(user_id, source).map(|user| (user.clone(), *n))
             }).collect()
         })
     }
-    pub fn project_cumulative_change_contributions(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<Percentage>> {
-        self.smart_load_project_cumulative_change_contributions(source).get(id).pirate()
+    pub fn project_cumulative_change_contributions(&self, id: &ProjectId, source: &Source) -> Option<Vec<Percentage>> {
+        lookup!(self, project_cumulative_change_contributions, smart_load_project_cumulative_change_contributions, source, id)
     }
     // TODO make a mechanism for caching parameterized attributes
     fn calculate_contributing_authors_at_cutoff(contributions: Option<Vec<(UserId, usize)>>, percentage: Percentage) -> Option<Vec<UserId>>{
@@ -384,36 +797,36 @@ impl Data {
             None
         }  
     }
-    pub fn project_author_ids_contributing_commits(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<UserId>> {        
+    pub fn project_author_ids_contributing_commits(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<UserId>> {        
         Self::calculate_contributing_authors_at_cutoff(self.project_commit_contribution_ids(id, source), percentage)
     }
-    pub fn project_author_ids_contributing_changes(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<UserId>> {
+    pub fn project_author_ids_contributing_changes(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<UserId>> {
         Self::calculate_contributing_authors_at_cutoff(self.project_change_contribution_ids(id, source), percentage)
     }
-    pub fn project_authors_contributing_commits(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<User>> {
+    pub fn project_authors_contributing_commits(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<User>> {
         self.project_author_ids_contributing_commits(id, percentage, source).map(|ids| {
             ids.iter().flat_map(|id| self.user(id, source)).collect()
         })
     }
-    pub fn project_authors_contributing_changes(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<User>> {
+    pub fn project_authors_contributing_changes(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<Vec<User>> {
         self.project_author_ids_contributing_changes(id, percentage, source).map(|ids| {
             ids.iter().flat_map(|id| self.user(id, source)).collect()
         })
     }
-    pub fn project_authors_contributing_commits_count(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<usize> {
+    pub fn project_authors_contributing_commits_count(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<usize> {
         self.project_author_ids_contributing_commits(id, percentage, source).map(|ids| ids.len())
     }
-    pub fn project_authors_contributing_changes_count(&mut self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<usize> {
+    pub fn project_authors_contributing_changes_count(&self, id: &ProjectId, percentage: Percentage, source: &Source) -> Option<usize> {
         self.project_author_ids_contributing_changes(id, percentage, source).map(|ids| ids.len())
     }
-    pub fn project_url(&mut self, id: &ProjectId, source: &Source) -> Option<String> {
-        self.smart_load_project_urls(source).get(id).pirate()
+    pub fn project_url(&self, id: &ProjectId, source: &Source) -> Option<String> {
+        lookup!(self, project_urls, smart_load_project_urls, source, id)
     }
-    pub fn project_heads(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<Head>> {
-        self.smart_load_project_heads(source).get(id).pirate()
+    pub fn project_heads(&self, id: &ProjectId, source: &Source) -> Option<Vec<Head>> {
+        lookup!(self, project_heads, smart_load_project_heads, source, id)
     }
-    // pub fn project_heads(&mut self, source: &DataSource, id: &ProjectId) -> Option<Vec<(String, Commit)>> {
-    //     self.smart_load_project_heads(source).get(id).pirate().map(|v| {
+    // pub fn project_heads(&self, source: &DataSource, id: &ProjectId) -> Option<Vec<(String, Commit)>> {
+    //     lookup!(self, project_heads, smart_load_project_heads, source, id).map(|v| {
     //         v.into_iter().flat_map(|(name, commit_id)| {
     //             self.commit(source, &commit_id).map(|commit| {
     //                 Head::new(name, commit.clone())
@@ -421,302 +834,348 @@ impl Data {
     //         }).collect()
     //     })
     // }
-    pub fn project_commit_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<CommitId>> {
-        self.smart_load_project_commits(source).get(id).pirate()
+    pub fn project_commit_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<CommitId>> {
+        lookup!(self, project_commits, smart_load_project_commits, source, id)
     }
-    pub fn project_commits(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<Commit>> {
-        self.smart_load_project_commits(source).get(id).pirate().map(|ids| {
+    pub fn project_commits(&self, id: &ProjectId, source: &Source) -> Option<Vec<Commit>> {
+        lookup!(self, project_commits, smart_load_project_commits, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.commit(id, source)).collect()
             // FIXME issue warnings in situations like these (when self.commit(id) fails etc.)
         })
     }
-    pub fn project_commit_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_commit_count(source).get(id).pirate()
+    pub fn project_commit_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_commit_count, smart_load_project_commit_count, source, id)
     }
-    pub fn project_path_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<PathId>> {
-        self.smart_load_project_paths(source).get(id).pirate()
+    pub fn project_path_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<PathId>> {
+        lookup!(self, project_paths, smart_load_project_paths, source, id)
     }
-    pub fn project_paths(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<Path>> {
-        self.smart_load_project_paths(source).get(id).pirate().map(|ids| {
+    pub fn project_paths(&self, id: &ProjectId, source: &Source) -> Option<Vec<Path>> {
+        lookup!(self, project_paths, smart_load_project_paths, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.path(id, source)).collect()
         })
     }
-    pub fn project_path_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_path_count(source).get(id).pirate()
+    pub fn project_path_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_path_count, smart_load_project_path_count, source, id)
     }
-    pub fn project_snapshot_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<SnapshotId>> {
-        self.smart_load_project_snapshots(source).get(id).pirate()
+    pub fn project_snapshot_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<SnapshotId>> {
+        lookup!(self, project_snapshots, smart_load_project_snapshots, source, id)
     }
-    pub fn project_snapshot_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_snapshot_count(source).get(id).pirate()
+    pub fn project_snapshot_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_snapshot_count, smart_load_project_snapshot_count, source, id)
     }
-    pub fn project_author_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
-        self.smart_load_project_authors(source).get(id).pirate()
+    pub fn project_author_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
+        lookup!(self, project_authors, smart_load_project_authors, source, id)
     }
-    pub fn project_authors(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
-        self.smart_load_project_authors(source).get(id).pirate().map(|ids| {
+    pub fn project_authors(&self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
+        lookup!(self, project_authors, smart_load_project_authors, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.user(id, source)).collect()
         })
     }
-    pub fn project_author_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_author_count(source).get(id).pirate()
+    pub fn project_author_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_author_count, smart_load_project_author_count, source, id)
     }
-    pub fn project_committer_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
-        self.smart_load_project_committers(source).get(id).pirate()
+    pub fn project_committer_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
+        lookup!(self, project_committers, smart_load_project_committers, source, id)
     }
-    pub fn project_committers(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
-        self.smart_load_project_committers(source).get(id).pirate().map(|ids| {
+    pub fn project_committers(&self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
+        lookup!(self, project_committers, smart_load_project_committers, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.user(id, source)).collect()
         })
     }
-    pub fn project_committer_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_committer_count(source).get(id).pirate()
+    pub fn project_committer_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_committer_count, smart_load_project_committer_count, source, id)
     }
-    pub fn project_user_ids(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
-        self.smart_load_project_users(source).get(id).pirate()
+    pub fn project_user_ids(&self, id: &ProjectId, source: &Source) -> Option<Vec<UserId>> {
+        lookup!(self, project_users, smart_load_project_users, source, id)
     }
-    pub fn project_users(&mut self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
-        self.smart_load_project_users(source).get(id).pirate().map(|ids| {
+    pub fn project_users(&self, id: &ProjectId, source: &Source) -> Option<Vec<User>> {
+        lookup!(self, project_users, smart_load_project_users, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.user(id, source)).collect()
         })
     }
-    pub fn project_user_count(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_user_count(source).get(id).pirate()
+    pub fn project_user_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_user_count, smart_load_project_user_count, source, id)
     }
-    pub fn project_lifetime(&mut self, id: &ProjectId, source: &Source) -> Option<Duration> {
+    pub fn project_lifetime(&self, id: &ProjectId, source: &Source) -> Option<Duration> {
         self.smart_load_project_lifetimes(source).get(id)
             .pirate()
             .map(|seconds| Duration::from(seconds))
     }
-    pub fn project_substore(&mut self, id: &ProjectId, source: &Source) -> Option<Store> {
+    pub fn project_substore(&self, id: &ProjectId, source: &Source) -> Option<Store> {
         self.smart_load_project_substore(source).get(id)
             .pirate()
     }
-    pub fn project_unique_files(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_unique_files(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_unique_files(source).get(id)
             .pirate()
     }
-    pub fn project_original_files(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_original_files(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_original_files(source).get(id)
             .pirate()
     }
-    pub fn project_impact(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_impact(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_impact(source).get(id)
             .pirate()
     }
-    pub fn project_files(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_files(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_files(source).get(id)
             .pirate()
     }
-    pub fn project_language_composition(& mut self, id: &ProjectId, source: &Source) -> Option<Vec<(Language,usize)>> {
+    pub fn project_language_composition(&self, id: &ProjectId, source: &Source) -> Option<Vec<(Language,usize)>> {
         self.smart_load_project_languages(source).get(id)
             .pirate()
     }
-    pub fn project_languages(& mut self, id: &ProjectId, source: &Source) -> Option<Vec<Language>> {
+    pub fn project_languages(&self, id: &ProjectId, source: &Source) -> Option<Vec<Language>> {
         self.smart_load_project_languages(source).get(id).map(|vector| {
             vector.iter().map(|e| e.0.clone()).collect::<Vec<Language>>()
         })
     }
-    pub fn project_languages_count(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_languages_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_languages_count(source).get(id)
             .pirate()
     }
-    pub fn project_major_language(& mut self, id: &ProjectId, source: &Source) -> Option<Language> {
+    pub fn project_major_language(&self, id: &ProjectId, source: &Source) -> Option<Language> {
         self.smart_load_project_major_language(source).get(id)
             .pirate()
     }
-    pub fn project_major_language_ratio(& mut self, id: &ProjectId, source: &Source) -> Option<f64> {
+    pub fn project_major_language_ratio(&self, id: &ProjectId, source: &Source) -> Option<f64> {
         self.smart_load_project_major_language_ratio(source).get(id)
             .pirate()
     }
-    pub fn project_major_language_changes(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_major_language_changes(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_major_language_changes(source).get(id)
             .pirate()
     }
-    pub fn project_all_forks(& mut self, id: &ProjectId, source: &Source) -> Option<Vec<ProjectId>> {
+    pub fn project_all_forks(&self, id: &ProjectId, source: &Source) -> Option<Vec<ProjectId>> {
         self.smart_load_project_all_forks(source).get(id)
             .pirate()
     }
-    pub fn project_all_forks_count(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_all_forks_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_all_forks_count(source).get(id)
             .pirate()
     }
-    pub fn project_head_trees(& mut self, id: &ProjectId, source: &Source) -> Option<Vec<(String, Vec<(PathId, SnapshotId)>)>> {
+    pub fn project_head_trees(&self, id: &ProjectId, source: &Source) -> Option<Vec<(String, Vec<(PathId, SnapshotId)>)>> {
         self.smart_load_project_head_trees(source).get(id)
             .pirate()
     }
-    pub fn project_head_trees_count(& mut self, id: &ProjectId, source: &Source) -> Option<usize> {
+    pub fn project_head_trees_count(&self, id: &ProjectId, source: &Source) -> Option<usize> {
         self.smart_load_project_head_trees_count(source).get(id)
             .pirate()
     }
-    pub fn user(&mut self, id: &UserId, source: &Source) -> Option<User> {
-        self.smart_load_users(source).get(id).pirate()
+    pub fn user(&self, id: &UserId, source: &Source) -> Option<User> {
+        lookup!(self, users, smart_load_users, source, id)
+    }
+    pub fn path(&self, id: &PathId, source: &Source) -> Option<Path> {
+        lookup!(self, paths, smart_load_paths, source, id)
     }
-    pub fn path(&mut self, id: &PathId, source: &Source) -> Option<Path> {
-        self.smart_load_paths(source).get(id).pirate()
+    pub fn path_language(&self, id: &PathId, source: &Source) -> Option<Language> {
+        lookup!(self, path_languages, smart_load_path_languages, source, id)
     }
-    pub fn commit(&mut self, id: &CommitId, source: &Source) -> Option<Commit> {
-        self.smart_load_commits(source).get(id).pirate()
+    pub fn commit(&self, id: &CommitId, source: &Source) -> Option<Commit> {
+        lookup!(self, commits, smart_load_commits, source, id)
     }
-    pub fn commit_hash(&mut self, id: &CommitId, source: &Source) -> Option<String> {
-        self.smart_load_commit_hashes(source).get(id).pirate()
+    pub fn commit_hash(&self, id: &CommitId, source: &Source) -> Option<String> {
+        lookup!(self, commit_hashes, smart_load_commit_hashes, source, id)
     }
-    pub fn commit_message(&mut self, id: &CommitId, source: &Source) -> Option<String> {
-        self.smart_load_commit_messages(source).get(id).pirate()
+    pub fn commit_message(&self, id: &CommitId, source: &Source) -> Option<String> {
+        lookup!(self, commit_messages, smart_load_commit_messages, source, id)
     }
-    pub fn commit_author_timestamp(&mut self, id: &CommitId, source: &Source) -> Option<Timestamp> {
-        self.smart_load_commit_author_timestamps(source).get(id).pirate()
+    pub fn commit_author_timestamp(&self, id: &CommitId, source: &Source) -> Option<Timestamp> {
+        lookup!(self, commit_author_timestamps, smart_load_commit_author_timestamps, source, id)
     }
-    pub fn commit_committer_timestamp(&mut self, id: &CommitId, source: &Source) -> Option<Timestamp> {
-        self.smart_load_commit_committer_timestamps(source).get(id).pirate()
+    pub fn commit_committer_timestamp(&self, id: &CommitId, source: &Source) -> Option<Timestamp> {
+        lookup!(self, commit_committer_timestamps, smart_load_commit_committer_timestamps, source, id)
     }
-    pub fn commit_changes(&mut self, id: &CommitId, source: &Source) -> Option<Vec<Change>> {
+    pub fn commit_changes(&self, id: &CommitId, source: &Source) -> Option<Vec<Change>> {
         self.smart_load_commit_changes(source).get(id).map(|vector| {
             vector.iter().map(|(path_id, snapshot_id)| {
-                Change::new(path_id.clone(), snapshot_id.clone())
+                Change::new(path_id.clone(), snapshot_id.clone()).in_commit(*id)
             }).collect()
         })
     }
-    pub fn commit_changes_with_contents(&mut self, id: &CommitId, source: &Source) -> Option<Vec<Change>> {
+    pub fn commit_changes_with_contents(&self, id: &CommitId, source: &Source) -> Option<Vec<Change>> {
         self.smart_load_commit_changes_with_contents(source).get(id).map(|vector| {
             vector.iter().map(|(path_id, snapshot_id)| {
-                Change::new(path_id.clone(), snapshot_id.clone())
+                Change::new(path_id.clone(), snapshot_id.clone()).in_commit(*id)
             }).collect()
         })
     }
-    pub fn commit_changed_paths(&mut self, id: &CommitId, source: &Source) -> Option<Vec<Path>> {
-        self.smart_load_commit_changes(source).get(id).pirate().map(|ids| {
+    pub fn commit_previous_snapshots(&self, id: &CommitId, source: &Source) -> Option<Vec<ChangeTuple>> {
+        lookup!(self, commit_previous_snapshots, smart_load_commit_previous_snapshots, source, id)
+    }
+    pub fn commit_line_changes(&self, id: &CommitId, source: &Source) -> Option<Vec<(PathId, usize, usize)>> {
+        lookup!(self, commit_line_changes, smart_load_commit_line_changes, source, id)
+    }
+    pub fn commit_changed_paths(&self, id: &CommitId, source: &Source) -> Option<Vec<Path>> {
+        lookup!(self, commit_changes, smart_load_commit_changes, source, id).map(|ids| {
             ids.iter().flat_map(|change| self.path(&change.0/*path_id()*/, source)).collect()
         })
     }
-    pub fn commit_change_count(&mut self, id: &CommitId, source: &Source) -> Option<usize> {
-        self.smart_load_commit_change_count(source).get(id).pirate()
+    pub fn commit_change_count(&self, id: &CommitId, source: &Source) -> Option<usize> {
+        lookup!(self, commit_change_count, smart_load_commit_change_count, source, id)
     }
-    pub fn commit_change_with_contents_count(&mut self, id: &CommitId, source: &Source) -> Option<usize> {
-        self.smart_load_commit_change_with_contents_count(source).get(id).pirate()
+    pub fn commit_change_with_contents_count(&self, id: &CommitId, source: &Source) -> Option<usize> {
+        lookup!(self, commit_change_with_contents_count, smart_load_commit_change_with_contents_count, source, id)
     }
-    pub fn commit_changed_path_count(&mut self, id: &CommitId, source: &Source) -> Option<usize> {
-        self.smart_load_commit_change_count(source).get(id).pirate()
+    pub fn commit_changed_path_count(&self, id: &CommitId, source: &Source) -> Option<usize> {
+        lookup!(self, commit_change_count, smart_load_commit_change_count, source, id)
     }
-    pub fn commit_projects(&mut self, id: &CommitId, source: &Source) -> Option<Vec<Project>> {
-        self.smart_load_commit_projects(source).get(id).pirate().map(|ids| {
+    pub fn commit_projects(&self, id: &CommitId, source: &Source) -> Option<Vec<Project>> {
+        lookup!(self, commit_projects, smart_load_commit_projects, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.project(id, source)).collect()
         })   
     }
-    pub fn commit_projects_count(&mut self, id: &CommitId, source: &Source) -> Option<usize> {
-        self.smart_load_commit_projects_count(source).get(id).pirate()
+    pub fn commit_projects_count(&self, id: &CommitId, source: &Source) -> Option<usize> {
+        lookup!(self, commit_projects_count, smart_load_commit_projects_count, source, id)
     }
-    pub fn commit_languages(&mut self, id: &CommitId, source: &Source) -> Option<Vec<Language>> {
-        self.smart_load_commit_languages(source).get(id).pirate()   
+    pub fn commit_languages(&self, id: &CommitId, source: &Source) -> Option<Vec<Language>> {
+        lookup!(self, commit_languages, smart_load_commit_languages, source, id)   
     }
-    pub fn commit_languages_count(&mut self, id: &CommitId, source: &Source) -> Option<usize> {
-        self.smart_load_commit_languages_count(source).get(id).pirate()
+    pub fn commit_languages_count(&self, id: &CommitId, source: &Source) -> Option<usize> {
+        lookup!(self, commit_languages_count, smart_load_commit_languages_count, source, id)
     }
-    pub fn user_committed_commit_ids(&mut self, id: &UserId, source: &Source) -> Option<Vec<CommitId>> {
-        self.smart_load_user_committed_commits(source).get(id).pirate()
+    pub fn user_committed_commit_ids(&self, id: &UserId, source: &Source) -> Option<Vec<CommitId>> {
+        lookup!(self, user_committed_commits, smart_load_user_committed_commits, source, id)
     }
-    pub fn user_authored_commits(&mut self, id: &UserId, source: &Source) -> Option<Vec<Commit>> {
-        self.smart_load_user_authored_commits(source).get(id).pirate().map(|ids| {
+    pub fn user_authored_commits(&self, id: &UserId, source: &Source) -> Option<Vec<Commit>> {
+        lookup!(self, user_authored_commits, smart_load_user_authored_commits, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.commit(id, source)).collect()
         })
     }
-    pub fn user_authored_commit_ids(&mut self, id: &UserId, source: &Source) -> Option<Vec<CommitId>> {
-        self.smart_load_user_authored_commits(source).get(id).pirate()
+    pub fn user_authored_commit_ids(&self, id: &UserId, source: &Source) -> Option<Vec<CommitId>> {
+        lookup!(self, user_authored_commits, smart_load_user_authored_commits, source, id)
     }
-    pub fn user_committed_experience(&mut self, id: &UserId, source: &Source) -> Option<Duration> {
+    pub fn user_committed_experience(&self, id: &UserId, source: &Source) -> Option<Duration> {
         self.smart_load_user_committer_experience(source)
             .get(id)
             .map(|seconds| Duration::from(*seconds))
     }
-    pub fn user_author_experience(&mut self, id: &UserId, source: &Source) -> Option<Duration> {
+    pub fn user_author_experience(&self, id: &UserId, source: &Source) -> Option<Duration> {
         self.smart_load_user_author_experience(source)
             .get(id)
             .map(|seconds| Duration::from(*seconds))
     }
-    pub fn user_experience(&mut self, id: &UserId, source: &Source) -> Option<Duration> {
+    pub fn user_experience(&self, id: &UserId, source: &Source) -> Option<Duration> {
         self.smart_load_user_experience(source)
             .get(id)
             .map(|seconds| Duration::from(*seconds))
     }
-    pub fn user_committed_commit_count(&mut self, id: &UserId, source: &Source) -> Option<usize> {
-        self.smart_load_user_committed_commit_count(source).get(id).pirate()
+    pub fn user_committed_commit_count(&self, id: &UserId, source: &Source) -> Option<usize> {
+        lookup!(self, user_committed_commit_count, smart_load_user_committed_commit_count, source, id)
     }
-    pub fn user_authored_commit_count(&mut self, id: &UserId, source: &Source) -> Option<usize> {
-        self.smart_load_user_authored_commit_count(source).get(id).pirate()
+    pub fn user_authored_commit_count(&self, id: &UserId, source: &Source) -> Option<usize> {
+        lookup!(self, user_authored_commit_count, smart_load_user_authored_commit_count, source, id)
     }
-    pub fn user_committed_commits(&mut self, id: &UserId, source: &Source) -> Option<Vec<Commit>> {
-        self.smart_load_user_committed_commits(source).get(id).pirate().map(|ids| {
+    pub fn user_committed_commits(&self, id: &UserId, source: &Source) -> Option<Vec<Commit>> {
+        lookup!(self, user_committed_commits, smart_load_user_committed_commits, source, id).map(|ids| {
             ids.iter().flat_map(|id| self.commit(id, source)).collect()
         })
     }
-    pub fn developer_experience(&mut self, id: &UserId, source: &Source) -> Option<i32> {
-        self.smart_load_developer_experience(source).get(id).pirate()
+    pub fn developer_experience(&self, id: &UserId, source: &Source) -> Option<i32> {
+        lookup!(self, developer_experience, smart_load_developer_experience, source, id)
     }
-    pub fn project_max_commit_delta(&mut self, id: &ProjectId, source: &Source) -> Option<i64> {
-        self.smart_load_project_max_commit_delta(source).get(id).pirate()
+    pub fn project_max_commit_delta(&self, id: &ProjectId, source: &Source) -> Option<i64> {
+        lookup!(self, project_max_commit_delta, smart_load_project_max_commit_delta, source, id)
     }
-    pub fn project_max_experience(&mut self, id: &ProjectId, source: &Source) -> Option<i32> {
-        self.smart_load_project_max_experience(source).get(id).pirate()
+    pub fn project_max_experience(&self, id: &ProjectId, source: &Source) -> Option<i32> {
+        lookup!(self, project_max_experience, smart_load_project_max_experience, source, id)
     }
-    pub fn project_experience(&mut self, id: &ProjectId, source: &Source) -> Option<f64> {
-        self.smart_load_project_experience(source).get(id).pirate()
+    pub fn project_experience(&self, id: &ProjectId, source: &Source) -> Option<f64> {
+        lookup!(self, project_experience, smart_load_project_experience, source, id)
     }
-    pub fn project_avg_commit_delta(&mut self, id: &ProjectId, source: &Source) -> Option<i64> {
-        self.smart_load_project_avg_commit_delta(source).get(id).pirate()
+    pub fn project_avg_commit_delta(&self, id: &ProjectId, source: &Source) -> Option<i64> {
+        lookup!(self, avg_commit_delta, smart_load_project_avg_commit_delta, source, id)
     }
-    pub fn project_time_since_last_commit(&mut self, id: &ProjectId, source: &Source) -> Option<i64> {
-        self.smart_load_project_time_since_last_commit(source).get(id).pirate()
+    pub fn project_time_since_last_commit(&self, id: &ProjectId, source: &Source) -> Option<i64> {
+        lookup!(self, project_time_since_last_commit, smart_load_project_time_since_last_commit, source, id)
     }
-    pub fn project_time_since_first_commit(&mut self, id: &ProjectId, source: &Source) -> Option<i64> {
-        self.smart_load_project_time_since_first_commit(source).get(id).pirate()
+    pub fn project_time_since_first_commit(&self, id: &ProjectId, source: &Source) -> Option<i64> {
+        lookup!(self, project_time_since_first_commit, smart_load_project_time_since_first_commit, source, id)
     }
-    pub fn project_is_abandoned(&mut self, id: &ProjectId, source: &Source) -> Option<bool> {
-        self.smart_load_project_is_abandoned(source).get(id).pirate()
+    pub fn project_is_abandoned(&self, id: &ProjectId, source: &Source) -> Option<bool> {
+        lookup!(self, is_abandoned, smart_load_project_is_abandoned, source, id)
     }
-    pub fn snapshot_locs(&mut self, id: &SnapshotId, source: &Source) -> Option<usize> {
-        self.smart_load_snapshot_locs(source).get(id).pirate()
+    pub fn snapshot_locs(&self, id: &SnapshotId, source: &Source) -> Option<usize> {
+        lookup!(self, snapshot_locs, smart_load_snapshot_locs, source, id)
     }
-    pub fn snapshot_has_contents(&mut self, id: &SnapshotId, source: &Source) -> bool {
+    pub fn snapshot_has_contents(&self, id: &SnapshotId, source: &Source) -> bool {
         self.smart_load_snapshot_has_contents(source).get(id).map(|x| *x).unwrap_or(false)
     }
-    pub fn project_locs(&mut self, id: &ProjectId, source: &Source) -> Option<usize> {
-        self.smart_load_project_locs(source).get(id).pirate()
+    pub fn project_locs(&self, id: &ProjectId, source: &Source) -> Option<usize> {
+        lookup!(self, project_locs, smart_load_project_locs, source, id)
+    }
+    pub fn snapshot_line_counts(&self, id: &SnapshotId, source: &Source) -> Option<LineCounts> {
+        lookup!(self, snapshot_line_counts, smart_load_snapshot_line_counts, source, id)
+    }
+    pub fn project_line_counts(&self, id: &ProjectId, source: &Source) -> Option<LineCounts> {
+        lookup!(self, project_line_counts, smart_load_project_line_counts, source, id)
     }
-    pub fn project_logs(&mut self, id: &ProjectId, source: &Source) -> Option<i64> {
-        self.smart_load_project_logs(source).get(id).pirate()
+    pub fn project_logs(&self, id: &ProjectId, source: &Source) -> Option<i64> {
+        lookup!(self, project_logs, smart_load_project_logs, source, id)
     }
-    pub fn project_duplicated_code(&mut self, id: &ProjectId, source: &Source) -> Option<f64> {
-        self.smart_load_project_duplicated_code(source).get(id).pirate()
+    pub fn project_duplicated_code(&self, id: &ProjectId, source: &Source) -> Option<f64> {
+        lookup!(self, duplicated_code, smart_load_project_duplicated_code, source, id)
     }
-    pub fn snapshot_unique_projects(&mut self, id : &SnapshotId, source: &Source) -> usize {
+    pub fn snapshot_unique_projects(&self, id : &SnapshotId, source: &Source) -> usize {
         // TODO I am sure rust frowns upon this, but how do I return ! attributes that are cached in the datastore? 
         self.smart_load_snapshot_projects(source).get(id).unwrap().0
     }
-    pub fn snapshot_original_project(&mut self, id : &SnapshotId, source: &Source) -> ProjectId {
+    pub fn snapshot_original_project(&self, id : &SnapshotId, source: &Source) -> ProjectId {
         // TODO I am sure rust frowns upon this, but how do I return ! attributes that are cached in the datastore? 
         self.smart_load_snapshot_projects(source).get(id).unwrap().1
     }
-    pub fn project_is_valid(&mut self, id : &ProjectId, source: &Source) ->  Option<bool>{
+    pub fn project_is_valid(&self, id : &ProjectId, source: &Source) ->  Option<bool>{
         // TODO I am sure rust frowns upon this, but how do I return ! attributes that are cached in the datastore? 
-        self.smart_load_project_is_valid(source).get(id).pirate()
+        lookup!(self, project_is_valid, smart_load_project_is_valid, source, id)
     }
 }
 
+macro_rules! track_loaded {
+    ($self:ident, $vector:ident) => {{
+        let fingerprint = $self.$vector.fingerprint();
+        $self.verified_caches.lock().unwrap().insert(stringify!($vector).to_owned(), Some(fingerprint));
+        if $self.budget.lock().unwrap().is_limited() {
+            let weight = $self.$vector.weigh_collection();
+            $self.budget.lock().unwrap().loaded(stringify!($vector), weight);
+        }
+    }}
+}
+
+/*
+ * These hold the lock of the collection they load while it is being extracted, but not the locks
+ * of its prerequisites: those are loaded (each under its own lock) and held on to beforehand.
+ * Since collections are only ever derived from collections earlier in the table, two threads
+ * never wait on each other in a circle.
+ */
 macro_rules! load_from_source {
     ($self:ident, $vector:ident, $source:expr)  => {{
-        if !$self.$vector.is_loaded() {
-            $self.$vector.load_from_source($source);
-        }
-        $self.$vector.grab_collection()
+        let collection = match $self.$vector.loaded() {
+            Some(collection) => collection,
+            None => {
+                debug_assert_eq!(Data::collection(stringify!($vector)).map(|c| c.prerequisites), Some(vec![]));
+                let collection = $self.$vector.load_from_source($source);
+                track_loaded!($self, $vector);
+                collection
+            }
+        };
+        $self.budget.lock().unwrap().touch(stringify!($vector));
+        collection
     }}
 }
 
 macro_rules! load_from_metadata {
     ($self:ident, $vector:ident, $source:expr)  => {{
-        if !$self.$vector.is_loaded() {
-            $self.$vector.load_from_metadata($source, &$self.project_metadata);
-        }
-        $self.$vector.grab_collection()
+        let collection = match $self.$vector.loaded() {
+            Some(collection) => collection,
+            None => {
+                debug_assert_eq!(Data::collection(stringify!($vector)).map(|c| c.prerequisites), Some(vec![]));
+                let collection = $self.$vector.load_from_metadata($source, &$self.project_metadata);
+                track_loaded!($self, $vector);
+                collection
+            }
+        };
+        $self.budget.lock().unwrap().touch(stringify!($vector));
+        collection
     }}
 }
 
@@ -726,311 +1185,480 @@ macro_rules! load_with_prerequisites {
             $( m["smart_load" $prereq] = smart_load_$prereq; )*
                m["load"] = load_from_$n;
         }
-        if !$self.$vector.is_loaded() {
-            m! { $(  $self."smart_load" $prereq($source); )*              }
-            m! { $self.$vector."load"($source, $($self.$prereq.grab_collection()), *); }
+        let collection = match $self.$vector.loaded() {
+            Some(collection) => collection,
+            None => {
+                debug_assert_eq!(Data::collection(stringify!($vector)).map(|c| c.prerequisites), Some(vec![$(stringify!($prereq)),*]));
+                m! { $( let $prereq = $self."smart_load" $prereq($source); )* }
+                $self.$vector.set_prerequisites(vec![$( (stringify!($prereq).to_owned(), $self.$prereq.fingerprint()) ),*]);
+                let collection = m! { $self.$vector."load"($source, $(&*$prereq),*) };
+                track_loaded!($self, $vector);
+                collection
+            }
+        };
+        $self.budget.lock().unwrap().touch(stringify!($vector));
+        collection
+    }}
+}
+
+/*
+ * Brings a collection up to date by updating the map cached for an earlier
+ * savepoint instead of extracting it anew.
+ *
+ * This does nothing and returns None if the collection is never cached, if it
+ * is already up to date, or if its cache for the earlier savepoint is missing
+ * or stale. Otherwise the earlier map is bound to `$map` and the prerequisites
+ * (which must already be loaded) to their own names, the update is evaluated,
+ * and the result is stored with the header of the current savepoint. The
+ * value of the update is returned.
+ */
+macro_rules! update_from_earlier {
+    ($self:ident, $earlier:ident, $collection:ident, [$($prereq:ident),*], |$map:ident| $update:block) => {{
+        let name = stringify!($collection);
+        if $self.$collection.skip_caching()
+            || $self.verified_fingerprint(name).is_some()
+            || $earlier.verified_fingerprint(name).is_none() {
+            None
+        } else if let Some(mut $map) = $earlier.$collection.take_cached() {
+            debug_assert_eq!(Data::collection(name).map(|c| c.prerequisites), Some(vec![$(stringify!($prereq)),*]));
+            $self.$collection.set_prerequisites(vec![$( (stringify!($prereq).to_owned(), $self.$prereq.fingerprint()) ),*]);
+            let outcome = {
+                $( let $prereq = $self.$prereq.shared(); let $prereq = &*$prereq; )*
+                $update
+            };
+            $self.$collection.load_updated($map);
+            track_loaded!($self, $collection);
+            Some(outcome)
+        } else {
+            None
         }
-        $self.$vector.grab_collection()
     }}
 }
 
+impl Data { // Updating caches to a later savepoint
+    /* Updates the caches of an earlier savepoint (held by `earlier`) to the savepoint of this
+       instance, processing only what was added in between.
+
+       Commits, their changes, and snapshots are only ever added, so their maps are extended with
+       the commits added since the earlier savepoint and their snapshots, which the source looks
+       up. The maps derived from them that are keyed by user or project are recomputed only for
+       the users who authored the new commits and the projects whose heads moved.
+
+       Only the collections listed here are updated. The caches of all others at the earlier
+       savepoint are never carried over: unless they are already cached at this savepoint, they
+       are extracted from scratch when they are next loaded (or built). Since that can take much
+       longer than the update, the ones that are not up to date afterwards are listed in a
+       warning.
+
+       Returns the names of the collections that were updated.
+     */
+    pub fn update_from(&mut self, earlier: &mut Data, source: &Source) -> Result<Vec<&'static str>> {
+        let savepoint = self.commits.header().savepoint;
+        let earlier_savepoint = earlier.commits.header().savepoint;
+        if earlier_savepoint >= savepoint {
+            bail!("cannot update caches from savepoint {} to savepoint {}, which is not later", earlier_savepoint, savepoint)
+        }
+
+        let mut updated: Vec<&'static str> = vec![];
+        macro_rules! record { ($name:ident, $outcome:expr) => { if $outcome.is_some() { updated.push(stringify!($name)) } } }
+
+        // Everything else is driven by which commits are new.
+        let new_commits = source.commits_added_since(earlier_savepoint);
+        record!(commits, update_from_earlier!(self, earlier, commits, [], |commits| {
+            commits.extend(CommitExtractor::extract_new(source, &commits, &new_commits));
+        }));
+        record!(commit_changes, update_from_earlier!(self, earlier, commit_changes, [], |changes| {
+            changes.extend(CommitChangesExtractor::extract_new(source, &changes, &new_commits));
+        }));
+        record!(snapshot_has_contents, update_from_earlier!(self, earlier, snapshot_has_contents, [], |snapshots| {
+            snapshots.extend(SnapshotHasContentsExtractor::extract_new(source, &snapshots, &new_commits));
+        }));
+
+        let changed_users = UserAuthoredCommitsExtractor::changed_users(&self.smart_load_commits(source), &new_commits);
+        record!(user_authored_commits, update_from_earlier!(self, earlier, user_authored_commits, [commits], |user_commits| {
+            UserAuthoredCommitsExtractor::update(&mut user_commits, commits, &new_commits);
+        }));
+        record!(user_committed_commits, update_from_earlier!(self, earlier, user_committed_commits, [commits], |user_commits| {
+            UserAuthoredCommitsExtractor::update(&mut user_commits, commits, &new_commits);
+        }));
+
+        self.smart_load_user_authored_commits(source);
+        record!(user_authored_commit_count, update_from_earlier!(self, earlier, user_authored_commit_count, [user_authored_commits], |counts| {
+            CountPerKeyExtractor::update(&mut counts, user_authored_commits, &changed_users);
+        }));
+        self.smart_load_user_committed_commits(source);
+        record!(user_committed_commit_count, update_from_earlier!(self, earlier, user_committed_commit_count, [user_committed_commits], |counts| {
+            CountPerKeyExtractor::update(&mut counts, user_committed_commits, &changed_users);
+        }));
+
+        // Heads are extracted from the source either way, but the heads of the earlier savepoint
+        // tell which projects have new commits. Without them, all projects are recomputed.
+        let project_heads = self.smart_load_project_heads(source);
+        let changed_projects = match earlier.verified_fingerprint("project_heads").and_then(|_| earlier.project_heads.take_cached()) {
+            Some(earlier_heads) => ProjectCommitsExtractor::changed_projects(&earlier_heads, &project_heads),
+            None => ProjectCommitsExtractor::changed_projects(&BTreeMap::new(), &project_heads),
+        };
+        record!(project_commits, update_from_earlier!(self, earlier, project_commits, [project_heads, commits], |project_commits| {
+            // Without the earlier heads, projects that disappeared are not among the changed ones.
+            project_commits.retain(|project_id, _| project_heads.contains_key(project_id));
+            ProjectCommitsExtractor::update(&mut project_commits, project_heads, commits, &changed_projects);
+        }));
+
+        self.smart_load_project_commits(source);
+        record!(project_commit_count, update_from_earlier!(self, earlier, project_commit_count, [project_commits], |counts| {
+            counts.retain(|project_id, _| project_commits.contains_key(project_id));
+            CountPerKeyExtractor::update(&mut counts, project_commits, &changed_projects);
+        }));
+
+        // What was verified before the update may have been verified against the maps it replaced.
+        let verified = std::mem::take(self.verified_caches.get_mut().unwrap());
+        self.verified_caches.get_mut().unwrap()
+            .extend(verified.into_iter().filter(|(name, _)| updated.iter().any(|updated| *updated == name.as_str())));
+
+        let not_updated: Vec<&'static str> = Data::collections().into_iter()
+            .map(|collection| collection.name)
+            .filter(|name| !updated.contains(name) && self.verified_fingerprint(name).is_none())
+            .collect();
+        if !not_updated.is_empty() {
+            eprintln!("WARNING! {} caches were not updated from savepoint {} and are extracted from scratch when needed: {}",
+                      not_updated.len(), earlier_savepoint, not_updated.join(", "));
+        }
+
+        Ok(updated)
+    }
+}
+
 impl Data {
-    fn smart_load_project_substore(&mut self, source: &Source) -> &BTreeMap<ProjectId, Store> {
+    fn smart_load_project_substore(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Store>> {
         load_from_source!(self, project_substores, source)
     }
-    fn smart_load_project_urls(&mut self, source: &Source) -> &BTreeMap<ProjectId, String> {
+    fn smart_load_project_urls(&self, source: &Source) -> Arc<BTreeMap<ProjectId, String>> {
         load_from_source!(self, project_urls, source)
     }
-    fn smart_load_project_heads(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<Head>> {
+    fn smart_load_project_heads(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<Head>>> {
         load_from_source!(self, project_heads, source)
     }
-    fn smart_load_project_users(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<UserId>> {
+    fn smart_load_project_users(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<UserId>>> {
         load_with_prerequisites!(self, project_users, source, two, project_authors, project_committers)
     }
-    fn smart_load_project_authors(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<UserId>> {
+    fn smart_load_project_authors(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<UserId>>> {
         load_with_prerequisites!(self, project_authors, source, two, project_commits, commits)
     }
-    fn smart_load_project_committers(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<UserId>> {
+    fn smart_load_project_committers(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<UserId>>> {
         load_with_prerequisites!(self, project_committers, source, two, project_commits, commits)
     }
-    fn smart_load_project_commits(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<CommitId>> {
+    fn smart_load_project_commits(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<CommitId>>> {
         load_with_prerequisites!(self, project_commits, source, two, project_heads, commits)
     }
-    fn smart_load_project_paths(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<PathId>> {
+    fn smart_load_project_paths(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<PathId>>> {
         load_with_prerequisites!(self, project_paths, source, two, project_commits, commit_changes)
     }
-    fn smart_load_project_snapshots(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<SnapshotId>> {
+    fn smart_load_project_snapshots(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<SnapshotId>>> {
         load_with_prerequisites!(self, project_snapshots, source, three, project_commits, commit_changes, snapshot_has_contents)
     }
-    fn smart_load_project_user_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_user_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_user_count, source, one, project_users)
     }
-    fn smart_load_project_author_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_author_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_author_count, source, one, project_authors)
     }
-    fn smart_load_project_path_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_path_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_path_count, source, one, project_paths)
     }
-    fn smart_load_project_snapshot_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_snapshot_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_snapshot_count, source, one, project_snapshots)
     }
-    fn smart_load_project_committer_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_committer_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_committer_count, source, one, project_committers)
     }
-    fn smart_load_project_commit_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_commit_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_commit_count, source, one, project_commits)
     }
-    fn smart_load_project_lifetimes(&mut self, source: &Source) -> &BTreeMap<ProjectId, u64> {
+    fn smart_load_project_lifetimes(&self, source: &Source) -> Arc<BTreeMap<ProjectId, u64>> {
         load_with_prerequisites!(self, project_lifetimes, source, three, project_commits,
                                                                         commit_author_timestamps,
                                                                         commit_committer_timestamps)
     }
-    fn smart_load_project_unique_files(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_unique_files(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_unique_files, source, three, project_commits, commit_changes_with_contents, snapshot_projects)
     }
-    fn smart_load_project_original_files(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_original_files(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_original_files, source, three, project_commits, commit_changes_with_contents, snapshot_projects)
     }
-    fn smart_load_project_impact(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_impact(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_impact, source, three, project_commits, commit_changes_with_contents, snapshot_projects)
     }
-    fn smart_load_project_files(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_files(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_files, source, two, project_commits, commit_changes)
     }
-    fn smart_load_project_languages(& mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<(Language,usize)>> {
-        load_with_prerequisites!(self, project_languages, source, three, project_commits, commit_changes, paths)
+    fn smart_load_project_languages(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<(Language,usize)>>> {
+        load_with_prerequisites!(self, project_languages, source, three, project_commits, commit_changes, path_languages)
     }
-    fn smart_load_project_languages_count(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_languages_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_languages_count, source, one, project_languages)
     }
-    fn smart_load_project_major_language(& mut self, source: &Source) -> &BTreeMap<ProjectId, Language> {
+    fn smart_load_project_major_language(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Language>> {
         load_with_prerequisites!(self, project_major_language, source, one, project_languages)
     }
-    fn smart_load_project_major_language_ratio(& mut self, source: &Source) -> &BTreeMap<ProjectId, f64> {
+    fn smart_load_project_major_language_ratio(&self, source: &Source) -> Arc<BTreeMap<ProjectId, f64>> {
         load_with_prerequisites!(self, project_major_language_ratio, source, one, project_languages)
     }
-    fn smart_load_project_major_language_changes(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_major_language_changes(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_major_language_changes, source, one, project_languages)
     }
-    fn smart_load_project_all_forks(& mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<ProjectId>> {
+    fn smart_load_project_all_forks(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<ProjectId>>> {
         load_with_prerequisites!(self, project_all_forks, source, three, project_commits, commit_projects, project_created)
     }
-    fn smart_load_project_all_forks_count(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_all_forks_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_all_forks_count, source, one, project_all_forks)
     }
-    fn smart_load_project_head_trees(& mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<(String, Vec<(PathId, SnapshotId)>)>> {
+    fn smart_load_project_head_trees(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<(String, Vec<(PathId, SnapshotId)>)>>> {
         load_with_prerequisites!(self, project_head_trees, source, three, project_heads, commits, commit_changes)
     }
-    fn smart_load_project_head_trees_count(& mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_head_trees_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_head_trees_count, source, one, project_head_trees)
     }
-    fn smart_load_users(&mut self, source: &Source) -> &BTreeMap<UserId, User> {
+    fn smart_load_users(&self, source: &Source) -> Arc<BTreeMap<UserId, User>> {
         load_from_source!(self, users, source)
     }
-    fn smart_load_user_authored_commits(&mut self, source: &Source) -> &BTreeMap<UserId, Vec<CommitId>> {
+    fn smart_load_user_authored_commits(&self, source: &Source) -> Arc<BTreeMap<UserId, Vec<CommitId>>> {
         load_with_prerequisites!(self, user_authored_commits, source, one, commits)
     }
-    fn smart_load_user_committed_commits(&mut self, source: &Source) -> &BTreeMap<UserId, Vec<CommitId>> {
+    fn smart_load_user_committed_commits(&self, source: &Source) -> Arc<BTreeMap<UserId, Vec<CommitId>>> {
         load_with_prerequisites!(self, user_committed_commits, source, one, commits)
     }
-    fn smart_load_user_author_experience(&mut self, source: &Source) -> &BTreeMap<UserId, u64> {
+    fn smart_load_user_author_experience(&self, source: &Source) -> Arc<BTreeMap<UserId, u64>> {
         load_with_prerequisites!(self, user_author_experience, source, two, user_authored_commits,
                                                                            commit_author_timestamps)
     }
-    fn smart_load_user_committer_experience(&mut self, source: &Source) -> &BTreeMap<UserId, u64> {
+    fn smart_load_user_committer_experience(&self, source: &Source) -> Arc<BTreeMap<UserId, u64>> {
         load_with_prerequisites!(self, user_committer_experience, source, two, user_committed_commits,
                                                                               commit_committer_timestamps)
     }
-    fn smart_load_user_experience(&mut self, source: &Source) -> &BTreeMap<UserId, u64> {
+    fn smart_load_user_experience(&self, source: &Source) -> Arc<BTreeMap<UserId, u64>> {
         load_with_prerequisites!(self, user_experience, source, three, user_committed_commits,
                                                                       commit_author_timestamps,
                                                                       commit_committer_timestamps)
     }
-    fn smart_load_user_committed_commit_count(&mut self, source: &Source) -> &BTreeMap<UserId, usize> {
+    fn smart_load_user_committed_commit_count(&self, source: &Source) -> Arc<BTreeMap<UserId, usize>> {
         load_with_prerequisites!(self, user_committed_commit_count, source, one, user_committed_commits)
     }
-    fn smart_load_user_authored_commit_count(&mut self, source: &Source) -> &BTreeMap<UserId, usize> {
+    fn smart_load_user_authored_commit_count(&self, source: &Source) -> Arc<BTreeMap<UserId, usize>> {
         load_with_prerequisites!(self, user_authored_commit_count, source, one, user_authored_commits)
     }
-    fn smart_load_developer_experience(&mut self, source: &Source) -> &BTreeMap<UserId, i32> {
+    fn smart_load_developer_experience(&self, source: &Source) -> Arc<BTreeMap<UserId, i32>> {
         load_with_prerequisites!(self, developer_experience, source, two, user_authored_commits, commit_author_timestamps)
     }
-    fn smart_load_paths(&mut self, source: &Source) -> &BTreeMap<PathId, Path> {
+    fn smart_load_paths(&self, source: &Source) -> Arc<BTreeMap<PathId, Path>> {
         load_from_source!(self, paths, source)
     }
-    // fn smart_load_snapshots(&mut self, source: &DataSource) -> &BTreeMap<SnapshotId, Snapshot> {
+    /* Like load_with_prerequisites, except that the language registry is a prerequisite too, so
+       that the cache is extracted anew when the registry changes. */
+    fn smart_load_path_languages(&self, source: &Source) -> Arc<BTreeMap<PathId, Language>> {
+        let collection = match self.path_languages.loaded() {
+            Some(collection) => collection,
+            None => {
+                debug_assert_eq!(Data::collection("path_languages").map(|c| c.prerequisites), Some(vec!["commit_changes", "paths"]));
+                let commit_changes = self.smart_load_commit_changes(source);
+                let paths = self.smart_load_paths(source);
+                self.path_languages.set_prerequisites(vec![
+                    ("commit_changes".to_owned(), self.commit_changes.fingerprint()),
+                    ("paths".to_owned(), self.paths.fingerprint()),
+                    (LANGUAGE_REGISTRY.to_owned(), self.language_registry),
+                ]);
+                let collection = self.path_languages.load_from_two(source, &commit_changes, &paths);
+                track_loaded!(self, path_languages);
+                collection
+            }
+        };
+        self.budget.lock().unwrap().touch("path_languages");
+        collection
+    }
+    // fn smart_load_snapshots(&self, source: &DataSource) -> Arc<BTreeMap<SnapshotId, Snapshot>> {
     //     load_from_source!(self, snapshots, source)
     // }
-    fn smart_load_commits(&mut self, source: &Source) -> &BTreeMap<CommitId, Commit> {
+    fn smart_load_commits(&self, source: &Source) -> Arc<BTreeMap<CommitId, Commit>> {
         load_from_source!(self, commits, source)
     }
-    fn smart_load_commit_hashes(&mut self, source: &Source) -> &BTreeMap<CommitId, String> {
+    fn smart_load_commit_hashes(&self, source: &Source) -> Arc<BTreeMap<CommitId, String>> {
         load_from_source!(self, commit_hashes, source)
     }
-    fn smart_load_commit_messages(&mut self, source: &Source) -> &BTreeMap<CommitId, String> {
+    fn smart_load_commit_messages(&self, source: &Source) -> Arc<BTreeMap<CommitId, String>> {
         load_from_source!(self, commit_messages, source)
     }
-    fn smart_load_commit_committer_timestamps(&mut self, source: &Source) -> &BTreeMap<CommitId, Timestamp> {
+    fn smart_load_commit_committer_timestamps(&self, source: &Source) -> Arc<BTreeMap<CommitId, Timestamp>> {
         load_from_source!(self, commit_committer_timestamps, source)
     }
-    fn smart_load_commit_author_timestamps(&mut self, source: &Source) -> &BTreeMap<CommitId, Timestamp> {
+    fn smart_load_commit_author_timestamps(&self, source: &Source) -> Arc<BTreeMap<CommitId, Timestamp>> {
         load_from_source!(self, commit_author_timestamps, source)
     }
-    fn smart_load_commit_changes(&mut self, source: &Source) -> &BTreeMap<CommitId, Vec<ChangeTuple>> {
+    fn smart_load_commit_changes(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<ChangeTuple>>> {
         load_from_source!(self, commit_changes, source)
     }
-    fn smart_load_commit_changes_with_contents(&mut self, source: &Source) -> &BTreeMap<CommitId, Vec<ChangeTuple>> {
+    fn smart_load_commit_changes_with_contents(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<ChangeTuple>>> {
         load_with_prerequisites!(self, commit_changes_with_contents, source, two, commit_changes, snapshot_has_contents)
     }
-    fn smart_load_commit_change_count(&mut self, source: &Source) -> &BTreeMap<CommitId, usize> {
+    fn smart_load_commit_previous_snapshots(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<ChangeTuple>>> {
+        load_with_prerequisites!(self, commit_previous_snapshots, source, two, commits, commit_changes)
+    }
+    fn smart_load_commit_line_changes(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<(PathId, usize, usize)>>> {
+        load_with_prerequisites!(self, commit_line_changes, source, two, commit_changes, commit_previous_snapshots)
+    }
+    fn smart_load_commit_change_count(&self, source: &Source) -> Arc<BTreeMap<CommitId, usize>> {
         load_with_prerequisites!(self, commit_change_count, source, one, commit_changes)
     }
-    fn smart_load_commit_change_with_contents_count(&mut self, source: &Source) -> &BTreeMap<CommitId, usize> {
+    fn smart_load_commit_change_with_contents_count(&self, source: &Source) -> Arc<BTreeMap<CommitId, usize>> {
         load_with_prerequisites!(self, commit_change_with_contents_count, source, one, commit_changes_with_contents)
     }
-    fn smart_load_project_max_commit_delta(&mut self, source: &Source) -> &BTreeMap<ProjectId, i64> {
+    fn smart_load_project_max_commit_delta(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i64>> {
         load_with_prerequisites!(self, project_max_commit_delta, source, two, project_commits, commit_committer_timestamps)
     }
-    fn smart_load_project_max_experience(&mut self, source: &Source) -> &BTreeMap<ProjectId, i32> {
+    fn smart_load_project_max_experience(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i32>> {
         load_with_prerequisites!(self, project_max_experience, source, two, project_authors, developer_experience)
     }
-    fn smart_load_project_experience(&mut self, source: &Source) -> &BTreeMap<ProjectId, f64> {
+    fn smart_load_project_experience(&self, source: &Source) -> Arc<BTreeMap<ProjectId, f64>> {
         load_with_prerequisites!(self, project_experience, source, three, developer_experience, project_commits, commits)
     }
-    fn smart_load_project_avg_commit_delta(&mut self, source: &Source) -> &BTreeMap<ProjectId, i64> {
+    fn smart_load_project_avg_commit_delta(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i64>> {
         load_with_prerequisites!(self, avg_commit_delta, source, two, project_commits, commit_committer_timestamps)
     }
-    fn smart_load_project_time_since_last_commit(&mut self, source: &Source) -> &BTreeMap<ProjectId, i64> {
+    fn smart_load_project_time_since_last_commit(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i64>> {
         load_with_prerequisites!(self, project_time_since_last_commit, source, three, project_commits, commit_committer_timestamps, project_logs)
     }
-    fn smart_load_project_time_since_first_commit(&mut self, source: &Source) -> &BTreeMap<ProjectId, i64> {
+    fn smart_load_project_time_since_first_commit(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i64>> {
         load_with_prerequisites!(self, project_time_since_first_commit, source, three, project_commits, commit_committer_timestamps, project_logs)
     }
-    fn smart_load_project_is_abandoned(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    fn smart_load_project_is_abandoned(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_with_prerequisites!(self, is_abandoned, source, two, project_max_commit_delta, project_time_since_last_commit)
     }
-    fn smart_load_snapshot_locs(&mut self, source: &Source) -> &BTreeMap<SnapshotId, usize> {
+    fn smart_load_snapshot_locs(&self, source: &Source) -> Arc<BTreeMap<SnapshotId, usize>> {
         load_from_source!(self, snapshot_locs, source)
         //load_with_prerequisites!(self, is_abandoned, source, one, project_snapshots)
     }
-    fn smart_load_snapshot_has_contents(&mut self, source: &Source) -> &BTreeMap<SnapshotId, bool> {
+    fn smart_load_snapshot_has_contents(&self, source: &Source) -> Arc<BTreeMap<SnapshotId, bool>> {
         load_from_source!(self, snapshot_has_contents, source)
         //load_with_prerequisites!(self, is_abandoned, source, one, project_snapshots)
     }
-    fn smart_load_project_locs(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    fn smart_load_project_locs(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_with_prerequisites!(self, project_locs, source, three, project_head_trees,  project_default_branch, snapshot_locs)
     }
-    fn smart_load_project_duplicated_code(&mut self, source: &Source) -> &BTreeMap<ProjectId, f64> {
+    fn smart_load_snapshot_line_counts(&self, source: &Source) -> Arc<BTreeMap<SnapshotId, LineCounts>> {
+        load_with_prerequisites!(self, snapshot_line_counts, source, two, commit_changes, path_languages)
+    }
+    fn smart_load_project_line_counts(&self, source: &Source) -> Arc<BTreeMap<ProjectId, LineCounts>> {
+        load_with_prerequisites!(self, project_line_counts, source, three, project_head_trees, project_default_branch, snapshot_line_counts)
+    }
+    fn smart_load_project_duplicated_code(&self, source: &Source) -> Arc<BTreeMap<ProjectId, f64>> {
         load_with_prerequisites!(self, duplicated_code, source, three, project_commits,  commit_changes_with_contents, snapshot_projects)
     }
-    fn smart_load_project_logs(&mut self, source: &Source) -> &BTreeMap<ProjectId, i64> {
+    fn smart_load_project_logs(&self, source: &Source) -> Arc<BTreeMap<ProjectId, i64>> {
         load_with_prerequisites!(self, project_logs, source, one, project_is_valid)
     }
-    fn smart_load_commit_languages(&mut self, source: &Source) -> &BTreeMap<CommitId, Vec<Language>> {
-        load_with_prerequisites!(self, commit_languages, source, two, commit_changes, paths)
+    fn smart_load_commit_languages(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<Language>>> {
+        load_with_prerequisites!(self, commit_languages, source, two, commit_changes, path_languages)
     }
-    fn smart_load_commit_languages_count(&mut self, source: &Source) -> &BTreeMap<CommitId, usize> {
+    fn smart_load_commit_languages_count(&self, source: &Source) -> Arc<BTreeMap<CommitId, usize>> {
         load_with_prerequisites!(self, commit_languages_count, source, one, commit_languages)
     }
-    fn smart_load_commit_projects(&mut self, source: &Source) -> &BTreeMap<CommitId, Vec<ProjectId>> {
+    fn smart_load_commit_projects(&self, source: &Source) -> Arc<BTreeMap<CommitId, Vec<ProjectId>>> {
         load_with_prerequisites!(self, commit_projects, source, one, project_commits)
     }
-    fn smart_load_commit_projects_count(&mut self, source: &Source) -> &BTreeMap<CommitId, usize> {
+    fn smart_load_commit_projects_count(&self, source: &Source) -> Arc<BTreeMap<CommitId, usize>> {
         load_with_prerequisites!(self, commit_projects_count, source, one, commit_projects)
     }
-    fn smart_load_snapshot_projects(& mut self, source: &Source) -> &BTreeMap<SnapshotId,(usize, ProjectId)> {
+    fn smart_load_snapshot_projects(&self, source: &Source) -> Arc<BTreeMap<SnapshotId,(usize, ProjectId)>> {
         load_with_prerequisites!(self, snapshot_projects, source, four, commit_changes, commit_projects, commit_author_timestamps, project_created)
     }
-    pub fn smart_load_project_change_contributions(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<(UserId, usize)>> {
+    pub fn smart_load_project_change_contributions(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<(UserId, usize)>>> {
         load_with_prerequisites!(self, project_change_contributions, source, three, project_commits, commits, commit_changes)
     }
-    pub fn smart_load_project_commit_contributions(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<(UserId, usize)>> {
+    pub fn smart_load_project_commit_contributions(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<(UserId, usize)>>> {
         load_with_prerequisites!(self, project_commit_contributions, source, two, project_commits, commits)
     }
-    pub fn smart_load_project_cumulative_change_contributions(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<Percentage>> {
+    pub fn smart_load_project_cumulative_change_contributions(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<Percentage>>> {
         load_with_prerequisites!(self, project_cumulative_change_contributions, source, one, project_change_contributions)
     }
-    pub fn smart_load_project_cumulative_commit_contributions(&mut self, source: &Source) -> &BTreeMap<ProjectId, Vec<Percentage>> {
+    pub fn smart_load_project_cumulative_commit_contributions(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Vec<Percentage>>> {
         load_with_prerequisites!(self, project_cumulative_commit_contributions, source, one, project_commit_contributions)
     }
 
-    pub fn smart_load_project_issues(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_issues(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_issue_count, source)
     }
-    pub fn smart_load_project_buggy_issues(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_buggy_issues(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_buggy_issue_count, source)
     }
-    pub fn smart_load_project_is_fork(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_is_fork(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_is_fork, source)
     }
-    pub fn smart_load_project_is_archived(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_is_archived(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_is_archived, source)
     }
-    pub fn smart_load_project_is_disabled(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_is_disabled(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_is_disabled, source)
     }
-    pub fn smart_load_project_star_gazer_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_star_gazer_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_star_gazer_count, source)
     }
-    pub fn smart_load_project_watcher_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_watcher_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_watcher_count, source)
     }
-    pub fn smart_load_project_size(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_size(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_project_size, source)
     }
-    pub fn smart_load_project_open_issue_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_open_issue_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_open_issue_count, source)
     }
-    pub fn smart_load_project_fork_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_fork_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_fork_count, source)
     }
-    pub fn smart_load_project_subscriber_count(&mut self, source: &Source) -> &BTreeMap<ProjectId, usize> {
+    pub fn smart_load_project_subscriber_count(&self, source: &Source) -> Arc<BTreeMap<ProjectId, usize>> {
         load_from_metadata!(self, project_subscriber_count, source)
     }
-    pub fn smart_load_project_license(&mut self, source: &Source) -> &BTreeMap<ProjectId, String> {
+    pub fn smart_load_project_license(&self, source: &Source) -> Arc<BTreeMap<ProjectId, String>> {
         load_from_metadata!(self, project_license, source)
     }
-    pub fn smart_load_project_language(&mut self, source: &Source) -> &BTreeMap<ProjectId, Language> {
+    pub fn smart_load_project_language(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Language>> {
         load_from_metadata!(self, project_language, source)
     }
-    pub fn smart_load_project_description(&mut self, source: &Source) -> &BTreeMap<ProjectId, String> {
+    pub fn smart_load_project_description(&self, source: &Source) -> Arc<BTreeMap<ProjectId, String>> {
         load_from_metadata!(self, project_description, source)
     }
-    pub fn smart_load_project_homepage(&mut self, source: &Source) -> &BTreeMap<ProjectId, String> {
+    pub fn smart_load_project_homepage(&self, source: &Source) -> Arc<BTreeMap<ProjectId, String>> {
         load_from_metadata!(self, project_homepage, source)
     }
-    pub fn smart_load_project_has_issues(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_has_issues(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_has_issues, source)
     }
-    pub fn smart_load_project_has_downloads(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_has_downloads(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_has_downloads, source)
     }
-    pub fn smart_load_project_has_wiki(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_has_wiki(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_has_wiki, source)
     }
-    pub fn smart_load_project_has_pages(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_has_pages(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_metadata!(self, project_has_pages, source)
     }
-    fn smart_load_project_created(&mut self, source: &Source) -> &BTreeMap<ProjectId, Timestamp> {
+    fn smart_load_project_created(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Timestamp>> {
         load_from_metadata!(self, project_created, source)
     }
-    pub fn smart_load_project_updated(&mut self, source: &Source) -> &BTreeMap<ProjectId, Timestamp> {
+    pub fn smart_load_project_updated(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Timestamp>> {
         load_from_metadata!(self, project_updated, source)
     }
-    pub fn smart_load_project_pushed(&mut self, source: &Source) -> &BTreeMap<ProjectId, Timestamp> {
+    pub fn smart_load_project_pushed(&self, source: &Source) -> Arc<BTreeMap<ProjectId, Timestamp>> {
         load_from_metadata!(self, project_pushed, source)
     }
-    pub fn smart_load_project_default_branch(&mut self, source: &Source) -> &BTreeMap<ProjectId, String> {
+    pub fn smart_load_project_default_branch(&self, source: &Source) -> Arc<BTreeMap<ProjectId, String>> {
         load_from_metadata!(self, project_default_branch, source)
     }
-    pub fn smart_load_project_is_valid(&mut self, source: &Source) -> &BTreeMap<ProjectId, bool> {
+    pub fn smart_load_project_is_valid(&self, source: &Source) -> Arc<BTreeMap<ProjectId, bool>> {
         load_from_source!(self, project_is_valid, source)
         
     }
 }
 
 impl Data {
-    pub fn export_to_csv(&mut self, _dir: impl Into<String>, _: &Source) -> Result<(), std::io::Error> {
+    pub fn export_to_csv(&self, _dir: impl Into<String>, _: &Source) -> Result<(), std::io::Error> {
         // let dir = dir.into();
         // std::fs::create_dir_all(&dir)?;
         // macro_rules! path {
@@ -0,0 +1,279 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::fs::create_dir_all;
+
+use parasite;
+use parasite::Metadata;
+use parasite::HashId;
+use parasite::StoreKind;
+use parasite::ValidateAll;
+use parasite::Table;
+
+use anyhow::*;
+use serde_json::Value as JSON;
+
+use itertools::Itertools;
+
+use crate::Store;
+use crate::objects;
+
+use super::source::*;
+
+macro_rules! convert {
+    ($type:ident from $id:expr) => {
+        objects::$type::from(Into::<u64>::into($id))
+    };
+    ($type:ident from $id:expr, except $exception:expr) => {
+        if $id == $exception {
+            None
+        } else {
+            Some(objects::$type::from(Into::<u64>::into($id)))
+        }
+    };
+    (($type0:ident, $type1:ident) from $pair:expr) => {
+        (convert!($type0 from $pair.0), convert!($type1 from $pair.1))
+    };
+    (($type0:ident, $type1:ident) from $pair:expr, except (_, $exception1:expr)) => {
+        (convert!($type0 from $pair.0), convert!($type1 from $pair.1, except $exception1))
+    };
+    (($type0:ident, $type1:ident) from $pair:expr, except ($exception0:expr, _)) => {
+        (convert!($type0 from $pair.0, except $exception0), convert!($type1 from $pair.1))
+    };
+    (Vec<$type:ident> from $collection:expr) => {
+        $collection.into_iter().map(|id| convert!($type from id)).collect()
+    };
+    (HashMap<$key_type:ident, $value_type:ident> from $collection:expr) => {
+        $collection.into_iter().map(|entry| convert!(($key_type, $value_type) from entry)).collect()
+    };
+    (HashMap<$key_type:ident, $value_type:ident> from $collection:expr, except (_, $value_exception:expr)) => {
+        $collection.into_iter()
+            .map(|entry| {
+                convert!(($key_type, $value_type) from entry, except (_, $value_exception))
+            }).collect()
+    };
+}
+
+macro_rules! commit_basics {
+    ($info:expr) => {{
+        let info = $info;
+        CommitBasics {
+            committer: convert!(UserId from info.committer),
+            committer_time: info.committer_time,
+            author: convert!(UserId from info.author),
+            author_time: info.author_time,
+            parents: convert!(Vec<CommitId> from info.parents),
+            changes: convert!(HashMap<PathId, SnapshotId> from info.changes,
+                                                          except (_, HashId::DELETED)),
+            message: info.message,
+        }
+    }}
+}
+
+macro_rules! parse_github_json {
+    ($entity_name:expr, $id:expr, $string:expr) => {
+        JSON::from_str($string.as_str())
+           .with_context(|| format!("Cannot read GitHub metadata for {} {}", $entity_name, $id));
+    }
+}
+
+pub const MERGED_SUBSTORE_DIR_NAME: &'static str = "merged_store";
+
+/*
+ * The data source backed by a parasite datastore, which is where the data of
+ * actual queries comes from.
+ */
+pub struct DatastoreSource {
+    store: parasite::DatastoreView,
+    substore: parasite::StoreKind,
+}
+
+impl DatastoreSource {
+    fn from_one_subset<Sd>(dataset_path: Sd, substore: Store) -> Result<Self> where Sd: Into<String> {
+        let dataset_path = dataset_path.into();
+        //println!("Store path: {}", s);
+        let store = parasite::DatastoreView::from(dataset_path.as_str());
+        Ok(DatastoreSource { store, substore: substore.kind() })
+    }
+
+    fn from_all_subsets<Sc,Sd>(dataset_path: Sd, cache_path: Sc) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
+        let all_stores: Vec<Store> = StoreKind::all().map(|kind| Store::from(kind)).collect();
+        Self::from_multiple_subsets(dataset_path, cache_path, all_stores)
+    }
+
+    fn from_multiple_subsets<Sc,Sd>(dataset_path: Sd, cache_path: Sc, substores: Vec<Store>) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
+        let mut cache_path = PathBuf::from(cache_path.into());
+        //let mut merged_store_path = CacheDir::from(cache_path, savepoint, substores.clone()).as_path();
+        cache_path.push(MERGED_SUBSTORE_DIR_NAME);
+        let merged_store_path_string = cache_path.as_os_str().to_str().unwrap();
+        create_dir_all(merged_store_path_string)?;
+
+        let mut merger = 
+            parasite::DatastoreMerger::new(merged_store_path_string, dataset_path.into().as_str());
+        for substore in substores {
+            merger.merge_substore(StoreKind::Generic, substore.kind(), ValidateAll::new())
+        }
+
+        //println!("Merged store path: {}", merged_store_path_string);
+        let store = parasite::DatastoreView::from(merged_store_path_string);
+        Ok(DatastoreSource { store, substore: StoreKind::Generic })
+    }
+
+    pub fn new<Sc,Sd>(dataset_path: Sd, cache_path: Sc, substores: Vec<Store>) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
+        match substores.len() {
+            0 => Self::from_all_subsets(dataset_path, cache_path),
+            1 => Self::from_one_subset(dataset_path, substores.into_iter().last().unwrap()),
+            _ => Self::from_multiple_subsets(dataset_path, cache_path, substores),
+        }
+    }
+}
+
+impl DataSource for DatastoreSource {
+    fn project_logs<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Vec<ProjectLog>)> + 'a> {
+        Box::new(self.store.project_updates()
+            .map(|(id, log)| {
+                let log = match &log {
+                    parasite::ProjectLog::Update { time, heads, .. } => {
+                        ProjectLog::update(*time, heads.iter().map(|(name, hash)| (name.clone(), hash.to_string())).collect())
+                    }
+                    log => ProjectLog::new(log.time(), log.is_error()),
+                };
+                (convert!(ProjectId from id), log)
+            })
+            .into_group_map()
+            .into_iter())
+    }
+
+    fn project_urls<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, URL)> + 'a> {
+        Box::new(self.store.project_urls()
+            .into_iter()
+            .map(|(id, url)| {
+                (convert!(ProjectId from id), url.clone_url())
+            }))
+    }
+
+    fn project_substores<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Store)> + 'a> {
+        Box::new(self.store.project_substores()
+            .into_iter()
+            .map(|(id, kind)| {
+                (convert!(ProjectId from id), Store::from(kind))
+            }))
+    }
+
+    fn project_credentials<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, String)> + 'a> {
+        Box::new(self.store.project_urls()
+            .into_iter()
+            .map(|(id, url)| {
+                (convert!(ProjectId from id), url.name())
+            }))
+    }
+
+    fn project_heads<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, HashMap<String, (objects::CommitId, SHA)>)> + 'a> {
+        Box::new(self.store.project_heads()
+            .into_iter()
+            .map(|(project_id, heads)| {
+                let heads = heads.into_iter()
+                    .map(|(name, (commit_id, hash))| {
+                        (name, (convert!(CommitId from commit_id), hash.to_string()))
+                    })
+                    .collect::<HashMap<String, (objects::CommitId, String)>>();
+                (convert!(ProjectId from project_id), heads)
+            }))
+    }
+
+    fn project_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Result<JSON>)> + 'a> {
+        Box::new(self.store.project_metadata()
+            .into_iter()
+            .filter(|(_, metadata)| metadata.key == Metadata::GITHUB_METADATA)
+            .map(|(id, metadata)| {
+                (convert!(ProjectId from id), parse_github_json!("Project", id, metadata.value))
+            }))
+    }
+
+    fn commit_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, SHA)> + 'a> {
+        Box::new(self.store.commits(self.substore)
+            .into_iter()
+            .map(|(id, sha)| (convert!(CommitId from id), sha.to_string())))
+    }
+
+    fn commit_info<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, CommitBasics)> + 'a> {
+        Box::new(self.store.commits_info(self.substore)
+            .into_iter()
+            .map(|(commit_id, info)| (convert!(CommitId from commit_id), commit_basics!(info))))
+    }
+
+    fn commit_info_of<'a>(&'a self, ids: &'a BTreeSet<objects::CommitId>) -> Box<dyn Iterator<Item=(objects::CommitId, CommitBasics)> + 'a> {
+        let mut commits_info = self.store.commits_info(self.substore);
+        Box::new(ids.iter().flat_map(move |id| {
+            commits_info.get(parasite::CommitId::from(Into::<u64>::into(*id)))
+                .map(|info| (*id, commit_basics!(info)))
+        }))
+    }
+
+    fn commit_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, Result<JSON>)> + 'a> {
+        Box::new(self.store.commits_metadata(self.substore)
+            .into_iter()
+            .filter(|(_, metadata)| metadata.key == Metadata::GITHUB_METADATA)
+            .map(|(id, metadata)| {
+                (convert!(CommitId from id), parse_github_json!("Commit", id, metadata.value))
+            }))
+    }
+
+    // TODO hashes?
+    fn get_snapshot(&self, id: objects::SnapshotId) -> Option<Bytes> {
+        self.store.contents(self.substore)
+            .get(parasite::HashId::from(Into::<u64>::into(id)))
+            .map(|(_kind, hash)| hash)
+    }
+
+    fn snapshot_bytes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::SnapshotId, Bytes)> + 'a> {
+        Box::new(self.store.contents(self.substore)
+            .into_iter()
+            .map(|(id, (_kind, contents))| {
+                (convert!(SnapshotId from id), contents)
+            }))
+        // Maybe I could do something with `kind` but I'm not sure how to handle it. On one hand it
+        // really is a distinction of languages, so I could classify it like that, but there's a big
+        // black hole of `small files` that don't make a lot of sense when filtering.
+    }
+
+    fn snapshot_has_contents<'a>(&'a self) -> Box<dyn Iterator<Item=objects::SnapshotId> + 'a> {
+        Box::new(self.store.contents(self.substore)
+            .into_iter()
+            .map(|(id, _)| {
+                convert!(SnapshotId from id)
+            }))
+    }
+
+    fn snapshot_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::SnapshotId, Result<JSON>)> + 'a> {
+        Box::new(self.store.contents_metadata(self.substore)
+            .into_iter()
+            .map(|(id, metadata)| {
+                (convert!(SnapshotId from id), parse_github_json!("Snapshot", id, metadata.value))
+            }))
+    }
+
+    fn path_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::PathId, SHA)> + 'a> {
+        Box::new(self.store.paths(self.substore).into_iter().map(|(id, hash)| {
+            (convert!(PathId from id), hash.to_string())
+        }))
+    }
+
+    fn paths<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::PathId, Path)> + 'a> {
+        Box::new(self.store.paths_strings(self.substore).into_iter().map(|(id, path)| {
+            (convert!(PathId from id), path)
+        }))
+    }
+
+    fn user_emails<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::UserId, String)> + 'a> {
+        Box::new(self.store.users(self.substore).into_iter().map(|(id, email)| {
+            (convert!(UserId from id), email)
+        }))
+    }
+
+    fn user_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::UserId, Result<JSON>)> + 'a> {
+        Box::new(self.store.users_metadata(self.substore).into_iter().map(|(id, metadata)| {
+            (convert!(UserId from id), parse_github_json!("User", id, metadata.value))
+        }))
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::BTreeSet;
+use std::path::Path as FilePath;
+
+use anyhow::*;
+
+use crate::log::Verbosity;
+use crate::objects::*;
+
+use super::Database;
+use super::memory::InMemorySource;
+
+/*
+ * Exporting a sample of projects as a dataset of its own, so that it can be
+ * shared without the datastore it was taken from.
+ *
+ * The dataset holds the projects along with the commits reachable from their
+ * heads at the savepoint, and the users, paths, snapshots, and metadata of
+ * those. It is written in the format that `InMemorySource::from_dir` reads,
+ * and `Source::new` recognizes it, so it is loaded like any datastore, eg.
+ * with `Djanco::from_spec`. Ids are the same as in the original dataset, so
+ * results of queries on the sample can be matched against the original.
+ */
+impl Database {
+    /** Writes the given projects, and everything they consist of, into a dataset at `location`. */
+    pub fn export_projects<I, P>(&self, projects: I, location: P) -> Result<()> where I: IntoIterator<Item=ProjectId>, P: AsRef<FilePath> {
+        let event = self.log.start(Verbosity::Log, format!("exporting dataset to {:?}", location.as_ref()));
+
+        let projects: BTreeSet<ProjectId> = projects.into_iter().collect();
+        let commits: BTreeSet<CommitId> = projects.iter()
+            .flat_map(|project| self.project_commit_ids(project).unwrap_or_default())
+            .collect();
+
+        InMemorySource::subset(&self.source, &projects, &commits).write_dir(location)?;
+
+        self.log.end(event);
+        Ok(())
+    }
+}
@@ -6,6 +6,8 @@ use std::iter::FromIterator;
 use itertools::{Itertools, MinMaxResult};
 
 use crate::objects::*;
+use crate::diff::LineDiff;
+use crate::lines::{CommentSyntax, LineCounts, is_binary};
 use crate::piracy::*;
 use crate::weights_and_measures::{Weighed};
 use crate::{Store, Percentage, Timestamp};
@@ -33,6 +35,7 @@ pub(crate) struct ProjectUrlExtractor;
 impl MapExtractor for ProjectUrlExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectUrlExtractor";
 }
 impl SourceMapExtractor for ProjectUrlExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -44,6 +47,7 @@ pub(crate) struct MaxCommitDeltaExtractor {}
 impl MapExtractor for MaxCommitDeltaExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "MaxCommitDeltaExtractor";
 }
 impl DoubleMapExtractor for MaxCommitDeltaExtractor  {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -77,6 +81,7 @@ pub(crate) struct ProjectMaxExperienceExtractor {}
 impl MapExtractor for ProjectMaxExperienceExtractor {
     type Key = ProjectId;
     type Value = i32;
+    const NAME: &'static str = "ProjectMaxExperienceExtractor";
 }
 impl DoubleMapExtractor for ProjectMaxExperienceExtractor  {
     type A = BTreeMap<ProjectId, Vec<UserId>>;
@@ -102,6 +107,7 @@ pub(crate) struct AvgCommitDeltaExtractor {}
 impl MapExtractor for AvgCommitDeltaExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "AvgCommitDeltaExtractor";
 }
 impl DoubleMapExtractor for AvgCommitDeltaExtractor  {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -136,6 +142,7 @@ pub(crate) struct TimeSinceLastCommitExtractor {}
 impl MapExtractor for TimeSinceLastCommitExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "TimeSinceLastCommitExtractor";
 }
 impl TripleMapExtractor for TimeSinceLastCommitExtractor  {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -169,6 +176,7 @@ pub(crate) struct TimeSinceFirstCommitExtractor {}
 impl MapExtractor for TimeSinceFirstCommitExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "TimeSinceFirstCommitExtractor";
 }
 impl TripleMapExtractor for TimeSinceFirstCommitExtractor  {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -201,6 +209,7 @@ pub(crate) struct IsAbandonedExtractor {}
 impl MapExtractor for IsAbandonedExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "IsAbandonedExtractor";
 }
 impl DoubleMapExtractor for IsAbandonedExtractor  {
     type A = BTreeMap<ProjectId, i64>;
@@ -220,6 +229,7 @@ pub(crate) struct ProjectSubstoreExtractor;
 impl MapExtractor for ProjectSubstoreExtractor {
     type Key = ProjectId;
     type Value = Store;
+    const NAME: &'static str = "ProjectSubstoreExtractor";
 }
 impl SourceMapExtractor for ProjectSubstoreExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -231,6 +241,7 @@ pub(crate) struct ProjectCredentialsExtractor; // TODO plug in
 impl MapExtractor for ProjectCredentialsExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectCredentialsExtractor";
 }
 impl SourceMapExtractor for ProjectCredentialsExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -242,6 +253,7 @@ pub(crate) struct ProjectHeadsExtractor;
 impl MapExtractor for ProjectHeadsExtractor {
     type Key = ProjectId;
     type Value = Vec<Head>;
+    const NAME: &'static str = "ProjectHeadsExtractor";
 }
 impl SourceMapExtractor for ProjectHeadsExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -260,6 +272,7 @@ pub(crate) struct ProjectSnapshotsExtractor {}
 impl MapExtractor for ProjectSnapshotsExtractor {
     type Key = ProjectId;
     type Value = Vec<SnapshotId>;
+    const NAME: &'static str = "ProjectSnapshotsExtractor";
 }
 impl TripleMapExtractor for ProjectSnapshotsExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -292,6 +305,7 @@ pub(crate) struct ProjectPathsExtractor {}
 impl MapExtractor for ProjectPathsExtractor {
     type Key = ProjectId;
     type Value = Vec<PathId>;
+    const NAME: &'static str = "ProjectPathsExtractor";
 }
 impl DoubleMapExtractor for ProjectPathsExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -320,6 +334,7 @@ pub(crate) struct ProjectUsersExtractor {}
 impl MapExtractor for ProjectUsersExtractor {
     type Key = ProjectId;
     type Value = Vec<UserId>;
+    const NAME: &'static str = "ProjectUsersExtractor";
 }
 impl DoubleMapExtractor for ProjectUsersExtractor {
     type A = BTreeMap<ProjectId, Vec<UserId>>;
@@ -341,6 +356,7 @@ pub(crate) struct ProjectAuthorsExtractor {}
 impl MapExtractor for ProjectAuthorsExtractor {
     type Key = ProjectId;
     type Value = Vec<UserId>;
+    const NAME: &'static str = "ProjectAuthorsExtractor";
 }
 impl DoubleMapExtractor for ProjectAuthorsExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -358,6 +374,7 @@ pub(crate) struct ProjectCommittersExtractor {}
 impl MapExtractor for ProjectCommittersExtractor {
     type Key = ProjectId;
     type Value = Vec<UserId>;
+    const NAME: &'static str = "ProjectCommittersExtractor";
 }
 impl DoubleMapExtractor for ProjectCommittersExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -375,6 +392,7 @@ pub(crate) struct CountPerKeyExtractor<K: Clone + Ord + Persistent, V>(PhantomDa
 impl<K, V> MapExtractor for CountPerKeyExtractor<K, V> where K: Clone + Ord + Persistent + Weighed {
     type Key = K;
     type Value = usize;
+    const NAME: &'static str = "CountPerKeyExtractor";
 }
 impl<K, V> SingleMapExtractor for CountPerKeyExtractor<K, V> where K: Clone + Ord + Persistent + Weighed {
     type A = BTreeMap<K, Vec<V>>;
@@ -383,6 +401,17 @@ impl<K, V> SingleMapExtractor for CountPerKeyExtractor<K, V> where K: Clone + Or
         primary.iter().map(|(key, value)| (key.clone(), value.len())).collect()
     }
 }
+impl<K, V> CountPerKeyExtractor<K, V> where K: Clone + Ord + Persistent + Weighed {
+    /* Recounts only the given keys, after their values in the primary map changed. */
+    pub fn update(counts: &mut BTreeMap<K, usize>, primary: &BTreeMap<K, Vec<V>>, keys: &BTreeSet<K>) {
+        for key in keys {
+            match primary.get(key) {
+                Some(values) => { counts.insert(key.clone(), values.len()); }
+                None => { counts.remove(key); }
+            }
+        }
+    }
+}
 
 pub(crate) struct ProjectCommitsExtractor {}
 impl ProjectCommitsExtractor {
@@ -404,22 +433,49 @@ impl ProjectCommitsExtractor {
         }
         commits_in_head
     }
+    fn commits_from_heads(commits: &BTreeMap<CommitId, Commit>, heads: &Vec<Head>) -> Vec<CommitId> {
+        let commits: BTreeSet<CommitId> = heads.iter().flat_map(|head| {
+            Self::commits_from_head(commits, &head.commit_id())
+        }).collect();
+        Vec::from_iter(commits.into_iter())
+    }
+
+    /* The projects whose heads differ between two savepoints, including projects that appeared
+       or disappeared. The commits of only these projects have to be recomputed. */
+    pub fn changed_projects(earlier_heads: &BTreeMap<ProjectId, Vec<Head>>, heads: &BTreeMap<ProjectId, Vec<Head>>) -> BTreeSet<ProjectId> {
+        let sorted = |heads: Option<&Vec<Head>>| {
+            heads.map(|heads| heads.iter().map(|head| (head.name(), head.commit_id())).sorted().collect::<Vec<(String, CommitId)>>())
+        };
+        earlier_heads.keys().chain(heads.keys())
+            .filter(|project_id| sorted(earlier_heads.get(project_id)) != sorted(heads.get(project_id)))
+            .cloned()
+            .collect()
+    }
+
+    /* Recomputes the commits of the given projects from their current heads. */
+    pub fn update(project_commits: &mut BTreeMap<ProjectId, Vec<CommitId>>,
+                  heads: &BTreeMap<ProjectId, Vec<Head>>,
+                  commits: &BTreeMap<CommitId, Commit>,
+                  projects: &BTreeSet<ProjectId>) {
+        for project_id in projects {
+            match heads.get(project_id) {
+                Some(heads) => { project_commits.insert(project_id.clone(), Self::commits_from_heads(commits, heads)); }
+                None => { project_commits.remove(project_id); }
+            }
+        }
+    }
 }
 impl MapExtractor for ProjectCommitsExtractor {
     type Key = ProjectId;
     type Value = Vec<CommitId>;
+    const NAME: &'static str = "ProjectCommitsExtractor";
 }
 impl DoubleMapExtractor for ProjectCommitsExtractor {
     type A = BTreeMap<ProjectId, Vec<Head>>;
     type B = BTreeMap<CommitId, Commit>;
     fn extract(_: &Source, heads: &Self::A, commits: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
         heads.iter().map(|(project_id, heads)| {
-            (project_id.clone(),
-             heads.iter().flat_map(|head| {
-                 Self::commits_from_head(commits, &head.commit_id())
-             }).collect::<BTreeSet<CommitId>>())
-        }).map(|(project_id, commits)| {
-            (project_id, Vec::from_iter(commits.into_iter()))
+            (project_id.clone(), Self::commits_from_heads(commits, heads))
         }).collect()
     }
 }
@@ -428,6 +484,7 @@ pub(crate) struct ProjectLifetimesExtractor {}
 impl MapExtractor for ProjectLifetimesExtractor {
     type Key = ProjectId;
     type Value = u64;
+    const NAME: &'static str = "ProjectLifetimesExtractor";
 }
 impl TripleMapExtractor for ProjectLifetimesExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -464,6 +521,7 @@ pub(crate) struct UserExtractor {}
 impl MapExtractor for UserExtractor {
     type Key = UserId;
     type Value = User;
+    const NAME: &'static str = "UserExtractor";
 }
 impl SourceMapExtractor for UserExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -477,6 +535,7 @@ pub(crate) struct UserAuthoredCommitsExtractor {}
 impl MapExtractor for UserAuthoredCommitsExtractor {
     type Key = UserId;
     type Value = Vec<CommitId>;
+    const NAME: &'static str = "UserAuthoredCommitsExtractor";
 }
 impl SingleMapExtractor for UserAuthoredCommitsExtractor {
     type A = BTreeMap<CommitId, Commit>;
@@ -489,6 +548,35 @@ impl SingleMapExtractor for UserAuthoredCommitsExtractor {
             .collect()
     }
 }
+impl UserAuthoredCommitsExtractor {
+    /* The authors of the given commits, whose lists of commits change when the commits are added. */
+    pub fn changed_users(commits: &BTreeMap<CommitId, Commit>, new_commits: &BTreeSet<CommitId>) -> BTreeSet<UserId> {
+        new_commits.iter()
+            .flat_map(|commit_id| commits.get(commit_id))
+            .map(|commit| commit.author_id())
+            .collect()
+    }
+
+    /* Adds the given commits to the lists of commits of their authors. */
+    pub fn update(user_commits: &mut BTreeMap<UserId, Vec<CommitId>>,
+                  commits: &BTreeMap<CommitId, Commit>,
+                  new_commits: &BTreeSet<CommitId>) {
+        let mut changed: BTreeSet<UserId> = BTreeSet::new();
+        for commit_id in new_commits {
+            if let Some(commit) = commits.get(commit_id) {
+                user_commits.entry(commit.author_id()).or_insert_with(Vec::new).push(commit_id.clone());
+                changed.insert(commit.author_id());
+            }
+        }
+        // Extracting the map from scratch lists each user's commits in order of their ids.
+        for user_id in changed {
+            if let Some(commit_ids) = user_commits.get_mut(&user_id) {
+                commit_ids.sort();
+                commit_ids.dedup();
+            }
+        }
+    }
+}
 
 
 
@@ -496,6 +584,7 @@ pub(crate) struct UserExperienceExtractor {}
 impl MapExtractor for UserExperienceExtractor {
     type Key = UserId;
     type Value = u64;
+    const NAME: &'static str = "UserExperienceExtractor";
 }
 impl DoubleMapExtractor for UserExperienceExtractor  {
     type A = BTreeMap<UserId, Vec<CommitId>>;
@@ -521,6 +610,7 @@ pub(crate) struct DeveloperExperienceExtractor {}
 impl MapExtractor for DeveloperExperienceExtractor {
     type Key = UserId;
     type Value = i32;
+    const NAME: &'static str = "DeveloperExperienceExtractor";
 }
 impl DoubleMapExtractor for DeveloperExperienceExtractor  {
     type A = BTreeMap<UserId, Vec<CommitId>>;
@@ -567,6 +657,7 @@ pub(crate) struct CombinedUserExperienceExtractor {}
 impl MapExtractor for CombinedUserExperienceExtractor {
     type Key = UserId;
     type Value = u64;
+    const NAME: &'static str = "CombinedUserExperienceExtractor";
 }
 impl TripleMapExtractor for CombinedUserExperienceExtractor  {
     type A = BTreeMap<UserId, Vec<CommitId>>;
@@ -599,6 +690,7 @@ pub(crate) struct PathExtractor {}
 impl MapExtractor for PathExtractor {
     type Key = PathId;
     type Value = Path;
+    const NAME: &'static str = "PathExtractor";
 }
 impl SourceMapExtractor for PathExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -614,6 +706,7 @@ pub(crate) struct SnapshotExtractor {}
 impl MapExtractor for SnapshotExtractor {
     type Key = SnapshotId;
     type Value = Snapshot;
+    const NAME: &'static str = "SnapshotExtractor";
 }
 impl SourceMapExtractor for SnapshotExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -628,6 +721,7 @@ pub(crate) struct CommitExtractor {}
 impl MapExtractor for CommitExtractor {
     type Key = CommitId;
     type Value = Commit;
+    const NAME: &'static str = "CommitExtractor";
 }
 impl SourceMapExtractor for CommitExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -636,11 +730,21 @@ impl SourceMapExtractor for CommitExtractor {
         }).collect()
     }
 }
+impl AppendSourceMapExtractor for CommitExtractor {
+    fn extract_new(source: &Source, previous: &BTreeMap<Self::Key, Self::Value>, added: &BTreeSet<CommitId>) -> BTreeMap<Self::Key, Self::Value> {
+        source.commit_info_of(added)
+            .filter(|(id, _)| !previous.contains_key(id))
+            .map(|(id, basics)| {
+                (id, Commit::new(id, basics.committer, basics.author, basics.parents))
+            }).collect()
+    }
+}
 
 pub(crate) struct CommitHashExtractor {}
 impl MapExtractor for CommitHashExtractor {
     type Key = CommitId;
     type Value = String;
+    const NAME: &'static str = "CommitHashExtractor";
 }
 impl SourceMapExtractor for CommitHashExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -652,6 +756,7 @@ pub(crate) struct CommitMessageExtractor {}
 impl MapExtractor for CommitMessageExtractor {
     type Key = CommitId;
     type Value = String;
+    const NAME: &'static str = "CommitMessageExtractor";
 }
 impl SourceMapExtractor for CommitMessageExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -665,6 +770,7 @@ pub(crate) struct CommitterTimestampExtractor {}
 impl MapExtractor for CommitterTimestampExtractor {
     type Key = CommitId;
     type Value = Timestamp;
+    const NAME: &'static str = "CommitterTimestampExtractor";
 }
 impl SourceMapExtractor for CommitterTimestampExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -679,6 +785,7 @@ pub(crate) struct CommitChangesExtractor {}
 impl MapExtractor for CommitChangesExtractor {
     type Key = CommitId;
     type Value = Vec<ChangeTuple>;
+    const NAME: &'static str = "CommitChangesExtractor";
 }
 impl SourceMapExtractor for CommitChangesExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -687,11 +794,20 @@ impl SourceMapExtractor for CommitChangesExtractor {
             .collect()
     }
 }
+impl AppendSourceMapExtractor for CommitChangesExtractor {
+    fn extract_new(source: &Source, previous: &BTreeMap<Self::Key, Self::Value>, added: &BTreeSet<CommitId>) -> BTreeMap<Self::Key, Self::Value> {
+        source.commit_info_of(added)
+            .filter(|(commit_id, _)| !previous.contains_key(commit_id))
+            .map(|(commit_id, info)| (commit_id, info.changes))
+            .collect()
+    }
+}
 
 pub(crate) struct CommitChangesWithContentsExtractor { }
 impl MapExtractor for CommitChangesWithContentsExtractor {
     type Key = CommitId;
     type Value = Vec<ChangeTuple>;
+    const NAME: &'static str = "CommitChangesWithContentsExtractor";
 }
 impl DoubleMapExtractor for CommitChangesWithContentsExtractor {
     type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
@@ -709,10 +825,110 @@ impl DoubleMapExtractor for CommitChangesWithContentsExtractor {
 }
 
 
+pub(crate) struct CommitPreviousSnapshotsExtractor {}
+impl MapExtractor for CommitPreviousSnapshotsExtractor {
+    type Key = CommitId;
+    type Value = Vec<ChangeTuple>; // aligned with the changes of the commit
+    const NAME: &'static str = "CommitPreviousSnapshotsExtractor";
+}
+impl DoubleMapExtractor for CommitPreviousSnapshotsExtractor {
+    type A = BTreeMap<CommitId, Commit>;
+    type B = BTreeMap<CommitId, Vec<ChangeTuple>>;
+    fn extract(_: &Source, commits: &Self::A, commit_changes: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
+        // A commit whose first parent is missing is a child of a commit that is never walked, so it
+        // and its descendants along first parents have no previous snapshots: the tree they start
+        // from is not known, and taking it to be empty would make every file look added.
+        let mut children = BTreeMap::<CommitId, Vec<CommitId>>::new();
+        let mut roots = Vec::new();
+        for (id, commit) in commits {
+            match commit.parents.first() {
+                Some(parent) => children.entry(*parent).or_insert_with(Vec::new).push(*id),
+                None => roots.push(*id),
+            }
+        }
+
+        // Walk down from the roots along first parents, keeping the tree of the current commit.
+        // Leaving a commit puts back the snapshots its changes replaced.
+        enum Step { Enter(CommitId), Leave(CommitId) }
+        let mut tree = BTreeMap::<PathId, SnapshotId>::new();
+        let mut previous_snapshots = BTreeMap::<CommitId, Vec<ChangeTuple>>::new();
+        let mut steps: Vec<Step> = roots.into_iter().rev().map(Step::Enter).collect();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(id) => {
+                    let changes = commit_changes.get(&id).map_or(&[][..], |changes| changes.as_slice());
+                    let previous: Vec<ChangeTuple> = changes.iter()
+                        .map(|(path_id, _)| (*path_id, tree.get(path_id).cloned()))
+                        .collect();
+                    for (path_id, snapshot_id) in changes {
+                        match snapshot_id {
+                            Some(snapshot_id) => { tree.insert(*path_id, *snapshot_id); }
+                            None => { tree.remove(path_id); }
+                        }
+                    }
+                    previous_snapshots.insert(id, previous);
+                    steps.push(Step::Leave(id));
+                    steps.extend(children.get(&id).into_iter().flatten().rev().map(|child| Step::Enter(*child)));
+                }
+                Step::Leave(id) => {
+                    for (path_id, snapshot_id) in previous_snapshots[&id].iter().rev() {
+                        match snapshot_id {
+                            Some(snapshot_id) => { tree.insert(*path_id, *snapshot_id); }
+                            None => { tree.remove(path_id); }
+                        }
+                    }
+                }
+            }
+        }
+        previous_snapshots
+    }
+}
+
+pub(crate) struct CommitLineChangesExtractor {}
+impl MapExtractor for CommitLineChangesExtractor {
+    type Key = CommitId;
+    type Value = Vec<(PathId, usize, usize)>; // lines added and deleted
+    const NAME: &'static str = "CommitLineChangesExtractor";
+}
+impl DoubleMapExtractor for CommitLineChangesExtractor {
+    type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
+    type B = BTreeMap<CommitId, Vec<ChangeTuple>>;
+    fn extract(source: &Source, commit_changes: &Self::A, commit_previous_snapshots: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
+        commit_changes.iter().filter_map(|(commit_id, changes)| {
+            let previous = commit_previous_snapshots.get(commit_id)?;
+            // Commits with any change that cannot be diffed have no line changes rather than too few.
+            let line_changes: Vec<(PathId, usize, usize)> = changes.iter().zip(previous.iter())
+                .map(|((path_id, new), (_, old))| {
+                    if old == new {
+                        return Some((*path_id, 0, 0))
+                    }
+                    let (old, new) = texts_to_diff(source, *old, *new)?;
+                    let diff = LineDiff::new(&old, &new);
+                    Some((*path_id, diff.lines_added(), diff.lines_deleted()))
+                }).collect::<Option<_>>()?;
+            Some((*commit_id, line_changes))
+        }).collect()
+    }
+}
+
+/* The contents of two versions of a file as text, a version that does not exist being empty.
+   None if the contents of either version are not known or are binary. */
+pub(crate) fn texts_to_diff(source: &Source, old: Option<SnapshotId>, new: Option<SnapshotId>) -> Option<(String, String)> {
+    let text = |snapshot_id: Option<SnapshotId>| match snapshot_id {
+        None => Some(String::new()),
+        Some(snapshot_id) => source.get_snapshot(snapshot_id)
+            .filter(|contents| !is_binary(contents))
+            .map(|contents| String::from_utf8_lossy(&contents).into_owned()),
+    };
+    Some((text(old)?, text(new)?))
+}
+
+
 pub(crate) struct AuthorTimestampExtractor {}
 impl MapExtractor for AuthorTimestampExtractor {
     type Key = CommitId;
     type Value = Timestamp; // TODO wrap
+    const NAME: &'static str = "AuthorTimestampExtractor";
 }
 impl SourceMapExtractor for AuthorTimestampExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -726,6 +942,7 @@ pub(crate) struct SnapshotLocsExtractor{}
 impl MapExtractor for SnapshotLocsExtractor {
     type Key = SnapshotId;
     type Value = usize;
+    const NAME: &'static str = "SnapshotLocsExtractor";
 }
 impl SourceMapExtractor for SnapshotLocsExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -737,22 +954,69 @@ impl SourceMapExtractor for SnapshotLocsExtractor {
     }
 }
 
+/*
+ * Lines of code, comments, and blank lines in each snapshot with contents,
+ * except binary files. Comments are told apart by the syntax of the language
+ * of the snapshot, which is that of the path with the lowest id that the
+ * snapshot is found at. Snapshots that are not found at any path, or whose
+ * language is not known, are counted without comments.
+ */
+pub(crate) struct SnapshotLineCountsExtractor{}
+impl MapExtractor for SnapshotLineCountsExtractor {
+    type Key = SnapshotId;
+    type Value = LineCounts;
+    const NAME: &'static str = "SnapshotLineCountsExtractor";
+}
+impl DoubleMapExtractor for SnapshotLineCountsExtractor {
+    type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
+    type B = BTreeMap<PathId, Language>;
+    fn extract(source: &Source, commit_changes: &Self::A, path_languages: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
+        let mut snapshot_paths = BTreeMap::<SnapshotId, PathId>::new();
+        for (path_id, snapshot_id) in commit_changes.values().flatten() {
+            if let Some(snapshot_id) = snapshot_id {
+                let first = snapshot_paths.entry(*snapshot_id).or_insert(*path_id);
+                if *path_id < *first {
+                    *first = *path_id;
+                }
+            }
+        }
+        source.snapshot_bytes().flat_map(|(id, contents)| {
+            let syntax = snapshot_paths.get(&id)
+                .and_then(|path_id| path_languages.get(path_id))
+                .map_or(CommentSyntax::NONE, |language| language.comment_syntax());
+            LineCounts::count(&contents, syntax).map(|counts| (id, counts))
+        }).collect()
+    }
+}
+
 pub(crate) struct SnapshotHasContentsExtractor{}
 impl MapExtractor for SnapshotHasContentsExtractor {
     type Key = SnapshotId;
     type Value = bool;
+    const NAME: &'static str = "SnapshotHasContentsExtractor";
 }
 impl SourceMapExtractor for SnapshotHasContentsExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
         source.snapshot_has_contents().map(|x| (x, true)).collect()
     }
 }
+impl AppendSourceMapExtractor for SnapshotHasContentsExtractor {
+    fn extract_new(source: &Source, previous: &BTreeMap<Self::Key, Self::Value>, added: &BTreeSet<CommitId>) -> BTreeMap<Self::Key, Self::Value> {
+        // Snapshots that are new at this savepoint can only be in the changes of new commits.
+        source.commit_info_of(added)
+            .flat_map(|(_, info)| info.changes.into_iter().flat_map(|(_, snapshot_id)| snapshot_id))
+            .filter(|id| !previous.contains_key(id) && source.has_snapshot(*id))
+            .map(|x| (x, true))
+            .collect()
+    }
+}
 
 
 pub(crate) struct ProjectLocsExtractor{} 
 impl MapExtractor for ProjectLocsExtractor{
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectLocsExtractor";
 }
 
 // project_default_branch, project_head_trees, snapshot_locs
@@ -802,11 +1066,33 @@ impl TripleMapExtractor for ProjectLocsExtractor {
     }
 }
 
+/* Like ProjectLocsExtractor, adding up the lines of the files at the head of the default branch. */
+pub(crate) struct ProjectLineCountsExtractor{}
+impl MapExtractor for ProjectLineCountsExtractor {
+    type Key = ProjectId;
+    type Value = LineCounts;
+    const NAME: &'static str = "ProjectLineCountsExtractor";
+}
+impl TripleMapExtractor for ProjectLineCountsExtractor {
+    type A = BTreeMap<ProjectId, Vec<(String, Vec<(PathId, SnapshotId)>)>>;
+    type B = BTreeMap<ProjectId, String>;
+    type C = BTreeMap<SnapshotId, LineCounts>;
+    fn extract(_: &Source, project_head_trees: &Self::A, project_default_branch: &Self::B, snapshot_line_counts: &Self::C) -> BTreeMap<Self::Key, Self::Value> {
+        project_head_trees.iter().filter_map(|(pid, heads)| {
+            let ref_name = format!("refs/heads/{}", project_default_branch.get(pid)?);
+            let (_, tree) = heads.iter().find(|(name, _)| *name == ref_name)?;
+            let line_counts: LineCounts = tree.iter().filter_map(|(_, snapshot_id)| snapshot_line_counts.get(snapshot_id)).sum();
+            Some((*pid, line_counts))
+        }).collect()
+    }
+}
+
 // TODO change this to usize so that we are the same as the other duplication related attributes
 pub(crate) struct DuplicatedCodeExtractor {}
 impl MapExtractor for DuplicatedCodeExtractor {
     type Key = ProjectId;
     type Value = f64;
+    const NAME: &'static str = "DuplicatedCodeExtractor";
 }
 
 impl TripleMapExtractor for DuplicatedCodeExtractor {
@@ -849,6 +1135,7 @@ pub(crate) struct DuplicatedCodeExtractor {}
 impl MapExtractor for DuplicatedCodeExtractor {
     type Key = ProjectId;
     type Value = f64;
+    const NAME: &'static str = "DuplicatedCodeExtractor";
 }
 impl TripleMapExtractor for DuplicatedCodeExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -889,6 +1176,7 @@ pub(crate) struct CommitProjectsExtractor {}
 impl MapExtractor for CommitProjectsExtractor {
     type Key = CommitId;
     type Value = Vec<ProjectId>;
+    const NAME: &'static str = "CommitProjectsExtractor";
 }
 
 impl SingleMapExtractor for CommitProjectsExtractor {
@@ -912,21 +1200,62 @@ pub(crate) struct CommitLanguagesExtractor {}
 impl MapExtractor for CommitLanguagesExtractor {
     type Key = CommitId;
     type Value = Vec<Language>;
+    const NAME: &'static str = "CommitLanguagesExtractor";
+    const VERSION: u32 = 2;
 }
 
 impl DoubleMapExtractor for CommitLanguagesExtractor {
     type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
-    type B = BTreeMap<PathId, Path>;
-    fn extract(_source: &Source, changes: &Self::A, paths: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
+    type B = BTreeMap<PathId, Language>;
+    fn extract(_source: &Source, changes: &Self::A, path_languages: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
         changes.iter().map(|(commit_id, commit_changes)| {
             (commit_id.clone(), 
              commit_changes.iter().flat_map(|(path_id, _snapshot_id)| {
-                paths.get(path_id).map(|path| path.language()).flatten()
+                path_languages.get(path_id).cloned()
              }).unique().collect::<Vec<Language>>())
         }).collect()
     }
 }
 
+/*
+ * The language of each path, according to the language registry of the
+ * source. Paths that cannot be told apart by their names alone are looked up
+ * by their contents: any snapshot of a path will do, so the one with the
+ * highest id is taken. Paths without a language are left out.
+ */
+pub(crate) struct PathLanguagesExtractor {}
+impl MapExtractor for PathLanguagesExtractor {
+    type Key = PathId;
+    type Value = Language;
+    const NAME: &'static str = "PathLanguagesExtractor";
+}
+
+impl DoubleMapExtractor for PathLanguagesExtractor {
+    type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
+    type B = BTreeMap<PathId, Path>;
+    fn extract(source: &Source, changes: &Self::A, paths: &Self::B) -> BTreeMap<Self::Key, Self::Value> {
+        let registry = source.language_registry();
+        let mut snapshots = BTreeMap::<PathId, SnapshotId>::new();
+        for (path_id, snapshot_id) in changes.values().flatten() {
+            if let Some(snapshot_id) = snapshot_id {
+                let latest = snapshots.entry(*path_id).or_insert(*snapshot_id);
+                if *latest < *snapshot_id {
+                    *latest = *snapshot_id;
+                }
+            }
+        }
+        paths.iter().flat_map(|(path_id, path)| {
+            let location = path.location();
+            let contents = if registry.needs_contents(&location) {
+                snapshots.get(path_id).and_then(|snapshot_id| source.get_snapshot(*snapshot_id))
+            } else {
+                None
+            };
+            registry.detect(&location, contents.as_deref()).map(|language| (*path_id, language))
+        }).collect()
+    }
+}
+
 pub(crate) struct SnapshotCloneInfo {
     original : ProjectId,
     oldest_commit_time : Timestamp,
@@ -961,6 +1290,7 @@ pub(crate) struct SnapshotProjectsExtractor {}
 impl MapExtractor for SnapshotProjectsExtractor {
     type Key = SnapshotId;
     type Value = (usize, ProjectId);
+    const NAME: &'static str = "SnapshotProjectsExtractor";
 }
 impl QuadrupleMapExtractor for SnapshotProjectsExtractor {
     type A = BTreeMap<CommitId, Vec<ChangeTuple>>;
@@ -1003,6 +1333,7 @@ pub(crate) struct ProjectCommitContributionsExtractor {}
 impl MapExtractor for ProjectCommitContributionsExtractor {
     type Key = ProjectId;
     type Value = Vec<(UserId, usize)>;
+    const NAME: &'static str = "ProjectCommitContributionsExtractor";
 }
 impl DoubleMapExtractor for ProjectCommitContributionsExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -1028,6 +1359,7 @@ pub(crate) struct ProjectChangeContributionsExtractor {}
 impl MapExtractor for ProjectChangeContributionsExtractor {
     type Key = ProjectId;
     type Value = Vec<(UserId, usize)>;
+    const NAME: &'static str = "ProjectChangeContributionsExtractor";
 }
 impl TripleMapExtractor for ProjectChangeContributionsExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -1059,6 +1391,7 @@ pub(crate) struct ProjectCumulativeContributionsExtractor {}
 impl MapExtractor for ProjectCumulativeContributionsExtractor {
     type Key = ProjectId;
     type Value = Vec<Percentage>;
+    const NAME: &'static str = "ProjectCumulativeContributionsExtractor";
 }
 impl SingleMapExtractor for ProjectCumulativeContributionsExtractor {
     type A = BTreeMap<ProjectId, Vec<(UserId, usize)>>;
@@ -1085,6 +1418,7 @@ pub(crate) struct ProjectUniqueFilesExtractor {}
 impl MapExtractor for ProjectUniqueFilesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectUniqueFilesExtractor";
 }
 
 impl TripleMapExtractor for ProjectUniqueFilesExtractor {
@@ -1124,6 +1458,7 @@ pub(crate) struct ProjectOriginalFilesExtractor {}
 impl MapExtractor for ProjectOriginalFilesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectOriginalFilesExtractor";
 }
 
 impl TripleMapExtractor for ProjectOriginalFilesExtractor {
@@ -1165,6 +1500,7 @@ pub(crate) struct ProjectImpactExtractor {}
 impl MapExtractor for ProjectImpactExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectImpactExtractor";
 }
 
 impl TripleMapExtractor for ProjectImpactExtractor {
@@ -1206,6 +1542,7 @@ pub(crate) struct ProjectFilesExtractor {}
 impl MapExtractor for ProjectFilesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectFilesExtractor";
 }
 impl DoubleMapExtractor for ProjectFilesExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
@@ -1230,28 +1567,23 @@ pub(crate) struct ProjectLanguagesExtractor {}
 impl MapExtractor for ProjectLanguagesExtractor {
     type Key = ProjectId;
     type Value = Vec<(Language,usize)>;
+    const NAME: &'static str = "ProjectLanguagesExtractor";
+    const VERSION: u32 = 2;
 }
 impl TripleMapExtractor for ProjectLanguagesExtractor {
     type A = BTreeMap<ProjectId, Vec<CommitId>>;
     type B = BTreeMap<CommitId, Vec<ChangeTuple>>;
-    type C = BTreeMap<PathId, Path>;
+    type C = BTreeMap<PathId, Language>;
 
-    fn extract (_: &Source, project_commits : &Self::A, commit_changes : &Self::B, paths : &Self::C) -> BTreeMap<ProjectId, Vec<(Language,usize)>> {
-        let mut cached_paths = BTreeMap::<PathId, Language>::new();
+    fn extract (_: &Source, project_commits : &Self::A, commit_changes : &Self::B, path_languages : &Self::C) -> BTreeMap<ProjectId, Vec<(Language,usize)>> {
         project_commits.iter().map(|(pid, commits)| {
             let mut languages = BTreeMap::<Language, usize>::new();
             for cid in commits {
                 if let Some(commits) = commit_changes.get(cid) {
                     for (path_id, hash) in commits {
                         if let Some(_) = hash {
-                            let lang = cached_paths.entry(*path_id).or_insert_with(|| {
-                                if let Some(language) = paths.get(path_id).unwrap().language() {
-                                    language
-                                } else {
-                                    Language::Other
-                                }
-                            });
-                            match languages.entry(*lang) {
+                            let lang = path_languages.get(path_id).cloned().unwrap_or(Language::Other);
+                            match languages.entry(lang) {
                                 Entry::Occupied(mut e) => { *e.get_mut() += 1; },
                                 Entry::Vacant(e) => { e.insert(1); },
                             }
@@ -1270,6 +1602,7 @@ pub(crate) struct ProjectMajorLanguageExtractor {}
 impl MapExtractor for ProjectMajorLanguageExtractor {
     type Key = ProjectId;
     type Value = Language;
+    const NAME: &'static str = "ProjectMajorLanguageExtractor";
 }
 impl SingleMapExtractor for ProjectMajorLanguageExtractor {
     type A = BTreeMap<ProjectId, Vec<(Language, usize)>>;
@@ -1286,6 +1619,7 @@ pub(crate) struct ProjectMajorLanguageRatioExtractor {}
 impl MapExtractor for ProjectMajorLanguageRatioExtractor {
     type Key = ProjectId;
     type Value = f64;
+    const NAME: &'static str = "ProjectMajorLanguageRatioExtractor";
 }
 impl SingleMapExtractor for ProjectMajorLanguageRatioExtractor {
     type A = BTreeMap<ProjectId, Vec<(Language, usize)>>;
@@ -1302,6 +1636,7 @@ pub(crate) struct ProjectMajorLanguageChangesExtractor {}
 impl MapExtractor for ProjectMajorLanguageChangesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectMajorLanguageChangesExtractor";
 }
 impl SingleMapExtractor for ProjectMajorLanguageChangesExtractor {
     type A = BTreeMap<ProjectId, Vec<(Language, usize)>>;
@@ -1318,6 +1653,7 @@ pub(crate) struct ProjectLogsExtractor;
 impl MapExtractor for ProjectLogsExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "ProjectLogsExtractor";
 }
 impl SingleMapExtractor for ProjectLogsExtractor {
     type A = BTreeMap<ProjectId, bool>;
@@ -1337,6 +1673,7 @@ pub(crate) struct ProjectIsValidExtractor;
 impl MapExtractor for ProjectIsValidExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectIsValidExtractor";
 }
 impl SourceMapExtractor for ProjectIsValidExtractor{
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value> {
@@ -1353,6 +1690,7 @@ pub(crate) struct ProjectAllForksExtractor {}
 impl MapExtractor for ProjectAllForksExtractor {
     type Key = ProjectId;
     type Value = Vec<ProjectId>;
+    const NAME: &'static str = "ProjectAllForksExtractor";
 }
 
 impl TripleMapExtractor for ProjectAllForksExtractor {
@@ -1393,6 +1731,7 @@ pub(crate) struct ProjectHeadTreesExtractor {}
 impl MapExtractor for ProjectHeadTreesExtractor {
     type Key = ProjectId;
     type Value = Vec<(String, Vec<(PathId, SnapshotId)>)>;
+    const NAME: &'static str = "ProjectHeadTreesExtractor";
 }
 
 impl TripleMapExtractor for ProjectHeadTreesExtractor {
@@ -1432,6 +1771,7 @@ pub(crate) struct ProjectExperienceExtractor {}
 impl MapExtractor for ProjectExperienceExtractor {
     type Key = ProjectId;
     type Value = f64;
+    const NAME: &'static str = "ProjectExperienceExtractor";
 }
 impl TripleMapExtractor for ProjectExperienceExtractor {
     type A = BTreeMap<UserId, i32>;
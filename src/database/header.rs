@@ -0,0 +1,94 @@
+use serde::{Serialize, Deserialize};
+
+use crate::Timestamp;
+
+use super::persistent::MapExtractor;
+
+/*
+ * Bump this whenever the way caches are laid out on disk changes, or whenever
+ * what is read from the datastore changes for all caches at once (as when
+ * Source started hiding everything after the savepoint), so that all caches
+ * written by older versions of djanco are rebuilt.
+ */
+pub const CACHE_FORMAT_VERSION: u32 = 2;
+
+/*
+ * Describes where the contents of a cache file came from.
+ *
+ * Every persistent map writes a header in front of its contents. Before a
+ * cache is loaded, its header is compared with the header the map would be
+ * written with right now. If they differ, the cache is stale and it is
+ * rebuilt from the source instead.
+ *
+ * A header records the caches that its contents were derived from by name
+ * and by fingerprint. When one of those is rebuilt with a different header,
+ * its fingerprint changes, and so every cache derived from it becomes stale
+ * as well.
+ */
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CacheHeader {
+    pub format_version: u32,
+    pub extractor: String,
+    pub extractor_version: u32,
+    pub savepoint: Timestamp,
+    pub substores: Vec<String>,
+    pub prerequisites: Vec<(String, u64)>,
+}
+
+impl CacheHeader {
+    pub fn for_extractor<E>() -> Self where E: MapExtractor {
+        CacheHeader {
+            format_version: CACHE_FORMAT_VERSION,
+            extractor: E::NAME.to_owned(),
+            extractor_version: E::VERSION,
+            savepoint: 0,
+            substores: vec![],
+            prerequisites: vec![],
+        }
+    }
+    pub fn with_prerequisites(mut self, prerequisites: Vec<(String, u64)>) -> Self {
+        self.prerequisites = prerequisites;
+        self
+    }
+
+    /** A stable hash of the header.
+
+        This uses FNV-1a over the CBOR encoding of the header rather than `std::hash`, because the
+        fingerprints are written to disk and must not change between compiler versions.
+     */
+    pub fn fingerprint(&self) -> u64 {
+        let bytes = serde_cbor::to_vec(self).expect("Cannot serialize cache header");
+        bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /** Explains why a cache with this header cannot be used where the `expected` header is needed.
+
+        Returns None if the headers match.
+     */
+    pub fn mismatch(&self, expected: &CacheHeader) -> Option<String> {
+        if self.format_version != expected.format_version {
+            return Some(format!("cache format version is {}, expected {}", self.format_version, expected.format_version))
+        }
+        if self.extractor != expected.extractor {
+            return Some(format!("written by extractor {}, expected {}", self.extractor, expected.extractor))
+        }
+        if self.extractor_version != expected.extractor_version {
+            return Some(format!("extractor version is {}, expected {}", self.extractor_version, expected.extractor_version))
+        }
+        if self.savepoint != expected.savepoint {
+            return Some(format!("savepoint is {}, expected {}", self.savepoint, expected.savepoint))
+        }
+        if self.substores != expected.substores {
+            return Some(format!("substores are {}, expected {}", self.substores.join(","), expected.substores.join(",")))
+        }
+        if self.prerequisites != expected.prerequisites {
+            let names = |prerequisites: &Vec<(String, u64)>| {
+                prerequisites.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>().join(",")
+            };
+            return Some(format!("derived from different versions of {}", names(&expected.prerequisites)))
+        }
+        None
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use super::persistent::{Persistent, write_cache_file};
+
+/*
+ * An on-disk map format that can be queried without loading the whole map.
+ *
+ * The file consists of a header, an index, and a data section:
+ *
+ * ```
+ * header: magic (8B) | format version (u32) | number of entries (u64)
+ *         | metadata length (u64) | metadata
+ * index:  entry 0 | entry 1 | ... | entry n-1
+ * data:   key 0 | key 1 | ... | key n-1 | value 0 | value 1 | ... | value n-1
+ * ```
+ *
+ * Each index entry is four u64s: the offset and length of the key and the
+ * offset and length of the value, where offsets are counted from the start of
+ * the file. Keys and values are each encoded separately as CBOR. All numbers
+ * are little endian. The metadata is an arbitrary CBOR-encoded value that
+ * describes the contents of the file, and can be read without reading them.
+ *
+ * Entries are written in the order of the keys (straight out of a BTreeMap),
+ * so finding a key is a binary search over the index that decodes O(log n)
+ * keys and then a single value. The file is memory-mapped, so only the pages
+ * that are actually touched are read from disk.
+ */
+pub static INDEXED_EXTENSION: &str = "index";
+
+const MAGIC: &[u8; 8] = b"DJANCOIX";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_SIZE: usize = 8 + 4 + 8 + 8;
+const INDEX_ENTRY_SIZE: usize = 4 * 8;
+
+pub fn write_indexed_map<M, K, V, P>(path: P, metadata: &M, map: &BTreeMap<K, V>) -> Result<(), Box<dyn Error>>
+    where M: Persistent, K: Persistent, V: Persistent, P: AsRef<Path> {
+
+    let metadata = serde_cbor::to_vec(metadata)?;
+
+    let keys: Vec<Vec<u8>> = map.keys()
+        .map(|key| serde_cbor::to_vec(key))
+        .collect::<Result<Vec<Vec<u8>>, serde_cbor::Error>>()?;
+
+    // Values can be big, so they are encoded twice (once to measure, once to write) instead of
+    // keeping all of their encodings in memory at once.
+    let value_lengths: Vec<u64> = map.values()
+        .map(|value| serde_cbor::to_vec(value).map(|bytes| bytes.len() as u64))
+        .collect::<Result<Vec<u64>, serde_cbor::Error>>()?;
+
+    write_cache_file(path, |writer| {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(map.len() as u64).to_le_bytes())?;
+        writer.write_all(&(metadata.len() as u64).to_le_bytes())?;
+        writer.write_all(&metadata)?;
+
+        let data_start = (HEADER_SIZE + metadata.len() + map.len() * INDEX_ENTRY_SIZE) as u64;
+        let keys_size: u64 = keys.iter().map(|key| key.len() as u64).sum();
+        let mut key_offset = data_start;
+        let mut value_offset = data_start + keys_size;
+        for (key, value_length) in keys.iter().zip(value_lengths.iter()) {
+            writer.write_all(&key_offset.to_le_bytes())?;
+            writer.write_all(&(key.len() as u64).to_le_bytes())?;
+            writer.write_all(&value_offset.to_le_bytes())?;
+            writer.write_all(&value_length.to_le_bytes())?;
+            key_offset += key.len() as u64;
+            value_offset += value_length;
+        }
+
+        for key in keys.iter() {
+            writer.write_all(key)?;
+        }
+        for value in map.values() {
+            serde_cbor::to_writer(&mut *writer, value)?;
+        }
+        Ok(())
+    })
+}
+
+pub struct IndexedMap<K, V> {
+    mmap: Mmap,
+    len: usize,
+    index_start: usize,
+    types: PhantomData<(K, V)>,
+}
+
+impl<K, V> IndexedMap<K, V> where K: Persistent + Ord, V: Persistent {
+    pub fn open<P>(path: P) -> Result<Self, Box<dyn Error>> where P: AsRef<Path> {
+        let file = File::open(path.as_ref())?;
+        let mmap = unsafe { Mmap::map(&file)? }; // Sound as long as the file is replaced rather than modified (see `write_cache_file`).
+
+        if mmap.len() < HEADER_SIZE || &mmap[0..8] != MAGIC {
+            return Err(format!("{:?} is not an indexed cache file", path.as_ref()).into())
+        }
+        let version = u32::from_le_bytes(mmap[8..12].try_into()?);
+        if version != FORMAT_VERSION {
+            return Err(format!("{:?} has format version {}, expected {}", path.as_ref(), version, FORMAT_VERSION).into())
+        }
+        let len = u64::from_le_bytes(mmap[12..20].try_into()?) as usize;
+        let metadata_len = u64::from_le_bytes(mmap[20..28].try_into()?) as usize;
+        // The lengths come from the file, so a damaged file could make these overflow.
+        let index_start = HEADER_SIZE.checked_add(metadata_len);
+        let index_end = index_start.and_then(|start| len.checked_mul(INDEX_ENTRY_SIZE)?.checked_add(start));
+        let index_start = match (index_start, index_end) {
+            (Some(start), Some(end)) if end <= mmap.len() => start,
+            _ => return Err(format!("{:?} is truncated", path.as_ref()).into()),
+        };
+
+        Ok(IndexedMap { mmap, len, index_start, types: PhantomData })
+    }
+
+    pub fn metadata<M>(&self) -> Result<M, serde_cbor::Error> where M: Persistent {
+        serde_cbor::from_slice(&self.mmap[HEADER_SIZE..self.index_start])
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    fn read_u64(&self, offset: usize) -> usize {
+        let bytes: [u8; 8] = self.mmap[offset..offset + 8].try_into().unwrap();
+        u64::from_le_bytes(bytes) as usize
+    }
+    /* The index itself was checked to fit into the file when it was opened, but the offsets and
+       lengths in it still have to be checked before they are used. */
+    fn slice(&self, entry: usize, field: usize) -> Result<&[u8], Box<dyn Error>> {
+        let start = self.index_start + entry * INDEX_ENTRY_SIZE + field * 16;
+        let offset = self.read_u64(start);
+        let length = self.read_u64(start + 8);
+        match offset.checked_add(length) {
+            Some(end) if end <= self.mmap.len() => Ok(&self.mmap[offset..end]),
+            _ => Err(format!("entry {} of the index points past the end of the file ({}+{} > {})",
+                             entry, offset, length, self.mmap.len()).into()),
+        }
+    }
+    fn key_at(&self, entry: usize) -> Result<K, Box<dyn Error>> {
+        Ok(serde_cbor::from_slice(self.slice(entry, 0)?)?)
+    }
+    fn value_at(&self, entry: usize) -> Result<V, Box<dyn Error>> {
+        Ok(serde_cbor::from_slice(self.slice(entry, 1)?)?)
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<V>, Box<dyn Error>> {
+        let mut low = 0usize;
+        let mut high = self.len;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.key_at(middle)?.cmp(key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return self.value_at(middle).map(Some),
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=Result<(K, V), Box<dyn Error>>> + 'a {
+        (0..self.len).map(move |entry| {
+            Ok((self.key_at(entry)?, self.value_at(entry)?))
+        })
+    }
+
+    pub fn to_map(&self) -> Result<BTreeMap<K, V>, Box<dyn Error>> {
+        self.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::fs::{OpenOptions, metadata, read_dir};
+
+    use super::{IndexedMap, write_indexed_map, HEADER_SIZE, INDEX_ENTRY_SIZE};
+
+    fn example() -> BTreeMap<u64, String> {
+        vec![(1, "one"), (2, "two"), (3, "three"), (5, "five")].into_iter()
+            .map(|(key, value)| (key, value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("example.index");
+        write_indexed_map(&path, &"metadata".to_owned(), &example()).unwrap();
+
+        let map: IndexedMap<u64, String> = IndexedMap::open(&path).unwrap();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.metadata::<String>().unwrap(), "metadata");
+        assert_eq!(map.get(&3).unwrap(), Some("three".to_owned()));
+        assert_eq!(map.get(&4).unwrap(), None);
+        assert_eq!(map.to_map().unwrap(), example());
+
+        let path = dir.path().join("empty.index");
+        write_indexed_map(&path, &(), &BTreeMap::<u64, String>::new()).unwrap();
+        let map: IndexedMap<u64, String> = IndexedMap::open(&path).unwrap();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&1).unwrap(), None);
+    }
+
+    #[test]
+    fn rewriting_leaves_mapped_file_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("example.index");
+        write_indexed_map(&path, &(), &example()).unwrap();
+        let old: IndexedMap<u64, String> = IndexedMap::open(&path).unwrap();
+
+        let mut changed = example();
+        changed.insert(3, "a much longer value than the one before".to_owned());
+        write_indexed_map(&path, &(), &changed).unwrap();
+
+        assert_eq!(old.to_map().unwrap(), example());
+        assert_eq!(IndexedMap::<u64, String>::open(&path).unwrap().to_map().unwrap(), changed);
+        assert_eq!(read_dir(dir.path()).unwrap().count(), 1, "no temporary files are left behind");
+    }
+
+    #[test]
+    fn truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("example.index");
+        write_indexed_map(&path, &(), &example()).unwrap();
+        let size = metadata(&path).unwrap().len();
+        let truncate = |size: u64| OpenOptions::new().write(true).open(&path).unwrap().set_len(size).unwrap();
+
+        // Losing the end of the last value leaves the index intact, but not the value.
+        truncate(size - 1);
+        let map: IndexedMap<u64, String> = IndexedMap::open(&path).unwrap();
+        assert_eq!(map.get(&1).unwrap(), Some("one".to_owned()));
+        assert!(map.get(&5).is_err());
+        assert!(map.to_map().is_err());
+        drop(map);
+
+        // Losing part of the index is noticed straight away.
+        let metadata_size = serde_cbor::to_vec(&()).unwrap().len();
+        truncate((HEADER_SIZE + metadata_size + 2 * INDEX_ENTRY_SIZE) as u64);
+        assert!(IndexedMap::<u64, String>::open(&path).is_err());
+
+        truncate(4);
+        assert!(IndexedMap::<u64, String>::open(&path).is_err());
+    }
+}
@@ -0,0 +1,886 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, read_dir, create_dir_all};
+use std::path::Path as FilePath;
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JSON;
+
+use parasite::StoreKind;
+
+use crate::Store;
+use crate::objects::*;
+
+use super::source::*;
+use super::source::Path; // not objects::Path
+
+/*
+ * A data source that keeps everything in memory, so that tests and examples
+ * can run queries without a datastore.
+ *
+ * It is either put together by hand, eg:
+ *
+ * ```
+ * let mut source = InMemorySource::new();
+ * let user = source.add_user("a@b.c");
+ * let path = source.add_path("src/main.rs");
+ * let snapshot = source.add_snapshot("fn main() {}");
+ * let commit = source.add_commit("5a1d...", CommitBasics { author: user, committer: user, ... });
+ * let project = source.add_project("https://github.com/a/b.git");
+ * source.set_head(project, "master", commit);
+ * ```
+ *
+ * or read from a directory of small files (see `from_dir`). Objects added by
+ * hand are numbered in the order they are added. A subset of another data
+ * source can be written out into such a directory (see `subset` and
+ * `write_dir`), which is how datasets are exported.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct InMemorySource {
+    projects: BTreeMap<ProjectId, InMemoryProject>,
+    commits: BTreeMap<CommitId, (SHA, CommitBasics)>,
+    commit_metadata: BTreeMap<CommitId, JSON>,
+    users: BTreeMap<UserId, String>,
+    user_metadata: BTreeMap<UserId, JSON>,
+    paths: BTreeMap<PathId, Path>,
+    snapshots: BTreeMap<SnapshotId, Bytes>,
+}
+
+#[derive(Clone, Debug)]
+struct InMemoryProject {
+    url: URL,
+    substore: Store,
+    heads: HashMap<String, CommitId>,
+    logs: Vec<ProjectLog>,
+    metadata: Option<JSON>,
+}
+
+impl InMemoryProject {
+    fn new(url: URL) -> Self {
+        InMemoryProject { url, substore: Store::Generic, heads: HashMap::new(), logs: vec![], metadata: None }
+    }
+}
+
+macro_rules! next_id {
+    ($map:expr, $type:ident) => {
+        $map.keys().last().map_or($type(0), |id| $type(id.0 + 1))
+    }
+}
+
+impl InMemorySource {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add_user<S>(&mut self, email: S) -> UserId where S: Into<String> {
+        let id = next_id!(self.users, UserId);
+        self.users.insert(id, email.into());
+        id
+    }
+    pub fn add_path<S>(&mut self, path: S) -> PathId where S: Into<String> {
+        let id = next_id!(self.paths, PathId);
+        self.paths.insert(id, path.into());
+        id
+    }
+    pub fn add_snapshot<B>(&mut self, contents: B) -> SnapshotId where B: Into<Bytes> {
+        let id = next_id!(self.snapshots, SnapshotId);
+        self.snapshots.insert(id, contents.into());
+        id
+    }
+    pub fn add_commit<S>(&mut self, hash: S, commit: CommitBasics) -> CommitId where S: Into<String> {
+        let id = next_id!(self.commits, CommitId);
+        self.commits.insert(id, (hash.into(), commit));
+        id
+    }
+    /** Adds a project along with an entry in its log at time 0, so that it is visible at any savepoint. */
+    pub fn add_project<S>(&mut self, url: S) -> ProjectId where S: Into<String> {
+        self.add_project_at(url, 0)
+    }
+    /** Adds a project that was first logged at the given time, so that it is hidden at earlier savepoints. */
+    pub fn add_project_at<S>(&mut self, url: S, time: Timestamp) -> ProjectId where S: Into<String> {
+        let id = next_id!(self.projects, ProjectId);
+        let mut project = InMemoryProject::new(url.into());
+        project.logs.push(ProjectLog::new(time, false));
+        self.projects.insert(id, project);
+        id
+    }
+
+    fn project_mut(&mut self, project: ProjectId) -> &mut InMemoryProject {
+        self.projects.get_mut(&project).unwrap_or_else(|| panic!("There is no project {}", project))
+    }
+    pub fn set_substore(&mut self, project: ProjectId, substore: Store) {
+        self.project_mut(project).substore = substore;
+    }
+    pub fn set_head<S>(&mut self, project: ProjectId, name: S, commit: CommitId) where S: Into<String> {
+        self.project_mut(project).heads.insert(name.into(), commit);
+    }
+    pub fn add_project_log(&mut self, project: ProjectId, time: Timestamp, error: bool) {
+        self.project_mut(project).logs.push(ProjectLog::new(time, error));
+    }
+    /** Logs an update of the project that found it with the heads it has now. At savepoints after
+        the update (and before the next one), the project has these heads. */
+    pub fn log_heads(&mut self, project: ProjectId, time: Timestamp) {
+        let heads = self.projects[&project].heads.iter().map(|(name, commit_id)| {
+            let hash = self.commits.get(commit_id).map_or(String::new(), |(hash, _)| hash.clone());
+            (name.clone(), hash)
+        }).collect();
+        self.project_mut(project).logs.push(ProjectLog::update(time, heads));
+    }
+    pub fn set_project_metadata(&mut self, project: ProjectId, metadata: JSON) {
+        self.project_mut(project).metadata = Some(metadata);
+    }
+    pub fn set_commit_metadata(&mut self, commit: CommitId, metadata: JSON) {
+        self.commit_metadata.insert(commit, metadata);
+    }
+    pub fn set_user_metadata(&mut self, user: UserId, metadata: JSON) {
+        self.user_metadata.insert(user, metadata);
+    }
+}
+
+#[derive(Deserialize, Serialize)] struct UserRecord    { id: u64, email: String }
+#[derive(Deserialize, Serialize)] struct PathRecord    { id: u64, path: String }
+#[derive(Deserialize, Serialize)] struct ProjectRecord { id: u64, url: String, substore: Option<String> }
+#[derive(Deserialize, Serialize)] struct HeadRecord    { project: u64, name: String, commit: u64 }
+#[derive(Deserialize, Serialize)] struct LogRecord     { project: u64, time: Timestamp, error: bool }
+#[derive(Deserialize, Serialize)] struct ChangeRecord  { commit: u64, path: u64, snapshot: Option<u64> }
+#[derive(Deserialize, Serialize)]
+struct CommitRecord {
+    id: u64,
+    hash: String,
+    author: u64,
+    committer: u64,
+    author_time: Timestamp,
+    committer_time: Timestamp,
+    parents: String,
+    message: String,
+}
+
+/* Reads all records from a CSV file, or none if there is no such file. */
+fn read_csv<R>(dir: &FilePath, name: &str) -> Result<Vec<R>> where R: serde::de::DeserializeOwned {
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Ok(vec![])
+    }
+    csv::Reader::from_path(&path)
+        .with_context(|| format!("Cannot open {:?}", path))?
+        .deserialize()
+        .collect::<std::result::Result<Vec<R>, csv::Error>>()
+        .with_context(|| format!("Cannot read {:?}", path))
+}
+
+/* Like `Store::from`, but without panicking on names that are not stores. */
+fn parse_store(name: &str) -> Option<Store> {
+    if name.to_lowercase() == "generic" {
+        return Some(Store::Generic)
+    }
+    StoreKind::from_string(name)
+        .filter(|kind| !matches!(kind, StoreKind::Unspecified))
+        .map(Store::from)
+}
+
+/* Writes all records into a CSV file, with a header even if there are none. */
+fn write_csv<R>(dir: &FilePath, name: &str, headers: &[&str], records: impl Iterator<Item=R>) -> Result<()> where R: Serialize {
+    let path = dir.join(name);
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_path(&path)
+        .with_context(|| format!("Cannot create {:?}", path))?;
+    writer.write_record(headers)?;
+    for record in records {
+        writer.serialize(record).with_context(|| format!("Cannot write to {:?}", path))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/* Writes a JSON object that maps ids to metadata. */
+fn write_metadata<'a>(dir: &FilePath, name: &str, metadata: impl Iterator<Item=(u64, &'a JSON)>) -> Result<()> {
+    let path = dir.join(name);
+    let metadata: BTreeMap<String, &JSON> = metadata.map(|(id, value)| (id.to_string(), value)).collect();
+    serde_json::to_writer(File::create(&path)?, &metadata)
+        .with_context(|| format!("Cannot write to {:?}", path))
+}
+
+/* Reads a JSON object that maps ids to metadata, or nothing if there is no such file. */
+fn read_metadata(dir: &FilePath, name: &str) -> Result<BTreeMap<u64, JSON>> {
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Ok(BTreeMap::new())
+    }
+    let metadata: BTreeMap<String, JSON> = serde_json::from_reader(File::open(&path)?)
+        .with_context(|| format!("Cannot read {:?}", path))?;
+    metadata.into_iter().map(|(id, value)| {
+        id.parse::<u64>().map(|id| (id, value))
+            .with_context(|| format!("{} is not an id in {:?}", id, path))
+    }).collect()
+}
+
+/* The file that tells a directory written by `write_dir` apart from a parasite datastore. */
+pub const DATASET_MARKER_FILE_NAME: &'static str = "djanco-dataset";
+
+impl InMemorySource {
+    /** Reads a data source from a directory of CSV and JSON files. All of them are optional.
+
+        - `users.csv`: `id,email`
+        - `paths.csv`: `id,path`
+        - `commits.csv`: `id,hash,author,committer,author_time,committer_time,parents,message`,
+           where parents are commit ids separated by spaces
+        - `changes.csv`: `commit,path,snapshot`, where the snapshot is empty if the file was deleted
+        - `projects.csv`: `id,url,substore`, where the substore can be empty
+        - `heads.csv`: `project,name,commit`
+        - `logs.csv`: `project,time,error`; projects without entries get one at time 0. The logs do
+           not record heads, so the heads in `heads.csv` are used at any savepoint
+        - `snapshots/<id>`: the contents of each snapshot
+        - `project_metadata.json`, `commit_metadata.json`, `user_metadata.json`: objects that map
+           ids to GitHub metadata
+        - `djanco-dataset`: present in directories written by `write_dir`, so that `Source::new`
+           reads them rather than expecting a datastore
+     */
+    pub fn from_dir<P>(dir: P) -> Result<Self> where P: AsRef<FilePath> {
+        let dir = dir.as_ref();
+        let mut source = InMemorySource::new();
+
+        for record in read_csv::<UserRecord>(dir, "users.csv")? {
+            source.users.insert(UserId(record.id), record.email);
+        }
+        for record in read_csv::<PathRecord>(dir, "paths.csv")? {
+            source.paths.insert(PathId(record.id), record.path);
+        }
+        for record in read_csv::<CommitRecord>(dir, "commits.csv")? {
+            let parents = record.parents.split_whitespace()
+                .map(|parent| parent.parse::<u64>().map(CommitId))
+                .collect::<std::result::Result<Vec<CommitId>, _>>()
+                .with_context(|| format!("Cannot read the parents of commit {}", record.id))?;
+            let commit = CommitBasics {
+                committer: UserId(record.committer),
+                committer_time: record.committer_time,
+                author: UserId(record.author),
+                author_time: record.author_time,
+                parents,
+                changes: vec![],
+                message: record.message,
+            };
+            source.commits.insert(CommitId(record.id), (record.hash, commit));
+        }
+        for record in read_csv::<ChangeRecord>(dir, "changes.csv")? {
+            let (_, commit) = source.commits.get_mut(&CommitId(record.commit))
+                .with_context(|| format!("changes.csv refers to commit {}, which is not in commits.csv", record.commit))?;
+            commit.changes.push((PathId(record.path), record.snapshot.map(SnapshotId)));
+        }
+        for (row, record) in read_csv::<ProjectRecord>(dir, "projects.csv")?.into_iter().enumerate() {
+            let mut project = InMemoryProject::new(record.url);
+            if let Some(substore) = record.substore {
+                // Rows are counted from 1, and the header is the first one.
+                project.substore = parse_store(&substore)
+                    .with_context(|| format!("projects.csv, row {}: `{}` is not a valid substore", row + 2, substore))?;
+            }
+            source.projects.insert(ProjectId(record.id), project);
+        }
+        for record in read_csv::<HeadRecord>(dir, "heads.csv")? {
+            let project = source.projects.get_mut(&ProjectId(record.project))
+                .with_context(|| format!("heads.csv refers to project {}, which is not in projects.csv", record.project))?;
+            project.heads.insert(record.name, CommitId(record.commit));
+        }
+        for record in read_csv::<LogRecord>(dir, "logs.csv")? {
+            let project = source.projects.get_mut(&ProjectId(record.project))
+                .with_context(|| format!("logs.csv refers to project {}, which is not in projects.csv", record.project))?;
+            project.logs.push(ProjectLog::new(record.time, record.error));
+        }
+        for project in source.projects.values_mut().filter(|project| project.logs.is_empty()) {
+            project.logs.push(ProjectLog::new(0, false));
+        }
+
+        let snapshot_dir = dir.join("snapshots");
+        if snapshot_dir.is_dir() {
+            for entry in read_dir(&snapshot_dir)? {
+                let path = entry?.path();
+                let id = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.parse::<u64>().ok())
+                    .with_context(|| format!("{:?} should be named after the id of the snapshot", path))?;
+                source.snapshots.insert(SnapshotId(id), std::fs::read(&path)?);
+            }
+        }
+
+        for (id, metadata) in read_metadata(dir, "project_metadata.json")? {
+            let project = source.projects.get_mut(&ProjectId(id))
+                .with_context(|| format!("project_metadata.json refers to project {}, which is not in projects.csv", id))?;
+            project.metadata = Some(metadata);
+        }
+        source.commit_metadata = read_metadata(dir, "commit_metadata.json")?.into_iter()
+            .map(|(id, metadata)| (CommitId(id), metadata)).collect();
+        source.user_metadata = read_metadata(dir, "user_metadata.json")?.into_iter()
+            .map(|(id, metadata)| (UserId(id), metadata)).collect();
+
+        Ok(source)
+    }
+}
+
+impl InMemorySource {
+    /** Copies the given projects and commits out of a data source, along with the users, paths, and
+        snapshots of those commits, and all their metadata. Ids stay the same. Heads that point to
+        commits that are not copied are left out.
+     */
+    pub fn subset(source: &Source, projects: &BTreeSet<ProjectId>, commits: &BTreeSet<CommitId>) -> Self {
+        let mut subset = InMemorySource::new();
+
+        let mut hashes: BTreeMap<CommitId, SHA> = source.commit_hashes()
+            .filter(|(id, _)| commits.contains(id))
+            .collect();
+        for (id, commit) in source.commit_info().filter(|(id, _)| commits.contains(id)) {
+            let hash = hashes.remove(&id).unwrap_or_default();
+            subset.commits.insert(id, (hash, commit));
+        }
+
+        let users: BTreeSet<UserId> = subset.commits.values()
+            .flat_map(|(_, commit)| vec![commit.author, commit.committer])
+            .collect();
+        let paths: BTreeSet<PathId> = subset.commits.values()
+            .flat_map(|(_, commit)| commit.changes.iter().map(|(path_id, _)| *path_id))
+            .collect();
+        let snapshots: BTreeSet<SnapshotId> = subset.commits.values()
+            .flat_map(|(_, commit)| commit.changes.iter().flat_map(|(_, snapshot_id)| *snapshot_id))
+            .collect();
+
+        subset.users = source.user_emails().filter(|(id, _)| users.contains(id)).collect();
+        subset.paths = source.paths().filter(|(id, _)| paths.contains(id)).collect();
+        for id in snapshots {
+            if let Some(contents) = source.get_snapshot(id) {
+                subset.snapshots.insert(id, contents);
+            }
+        }
+
+        subset.projects = source.project_urls()
+            .filter(|(id, _)| projects.contains(id))
+            .map(|(id, url)| (id, InMemoryProject::new(url)))
+            .collect();
+        for (id, substore) in source.project_substores() {
+            if let Some(project) = subset.projects.get_mut(&id) {
+                project.substore = substore;
+            }
+        }
+        for (id, heads) in source.project_heads() {
+            if let Some(project) = subset.projects.get_mut(&id) {
+                project.heads = heads.into_iter()
+                    .filter(|(_, (commit_id, _))| commits.contains(commit_id))
+                    .map(|(name, (commit_id, _))| (name, commit_id))
+                    .collect();
+            }
+        }
+        for (id, logs) in source.project_logs() {
+            if let Some(project) = subset.projects.get_mut(&id) {
+                project.logs = logs;
+            }
+        }
+        for (id, metadata) in source.project_github_metadata() {
+            if let (Some(project), Ok(metadata)) = (subset.projects.get_mut(&id), metadata) {
+                project.metadata = Some(metadata);
+            }
+        }
+
+        subset.commit_metadata = source.commit_github_metadata()
+            .filter(|(id, _)| commits.contains(id))
+            .flat_map(|(id, metadata)| metadata.ok().map(|metadata| (id, metadata)))
+            .collect();
+        subset.user_metadata = source.user_metadata()
+            .filter(|(id, _)| users.contains(id))
+            .flat_map(|(id, metadata)| metadata.ok().map(|metadata| (id, metadata)))
+            .collect();
+
+        subset
+    }
+
+    /** Writes the data source into a directory, in the format that `from_dir` reads. */
+    pub fn write_dir<P>(&self, dir: P) -> Result<()> where P: AsRef<FilePath> {
+        let dir = dir.as_ref();
+        create_dir_all(dir).with_context(|| format!("Cannot create directory {:?}", dir))?;
+
+        write_csv(dir, "users.csv", &["id", "email"], self.users.iter().map(|(id, email)| {
+            UserRecord { id: id.0, email: email.clone() }
+        }))?;
+        write_csv(dir, "paths.csv", &["id", "path"], self.paths.iter().map(|(id, path)| {
+            PathRecord { id: id.0, path: path.clone() }
+        }))?;
+        write_csv(dir, "commits.csv",
+                  &["id", "hash", "author", "committer", "author_time", "committer_time", "parents", "message"],
+                  self.commits.iter().map(|(id, (hash, commit))| CommitRecord {
+                      id: id.0,
+                      hash: hash.clone(),
+                      author: commit.author.0,
+                      committer: commit.committer.0,
+                      author_time: commit.author_time,
+                      committer_time: commit.committer_time,
+                      parents: commit.parents.iter().map(|parent| parent.0.to_string()).collect::<Vec<String>>().join(" "),
+                      message: commit.message.clone(),
+                  }))?;
+        write_csv(dir, "changes.csv", &["commit", "path", "snapshot"], self.commits.iter().flat_map(|(id, (_, commit))| {
+            commit.changes.iter().map(move |(path, snapshot)| {
+                ChangeRecord { commit: id.0, path: path.0, snapshot: snapshot.map(|snapshot| snapshot.0) }
+            })
+        }))?;
+        write_csv(dir, "projects.csv", &["id", "url", "substore"], self.projects.iter().map(|(id, project)| {
+            ProjectRecord { id: id.0, url: project.url.clone(), substore: Some(project.substore.to_string()) }
+        }))?;
+        write_csv(dir, "heads.csv", &["project", "name", "commit"], self.projects.iter().flat_map(|(id, project)| {
+            project.heads.iter().map(move |(name, commit)| {
+                HeadRecord { project: id.0, name: name.clone(), commit: commit.0 }
+            })
+        }))?;
+        write_csv(dir, "logs.csv", &["project", "time", "error"], self.projects.iter().flat_map(|(id, project)| {
+            project.logs.iter().map(move |log| {
+                LogRecord { project: id.0, time: log.time(), error: log.is_error() }
+            })
+        }))?;
+
+        let snapshot_dir = dir.join("snapshots");
+        create_dir_all(&snapshot_dir)?;
+        for (id, contents) in self.snapshots.iter() {
+            std::fs::write(snapshot_dir.join(id.0.to_string()), contents)?;
+        }
+
+        write_metadata(dir, "project_metadata.json", self.projects.iter().flat_map(|(id, project)| {
+            project.metadata.as_ref().map(|metadata| (id.0, metadata))
+        }))?;
+        write_metadata(dir, "commit_metadata.json", self.commit_metadata.iter().map(|(id, metadata)| (id.0, metadata)))?;
+        write_metadata(dir, "user_metadata.json", self.user_metadata.iter().map(|(id, metadata)| (id.0, metadata)))?;
+
+        std::fs::write(dir.join(DATASET_MARKER_FILE_NAME), "")?;
+        Ok(())
+    }
+
+    /** Checks whether a directory was written by `write_dir`. */
+    pub fn is_dataset_dir<P>(dir: P) -> bool where P: AsRef<FilePath> {
+        dir.as_ref().join(DATASET_MARKER_FILE_NAME).is_file()
+    }
+}
+
+impl DataSource for InMemorySource {
+    fn project_logs<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, Vec<ProjectLog>)> + 'a> {
+        Box::new(self.projects.iter()
+            .filter(|(_, project)| !project.logs.is_empty())
+            .map(|(id, project)| (*id, project.logs.clone())))
+    }
+    fn project_urls<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, URL)> + 'a> {
+        Box::new(self.projects.iter().map(|(id, project)| (*id, project.url.clone())))
+    }
+    fn project_substores<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, Store)> + 'a> {
+        Box::new(self.projects.iter().map(|(id, project)| (*id, project.substore)))
+    }
+    fn project_credentials<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, String)> + 'a> {
+        Box::new(self.projects.iter().map(|(id, project)| (*id, project.url.clone())))
+    }
+    fn project_heads<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, HashMap<String, (CommitId, SHA)>)> + 'a> {
+        Box::new(self.projects.iter().map(move |(id, project)| {
+            let heads = project.heads.iter().map(|(name, commit_id)| {
+                let hash = self.commits.get(commit_id).map_or(String::new(), |(hash, _)| hash.clone());
+                (name.clone(), (*commit_id, hash))
+            }).collect();
+            (*id, heads)
+        }))
+    }
+    fn project_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(ProjectId, Result<JSON>)> + 'a> {
+        Box::new(self.projects.iter().flat_map(|(id, project)| {
+            project.metadata.as_ref().map(|metadata| (*id, Ok(metadata.clone())))
+        }))
+    }
+
+    fn commit_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(CommitId, SHA)> + 'a> {
+        Box::new(self.commits.iter().map(|(id, (hash, _))| (*id, hash.clone())))
+    }
+    fn commit_info<'a>(&'a self) -> Box<dyn Iterator<Item=(CommitId, CommitBasics)> + 'a> {
+        Box::new(self.commits.iter().map(|(id, (_, commit))| (*id, commit.clone())))
+    }
+    fn commit_info_of<'a>(&'a self, ids: &'a BTreeSet<CommitId>) -> Box<dyn Iterator<Item=(CommitId, CommitBasics)> + 'a> {
+        Box::new(ids.iter().flat_map(move |id| self.commits.get(id).map(|(_, commit)| (*id, commit.clone()))))
+    }
+    fn commit_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(CommitId, Result<JSON>)> + 'a> {
+        Box::new(self.commit_metadata.iter().map(|(id, metadata)| (*id, Ok(metadata.clone()))))
+    }
+
+    fn get_snapshot(&self, id: SnapshotId) -> Option<Bytes> {
+        self.snapshots.get(&id).cloned()
+    }
+    fn has_snapshot(&self, id: SnapshotId) -> bool {
+        self.snapshots.contains_key(&id)
+    }
+    fn snapshot_bytes<'a>(&'a self) -> Box<dyn Iterator<Item=(SnapshotId, Bytes)> + 'a> {
+        Box::new(self.snapshots.iter().map(|(id, contents)| (*id, contents.clone())))
+    }
+    fn snapshot_has_contents<'a>(&'a self) -> Box<dyn Iterator<Item=SnapshotId> + 'a> {
+        Box::new(self.snapshots.keys().copied())
+    }
+    fn snapshot_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(SnapshotId, Result<JSON>)> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn path_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(PathId, SHA)> + 'a> {
+        Box::new(std::iter::empty())
+    }
+    fn paths<'a>(&'a self) -> Box<dyn Iterator<Item=(PathId, Path)> + 'a> {
+        Box::new(self.paths.iter().map(|(id, path)| (*id, path.clone())))
+    }
+
+    fn user_emails<'a>(&'a self) -> Box<dyn Iterator<Item=(UserId, String)> + 'a> {
+        Box::new(self.users.iter().map(|(id, email)| (*id, email.clone())))
+    }
+    fn user_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(UserId, Result<JSON>)> + 'a> {
+        Box::new(self.user_metadata.iter().map(|(id, metadata)| (*id, Ok(metadata.clone()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs::{write, create_dir, read, read_dir, File};
+    use std::path::{Path as FilePath, PathBuf};
+
+    use serde::Deserialize;
+
+    use crate::{Djanco, CacheDir, AttributeIterator, Count, AtLeast, project};
+    use crate::log::{Log, Verbosity};
+    use crate::objects::*;
+    use crate::database::source::CommitBasics;
+    use crate::database::header::CacheHeader;
+    use crate::database::persistent::Persistent;
+    use crate::database::testing::{self, commit};
+
+    use super::InMemorySource;
+
+    /* One project with a line of three commits, committed at 100, 200, and 300. */
+    fn three_commits() -> (InMemorySource, ProjectId, Vec<CommitId>) {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        let first = source.add_commit("a", commit(user, 100, vec![]));
+        let second = source.add_commit("b", commit(user, 200, vec![first]));
+        let third = source.add_commit("c", commit(user, 300, vec![second]));
+        let project = source.add_project("https://github.com/example/example.git");
+        source.set_head(project, "master", third);
+        (source, project, vec![first, second, third])
+    }
+
+    #[test]
+    fn queries_in_memory() {
+        let (source, project, commits) = three_commits();
+        let database = testing::database(source).open();
+
+        assert_eq!(database.projects().count(), 1);
+        assert_eq!(database.project_commit_ids(&project), Some(commits));
+        assert_eq!(database.commits().count(), 3);
+    }
+
+    /* Parallel operators evaluate attributes from several threads at once, each of them loading
+       caches as needed, and should come to the same results as the sequential ones. */
+    #[test]
+    fn parallel_queries_match_sequential_ones() {
+        let (mut source, _, commits) = three_commits();
+        for (index, head) in [0, 2, 1, 2, 0].iter().enumerate() {
+            let project = source.add_project(format!("https://github.com/example/fork{}.git", index));
+            source.set_head(project, "master", commits[*head]);
+        }
+        let database = testing::database(source).open();
+
+        let sequential: Vec<Option<usize>> = database.projects().map_into(Count(project::Commits)).collect();
+        let parallel: Vec<Option<usize>> = database.projects().par_map_into(Count(project::Commits)).collect();
+        assert_eq!(parallel, sequential);
+
+        let sequential: Vec<ProjectId> = database.projects().filter_by(AtLeast(Count(project::Commits), 2)).map(|project| project.id()).collect();
+        let parallel: Vec<ProjectId> = database.projects().par_filter_by(AtLeast(Count(project::Commits), 2)).map(|project| project.id()).collect();
+        assert_eq!(parallel, sequential);
+
+        let sequential: Vec<ProjectId> = database.projects().sort_by(Count(project::Commits)).map(|project| project.id()).collect();
+        let parallel: Vec<ProjectId> = database.projects().par_sort_by(Count(project::Commits)).map(|project| project.id()).collect();
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn memory_budget_is_enforced_after_each_call() {
+        let (source, project, commits) = three_commits();
+        let database = testing::database(source).with_memory_budget(1).open();
+
+        // Counting commits loads the heads, the commits, and the commits of each project first, but
+        // only the collection used last stays in memory once the call returns.
+        assert_eq!(database.project_commit_count(&project), Some(3));
+        assert_eq!(database.loaded_caches(), vec!["project_commit_count"]);
+
+        assert_eq!(database.project_commit_ids(&project), Some(commits));
+        assert_eq!(database.loaded_caches(), vec!["project_commits"]);
+        assert_eq!(database.project_commit_count(&project), Some(3));
+    }
+
+    /* Finds the file a collection is cached in somewhere under the cache directory. */
+    fn cache_file(dir: &FilePath, name: &str) -> Option<PathBuf> {
+        read_dir(dir).unwrap().flatten().find_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                cache_file(&path, name)
+            } else if path.file_stem().map_or(false, |stem| stem == name) {
+                Some(path)
+            } else {
+                None
+            }
+        })
+    }
+
+    /* Rewrites the cache of a collection, and returns the fingerprint of its new header. */
+    fn rewrite_cache<K, V, F>(dir: &FilePath, name: &str, rewrite: F) -> u64
+        where K: Persistent + Ord, V: Persistent, F: FnOnce(&mut CacheHeader, &mut BTreeMap<K, V>) {
+        let path = cache_file(dir, name).unwrap();
+        let mut deserializer = serde_cbor::Deserializer::from_reader(File::open(&path).unwrap());
+        let mut header = CacheHeader::deserialize(&mut deserializer).unwrap();
+        let mut map = BTreeMap::<K, V>::deserialize(&mut deserializer).unwrap();
+        rewrite(&mut header, &mut map);
+        let mut writer = File::create(&path).unwrap();
+        serde_cbor::to_writer(&mut writer, &header).unwrap();
+        serde_cbor::to_writer(&mut writer, &map).unwrap();
+        header.fingerprint()
+    }
+
+    fn derived_from(header: &mut CacheHeader, prerequisite: &str, fingerprint: u64) {
+        for (name, old) in header.prerequisites.iter_mut() {
+            if name == prerequisite { *old = fingerprint }
+        }
+    }
+
+    #[test]
+    fn stale_cache_is_rebuilt_with_its_dependents() {
+        let cache = tempfile::tempdir().unwrap();
+        let (source, project, _) = three_commits();
+        assert_eq!(testing::database(source).in_cache(cache.path()).open().project_commit_count(&project), Some(3));
+
+        // Make it look as if the commits were cached by another version of their extractor, and the
+        // caches derived from them (wrongly, as it were) from those.
+        let commits = rewrite_cache(cache.path(), "commits", |header, _: &mut BTreeMap<CommitId, Commit>| {
+            header.extractor_version += 1
+        });
+        let project_commits = rewrite_cache(cache.path(), "project_commits", |header, _: &mut BTreeMap<ProjectId, Vec<CommitId>>| {
+            derived_from(header, "commits", commits)
+        });
+        rewrite_cache(cache.path(), "project_commit_count", |header, counts: &mut BTreeMap<ProjectId, usize>| {
+            derived_from(header, "project_commits", project_commits);
+            counts.insert(project, 42);
+        });
+
+        let (source, project, _) = three_commits();
+        let database = testing::database(source).in_cache(cache.path()).open();
+        assert_eq!(database.project_commit_count(&project), Some(3));
+        assert_eq!(database.commits().count(), 3);
+    }
+
+    #[test]
+    fn savepoint_hides_later_commits() {
+        let (source, project, commits) = three_commits();
+        let database = testing::database(source).at(250).open();
+
+        assert_eq!(database.commits().count(), 2);
+        assert_eq!(database.project_commit_ids(&project), Some(commits[0..2].to_vec()));
+        let heads = database.project_heads(&project).unwrap();
+        assert_eq!(heads.iter().map(|head| head.commit_id()).collect::<Vec<CommitId>>(), vec![commits[1]]);
+    }
+
+    #[test]
+    fn savepoint_hides_projects_added_later() {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        let path = source.add_path("src/main.rs");
+        let snapshot = source.add_snapshot("fn main() {}");
+        let first = source.add_commit("a", CommitBasics { changes: vec![(path, Some(snapshot))], ..commit(user, 100, vec![]) });
+        let second = source.add_commit("b", commit(user, 200, vec![first]));
+        // Committed before the savepoint, but only downloaded after it.
+        let third = source.add_commit("c", commit(user, 250, vec![second]));
+        let project = source.add_project("https://github.com/example/example.git");
+        source.set_head(project, "master", second);
+        source.log_heads(project, 280);
+        source.set_head(project, "master", third);
+        source.log_heads(project, 400);
+
+        // A project that was added after the savepoint, with an old commit that shares nothing with the others.
+        let late_user = source.add_user("late@example.com");
+        let late_path = source.add_path("src/late.rs");
+        let late_snapshot = source.add_snapshot("fn late() {}");
+        let late_commit = source.add_commit("d", CommitBasics { changes: vec![(late_path, Some(late_snapshot))], ..commit(late_user, 150, vec![]) });
+        let late_project = source.add_project_at("https://github.com/example/late.git", 350);
+        source.set_head(late_project, "master", late_commit);
+        source.log_heads(late_project, 350);
+
+        let before = testing::database(source.clone()).at(300).open();
+        assert_eq!(before.projects().map(|project| project.id()).collect::<Vec<ProjectId>>(), vec![project]);
+        assert_eq!(before.project_commit_ids(&project), Some(vec![first, second]));
+        let heads = before.project_heads(&project).unwrap();
+        assert_eq!(heads.iter().map(|head| head.commit_id()).collect::<Vec<CommitId>>(), vec![second]);
+        assert_eq!(before.commits().count(), 2);
+        assert!(before.commit(&late_commit).is_none());
+        assert_eq!(before.users().map(|user| user.id()).collect::<Vec<UserId>>(), vec![user]);
+        assert_eq!(before.paths().map(|path| path.id()).collect::<Vec<PathId>>(), vec![path]);
+        assert!(before.snapshot(&late_snapshot).is_none());
+
+        let after = testing::database(source).open();
+        assert_eq!(after.projects().count(), 2);
+        assert_eq!(after.commits().count(), 4);
+        assert_eq!(after.project_commit_ids(&late_project), Some(vec![late_commit]));
+    }
+
+    /* Line changes are worked out against the tree of the first parent, which is put back together
+       by walking down from the roots: each branch starts from the tree its parent left, whatever its
+       siblings did, and files deleted and added back again are new files. */
+    #[test]
+    fn line_changes_follow_first_parents() {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        let path = source.add_path("notes.txt");
+        let one = source.add_snapshot("one\n");
+        let two = source.add_snapshot("one\ntwo\n");
+        let other = source.add_snapshot("other\n");
+        let change = |time, parents, snapshot| CommitBasics { changes: vec![(path, snapshot)], ..commit(user, time, parents) };
+
+        let root = source.add_commit("a", change(100, vec![], Some(one)));
+        let appended = source.add_commit("b", change(200, vec![root], Some(two)));
+        let replaced = source.add_commit("c", change(300, vec![root], Some(other)));
+        let deleted = source.add_commit("d", change(400, vec![replaced], None));
+        let added_back = source.add_commit("e", change(500, vec![deleted], Some(one)));
+        // Its parent was never downloaded, so neither it nor its child can be diffed.
+        let orphan = source.add_commit("f", change(600, vec![CommitId(1000)], Some(two)));
+        let orphan_child = source.add_commit("g", change(700, vec![orphan], Some(one)));
+
+        let project = source.add_project("https://github.com/example/example.git");
+        source.set_head(project, "master", appended);
+        source.set_head(project, "branch", added_back);
+        source.set_head(project, "shallow", orphan_child);
+
+        let database = testing::database(source).open();
+        let line_changes = |commit| database.commit_line_changes(&commit);
+        assert_eq!(line_changes(root), Some(vec![(path, 1, 0)]));
+        assert_eq!(line_changes(appended), Some(vec![(path, 1, 0)]));
+        assert_eq!(line_changes(replaced), Some(vec![(path, 1, 1)]));
+        assert_eq!(line_changes(deleted), Some(vec![(path, 0, 1)]));
+        assert_eq!(line_changes(added_back), Some(vec![(path, 1, 0)]));
+        assert_eq!(line_changes(orphan), None);
+        assert_eq!(line_changes(orphan_child), None);
+        assert_eq!(database.commit(&orphan_child).unwrap().churn(&database), None);
+    }
+
+    /* A commit that changes a binary file along with text files has no line changes at all, rather
+       than only those of the text files. */
+    #[test]
+    fn line_changes_need_every_change_diffed() {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        let notes = source.add_path("notes.txt");
+        let readme = source.add_path("README.md");
+        let image = source.add_path("image.png");
+        let text = source.add_snapshot("one\ntwo\n");
+        let binary = source.add_snapshot("\u{0}PNG\n");
+
+        let text_only = source.add_commit("a", CommitBasics {
+            changes: vec![(notes, Some(text)), (readme, Some(text))], ..commit(user, 100, vec![])
+        });
+        let mixed = source.add_commit("b", CommitBasics {
+            changes: vec![(notes, None), (image, Some(binary)), (readme, None)], ..commit(user, 200, vec![text_only])
+        });
+        let project = source.add_project("https://github.com/example/example.git");
+        source.set_head(project, "master", mixed);
+
+        let database = testing::database(source).open();
+        assert_eq!(database.commit_line_changes(&text_only), Some(vec![(notes, 2, 0), (readme, 2, 0)]));
+        assert_eq!(database.commit_line_changes(&mixed), None);
+        assert_eq!(database.commit(&mixed).unwrap().churn(&database), None);
+    }
+
+    /* Caches updated from an earlier savepoint have to be the same as caches built from scratch,
+       down to the bytes of their files. */
+    #[test]
+    fn updated_caches_match_caches_built_from_scratch() {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        let path = source.add_path("src/main.rs");
+        let first_snapshot = source.add_snapshot("fn main() {}");
+        let first = source.add_commit("a", CommitBasics { changes: vec![(path, Some(first_snapshot))], ..commit(user, 100, vec![]) });
+        let second = source.add_commit("b", commit(user, 200, vec![first]));
+        let project = source.add_project("https://github.com/example/example.git");
+        source.set_head(project, "master", second);
+        source.log_heads(project, 210);
+
+        // After the earlier savepoint, the project gains a commit by a new user...
+        let new_user = source.add_user("new@example.com");
+        let new_path = source.add_path("src/lib.rs");
+        let new_snapshot = source.add_snapshot("pub fn lib() {}");
+        let third = source.add_commit("c", CommitBasics { changes: vec![(new_path, Some(new_snapshot))], ..commit(new_user, 300, vec![second]) });
+        source.set_head(project, "master", third);
+        source.log_heads(project, 310);
+
+        // ...and a new project appears, whose commit was committed before the earlier savepoint.
+        let late_snapshot = source.add_snapshot("fn main() { late() }");
+        let late_commit = source.add_commit("d", CommitBasics { changes: vec![(path, Some(late_snapshot))], ..commit(user, 150, vec![]) });
+        let late_project = source.add_project_at("https://github.com/example/late.git", 400);
+        source.set_head(late_project, "master", late_commit);
+        source.log_heads(late_project, 400);
+
+        let names: Vec<String> = vec!["snapshot_has_contents", "user_authored_commit_count", "user_committed_commit_count", "project_commit_count"]
+            .into_iter().map(|name| name.to_owned()).collect();
+
+        let cache = tempfile::tempdir().unwrap();
+        testing::database(source.clone()).at(250).in_cache(cache.path()).open().build_caches(&names).unwrap();
+        let updated = testing::database(source.clone()).in_cache(cache.path()).open().update_caches(Some(250)).unwrap();
+        assert_eq!(updated, vec!["commits", "snapshot_has_contents",
+                                 "user_authored_commits", "user_committed_commits",
+                                 "user_authored_commit_count", "user_committed_commit_count",
+                                 "project_commits", "project_commit_count"]);
+
+        let database = testing::database(source).open();
+        database.build_caches(&names).unwrap();
+        assert_eq!(database.commits().count(), 4);
+        assert_eq!(database.users().count(), 2);
+        assert_eq!(database.projects().count(), 2);
+
+        let updated_dir = CacheDir::from(cache.path().to_str().unwrap(), 1000, vec![]).as_path();
+        let scratch_dir = CacheDir::from(database.cache_path().to_str().unwrap(), 1000, vec![]).as_path();
+        for name in updated {
+            let updated_file = cache_file(&updated_dir, name).unwrap();
+            let scratch_file = cache_file(&scratch_dir, name).unwrap();
+            assert_eq!(read(updated_file).unwrap(), read(scratch_file).unwrap(), "{} differs from a cache built from scratch", name);
+        }
+    }
+
+    #[test]
+    fn reads_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("users.csv"), "id,email\n0,author@example.com\n").unwrap();
+        write(dir.path().join("paths.csv"), "id,path\n0,src/main.rs\n").unwrap();
+        write(dir.path().join("commits.csv"), "id,hash,author,committer,author_time,committer_time,parents,message\n\
+                                               0,a,0,0,100,100,,initial\n\
+                                               1,b,0,0,200,200,0,\"second, with a comma\"\n").unwrap();
+        write(dir.path().join("changes.csv"), "commit,path,snapshot\n0,0,0\n1,0,\n").unwrap();
+        write(dir.path().join("projects.csv"), "id,url,substore\n0,https://github.com/example/example.git,\n").unwrap();
+        write(dir.path().join("heads.csv"), "project,name,commit\n0,master,1\n").unwrap();
+        create_dir(dir.path().join("snapshots")).unwrap();
+        write(dir.path().join("snapshots").join("0"), "fn main() {}\n").unwrap();
+
+        let database = testing::database(InMemorySource::from_dir(dir.path()).unwrap()).open();
+
+        assert_eq!(database.project_commit_ids(&ProjectId(0)), Some(vec![CommitId(0), CommitId(1)]));
+        assert_eq!(database.commit_message(&CommitId(1)), Some("second, with a comma".to_owned()));
+        assert_eq!(database.commit_changed_path_count(&CommitId(1)), Some(1));
+        assert_eq!(database.snapshot(&SnapshotId(0)).map(|snapshot| snapshot.contents_owned()), Some("fn main() {}\n".to_owned()));
+    }
+
+    #[test]
+    fn reports_invalid_substore() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("projects.csv"), "id,url,substore\n\
+                                                0,https://github.com/example/example.git,\n\
+                                                1,https://github.com/example/other.git,NotAStore\n").unwrap();
+
+        let error = InMemorySource::from_dir(dir.path()).err().unwrap();
+        assert_eq!(error.to_string(), "projects.csv, row 3: `NotAStore` is not a valid substore");
+    }
+
+    #[test]
+    fn exports_loadable_dataset() {
+        let (mut source, project, commits) = three_commits();
+        source.add_project("https://github.com/example/unrelated.git");
+        let database = testing::database(source).open();
+
+        let dataset = tempfile::tempdir().unwrap();
+        database.export_projects(vec![project], dataset.path()).unwrap();
+
+        let exported_cache = tempfile::tempdir().unwrap();
+        let exported = Djanco::from_spec(dataset.path().to_str().unwrap(), exported_cache.path().to_str().unwrap(),
+                                         1000, vec![], Log::new(Verbosity::Warning)).unwrap();
+        assert_eq!(exported.projects().count(), 1);
+        assert_eq!(exported.project_commit_ids(&project), Some(commits));
+        assert_eq!(exported.commit_hash(&CommitId(2)), Some("c".to_owned()));
+    }
+}
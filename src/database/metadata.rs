@@ -2,6 +2,7 @@ use std::path::PathBuf;
 use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::{File, create_dir_all};
+use std::sync::Arc;
 
 use serde_json::Value as JSON;
 use chrono::DateTime;
@@ -15,6 +16,14 @@ use super::cache::*;
 use super::persistent::*;
 use super::source::Source;
 
+/*
+ * Metadata for all fields is extracted from the store in one go and cached in
+ * files of its own, which the persistent maps for individual fields are then
+ * extracted from. Those files need an extension of their own, so that they do
+ * not collide with the cache files of the persistent maps.
+ */
+pub static METADATA_EXTENSION: &str = "metadata.cbor";
+
 trait MetadataFieldExtractor {
     type Value: Persistent + Weighed;
     fn get(&self, value: &JSON) -> Option<Self::Value>;
@@ -148,7 +157,7 @@ impl<M> MetadataCacher<M> where M: MetadataFieldExtractor {
 
         let mut cache_path = cache_dir.clone();
         cache_path.push(std::path::Path::new(filename.as_str()));
-        cache_path.set_extension(PERSISTENT_EXTENSION);
+        cache_path.set_extension(METADATA_EXTENSION);
 
         Self { field, extractor, cache_dir, cache_path, log: log.clone() }
     }
@@ -459,6 +468,20 @@ impl ProjectMetadataSource {
     // }
 }
 
+impl ProjectMetadataSource {
+    /* Converts metadata into cache files for all fields at once, unless all of them are cached already. */
+    pub fn precache_all(&self, source: &Source) -> Result<(), Vec<Box<dyn Error>>> {
+        macro_rules! all_cached {
+            ($($id:ident),+) => { true $( && self.$id.already_cached() )* }
+        }
+        let cached = all_cached!(are_forks, are_archived, are_disabled, star_gazers, watchers, size,
+                                 open_issues, forks, subscribers, licenses, languages, descriptions,
+                                 homepages, has_issues, has_downloads, has_wiki, has_pages, created,
+                                 updated, pushed, master, issues, buggy_issues);
+        if cached { Ok(()) } else { self.convert_all_into_cache_from_store(source) }
+    }
+}
+
 impl MetadataSource for ProjectMetadataSource {
     fn convert_all_into_cache(&self, metadata: &HashMap<ProjectId, serde_json::Map<String, JSON>>) -> Result<(), Vec<Box<dyn Error>>> {
         macro_rules! convert_into_store {
@@ -486,8 +509,8 @@ pub trait MetadataMapExtractor<M>: MapExtractor where M: MetadataSource {
 }
 
 impl<E> PersistentMap<E> where E: MetadataMapExtractor<ProjectMetadataSource> {
-    pub fn load_from_metadata(&mut self, source: &Source, metadata: &ProjectMetadataSource) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source, metadata) })
+    pub fn load_from_metadata(&self, source: &Source, metadata: &ProjectMetadataSource) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source, metadata) })
     }
 }
 
@@ -508,6 +531,7 @@ pub(crate) struct ProjectBuggyIssuesExtractor {}
 impl MapExtractor for ProjectBuggyIssuesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectBuggyIssuesExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectBuggyIssuesExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -520,6 +544,7 @@ pub(crate) struct ProjectIsForkExtractor {}
 impl MapExtractor for ProjectIsForkExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectIsForkExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectIsForkExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -531,6 +556,7 @@ pub(crate) struct ProjectIsArchivedExtractor {}
 impl MapExtractor for ProjectIsArchivedExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectIsArchivedExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectIsArchivedExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -542,6 +568,7 @@ pub(crate) struct ProjectIsDisabledExtractor {}
 impl MapExtractor for ProjectIsDisabledExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectIsDisabledExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectIsDisabledExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -553,6 +580,7 @@ pub(crate) struct ProjectStargazersExtractor {}
 impl MapExtractor for ProjectStargazersExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectStargazersExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectStargazersExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -564,6 +592,7 @@ pub(crate) struct ProjectWatchersExtractor {}
 impl MapExtractor for ProjectWatchersExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectWatchersExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectWatchersExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -575,6 +604,7 @@ pub(crate) struct ProjectSizeExtractor {}
 impl MapExtractor for ProjectSizeExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectSizeExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectSizeExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -586,6 +616,7 @@ pub(crate) struct ProjectOpenIssuesExtractor {}
 impl MapExtractor for ProjectOpenIssuesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectOpenIssuesExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectOpenIssuesExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -597,6 +628,7 @@ pub(crate) struct ProjectIssuesExtractor {}
 impl MapExtractor for ProjectIssuesExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectIssuesExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectIssuesExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -608,6 +640,7 @@ pub(crate) struct ProjectForksExtractor {}
 impl MapExtractor for ProjectForksExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectForksExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectForksExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -619,6 +652,7 @@ pub(crate) struct ProjectSubscribersExtractor {}
 impl MapExtractor for ProjectSubscribersExtractor {
     type Key = ProjectId;
     type Value = usize;
+    const NAME: &'static str = "ProjectSubscribersExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectSubscribersExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -630,6 +664,7 @@ pub(crate) struct ProjectLicenseExtractor {}
 impl MapExtractor for ProjectLicenseExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectLicenseExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectLicenseExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -641,6 +676,7 @@ pub(crate) struct ProjectLanguageExtractor {}
 impl MapExtractor for ProjectLanguageExtractor {
     type Key = ProjectId;
     type Value = Language;
+    const NAME: &'static str = "ProjectLanguageExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectLanguageExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -652,6 +688,7 @@ pub(crate) struct ProjectDescriptionExtractor {}
 impl MapExtractor for ProjectDescriptionExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectDescriptionExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectDescriptionExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -663,6 +700,7 @@ pub(crate) struct ProjectHomepageExtractor {}
 impl MapExtractor for ProjectHomepageExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectHomepageExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectHomepageExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -674,6 +712,7 @@ pub(crate) struct ProjectHasIssuesExtractor {}
 impl MapExtractor for ProjectHasIssuesExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectHasIssuesExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectHasIssuesExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -685,6 +724,7 @@ pub(crate) struct ProjectHasDownloadsExtractor {}
 impl MapExtractor for ProjectHasDownloadsExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectHasDownloadsExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectHasDownloadsExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -696,6 +736,7 @@ pub(crate) struct ProjectHasWikiExtractor {}
 impl MapExtractor for ProjectHasWikiExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectHasWikiExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectHasWikiExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -707,6 +748,7 @@ pub(crate) struct ProjectHasPagesExtractor {}
 impl MapExtractor for ProjectHasPagesExtractor {
     type Key = ProjectId;
     type Value = bool;
+    const NAME: &'static str = "ProjectHasPagesExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectHasPagesExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -718,6 +760,7 @@ pub(crate) struct ProjectCreatedExtractor {}
 impl MapExtractor for ProjectCreatedExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "ProjectCreatedExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectCreatedExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -729,6 +772,7 @@ pub(crate) struct ProjectUpdatedExtractor {}
 impl MapExtractor for ProjectUpdatedExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "ProjectUpdatedExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectUpdatedExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -740,6 +784,7 @@ pub(crate) struct ProjectPushedExtractor {}
 impl MapExtractor for ProjectPushedExtractor {
     type Key = ProjectId;
     type Value = i64;
+    const NAME: &'static str = "ProjectPushedExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectPushedExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
@@ -751,6 +796,7 @@ pub(crate) struct ProjectDefaultBranchExtractor {}
 impl MapExtractor for ProjectDefaultBranchExtractor {
     type Key = ProjectId;
     type Value = String;
+    const NAME: &'static str = "ProjectDefaultBranchExtractor";
 }
 impl MetadataMapExtractor<ProjectMetadataSource> for ProjectDefaultBranchExtractor {
     fn extract(source: &Source, metadata: &ProjectMetadataSource) -> BTreeMap<Self::Key, Self::Value> {
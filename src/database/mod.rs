@@ -1,11 +1,20 @@
 pub mod metadata;
+pub mod budget;
 pub mod cache;
 pub mod persistent;
+pub mod indexed;
+pub mod header;
+pub mod warmup;
 pub mod source;
+pub mod datastore;
+pub mod memory;
+pub mod repository;
+pub mod export;
 pub mod data;
 pub mod extractors;
+#[cfg(test)] pub(crate) mod testing;
 
-use std::cell::RefCell;
+use std::ops::Deref;
 
 use anyhow::*;
 use delegate::delegate;
@@ -17,21 +26,98 @@ use crate::time::Duration;
 use crate::{CacheDir, Store, Percentage, Timestamp};
 
 use source::Source;
+use persistent::CacheFormat;
+use crate::languages::LanguageRegistry;
+use crate::lines::LineCounts;
+use crate::diff::LineDiff;
 
 use data::Data;
+use extractors::{ChangeTuple, texts_to_diff};
 
-// Internally Mutable Data
+// Internally Mutable Data, shareable between threads
 pub struct Database {
-    data: RefCell<Data>,
+    data: Data,
     source: Source,
+    cache_dir: CacheDir,
     log: Log,
 }
 
 // Constructors
 impl Database {
     pub fn new(source: Source, cache_dir: CacheDir, log: Log) -> Self {
-        let data = RefCell::new(Data::new(cache_dir, log.clone()));
-        Database { data, source, log }
+        let mut data = Data::new(cache_dir.clone(), log.clone());
+        data.set_language_registry(source.language_registry().fingerprint());
+        Database { data, source, cache_dir, log }
+    }
+    /** Tells the languages of files apart using the given registry instead of the default one.
+
+        The languages of paths, projects, and commits that were cached using another registry are
+        worked out anew the next time they are needed. This should be set up before any queries
+        are run, since the languages that are already loaded are not reloaded.
+     */
+    pub fn with_language_registry(mut self, registry: LanguageRegistry) -> Self {
+        self.data.set_language_registry(registry.fingerprint());
+        self.source.set_language_registry(registry);
+        self
+    }
+    pub fn language_registry(&self) -> &LanguageRegistry {
+        self.source.language_registry()
+    }
+    /** Limits the amount of memory taken up by loaded caches.
+
+        When the limit is exceeded, the least recently used caches are dropped from memory. They are
+        loaded back from their cache files the next time they are needed. Caches that are not stored
+        on disk are dropped as well, and extracted anew from the collections they are derived from.
+     */
+    pub fn with_memory_budget(self, bytes: usize) -> Self {
+        self.data.set_memory_budget(Some(bytes));
+        self
+    }
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.data.memory_budget()
+    }
+    /** The names of the caches that are currently loaded into memory. */
+    pub fn loaded_caches(&self) -> Vec<&'static str> {
+        self.data.loaded_collections()
+    }
+    /** Selects how caches are stored on disk.
+
+        The default is CBOR, which stores each cache as a single blob. The indexed format allows
+        single values to be read from a cache file without loading the entire cache, which is much
+        faster for queries that only look at a handful of objects. The two formats use different
+        file extensions, so switching between them means the caches have to be created anew.
+     */
+    pub fn with_cache_format(mut self, format: CacheFormat) -> Self {
+        self.data.set_cache_format(format);
+        self
+    }
+    pub fn cache_format(&self) -> CacheFormat {
+        self.data.cache_format()
+    }
+    fn lock_data(&self) -> DataGuard {
+        DataGuard { data: &self.data }
+    }
+}
+
+/*
+ * Access to the Data inside a Database for the duration of one delegated call.
+ * Data locks each of its collections separately, so any number of these can
+ * be held at once. When the guard is dropped, ie. after a delegated call has
+ * returned and its results were copied out, Data gets a chance to unload
+ * caches that do not fit into its memory budget.
+ */
+struct DataGuard<'a> {
+    data: &'a Data,
+}
+
+impl<'a> Deref for DataGuard<'a> {
+    type Target = Data;
+    fn deref(&self) -> &Self::Target { self.data }
+}
+
+impl<'a> Drop for DataGuard<'a> {
+    fn drop(&mut self) {
+        self.data.enforce_memory_budget()
     }
 }
 
@@ -106,7 +192,7 @@ impl Database {
      * ```
      * #[inline(always)] 
      * pub fn project(&self, id: &ProjectId) -> Option<Project> {
-     *      self.data.borrow_mut().project(id, &self.source)
+     *      self.lock_data().project(id, &self.source)
      * }
      * ```
      * 
@@ -124,7 +210,12 @@ impl Database {
      * 
      * > Is this thread safe? 
      * 
-     * No, but it can be (should be) made thread safe in the future.
+     * Yes. Each collection inside the Data object sits behind its own mutex,
+     * which is only held while the collection is loaded (or unloaded). Once
+     * loaded, a collection is handed out as a shared pointer and read without
+     * holding any lock, so parallel query operators (`par_filter_by`, etc.)
+     * only contend when two threads need the same collection that is not yet
+     * loaded, and one of them waits for the other to load it.
      * 
      * > Why do we generate these instead fo writing them by hand?
      * 
@@ -139,7 +230,7 @@ impl Database {
      * silly mistakes is a positive thing for a bit of expended macro magic.
      */
     delegate! {
-        to self.data.borrow_mut() {
+        to self.lock_data() {
             // Project attributes
             #[append_args(&self.source)] pub fn project(&self, id: &ProjectId) -> Option<Project>;
             #[append_args(&self.source)] pub fn project_issues(&self, id: &ProjectId) -> Option<usize>;
@@ -220,6 +311,7 @@ impl Database {
             #[append_args(&self.source)] pub fn project_time_since_first_commit(&self, id: &ProjectId) -> Option<i64>;
             #[append_args(&self.source)] pub fn project_is_abandoned(&self, id: &ProjectId) -> Option<bool>;
             #[append_args(&self.source)] pub fn project_locs(&self, id: &ProjectId) -> Option<usize>;
+            #[append_args(&self.source)] pub fn project_line_counts(&self, id: &ProjectId) -> Option<LineCounts>;
             #[append_args(&self.source)] pub fn project_duplicated_code(&self, id: &ProjectId) -> Option<f64>;
             #[append_args(&self.source)] pub fn project_logs(&self, id : &ProjectId) -> Option<i64>;
             #[append_args(&self.source)] pub fn project_is_valid(&self, id : &ProjectId) -> Option<bool>;
@@ -239,6 +331,7 @@ impl Database {
 
             // File path attributes
             #[append_args(&self.source)] pub fn path(&self, id: &PathId) -> Option<Path>;
+            #[append_args(&self.source)] pub fn path_language(&self, id: &PathId) -> Option<Language>;
 
             // Commit attributes
             #[append_args(&self.source)] pub fn commit(&self, id: &CommitId) -> Option<Commit>;
@@ -256,9 +349,12 @@ impl Database {
             #[append_args(&self.source)] pub fn commit_languages_count(&self, id: &CommitId) -> Option<usize>;
             #[append_args(&self.source)] pub fn commit_changes_with_contents(&self, id: &CommitId) -> Option<Vec<Change>>;
             #[append_args(&self.source)] pub fn commit_change_with_contents_count(&self, id: &CommitId) -> Option<usize>;
+            #[append_args(&self.source)] pub fn commit_previous_snapshots(&self, id: &CommitId) -> Option<Vec<ChangeTuple>>;
+            #[append_args(&self.source)] pub fn commit_line_changes(&self, id: &CommitId) -> Option<Vec<(PathId, usize, usize)>>;
 
             // Snapshot attributes
             #[append_args(&self.source)] pub fn snapshot_locs(&self, id: &SnapshotId) -> Option<usize>;
+            #[append_args(&self.source)] pub fn snapshot_line_counts(&self, id: &SnapshotId) -> Option<LineCounts>;
             #[append_args(&self.source)] pub fn snapshot_unique_projects(&self, id: &SnapshotId) -> usize;
             #[append_args(&self.source)] pub fn snapshot_original_project(&self, id: &SnapshotId) -> ProjectId;
             #[append_args(&self.source)] pub fn snapshot_has_contents(&self, id: &SnapshotId) -> bool;
@@ -278,6 +374,22 @@ impl Database {
         self.source.get_snapshot(id.clone()).map(|bytes| Snapshot::new(id.clone(), bytes))
     }
 
+    /** The unified diff of the change a commit made to a path, against the version of the path in the
+        first parent of the commit. None if the commit did not change the path, or if the contents of
+        either version are not known or are binary. */
+    pub fn change_diff(&self, commit: &CommitId, path: &PathId) -> Option<String> {
+        let changes = self.commit_changes(commit)?;
+        let previous = self.commit_previous_snapshots(commit)?;
+        let index = changes.iter().position(|change| change.path_id() == *path)?;
+        let (old, new) = (previous.get(index)?.1, changes[index].snapshot_id());
+        let (old_text, new_text) = texts_to_diff(&self.source, old, new)?;
+        let location = self.path(path)?.location();
+        let name = |prefix: &str, snapshot_id: Option<SnapshotId>| {
+            snapshot_id.map_or("/dev/null".to_owned(), |_| format!("{}/{}", prefix, location))
+        };
+        Some(LineDiff::new(&old_text, &new_text).unified(&name("a", old), &name("b", new)))
+    }
+
     pub fn project_snapshots(&self, id: &ProjectId) -> Option<Vec<Snapshot>> {
         self.project_snapshot_ids(id).map(|vector| {
             vector.into_iter()
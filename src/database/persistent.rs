@@ -1,22 +1,75 @@
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::path::{Path, PathBuf};
 use std::marker::PhantomData;
 use std::fs::{File, create_dir_all};
 use std::error::Error;
+use std::io::{Write, BufWriter};
 
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 use serde::de::DeserializeOwned;
+use tempfile::NamedTempFile;
 
-use crate::log::{Log, Verbosity};
+use crate::log::{Log, Verbosity, Warning};
 use crate::weights_and_measures::*;
+use crate::Timestamp;
+use crate::objects::CommitId;
 
 use super::source::Source;
+use super::indexed::{IndexedMap, write_indexed_map, INDEXED_EXTENSION};
+use super::header::CacheHeader;
 
 pub static PERSISTENT_EXTENSION: &str = "cbor";
 
+/*
+ * How a persistent map is stored in its cache file.
+ *
+ * CBOR stores the whole map as one blob, which has to be read in its entirety
+ * before any value can be retrieved. Indexed stores a sorted index of keys
+ * next to the values (see `indexed`), so single values can be looked up
+ * straight from the file without loading the map into memory.
+ */
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum CacheFormat {
+    CBOR,
+    Indexed,
+}
+
+impl CacheFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CacheFormat::CBOR => PERSISTENT_EXTENSION,
+            CacheFormat::Indexed => INDEXED_EXTENSION,
+        }
+    }
+}
+
+impl Default for CacheFormat {
+    fn default() -> Self { CacheFormat::CBOR }
+}
+
 pub trait Persistent: Serialize + DeserializeOwned {}
 impl<T> Persistent for T where T: Serialize + DeserializeOwned {}
 
+/*
+ * Writes a cache file under a temporary name in the same directory and then
+ * renames it over the old one. Whoever still has the old file open (or mapped,
+ * as indexed caches are, possibly in another process) keeps reading the old
+ * file whole, and nobody ever finds a file that is only half written.
+ */
+pub(crate) fn write_cache_file<P, F>(path: P, write: F) -> Result<(), Box<dyn Error>>
+    where P: AsRef<Path>, F: FnOnce(&mut BufWriter<&mut NamedTempFile>) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut file = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(&mut file);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.persist(path)?;
+    Ok(())
+}
+
 pub trait VectorExtractor {
     type Value: Clone + Persistent + Weighed;
 }
@@ -43,12 +96,32 @@ pub trait TripleVectorExtractor: VectorExtractor {
 pub trait MapExtractor {
     type Key:   Ord + Persistent + Weighed;
     type Value: Clone + Persistent + Countable + Weighed;
+    /* Identifies the extractor in the headers of the cache files it writes. This is spelled out
+       rather than taken from the type, so that moving or renaming the extractor in the code does
+       not make its caches stale. */
+    const NAME: &'static str;
+    /* Bump when the extractor starts computing something different, so that caches written by
+       the previous version (and everything derived from them) are rebuilt. */
+    const VERSION: u32 = 1;
 }
 
 pub trait SourceMapExtractor: MapExtractor {
     fn extract(source: &Source) -> BTreeMap<Self::Key, Self::Value>;
 }
 
+/*
+ * Source extractors whose maps only ever grow as the savepoint moves forward
+ * (eg. commits are added, but existing commits never change). A map extracted
+ * for an earlier savepoint is brought up to date by adding the entries that
+ * it does not have yet, instead of extracting the whole map again. Those
+ * entries come from the commits added since the earlier savepoint (see
+ * `Source::commits_added_since`), which are looked up rather than found
+ * among all commits.
+ */
+pub trait AppendSourceMapExtractor: SourceMapExtractor {
+    fn extract_new(source: &Source, previous: &BTreeMap<Self::Key, Self::Value>, added: &BTreeSet<CommitId>) -> BTreeMap<Self::Key, Self::Value>;
+}
+
 pub trait SingleMapExtractor: MapExtractor {
     type A;
     fn extract(source: &Source, a: &Self::A) -> BTreeMap<Self::Key, Self::Value>;
@@ -94,12 +167,13 @@ pub trait PersistentCollection {
     fn log(&self) -> &Log;
     fn cache_path(&self) -> &Option<PathBuf>;
     fn cache_dir(&self) -> &Option<PathBuf>;
-    fn collection(&self) -> &Option<Self::Collection>;
+    fn collection(&self) -> Option<&Self::Collection>;
     //fn weigh(&self) -> usize;
     fn set_collection(&mut self, collection: Self::Collection);
+    fn unset_collection(&mut self);
 
     fn grab_collection(&mut self) -> &Self::Collection {
-        self.collection().as_ref().unwrap()
+        self.collection().unwrap()
     }
     fn is_loaded(&self) -> bool {
         self.collection().is_some()
@@ -110,40 +184,68 @@ pub trait PersistentCollection {
     fn skip_caching(&self) -> bool {
         self.cache_path().is_none()
     }
+    fn weigh_collection(&self) -> usize {
+        self.collection().map_or(0, |collection| collection.weigh())
+    }
+    /** Drops the loaded collection from memory, if it can be loaded back from its cache file later,
+        or if it is never cached, in which case it is extracted anew.
+
+        Returns true if the collection was unloaded.
+     */
+    fn unload(&mut self) -> bool {
+        if !self.is_loaded() || !(self.skip_caching() || self.already_cached()) {
+            return false
+        }
+        self.unset_collection();
+        true
+    }
+    /** Checks whether the existing cache file can be loaded, or whether it is stale and has to be rebuilt. */
+    fn cache_is_current(&mut self) -> bool {
+        true
+    }
 
     fn load_from_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        self.load_from_cbor_cache()
+    }
+    fn store_to_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        self.store_to_cbor_cache()
+    }
+    fn load_from_cbor_cache(&mut self) -> Result<(), Box<dyn Error>> {
         let reader = File::open(&self.cache_path().as_ref().unwrap())?; // Probably ot the best solution to unwrap
         self.set_collection(serde_cbor::from_reader(reader)?);
         Ok(())
     }
-    fn store_to_cache(&mut self) -> Result<(), Box<dyn Error>> {
+    fn store_to_cbor_cache(&mut self) -> Result<(), Box<dyn Error>> {
         create_dir_all(&self.cache_dir().as_ref().unwrap())?; // Probably ot the best solution to unwrap
-        let writer = File::create(&self.cache_path().as_ref().unwrap())?; // Probably ot the best solution to unwrap
-        serde_cbor::to_writer(writer, &self.grab_collection())?;
-        Ok(())
+        let path = self.cache_path().clone().unwrap();
+        let collection = self.grab_collection();
+        write_cache_file(path, |writer| Ok(serde_cbor::to_writer(writer, collection)?))
     }
     fn data_from_loader<F>(&mut self, mut load: F) -> &Self::Collection
         where F: FnMut() -> Self::Collection {
 
+        if self.collection().is_none() && self.already_cached() && self.cache_is_current() {
+            let mut event = self.log().start(Verbosity::Log, format!("loading {} from cache {}", self.name(), self.cache_path().as_ref().unwrap().to_str().unwrap()));
+            // A cache file that cannot be read (eg. because it was cut short) is as good as none.
+            let loaded = self.load_from_cache()
+                .warn(format!("cannot read the cache of {}, so it is extracted anew", self.name()));
+            if loaded.is_ok() {
+                event.counted(self.collection().map_or(0, |c| c.count_items()));
+                event.weighed(self.collection().unwrap());
+            }
+            self.log().end(event);
+        }
         if self.collection().is_none() {
-            if self.already_cached() {
-                let mut event = self.log().start(Verbosity::Log, format!("loading {} from cache {}", self.name(), self.cache_path().as_ref().unwrap().to_str().unwrap()));
-                self.load_from_cache().unwrap();
-                event.counted(self.collection().as_ref().map_or(0, |c| c.count_items()));
-                event.weighed(self.collection().as_ref().unwrap());
+            let mut event = self.log().start(Verbosity::Log, format!("loading {} from source", self.name()));
+            self.set_collection(load());
+            event.counted(self.collection().map_or(0, |c| c.count_items()));
+            event.weighed(self.collection().unwrap());
+            self.log().end(event);
+
+            if !self.skip_caching() {
+                let event = self.log().start(Verbosity::Log, format!("storing {} into cache at {}", self.name(), self.cache_path().as_ref().unwrap().to_str().unwrap()));
+                self.store_to_cache().unwrap();
                 self.log().end(event);
-            } else {
-                let mut event = self.log().start(Verbosity::Log, format!("loading {} from source", self.name()));
-                self.set_collection(load());
-                event.counted(self.collection().as_ref().map_or(0, |c| c.count_items()));
-                event.weighed(self.collection().as_ref().unwrap());
-                self.log().end(event);
-
-                if !self.skip_caching() {
-                    let event = self.log().start(Verbosity::Log, format!("storing {} into cache at {}", self.name(), self.cache_path().as_ref().unwrap().to_str().unwrap()));
-                    self.store_to_cache().unwrap();
-                    self.log().end(event);
-                }
             }
         }
         self.grab_collection()
@@ -166,8 +268,9 @@ impl<E> PersistentCollection for PersistentVector<E> where E: VectorExtractor {
     fn log(&self) -> &Log { &self.log }
     fn cache_path(&self) -> &Option<PathBuf> { &self.cache_path }
     fn cache_dir(&self) -> &Option<PathBuf> { &self.cache_dir }
-    fn collection(&self) -> &Option<Self::Collection> { &self.vector }
+    fn collection(&self) -> Option<&Self::Collection> { self.vector.as_ref() }
     fn set_collection(&mut self, vector: Self::Collection) { self.vector = Some(vector) }
+    fn unset_collection(&mut self) { self.vector = None }
 }
 
 impl<E> PersistentVector<E> where E: VectorExtractor {
@@ -214,73 +317,307 @@ impl<E,A,B,C> PersistentVector<E> where E: TripleVectorExtractor<A=A, B=B, C=C>
     }
 }
 
+/*
+ * A map that is extracted from the source, or from other maps, and cached on disk.
+ *
+ * Each map has its own lock, which is held while the map is loaded (so that it is only ever
+ * extracted once, even if several threads need it at the same time), and only briefly
+ * otherwise. A loaded map is handed out as an `Arc`, so reading from it does not hold the lock,
+ * and unloading it does not pull it from under whoever is still reading it.
+ */
 pub struct PersistentMap<E: MapExtractor> {
+    cache: Mutex<MapCache<E>>,
+}
+
+/* The state of a persistent map, which is only ever accessed under its lock. */
+struct MapCache<E: MapExtractor> {
     log: Log,
     name: String,
     cache_path: Option<PathBuf>,
     cache_dir: Option<PathBuf>,
-    map: Option<BTreeMap<E::Key, E::Value>>,
+    map: Option<Arc<BTreeMap<E::Key, E::Value>>>,
+    format: CacheFormat,
+    index: Option<IndexedMap<E::Key, E::Value>>,
+    header: CacheHeader,
     extractor: PhantomData<E>,
 }
 
-impl<E> PersistentCollection for PersistentMap<E> where E: MapExtractor {
+impl<E> PersistentCollection for MapCache<E> where E: MapExtractor {
     type Collection = BTreeMap<E::Key, E::Value>;
     //fn weigh(&self) -> usize { Self.weight_in_bytes() }
     fn name(&self) -> String { self.name.clone() }
     fn log(&self) -> &Log { &self.log }
     fn cache_path(&self) -> &Option<PathBuf> { &self.cache_path }
     fn cache_dir(&self) -> &Option<PathBuf> { &self.cache_dir }
-    fn collection(&self) -> &Option<Self::Collection> { &self.map }
-    fn set_collection(&mut self, map: Self::Collection) { self.map = Some(map) }
+    fn collection(&self) -> Option<&Self::Collection> { self.map.as_deref() }
+    fn set_collection(&mut self, map: Self::Collection) { self.map = Some(Arc::new(map)) }
+    fn unset_collection(&mut self) { self.map = None }
+
+    fn cache_is_current(&mut self) -> bool {
+        let reason = match self.stored_header() {
+            None => "it has no readable header".to_owned(),
+            Some(stored) => match stored.mismatch(&self.header) {
+                None => return true,
+                Some(reason) => reason,
+            }
+        };
+        let event = self.log.start(Verbosity::Log, format!("discarding stale cache of {} because {}", self.name, reason));
+        self.log.end(event);
+        false
+    }
+
+    fn load_from_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            CacheFormat::CBOR => {
+                let reader = File::open(&self.cache_path.as_ref().unwrap())?;
+                let mut deserializer = serde_cbor::Deserializer::from_reader(reader);
+                let _header = CacheHeader::deserialize(&mut deserializer)?;
+                let map = BTreeMap::deserialize(&mut deserializer)?;
+                self.set_collection(map);
+                Ok(())
+            }
+            CacheFormat::Indexed => {
+                let map = self.index()?.to_map()?;
+                self.set_collection(map);
+                Ok(())
+            }
+        }
+    }
+    fn store_to_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            CacheFormat::CBOR => {
+                create_dir_all(&self.cache_dir.as_ref().unwrap())?;
+                let map = self.map.as_deref().unwrap();
+                write_cache_file(self.cache_path.as_ref().unwrap(), |writer| {
+                    serde_cbor::to_writer(&mut *writer, &self.header)?;
+                    serde_cbor::to_writer(&mut *writer, map)?;
+                    Ok(())
+                })
+            }
+            CacheFormat::Indexed => {
+                self.index = None; // the file is about to be replaced, so the old one need not stay mapped
+                create_dir_all(&self.cache_dir.as_ref().unwrap())?;
+                write_indexed_map(&self.cache_path.as_ref().unwrap(), &self.header, self.grab_collection())
+            }
+        }
+    }
+}
+
+impl<E> MapCache<E> where E: MapExtractor {
+    fn set_format(&mut self, format: CacheFormat) {
+        self.index = None;
+        self.format = format;
+        if let Some(cache_path) = self.cache_path.as_mut() {
+            cache_path.set_extension(format.extension());
+        }
+    }
+
+    /* Reads just the header from the cache file, or returns None if there is no cache file or
+       its header cannot be read (eg. because the file was written by an older version). */
+    fn stored_header(&mut self) -> Option<CacheHeader> {
+        if self.skip_caching() || !self.already_cached() {
+            return None
+        }
+        match self.format {
+            CacheFormat::CBOR => {
+                let reader = File::open(&self.cache_path.as_ref().unwrap()).ok()?;
+                let mut deserializer = serde_cbor::Deserializer::from_reader(reader);
+                CacheHeader::deserialize(&mut deserializer).ok()
+            }
+            CacheFormat::Indexed => {
+                self.index().ok()?.metadata().ok()
+            }
+        }
+    }
+
+    fn index(&mut self) -> Result<&IndexedMap<E::Key, E::Value>, Box<dyn Error>> {
+        if self.index.is_none() {
+            self.index = Some(IndexedMap::open(self.cache_path.as_ref().unwrap())?);
+        }
+        Ok(self.index.as_ref().unwrap())
+    }
+
+    fn load_shared<F>(&mut self, load: F) -> Arc<BTreeMap<E::Key, E::Value>> where F: FnMut() -> BTreeMap<E::Key, E::Value> {
+        self.data_from_loader(load);
+        self.map.clone().unwrap()
+    }
 }
 
 impl<E> PersistentMap<E> where E: MapExtractor {
     pub fn new<Sa, Sb>(name: Sa, log: Log, dir: Sb) -> Self where Sa: Into<String>, Sb: Into<String> {
         let name = name.into();
-        let (cache_dir, cache_path) = Self::setup_files(name.clone(), dir);
-        PersistentMap { name, log, cache_path: Some(cache_path), cache_dir: Some(cache_dir), map: None, extractor: PhantomData }
+        let (cache_dir, cache_path) = MapCache::<E>::setup_files(name.clone(), dir);
+        Self::from_cache(MapCache { name, log, cache_path: Some(cache_path), cache_dir: Some(cache_dir), map: None, format: CacheFormat::default(), index: None, header: CacheHeader::for_extractor::<E>(), extractor: PhantomData })
     }
     pub fn new_without_cache<S>(name: S, log: Log) -> Self where S: Into<String> {
-        PersistentMap { name: name.into(), log, cache_path: None, cache_dir: None, map: None, extractor: PhantomData }
+        Self::from_cache(MapCache { name: name.into(), log, cache_path: None, cache_dir: None, map: None, format: CacheFormat::default(), index: None, header: CacheHeader::for_extractor::<E>(), extractor: PhantomData })
+    }
+    fn from_cache(cache: MapCache<E>) -> Self {
+        PersistentMap { cache: Mutex::new(cache) }
     }
     pub fn without_cache(mut self) -> Self {
-        self.cache_dir = None;
-        self.cache_path = None;
+        let cache = self.cache.get_mut().unwrap();
+        cache.cache_dir = None;
+        cache.cache_path = None;
+        self
+    }
+    pub fn with_format(mut self, format: CacheFormat) -> Self {
+        self.set_format(format);
         self
     }
-    pub fn iter(&self) -> impl Iterator<Item=(&E::Key, &E::Value)> {
-        self.map.as_ref().map(|vector| vector.iter())
-            .expect("Attempted to iterate over persistent map before initializing it")
+    pub fn set_format(&mut self, format: CacheFormat) {
+        self.cache.get_mut().unwrap().set_format(format)
+    }
+    pub fn format(&self) -> CacheFormat {
+        self.cache().format
+    }
+    fn cache(&self) -> MutexGuard<MapCache<E>> {
+        self.cache.lock().unwrap()
+    }
+
+    pub fn name(&self) -> String { self.cache().name.clone() }
+    pub fn is_loaded(&self) -> bool { self.cache().is_loaded() }
+    pub fn already_cached(&self) -> bool { self.cache().already_cached() }
+    pub fn skip_caching(&self) -> bool { self.cache().skip_caching() }
+    pub fn weigh_collection(&self) -> usize { self.cache().weigh_collection() }
+    /** Drops the loaded map from memory, if it can be loaded back from its cache file later or
+        extracted anew.
+
+        Returns true if the map was unloaded. Whoever is still holding on to the map keeps it.
+     */
+    pub fn unload(&self) -> bool { self.cache().unload() }
+
+    /** The map, if it is loaded. */
+    pub fn loaded(&self) -> Option<Arc<BTreeMap<E::Key, E::Value>>> {
+        self.cache().map.clone()
+    }
+    /** The map, which has to be loaded already. */
+    pub fn shared(&self) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.loaded().expect("Attempted to use persistent map before initializing it")
+    }
+
+    /** Sets the savepoint and substores that the contents of this map are extracted for. */
+    pub fn set_context(&mut self, savepoint: Timestamp, substores: &Vec<String>) {
+        let cache = self.cache.get_mut().unwrap();
+        cache.header.savepoint = savepoint;
+        cache.header.substores = substores.clone();
+    }
+    /** Records the names and fingerprints of the collections this map is about to be extracted from. */
+    pub fn set_prerequisites(&self, prerequisites: Vec<(String, u64)>) {
+        let mut cache = self.cache();
+        cache.header = cache.header.clone().with_prerequisites(prerequisites);
+    }
+    /** The header that describes the contents of this map, if it is loaded, or that its cache
+        file has to have in order to be loaded. */
+    pub fn header(&self) -> CacheHeader {
+        self.cache().header.clone()
+    }
+    pub fn fingerprint(&self) -> u64 {
+        self.cache().header.fingerprint()
+    }
+    /** Reads just the header from the cache file, or returns None if there is no cache file or
+        its header cannot be read (eg. because the file was written by an older version). */
+    pub fn stored_header(&self) -> Option<CacheHeader> {
+        self.cache().stored_header()
+    }
+
+    /** Retrieves a single value without loading the whole map into memory, if possible.
+
+        If the map is already loaded, the value comes from memory. Otherwise, if the map is
+        cached in the indexed format, the value is read straight from the cache file. If
+        neither is possible this returns None, and the map has to be loaded the usual way.
+
+        This does not check whether the cache file is stale, because that depends on the
+        collections the map is derived from. The caller has to check that first.
+     */
+    pub fn lookup(&self, key: &E::Key) -> Option<Option<E::Value>> {
+        let mut cache = self.cache();
+        if let Some(map) = cache.map.as_ref() {
+            return Some(map.get(key).cloned())
+        }
+        if cache.format != CacheFormat::Indexed || cache.skip_caching() || !cache.already_cached() {
+            return None
+        }
+        let name = cache.name.clone();
+        cache.index()
+            .and_then(|index| index.get(key))
+            .warn(format!("cannot look up a value in the indexed cache of {}", name))
+            .ok()
+    }
+
+    /** Takes the map out of memory or, failing that, out of its cache file, without ever extracting it.
+
+        Returns None if the map is neither loaded nor cached. This does not check whether the cache
+        is stale.
+     */
+    pub fn take_cached(&mut self) -> Option<BTreeMap<E::Key, E::Value>> {
+        let cache = self.cache.get_mut().unwrap();
+        if let Some(map) = cache.map.take().and_then(|map| Arc::try_unwrap(map).ok()) {
+            return Some(map)
+        }
+        if cache.skip_caching() || !cache.already_cached() {
+            return None
+        }
+        let name = cache.name.clone();
+        cache.load_from_cache()
+            .warn(format!("cannot read the cache of {}", name))
+            .ok()?;
+        cache.map.take().and_then(|map| Arc::try_unwrap(map).ok())
+    }
+    /** Uses a map that was computed elsewhere as the contents of this one, and caches it.
+
+        This is for maps that are brought up to date by updating a previous version of them,
+        rather than by extracting them anew (see `Data::update_from`).
+     */
+    pub fn load_updated(&self, map: BTreeMap<E::Key, E::Value>) -> Arc<BTreeMap<E::Key, E::Value>> {
+        let mut cache = self.cache();
+        let mut event = cache.log.start(Verbosity::Log, format!("loading {} from an updated map", cache.name));
+        cache.set_collection(map);
+        event.counted(cache.grab_collection().count_items());
+        event.weighed(cache.grab_collection());
+        cache.log.end(event);
+        if !cache.skip_caching() {
+            let event = cache.log.start(Verbosity::Log, format!("storing {} into cache at {}", cache.name, cache.cache_path.as_ref().unwrap().to_str().unwrap()));
+            cache.store_to_cache().unwrap();
+            cache.log.end(event);
+        }
+        cache.map.clone().unwrap()
+    }
+
+    /* Loads the map, unless it is loaded already, holding the lock of this map (but no other)
+       while it is extracted. */
+    pub(crate) fn load_with<F>(&self, load: F) -> Arc<BTreeMap<E::Key, E::Value>> where F: FnMut() -> BTreeMap<E::Key, E::Value> {
+        self.cache().load_shared(load)
     }
 }
 
 impl<E> PersistentMap<E> where E: SourceMapExtractor {
-    pub fn load_from_source(&mut self, source: &Source) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source) })
+    pub fn load_from_source(&self, source: &Source) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source) })
     }
 }
 
 impl<E,A> PersistentMap<E> where E: SingleMapExtractor<A=A> {
-    pub fn load_from_one(&mut self, source: &Source, input: &A) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source, input) })
+    pub fn load_from_one(&self, source: &Source, input: &A) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source, input) })
     }
 }
 
 impl<E,A,B> PersistentMap<E> where E: DoubleMapExtractor<A=A,B=B> {
-    pub fn load_from_two(&mut self, source: &Source, input_a: &A, input_b: &B) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source, input_a, input_b) })
+    pub fn load_from_two(&self, source: &Source, input_a: &A, input_b: &B) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source, input_a, input_b) })
     }
 }
 
 impl<E,A,B,C> PersistentMap<E> where E: TripleMapExtractor<A=A,B=B,C=C> {
-    pub fn load_from_three(&mut self, source: &Source, input_a: &A, input_b: &B, input_c: &C) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source, input_a, input_b, input_c) })
+    pub fn load_from_three(&self, source: &Source, input_a: &A, input_b: &B, input_c: &C) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source, input_a, input_b, input_c) })
     }
 }
 
 impl<E,A,B,C, D> PersistentMap<E> where E: QuadrupleMapExtractor<A=A,B=B,C=C,D=D> {
-    pub fn load_from_four(&mut self, source: &Source, input_a: &A, input_b: &B, input_c: &C, input_d : &D) -> &BTreeMap<E::Key, E::Value> {
-        self.data_from_loader(|| { E::extract(source, input_a, input_b, input_c, input_d) })
+    pub fn load_from_four(&self, source: &Source, input_a: &A, input_b: &B, input_c: &C, input_d : &D) -> Arc<BTreeMap<E::Key, E::Value>> {
+        self.load_with(|| { E::extract(source, input_a, input_b, input_c, input_d) })
     }
 }
 
@@ -301,3 +638,49 @@ impl<E,A,B,C, D> PersistentMap<E> where E: QuadrupleMapExtractor<A=A,B=B,C=C,D=D
 
 // pub struct Created
 
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::fs::{OpenOptions, metadata};
+
+    use crate::log::{Log, Verbosity};
+
+    use super::{PersistentMap, MapExtractor, CacheFormat};
+
+    struct NumberExtractor;
+    impl MapExtractor for NumberExtractor {
+        type Key = u64;
+        type Value = String;
+        const NAME: &'static str = "NumberExtractor";
+    }
+
+    fn numbers() -> BTreeMap<u64, String> {
+        vec![(1, "one"), (2, "two"), (3, "three")].into_iter()
+            .map(|(key, value)| (key, value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn unreadable_cache_is_extracted_anew() {
+        for format in vec![CacheFormat::CBOR, CacheFormat::Indexed] {
+            let dir = tempfile::tempdir().unwrap();
+            let open = || {
+                PersistentMap::<NumberExtractor>::new("numbers", Log::new(Verbosity::Warning), dir.path().to_str().unwrap())
+                    .with_format(format)
+            };
+            let mut extracted = 0;
+            let mut extract = || { extracted += 1; numbers() };
+
+            assert_eq!(*open().load_with(&mut extract), numbers());
+            // The header is still there, but the last value is cut short.
+            let path = dir.path().join("numbers").with_extension(format.extension());
+            let size = metadata(&path).unwrap().len();
+            OpenOptions::new().write(true).open(&path).unwrap().set_len(size - 2).unwrap();
+
+            assert_eq!(*open().load_with(&mut extract), numbers());
+            assert_eq!(*open().load_with(&mut extract), numbers());
+            assert_eq!(extracted, 2, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path as FilePath;
+
+use anyhow::*;
+use git2::{BranchType, Delta, FileMode, Oid, Repository, Sort};
+use serde_json::Value as JSON;
+
+use crate::Store;
+use crate::objects::*;
+
+use super::memory::InMemorySource;
+use super::source::*;
+use super::source::Path; // not objects::Path
+
+/*
+ * A data source that reads local git repositories, for running queries on
+ * projects that are not in the datastore.
+ *
+ * Each repository becomes a project whose URL is that of its `origin` remote,
+ * or its location on disk if it has none, and whose heads are its local
+ * branches. Commits reachable from any branch are read along with their
+ * authors, committers, and changes, and the contents of every changed file
+ * become snapshots. Changes are taken against the first parent only, the way
+ * `git log --first-parent` shows them, and changes to submodules are left
+ * out. Commits, users, paths, and snapshots that occur in several repositories
+ * (eg. in forks) are read once and shared between the projects.
+ *
+ * Everything is read up front and kept in memory, so this is meant for a
+ * handful of repositories rather than for a whole datastore. There is no
+ * GitHub metadata for these projects.
+ */
+#[derive(Default)]
+pub struct RepositorySource {
+    data: InMemorySource,
+    commits: HashMap<Oid, CommitId>,
+    snapshots: HashMap<Oid, SnapshotId>,
+    users: HashMap<String, UserId>,
+    paths: HashMap<String, PathId>,
+}
+
+impl RepositorySource {
+    pub fn new() -> Self { Self::default() }
+
+    /** Reads all the given repositories, in order, so that they become projects 0, 1, 2, and so on. */
+    pub fn open<I, P>(repositories: I) -> Result<Self> where I: IntoIterator<Item=P>, P: AsRef<FilePath> {
+        let mut source = RepositorySource::new();
+        for repository in repositories {
+            source.add_repository(repository)?;
+        }
+        Ok(source)
+    }
+
+    /** Reads one more repository and returns the id of the project it became. */
+    pub fn add_repository<P>(&mut self, path: P) -> Result<ProjectId> where P: AsRef<FilePath> {
+        let path = path.as_ref();
+        let repository = Repository::open(path)
+            .with_context(|| format!("Cannot open git repository at {:?}", path))?;
+
+        let url = repository.find_remote("origin").ok()
+            .and_then(|remote| remote.url().map(|url| url.to_owned()))
+            .unwrap_or_else(|| path.canonicalize().unwrap_or(path.to_path_buf()).to_string_lossy().to_string());
+
+        let mut heads: Vec<(String, Oid)> = Vec::new();
+        for branch in repository.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            let reference = branch.get();
+            if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+                heads.push((name.to_owned(), target));
+            }
+        }
+
+        /* Parents come before their children, so their ids are known by the time children are read. */
+        let mut walk = repository.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        for (_, target) in heads.iter() {
+            walk.push(*target)?;
+        }
+        for oid in walk {
+            let oid = oid?;
+            if !self.commits.contains_key(&oid) {
+                self.add_commit(&repository, oid)
+                    .with_context(|| format!("Cannot read commit {} in {:?}", oid, path))?;
+            }
+        }
+
+        let project = self.data.add_project(url);
+        self.data.set_substore(project, Store::Generic);
+        for (name, target) in heads {
+            if let Some(commit) = self.commits.get(&target) {
+                self.data.set_head(project, name, *commit);
+            }
+        }
+        Ok(project)
+    }
+
+    fn add_commit(&mut self, repository: &Repository, oid: Oid) -> Result<CommitId> {
+        let commit = repository.find_commit(oid)?;
+
+        let parents = commit.parent_ids()
+            .map(|parent| self.commits.get(&parent).copied()
+                .with_context(|| format!("parent {} was not read before its child", parent)))
+            .collect::<Result<Vec<CommitId>>>()?;
+
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = repository.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+
+        let mut changes = Vec::new();
+        for delta in diff.deltas() {
+            let file = match delta.status() {
+                Delta::Deleted => delta.old_file(),
+                _ => delta.new_file(),
+            };
+            if file.mode() == FileMode::Commit {
+                continue // submodule
+            }
+            let path = match file.path() {
+                Some(path) => self.path_id(path.to_string_lossy().to_string()),
+                None => continue,
+            };
+            let snapshot = match delta.status() {
+                Delta::Deleted => None,
+                _ => Some(self.snapshot_id(repository, file.id())?),
+            };
+            changes.push((path, snapshot));
+        }
+
+        let author = commit.author();
+        let committer = commit.committer();
+        let basics = CommitBasics {
+            author: self.user_id(String::from_utf8_lossy(author.email_bytes()).to_string()),
+            committer: self.user_id(String::from_utf8_lossy(committer.email_bytes()).to_string()),
+            author_time: author.when().seconds(),
+            committer_time: committer.when().seconds(),
+            parents,
+            changes,
+            message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+        };
+
+        let id = self.data.add_commit(oid.to_string(), basics);
+        self.commits.insert(oid, id);
+        Ok(id)
+    }
+
+    fn user_id(&mut self, email: String) -> UserId {
+        let data = &mut self.data;
+        *self.users.entry(email).or_insert_with_key(|email| data.add_user(email.clone()))
+    }
+
+    fn path_id(&mut self, path: String) -> PathId {
+        let data = &mut self.data;
+        *self.paths.entry(path).or_insert_with_key(|path| data.add_path(path.clone()))
+    }
+
+    fn snapshot_id(&mut self, repository: &Repository, oid: Oid) -> Result<SnapshotId> {
+        if let Some(id) = self.snapshots.get(&oid) {
+            return Ok(*id)
+        }
+        let blob = repository.find_blob(oid)?;
+        let id = self.data.add_snapshot(blob.content().to_vec());
+        self.snapshots.insert(oid, id);
+        Ok(id)
+    }
+}
+
+macro_rules! delegate_to_data {
+    ($($method:ident -> $item:ty),* $(,)?) => {
+        $(fn $method<'a>(&'a self) -> Box<dyn Iterator<Item=$item> + 'a> { self.data.$method() })*
+    }
+}
+
+impl DataSource for RepositorySource {
+    delegate_to_data! {
+        project_logs -> (ProjectId, Vec<ProjectLog>),
+        project_urls -> (ProjectId, URL),
+        project_substores -> (ProjectId, Store),
+        project_credentials -> (ProjectId, String),
+        project_heads -> (ProjectId, HashMap<String, (CommitId, SHA)>),
+        project_github_metadata -> (ProjectId, Result<JSON>),
+        commit_hashes -> (CommitId, SHA),
+        commit_info -> (CommitId, CommitBasics),
+        commit_github_metadata -> (CommitId, Result<JSON>),
+        snapshot_bytes -> (SnapshotId, Bytes),
+        snapshot_has_contents -> SnapshotId,
+        snapshot_metadata -> (SnapshotId, Result<JSON>),
+        path_hashes -> (PathId, SHA),
+        paths -> (PathId, Path),
+        user_emails -> (UserId, String),
+        user_metadata -> (UserId, Result<JSON>),
+    }
+
+    fn commit_info_of<'a>(&'a self, ids: &'a BTreeSet<CommitId>) -> Box<dyn Iterator<Item=(CommitId, CommitBasics)> + 'a> {
+        self.data.commit_info_of(ids)
+    }
+
+    fn get_snapshot(&self, id: SnapshotId) -> Option<Bytes> {
+        self.data.get_snapshot(id)
+    }
+    fn has_snapshot(&self, id: SnapshotId) -> bool {
+        self.data.has_snapshot(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{Oid, Repository, Signature, Time, TreeBuilder};
+
+    use crate::objects::*;
+    use crate::database::Database;
+    use crate::database::testing::{self, TestDatabase};
+
+    use super::RepositorySource;
+
+    /* A tree with the given files, and nothing else. */
+    fn tree<'r>(repository: &'r Repository, files: &[(&str, &str)]) -> TreeBuilder<'r> {
+        let mut tree = repository.treebuilder(None).unwrap();
+        for (name, contents) in files {
+            let blob = repository.blob(contents.as_bytes()).unwrap();
+            tree.insert(name, blob, 0o100644).unwrap();
+        }
+        tree
+    }
+
+    /* Commits the tree on the branch, on top of its current head (if it has one yet) and of any
+       commits merged into it. */
+    fn commit(repository: &Repository, branch: &str, tree: TreeBuilder, time: i64, merged: &[Oid]) -> Oid {
+        let signature = Signature::new("Author", "author@example.com", &Time::new(time, 0)).unwrap();
+        let tree = repository.find_tree(tree.write().unwrap()).unwrap();
+        let reference = format!("refs/heads/{}", branch);
+        let mut parents: Vec<git2::Commit> = repository.find_reference(&reference).ok()
+            .map(|reference| reference.peel_to_commit().unwrap())
+            .into_iter()
+            .collect();
+        parents.extend(merged.iter().map(|oid| repository.find_commit(*oid).unwrap()));
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repository.commit(Some(&reference), &signature, &signature, "message", &tree, &parents).unwrap()
+    }
+
+    fn database(repositories: Vec<&std::path::Path>) -> TestDatabase {
+        testing::database(RepositorySource::open(repositories).unwrap()).open()
+    }
+
+    fn commit_id(database: &Database, oid: Oid) -> CommitId {
+        database.all_commit_ids().into_iter()
+            .find(|id| database.commit_hash(id) == Some(oid.to_string()))
+            .unwrap()
+    }
+
+    /* The paths a commit changed, each with whether the file is still there after the commit. */
+    fn changes(database: &Database, oid: Oid) -> Vec<(String, bool)> {
+        let mut changes: Vec<(String, bool)> = database.commit_changes(&commit_id(database, oid)).unwrap().into_iter()
+            .map(|change| (database.path(&change.path_id()).unwrap().location(), change.snapshot_id().is_some()))
+            .collect();
+        changes.sort();
+        changes
+    }
+
+    #[test]
+    fn reads_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit(&repository, "master", tree(&repository, &[("main.rs", "fn main() {}\n")]), 100, &[]);
+        commit(&repository, "master", tree(&repository, &[("main.rs", "fn main() { println!(\"hello\") }\n")]), 200, &[]);
+
+        let database = database(vec![dir.path()]);
+
+        assert_eq!(database.project_commit_ids(&ProjectId(0)), Some(vec![CommitId(0), CommitId(1)]));
+        assert_eq!(database.commit(&CommitId(1)).map(|commit| commit.parent_ids().clone()), Some(vec![CommitId(0)]));
+        assert_eq!(database.user(&UserId(0)).map(|user| user.email()), Some("author@example.com".to_owned()));
+        assert_eq!(database.path(&PathId(0)).map(|path| path.location()), Some("main.rs".to_owned()));
+        assert_eq!(database.snapshot(&SnapshotId(1)).map(|snapshot| snapshot.contents_owned()),
+                   Some("fn main() { println!(\"hello\") }\n".to_owned()));
+    }
+
+    /* All local branches are heads, merges are diffed against their first parent only, and deleted
+       files are changes without a snapshot. */
+    #[test]
+    fn reads_branches_merges_and_deletions() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let root = commit(&repository, "master", tree(&repository, &[("a.txt", "a\n"), ("b.txt", "b\n")]), 100, &[]);
+        repository.branch("feature", &repository.find_commit(root).unwrap(), false).unwrap();
+        let feature = commit(&repository, "feature", tree(&repository, &[("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")]), 200, &[]);
+        let deletion = commit(&repository, "master", tree(&repository, &[("a.txt", "a\nA\n")]), 300, &[]);
+        let merge = commit(&repository, "master", tree(&repository, &[("a.txt", "a\nA\n"), ("c.txt", "c\n")]), 400, &[feature]);
+
+        let database = database(vec![dir.path()]);
+
+        let mut heads: Vec<(String, CommitId)> = database.project_heads(&ProjectId(0)).unwrap().into_iter()
+            .map(|head| (head.name(), head.commit_id()))
+            .collect();
+        heads.sort();
+        assert_eq!(heads, vec![("refs/heads/feature".to_owned(), commit_id(&database, feature)),
+                               ("refs/heads/master".to_owned(), commit_id(&database, merge))]);
+        assert_eq!(database.project_commit_count(&ProjectId(0)), Some(4));
+        assert_eq!(database.commit(&commit_id(&database, merge)).map(|commit| commit.parent_ids().clone()),
+                   Some(vec![commit_id(&database, deletion), commit_id(&database, feature)]));
+
+        assert_eq!(changes(&database, root), vec![("a.txt".to_owned(), true), ("b.txt".to_owned(), true)]);
+        assert_eq!(changes(&database, feature), vec![("c.txt".to_owned(), true)]);
+        assert_eq!(changes(&database, deletion), vec![("a.txt".to_owned(), true), ("b.txt".to_owned(), false)]);
+        assert_eq!(changes(&database, merge), vec![("c.txt".to_owned(), true)]);
+    }
+
+    /* Commits with the same contents, authors, times, and parents have the same hash in every
+       repository, so they are read once and belong to every project that has them. */
+    #[test]
+    fn shares_commits_between_repositories() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let shared: Vec<Oid> = [&first, &second].iter().map(|dir| {
+            let repository = Repository::init(dir.path()).unwrap();
+            commit(&repository, "master", tree(&repository, &[("main.rs", "fn main() {}\n")]), 100, &[])
+        }).collect();
+        assert_eq!(shared[0], shared[1]);
+        let repository = Repository::open(second.path()).unwrap();
+        let own = commit(&repository, "master", tree(&repository, &[("main.rs", "fn main() { own() }\n")]), 200, &[]);
+
+        let database = database(vec![first.path(), second.path()]);
+
+        let shared = commit_id(&database, shared[0]);
+        assert_eq!(database.commits().count(), 2);
+        assert_eq!(database.project_commit_ids(&ProjectId(0)), Some(vec![shared]));
+        assert_eq!(database.project_commit_ids(&ProjectId(1)), Some(vec![shared, commit_id(&database, own)]));
+        assert_eq!(database.commit_projects_count(&shared), Some(2));
+        assert_eq!(database.users().count(), 1);
+        assert_eq!(database.paths().count(), 1);
+    }
+
+    #[test]
+    fn skips_submodules() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let mut tree = tree(&repository, &[("main.rs", "fn main() {}\n")]);
+        // A submodule is a commit of another repository, which is not in this one.
+        tree.insert("vendor", Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap(), 0o160000).unwrap();
+        let oid = commit(&repository, "master", tree, 100, &[]);
+
+        let database = database(vec![dir.path()]);
+
+        assert_eq!(changes(&database, oid), vec![("main.rs".to_owned(), true)]);
+        assert_eq!(database.paths().count(), 1);
+    }
+}
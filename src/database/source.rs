@@ -1,14 +1,5 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::fs::create_dir_all;
-
-use parasite;
-use parasite::Metadata;
-use parasite::HashId;
-use parasite::StoreKind;
-use parasite::ValidateAll;
-use parasite::Table;
+use std::collections::{HashMap, BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
 
 use anyhow::*;
 use serde_json::Value as JSON;
@@ -17,47 +8,10 @@ use itertools::Itertools;
 
 use crate::Store;
 use crate::objects;
+use crate::languages::LanguageRegistry;
 
-macro_rules! convert {
-    ($type:ident from $id:expr) => {
-        objects::$type::from(Into::<u64>::into($id))
-    };
-    ($type:ident from $id:expr, except $exception:expr) => {
-        if $id == $exception {
-            None
-        } else {
-            Some(objects::$type::from(Into::<u64>::into($id)))
-        }
-    };
-    (($type0:ident, $type1:ident) from $pair:expr) => {
-        (convert!($type0 from $pair.0), convert!($type1 from $pair.1))
-    };
-    (($type0:ident, $type1:ident) from $pair:expr, except (_, $exception1:expr)) => {
-        (convert!($type0 from $pair.0), convert!($type1 from $pair.1, except $exception1))
-    };
-    (($type0:ident, $type1:ident) from $pair:expr, except ($exception0:expr, _)) => {
-        (convert!($type0 from $pair.0, except $exception0), convert!($type1 from $pair.1))
-    };
-    (Vec<$type:ident> from $collection:expr) => {
-        $collection.into_iter().map(|id| convert!($type from id)).collect()
-    };
-    (HashMap<$key_type:ident, $value_type:ident> from $collection:expr) => {
-        $collection.into_iter().map(|entry| convert!(($key_type, $value_type) from entry)).collect()
-    };
-    (HashMap<$key_type:ident, $value_type:ident> from $collection:expr, except (_, $value_exception:expr)) => {
-        $collection.into_iter()
-            .map(|entry| {
-                convert!(($key_type, $value_type) from entry, except (_, $value_exception))
-            }).collect()
-    };
-}
-
-macro_rules! parse_github_json {
-    ($entity_name:expr, $id:expr, $string:expr) => {
-        JSON::from_str($string.as_str())
-           .with_context(|| format!("Cannot read GitHub metadata for {} {}", $entity_name, $id));
-    }
-}
+use super::datastore::DatastoreSource;
+use super::memory::InMemorySource;
 
 pub type URL = String;
 pub type SHA = String;
@@ -65,201 +19,402 @@ pub type Path = String;
 pub type Timestamp = i64;
 pub type Bytes = Vec<u8>;
 
-pub const MERGED_SUBSTORE_DIR_NAME: &'static str = "merged_store";
+/*
+ * Where the data that Database serves ultimately comes from.
+ *
+ * A data source lists everything it contains, regardless of the savepoint:
+ * Source hides whatever was recorded after the savepoint on top of any data
+ * source. The actual data comes from a parasite datastore (`DatastoreSource`),
+ * but for tests and examples it is more convenient to provide a handful of
+ * projects by hand (`InMemorySource`), which is also what exported datasets
+ * are read into.
+ */
+pub trait DataSource: Send + Sync {
+    fn project_logs<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Vec<ProjectLog>)> + 'a>;
+    fn project_urls<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, URL)> + 'a>;
+    fn project_substores<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Store)> + 'a>;
+    fn project_credentials<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, String)> + 'a>;
+    fn project_heads<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, HashMap<String, (objects::CommitId, SHA)>)> + 'a>;
+    fn project_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::ProjectId, Result<JSON>)> + 'a>;
+
+    fn commit_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, SHA)> + 'a>;
+    fn commit_info<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, CommitBasics)> + 'a>;
+    fn commit_github_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::CommitId, Result<JSON>)> + 'a>;
+
+    /* The commits with the given ids, for backends that can look them up faster than by going
+       through all of them. */
+    fn commit_info_of<'a>(&'a self, ids: &'a BTreeSet<objects::CommitId>) -> Box<dyn Iterator<Item=(objects::CommitId, CommitBasics)> + 'a> {
+        Box::new(self.commit_info().filter(move |(id, _)| ids.contains(id)))
+    }
+
+    fn get_snapshot(&self, id: objects::SnapshotId) -> Option<Bytes>;
+    fn has_snapshot(&self, id: objects::SnapshotId) -> bool {
+        self.get_snapshot(id).is_some()
+    }
+    fn snapshot_bytes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::SnapshotId, Bytes)> + 'a>;
+    fn snapshot_has_contents<'a>(&'a self) -> Box<dyn Iterator<Item=objects::SnapshotId> + 'a> {
+        Box::new(self.snapshot_bytes().map(|(id, _)| id))
+    }
+    fn snapshot_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::SnapshotId, Result<JSON>)> + 'a>;
+
+    fn path_hashes<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::PathId, SHA)> + 'a>;
+    fn paths<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::PathId, Path)> + 'a>;
+
+    fn user_emails<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::UserId, String)> + 'a>;
+    fn user_metadata<'a>(&'a self) -> Box<dyn Iterator<Item=(objects::UserId, Result<JSON>)> + 'a>;
+}
+
+/* An entry in the log of updates the datastore made to a project. Updates that downloaded new
+   commits record the heads (by name and hash) the project had at the time. */
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ProjectLog {
+    time: Timestamp,
+    error: bool,
+    heads: Option<Vec<(String, SHA)>>,
+}
+
+impl ProjectLog {
+    pub fn new(time: Timestamp, error: bool) -> Self { ProjectLog { time, error, heads: None } }
+    pub fn update(time: Timestamp, heads: Vec<(String, SHA)>) -> Self { ProjectLog { time, error: false, heads: Some(heads) } }
+    pub fn time(&self) -> Timestamp { self.time }
+    pub fn is_error(&self) -> bool { self.error }
+    pub fn heads(&self) -> Option<&Vec<(String, SHA)>> { self.heads.as_ref() }
+}
+
+/*
+ * The parts of the datastore that were not there yet at the savepoint.
+ *
+ * The datastore keeps growing, but queries should see it as it was at the
+ * savepoint, so that their results do not change between runs. What was
+ * there is told by the project logs: a project is hidden if everything that
+ * was logged about it was logged after the savepoint, and the heads of the
+ * other projects are the heads logged by their last update before the
+ * savepoint. The commits that are not reachable from those heads were
+ * downloaded after the savepoint (or only for hidden projects), so they are
+ * hidden, whenever they were committed. Snapshots, paths, and users are
+ * hidden if they occur only in hidden commits.
+ *
+ * Projects whose logs do not record heads (as those of older datastores)
+ * fall back on their current heads, moved back along first parents to the
+ * most recent commit committed before the savepoint.
+ *
+ * Metadata is hidden along with the project, commit, snapshot, or user that
+ * it describes. The metadata of projects that are not hidden is whatever was
+ * downloaded last, because the datastore does not keep older versions of it.
+ */
+#[derive(Default)]
+struct Hidden {
+    projects: BTreeSet<objects::ProjectId>,
+    commits: BTreeSet<objects::CommitId>,
+    snapshots: BTreeSet<objects::SnapshotId>,
+    paths: BTreeSet<objects::PathId>,
+    users: BTreeSet<objects::UserId>,
+    heads: BTreeMap<objects::ProjectId, HashMap<String, (objects::CommitId, SHA)>>, // of the projects that are not hidden
+}
 
-#[allow(dead_code)]
 pub struct Source {
-    store: parasite::DatastoreView,
-    substore: parasite::StoreKind,
+    backend: Box<dyn DataSource>,
     savepoint: Timestamp,
+    hidden: Mutex<Option<Arc<Hidden>>>,
+    languages: LanguageRegistry,
 }
 
 impl Source {
-    fn from_one_subset<Sd>(dataset_path: Sd, savepoint: i64, substore: Store) -> Result<Self> where Sd: Into<String> {
+    /** Reads from the parasite datastore at `dataset_path`, merging the given substores if necessary.
+
+        If `dataset_path` holds a dataset exported by djanco instead (see `Database::export_projects`),
+        it is read into memory whole, and the substores are ignored.
+     */
+    pub fn new<Sc,Sd>(dataset_path: Sd, cache_path: Sc, savepoint: i64, substores: Vec<Store>) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
         let dataset_path = dataset_path.into();
-        //println!("Store path: {}", s);
-        let store = parasite::DatastoreView::from(dataset_path.as_str());
-        Ok(Source { store, savepoint, substore: substore.kind() })
+        if InMemorySource::is_dataset_dir(&dataset_path) {
+            let backend = InMemorySource::from_dir(&dataset_path)?;
+            return Ok(Self::from_backend(backend, savepoint))
+        }
+        let backend = DatastoreSource::new(dataset_path, cache_path, substores)?;
+        Ok(Self::from_backend(backend, savepoint))
+    }
+
+    /** Reads from any data source, as of the given savepoint. */
+    pub fn from_backend<D>(backend: D, savepoint: Timestamp) -> Self where D: DataSource + 'static {
+        Source { backend: Box::new(backend), savepoint, hidden: Mutex::new(None), languages: LanguageRegistry::default() }
+    }
+
+    pub fn savepoint(&self) -> Timestamp {
+        self.savepoint
     }
 
-    fn from_all_subsets<Sc,Sd>(dataset_path: Sd, cache_path: Sc, savepoint: i64) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
-        let all_stores: Vec<Store> = StoreKind::all().map(|kind| Store::from(kind)).collect();
-        Self::from_multiple_subsets(dataset_path, cache_path, savepoint, all_stores)
+    /** The registry that tells which language the files in this source are written in. */
+    pub fn language_registry(&self) -> &LanguageRegistry {
+        &self.languages
+    }
+    pub fn set_language_registry(&mut self, registry: LanguageRegistry) {
+        self.languages = registry;
+    }
+
+    /* Works out what to hide on first use, because it takes a pass over all commits. */
+    fn hidden(&self) -> Arc<Hidden> {
+        let mut hidden = self.hidden.lock().unwrap();
+        if hidden.is_none() {
+            let mut found = self.find_hidden(self.savepoint);
+            self.hide_contents(&mut found);
+            *hidden = Some(Arc::new(found));
+        }
+        hidden.as_ref().unwrap().clone()
     }
 
-    fn from_multiple_subsets<Sc,Sd>(dataset_path: Sd, cache_path: Sc, savepoint: i64, substores: Vec<Store>) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
-        let mut cache_path = PathBuf::from(cache_path.into());
-        //let mut merged_store_path = CacheDir::from(cache_path, savepoint, substores.clone()).as_path();
-        cache_path.push(MERGED_SUBSTORE_DIR_NAME);
-        let merged_store_path_string = cache_path.as_os_str().to_str().unwrap();
-        create_dir_all(merged_store_path_string)?;
-
-        let mut merger = 
-            parasite::DatastoreMerger::new(merged_store_path_string, dataset_path.into().as_str());
-        for substore in substores {
-            merger.merge_substore(StoreKind::Generic, substore.kind(), ValidateAll::new())
+    /* Finds the projects and commits that are hidden at the given savepoint, and the heads of the
+       other projects. */
+    fn find_hidden(&self, savepoint: Timestamp) -> Hidden {
+        let mut hidden = Hidden::default();
+
+        let mut logged_heads: BTreeMap<objects::ProjectId, Vec<(String, SHA)>> = BTreeMap::new();
+        for (project_id, logs) in self.backend.project_logs() {
+            let logs: Vec<ProjectLog> = logs.into_iter().filter(|log| log.time() <= savepoint).collect();
+            if logs.is_empty() {
+                hidden.projects.insert(project_id);
+                continue
+            }
+            let last_update = logs.into_iter()
+                .filter(|log| log.heads.is_some())
+                .max_by_key(|log| log.time());
+            if let Some(heads) = last_update.and_then(|log| log.heads) {
+                logged_heads.insert(project_id, heads);
+            }
         }
 
-        //println!("Merged store path: {}", merged_store_path_string);
-        let store = parasite::DatastoreView::from(merged_store_path_string);
-        Ok(Source { store, savepoint, substore: StoreKind::Generic })
+        let graph: BTreeMap<objects::CommitId, (Timestamp, Vec<objects::CommitId>)> = self.backend.commit_info()
+            .map(|(commit_id, commit)| (commit_id, (commit.committer_time, commit.parents)))
+            .collect();
+
+        // Projects without logged heads: their current heads, moved back to before the savepoint.
+        let mut current_heads: BTreeMap<objects::ProjectId, HashMap<String, (objects::CommitId, SHA)>> = BTreeMap::new();
+        for (project_id, heads) in self.backend.project_heads() {
+            if hidden.projects.contains(&project_id) || logged_heads.contains_key(&project_id) {
+                continue
+            }
+            let heads = heads.into_iter().flat_map(|(name, (commit_id, hash))| {
+                let mut moved_to = commit_id;
+                while graph.get(&moved_to).map_or(false, |(time, _)| *time > savepoint) {
+                    moved_to = *graph[&moved_to].1.first()?;
+                }
+                // The hash of the commit the head is moved to is filled in below.
+                Some((name, (moved_to, if moved_to == commit_id { hash } else { String::new() })))
+            }).collect();
+            current_heads.insert(project_id, heads);
+        }
+
+        let wanted_hashes: BTreeSet<&SHA> = logged_heads.values().flatten().map(|(_, hash)| hash).collect();
+        let wanted_ids: BTreeSet<objects::CommitId> = current_heads.values().flat_map(|heads| heads.values())
+            .filter(|(_, hash)| hash.is_empty())
+            .map(|(commit_id, _)| *commit_id)
+            .collect();
+        let mut ids_by_hash: BTreeMap<SHA, objects::CommitId> = BTreeMap::new();
+        let mut hashes_by_id: BTreeMap<objects::CommitId, SHA> = BTreeMap::new();
+        if !wanted_hashes.is_empty() || !wanted_ids.is_empty() {
+            for (commit_id, hash) in self.backend.commit_hashes() {
+                if wanted_ids.contains(&commit_id) {
+                    hashes_by_id.insert(commit_id, hash.clone());
+                }
+                if wanted_hashes.contains(&hash) {
+                    ids_by_hash.insert(hash, commit_id);
+                }
+            }
+        }
+
+        for (project_id, heads) in logged_heads {
+            let heads = heads.into_iter()
+                .flat_map(|(name, hash)| ids_by_hash.get(&hash).map(|commit_id| (name, (*commit_id, hash))))
+                .collect();
+            hidden.heads.insert(project_id, heads);
+        }
+        for (project_id, mut heads) in current_heads {
+            for (commit_id, hash) in heads.values_mut() {
+                if hash.is_empty() {
+                    *hash = hashes_by_id.get(&*commit_id).cloned().unwrap_or_default();
+                }
+            }
+            hidden.heads.insert(project_id, heads);
+        }
+
+        // Everything reachable from the heads was there at the savepoint.
+        let mut visible: BTreeSet<objects::CommitId> = BTreeSet::new();
+        let mut stack: Vec<objects::CommitId> = hidden.heads.values()
+            .flat_map(|heads| heads.values().map(|(commit_id, _)| *commit_id))
+            .collect();
+        while let Some(commit_id) = stack.pop() {
+            if !visible.insert(commit_id) {
+                continue
+            }
+            if let Some((_, parents)) = graph.get(&commit_id) {
+                stack.extend(parents.iter().filter(|parent| !visible.contains(parent)));
+            }
+        }
+        hidden.commits = graph.into_iter()
+            .map(|(commit_id, _)| commit_id)
+            .filter(|commit_id| !visible.contains(commit_id))
+            .collect();
+        hidden
     }
 
-    pub fn new<Sc,Sd>(dataset_path: Sd, cache_path: Sc, savepoint: i64, substores: Vec<Store>) -> Result<Self> where Sd: Into<String>, Sc: Into<String> {
-        match substores.len() {
-            0 => Self::from_all_subsets(dataset_path, cache_path, savepoint),
-            1 => Self::from_one_subset(dataset_path, savepoint, substores.into_iter().last().unwrap()),
-            _ => Self::from_multiple_subsets(dataset_path, cache_path, savepoint, substores),
+    /* Finds the snapshots, paths, and users that occur only in hidden commits. Whatever the hidden
+       commits share with the visible ones has to stay visible. */
+    fn hide_contents(&self, hidden: &mut Hidden) {
+        if !hidden.commits.is_empty() {
+            let mut visible_snapshots: BTreeSet<objects::SnapshotId> = BTreeSet::new();
+            let mut visible_paths: BTreeSet<objects::PathId> = BTreeSet::new();
+            let mut visible_users: BTreeSet<objects::UserId> = BTreeSet::new();
+            for (commit_id, commit) in self.backend.commit_info() {
+                let (snapshots, paths, users) = if hidden.commits.contains(&commit_id) {
+                    (&mut hidden.snapshots, &mut hidden.paths, &mut hidden.users)
+                } else {
+                    (&mut visible_snapshots, &mut visible_paths, &mut visible_users)
+                };
+                snapshots.extend(commit.changes.iter().flat_map(|(_, snapshot_id)| *snapshot_id));
+                paths.extend(commit.changes.iter().map(|(path_id, _)| *path_id));
+                users.insert(commit.author);
+                users.insert(commit.committer);
+            }
+            hidden.snapshots.retain(|snapshot_id| !visible_snapshots.contains(snapshot_id));
+            hidden.paths.retain(|path_id| !visible_paths.contains(path_id));
+            hidden.users.retain(|user_id| !visible_users.contains(user_id));
         }
     }
 
-    pub fn project_logs(&self) -> impl Iterator<Item = (objects::ProjectId, Vec<parasite::ProjectLog>)> {
-        self.store.project_updates()
-            .map(|(id, log)| (convert!(ProjectId from id), log))
-            .into_group_map()
-            .into_iter()
-            .map(|(project, logs)| (project, logs.into_iter().sorted_by_key(|log| -log.time()).collect() ))
-    } 
-
-    pub fn project_urls(&self) -> impl Iterator<Item=(objects::ProjectId, URL)> {
-        self.store.project_urls()
-            .into_iter()
-            .map(|(id, url)| {
-                (convert!(ProjectId from id), url.clone_url())
-            })
+    /** The commits that are there at this savepoint, but were not there yet at an earlier one.
+
+        Caches of the earlier savepoint are brought up to date by extracting just these (see
+        `Data::update_from`). Telling which they are takes another pass over the parents of all
+        commits, as working out what is hidden at this savepoint does.
+     */
+    pub fn commits_added_since(&self, earlier_savepoint: Timestamp) -> BTreeSet<objects::CommitId> {
+        let hidden_then = self.find_hidden(earlier_savepoint);
+        let hidden_now = self.hidden();
+        hidden_then.commits.difference(&hidden_now.commits).copied().collect()
     }
 
-    pub fn project_substores(&self) -> impl Iterator<Item=(objects::ProjectId, Store)> {
-        self.store.project_substores()
-            .into_iter()
-            .map(|(id, kind)| {
-                (convert!(ProjectId from id), Store::from(kind))
+    pub fn project_logs(&self) -> impl Iterator<Item = (objects::ProjectId, Vec<ProjectLog>)> + '_ {
+        let hidden = self.hidden();
+        let savepoint = self.savepoint;
+        self.backend.project_logs()
+            .filter(move |(project_id, _)| !hidden.projects.contains(project_id))
+            .map(move |(project_id, logs)| {
+                let logs = logs.into_iter()
+                    .filter(|log| log.time() <= savepoint)
+                    .sorted_by_key(|log| -log.time())
+                    .collect();
+                (project_id, logs)
             })
     }
 
+    pub fn project_urls(&self) -> impl Iterator<Item=(objects::ProjectId, URL)> + '_ {
+        let hidden = self.hidden();
+        self.backend.project_urls()
+            .filter(move |(id, _)| !hidden.projects.contains(id))
+    }
 
-    pub fn project_credentials(&self) -> impl Iterator<Item=(objects::ProjectId, String)> {
-        self.store.project_urls()
-            .into_iter()
-            .map(|(id, url)| {
-                (convert!(ProjectId from id), url.name())
-            })
+    pub fn project_substores(&self) -> impl Iterator<Item=(objects::ProjectId, Store)> + '_ {
+        let hidden = self.hidden();
+        self.backend.project_substores()
+            .filter(move |(id, _)| !hidden.projects.contains(id))
+    }
+
+    pub fn project_credentials(&self) -> impl Iterator<Item=(objects::ProjectId, String)> + '_ {
+        let hidden = self.hidden();
+        self.backend.project_credentials()
+            .filter(move |(id, _)| !hidden.projects.contains(id))
     }
 
     pub fn project_heads(&self) -> impl Iterator<Item=(objects::ProjectId, HashMap<String, (objects::CommitId, SHA)>)> {
-        self.store.project_heads()
-            .into_iter()
-            .map(|(project_id, heads)| {
-                let heads = heads.into_iter()
-                    .map(|(name, (commit_id, hash))| {
-                        (name, (convert!(CommitId from commit_id), hash.to_string()))
-                    })
-                    .collect::<HashMap<String, (objects::CommitId, String)>>();
-                (convert!(ProjectId from project_id), heads)
-            })
+        self.hidden().heads.clone().into_iter()
     }
 
-    pub fn project_github_metadata(&self) -> impl Iterator<Item=(objects::ProjectId, Result<JSON>)> {
-        self.store.project_metadata()
-            .into_iter()
-            .filter(|(_, metadata)| metadata.key == Metadata::GITHUB_METADATA)
-            .map(|(id, metadata)| {
-                (convert!(ProjectId from id), parse_github_json!("Project", id, metadata.value))
-            })
+    pub fn project_github_metadata(&self) -> impl Iterator<Item=(objects::ProjectId, Result<JSON>)> + '_ {
+        let hidden = self.hidden();
+        self.backend.project_github_metadata()
+            .filter(move |(id, _)| !hidden.projects.contains(id))
     }
 
-    pub fn commit_hashes(&self) -> impl Iterator<Item=(objects::CommitId, SHA)> {
-        self.store.commits(self.substore)
-            .into_iter()
-            .map(|(id, sha)| (convert!(CommitId from id), sha.to_string()))
+    pub fn commit_hashes(&self) -> impl Iterator<Item=(objects::CommitId, SHA)> + '_ {
+        let hidden = self.hidden();
+        self.backend.commit_hashes()
+            .filter(move |(id, _)| !hidden.commits.contains(id))
     }
 
-    pub fn commit_info(&self) -> impl Iterator<Item=(objects::CommitId, CommitBasics)> {
-        self.store.commits_info(self.substore)
-            .into_iter()
-            .map(|(commit_id, info)| {
-                let commit_basics = CommitBasics {
-                    committer: convert!(UserId from info.committer),
-                    committer_time: info.committer_time,
-                    author: convert!(UserId from info.author),
-                    author_time: info.author_time,
-                    parents: convert!(Vec<CommitId> from info.parents),
-                    changes: convert!(HashMap<PathId, SnapshotId> from info.changes,
-                                                                  except (_, HashId::DELETED)),
-                    message: info.message,
-                };
-                (convert!(CommitId from commit_id), commit_basics)
-            })
+    pub fn commit_info(&self) -> impl Iterator<Item=(objects::CommitId, CommitBasics)> + '_ {
+        let hidden = self.hidden();
+        self.backend.commit_info()
+            .filter(move |(id, _)| !hidden.commits.contains(id))
     }
 
-    pub fn commit_github_metadata(&self) -> impl Iterator<Item=(objects::CommitId, Result<JSON>)> {
-        self.store.commits_metadata(self.substore)
-            .into_iter()
-            .filter(|(_, metadata)| metadata.key == Metadata::GITHUB_METADATA)
-            .map(|(id, metadata)| {
-                (convert!(CommitId from id), parse_github_json!("Commit", id, metadata.value))
-            })
+    /** The commits with the given ids, leaving out those that are hidden. */
+    pub fn commit_info_of<'a>(&'a self, ids: &'a BTreeSet<objects::CommitId>) -> impl Iterator<Item=(objects::CommitId, CommitBasics)> + 'a {
+        let hidden = self.hidden();
+        self.backend.commit_info_of(ids)
+            .filter(move |(id, _)| !hidden.commits.contains(id))
+    }
+
+    pub fn commit_github_metadata(&self) -> impl Iterator<Item=(objects::CommitId, Result<JSON>)> + '_ {
+        let hidden = self.hidden();
+        self.backend.commit_github_metadata()
+            .filter(move |(id, _)| !hidden.commits.contains(id))
     }
 
-    // TODO hashes?
     pub fn get_snapshot(&self, id: objects::SnapshotId) -> Option<Bytes> {
-        self.store.contents(self.substore)
-            .get(parasite::HashId::from(Into::<u64>::into(id)))
-            .map(|(_kind, hash)| hash)
+        if self.hidden().snapshots.contains(&id) {
+            return None
+        }
+        self.backend.get_snapshot(id)
     }
 
-    pub fn snapshot_bytes(&self) -> impl Iterator<Item=(objects::SnapshotId, Bytes)> {
-        self.store.contents(self.substore)
-            .into_iter()
-            .map(|(id, (_kind, contents))| {
-                (convert!(SnapshotId from id), contents)
-            })
-        // Maybe I could do something with `kind` but I'm not sure how to handle it. On one hand it
-        // really is a distinction of languages, so I could classify it like that, but there's a big
-        // black hole of `small files` that don't make a lot of sense when filtering.
+    pub fn has_snapshot(&self, id: objects::SnapshotId) -> bool {
+        !self.hidden().snapshots.contains(&id) && self.backend.has_snapshot(id)
     }
 
-    pub fn snapshot_has_contents(&self) -> impl Iterator<Item = objects::SnapshotId> {
-        self.store.contents(self.substore)
-            .into_iter()
-            .map(|(id, _)| {
-                convert!(SnapshotId from id)
-            })
+    pub fn snapshot_bytes(&self) -> impl Iterator<Item=(objects::SnapshotId, Bytes)> + '_ {
+        let hidden = self.hidden();
+        self.backend.snapshot_bytes()
+            .filter(move |(id, _)| !hidden.snapshots.contains(id))
     }
 
-    pub fn snapshot_metadata(&self) -> impl Iterator<Item=(objects::SnapshotId, Result<JSON>)> {
-        self.store.contents_metadata(self.substore)
-            .into_iter()
-            .map(|(id, metadata)| {
-                (convert!(SnapshotId from id), parse_github_json!("Snapshot", id, metadata.value))
-            })
+    pub fn snapshot_has_contents(&self) -> impl Iterator<Item = objects::SnapshotId> + '_ {
+        let hidden = self.hidden();
+        self.backend.snapshot_has_contents()
+            .filter(move |id| !hidden.snapshots.contains(id))
+    }
+
+    pub fn snapshot_metadata(&self) -> impl Iterator<Item=(objects::SnapshotId, Result<JSON>)> + '_ {
+        let hidden = self.hidden();
+        self.backend.snapshot_metadata()
+            .filter(move |(id, _)| !hidden.snapshots.contains(id))
     }
 
-    pub fn path_hashes(&self) -> impl Iterator<Item=(objects::PathId, SHA)> {
-        self.store.paths(self.substore).into_iter().map(|(id, hash)| {
-            (convert!(PathId from id), hash.to_string())
-        })
+    pub fn path_hashes(&self) -> impl Iterator<Item=(objects::PathId, SHA)> + '_ {
+        let hidden = self.hidden();
+        self.backend.path_hashes()
+            .filter(move |(id, _)| !hidden.paths.contains(id))
     }
 
-    pub fn paths(&self) -> impl Iterator<Item=(objects::PathId, Path)> {
-        self.store.paths_strings(self.substore).into_iter().map(|(id, path)| {
-            (convert!(PathId from id), path)
-        })
+    pub fn paths(&self) -> impl Iterator<Item=(objects::PathId, Path)> + '_ {
+        let hidden = self.hidden();
+        self.backend.paths()
+            .filter(move |(id, _)| !hidden.paths.contains(id))
     }
 
-    pub fn user_emails(&self) -> impl Iterator<Item=(objects::UserId, String)> {
-        self.store.users(self.substore).into_iter().map(|(id, email)| {
-            (convert!(UserId from id), email)
-        })
+    pub fn user_emails(&self) -> impl Iterator<Item=(objects::UserId, String)> + '_ {
+        let hidden = self.hidden();
+        self.backend.user_emails()
+            .filter(move |(id, _)| !hidden.users.contains(id))
     }
 
-    pub fn user_metadata(&self) -> impl Iterator<Item=(objects::UserId, Result<JSON>)> {
-        self.store.users_metadata(self.substore).into_iter().map(|(id, metadata)| {
-            (convert!(UserId from id), parse_github_json!("User", id, metadata.value))
-        })
+    pub fn user_metadata(&self) -> impl Iterator<Item=(objects::UserId, Result<JSON>)> + '_ {
+        let hidden = self.hidden();
+        self.backend.user_metadata()
+            .filter(move |(id, _)| !hidden.users.contains(id))
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct CommitBasics {
     pub committer : objects::UserId,
     pub committer_time : Timestamp,
@@ -0,0 +1,99 @@
+use std::ops::Deref;
+use std::path::{Path as FilePath, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::Djanco;
+use crate::log::{Log, Verbosity};
+use crate::objects::{CommitId, UserId};
+
+use super::Database;
+use super::source::{DataSource, Source, CommitBasics, Timestamp};
+
+/*
+ * Fixtures shared by the tests that run queries on a handful of projects.
+ *
+ * The projects come from a data source put together by the test (usually an
+ * `InMemorySource` with commits made by `commit`), which a `TestDatabase`
+ * serves. Its caches go into a temporary directory that is removed along with
+ * it, unless the test wants to open several databases over the same caches:
+ *
+ * ```
+ * let database = testing::database(source).open();
+ * let earlier = testing::database(source.clone()).at(250).in_cache(cache.path()).open();
+ * ```
+ */
+
+/** A commit with no changes and an empty message, by one user who both authored and committed it. */
+pub(crate) fn commit(user: UserId, time: Timestamp, parents: Vec<CommitId>) -> CommitBasics {
+    CommitBasics {
+        committer: user, committer_time: time,
+        author: user, author_time: time,
+        parents, changes: vec![], message: String::new(),
+    }
+}
+
+/** Starts opening a database over the given source, at savepoint 1000 with a cache of its own. */
+pub(crate) fn database<D>(source: D) -> TestDatabaseBuilder<D> where D: DataSource + 'static {
+    TestDatabaseBuilder { source, savepoint: 1000, cache: None, memory_budget: None }
+}
+
+pub(crate) struct TestDatabaseBuilder<D> {
+    source: D,
+    savepoint: Timestamp,
+    cache: Option<PathBuf>,
+    memory_budget: Option<usize>,
+}
+
+impl<D> TestDatabaseBuilder<D> where D: DataSource + 'static {
+    pub(crate) fn at(mut self, savepoint: Timestamp) -> Self {
+        self.savepoint = savepoint;
+        self
+    }
+    /** Keeps the caches in the given directory instead of a temporary one. */
+    pub(crate) fn in_cache(mut self, dir: &FilePath) -> Self {
+        self.cache = Some(dir.to_path_buf());
+        self
+    }
+    pub(crate) fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+    pub(crate) fn open(self) -> TestDatabase {
+        let (temporary, cache) = match self.cache {
+            Some(dir) => (None, dir),
+            None => {
+                let temporary = tempfile::tempdir().unwrap();
+                let cache = temporary.path().to_path_buf();
+                (Some(temporary), cache)
+            }
+        };
+        let source = Source::from_backend(self.source, self.savepoint);
+        let database = Djanco::from_source(source, cache.to_str().unwrap(), Log::new(Verbosity::Warning));
+        let database = match self.memory_budget {
+            Some(bytes) => database.with_memory_budget(bytes),
+            None => database,
+        };
+        TestDatabase { database, cache, _temporary: temporary }
+    }
+}
+
+/** A database whose caches are removed when it is dropped, unless they were put somewhere else. */
+pub(crate) struct TestDatabase {
+    database: Database,
+    cache: PathBuf,
+    _temporary: Option<TempDir>,
+}
+
+impl TestDatabase {
+    pub(crate) fn cache_path(&self) -> &FilePath {
+        &self.cache
+    }
+}
+
+impl Deref for TestDatabase {
+    type Target = Database;
+    fn deref(&self) -> &Database {
+        &self.database
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::*;
+use rayon::prelude::*;
+
+use crate::log::Verbosity;
+use crate::Timestamp;
+
+use super::Database;
+use super::data::{Data, CollectionSpec, CollectionOrigin};
+
+/*
+ * Building caches ahead of time, so that queries do not have to.
+ *
+ * Caches are built in waves. Each wave consists of the caches whose
+ * prerequisites were all built in earlier waves, so caches within a wave do
+ * not depend on one another and are built in parallel. The caches of a wave
+ * are built by an instance of Data of their own, which loads the
+ * prerequisites they share from their cache files once, and drops everything
+ * once the wave is done. Between waves nothing is kept in memory.
+ *
+ * When caches for an earlier savepoint exist, the caches that only grow over
+ * time (commits and the like) can be updated from those rather than built
+ * from scratch, before the remaining caches are built.
+ */
+
+/** Lists all caches that can be built, with the caches each of them is derived from. */
+pub fn all_caches() -> Vec<CollectionSpec> {
+    Data::collections()
+}
+
+/** Arranges the given caches, and all the caches they are derived from, into waves.
+
+    If no names are given, all caches are included.
+ */
+pub fn plan_waves(names: &[String]) -> Result<Vec<Vec<&'static str>>> {
+    let collections: BTreeMap<&'static str, CollectionSpec> =
+        Data::collections().into_iter().map(|collection| (collection.name, collection)).collect();
+
+    let requested: Vec<&'static str> = if names.is_empty() {
+        collections.keys().copied().collect()
+    } else {
+        names.iter().map(|name| {
+            collections.get_key_value(name.as_str())
+                .map(|(name, _)| *name)
+                .ok_or_else(|| anyhow!("there is no cache named {}", name))
+        }).collect::<Result<Vec<&'static str>>>()?
+    };
+
+    fn wave_of(name: &'static str,
+               collections: &BTreeMap<&'static str, CollectionSpec>,
+               waves: &mut BTreeMap<&'static str, usize>) -> usize {
+        if let Some(wave) = waves.get(name) {
+            return *wave
+        }
+        let wave = collections[name].prerequisites.iter()
+            .map(|prerequisite| wave_of(*prerequisite, collections, waves) + 1)
+            .max()
+            .unwrap_or(0);
+        waves.insert(name, wave);
+        wave
+    }
+
+    let mut waves: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for name in requested {
+        wave_of(name, &collections, &mut waves);
+    }
+
+    let mut plan: Vec<Vec<&'static str>> = vec![vec![]; waves.values().max().map_or(0, |wave| wave + 1)];
+    for (name, wave) in waves {
+        plan[wave].push(name);
+    }
+    Ok(plan)
+}
+
+impl Database {
+    /** Builds the given caches, and all the caches they are derived from, unless they are up to date.
+
+        If no names are given, all caches are built. Independent caches are built in parallel on
+        the rayon thread pool. The caches built in one wave stay in memory until the wave is done,
+        unless that exceeds the memory budget of the database. Returns the number of caches that
+        were built.
+     */
+    pub fn build_caches(&self, names: &[String]) -> Result<usize> {
+        let waves = plan_waves(names)?;
+        let total: usize = waves.iter().map(|wave| wave.len()).sum();
+        let format = self.cache_format();
+
+        let needs_metadata = waves.iter().flatten().any(|name| {
+            Data::collection(name).map_or(false, |collection| collection.origin == CollectionOrigin::Metadata)
+        });
+        if needs_metadata {
+            let event = self.log.start(Verbosity::Log, "preparing project metadata");
+            Data::new(self.cache_dir.clone(), self.log.clone()).prepare_metadata(&self.source)?;
+            self.log.end(event);
+        }
+
+        let started = AtomicUsize::new(0);
+        let built = AtomicUsize::new(0);
+        for (index, wave) in waves.iter().enumerate() {
+            let event = self.log.start(Verbosity::Log,
+                format!("building wave {} of {} ({} caches)", index + 1, waves.len(), wave.len()));
+
+            let mut data = Data::new(self.cache_dir.clone(), self.log.clone());
+            data.set_cache_format(format);
+            data.set_language_registry(self.source.language_registry().fingerprint());
+            data.set_memory_budget(self.memory_budget());
+
+            let outcomes: Vec<Result<()>> = wave.par_iter().map(|name| {
+                let number = started.fetch_add(1, Ordering::SeqCst) + 1;
+                let event = self.log.start(Verbosity::Log, format!("cache {} ({}/{})", name, number, total));
+                if data.build_cache(name, &self.source)? {
+                    built.fetch_add(1, Ordering::SeqCst);
+                }
+                data.enforce_memory_budget();
+                self.log.end(event);
+                Ok(())
+            }).collect();
+
+            self.log.end(event);
+            outcomes.into_iter().collect::<Result<()>>()?;
+        }
+
+        Ok(built.into_inner())
+    }
+
+    /** Updates the caches of an earlier savepoint to this one, for those caches where processing
+        only what was added in between is enough (see `Data::update_from`).
+
+        If no savepoint is given, the most recent savepoint earlier than this one that has caches
+        is used, and if there is none nothing is updated. Returns the names of updated caches.
+        The caches that were not updated can then be built as usual by `build_caches`.
+     */
+    pub fn update_caches(&self, earlier_savepoint: Option<Timestamp>) -> Result<Vec<&'static str>> {
+        let earlier_savepoint = match earlier_savepoint.or_else(|| self.cache_dir.earlier_savepoints().last().copied()) {
+            Some(savepoint) => savepoint,
+            None => return Ok(vec![]),
+        };
+        let format = self.cache_format();
+
+        let event = self.log.start(Verbosity::Log, format!("updating caches from savepoint {}", earlier_savepoint));
+        let mut data = Data::new(self.cache_dir.clone(), self.log.clone());
+        data.set_cache_format(format);
+        let mut earlier = Data::new(self.cache_dir.at_savepoint(earlier_savepoint), self.log.clone());
+        earlier.set_cache_format(format);
+        let updated = data.update_from(&mut earlier, &self.source)?;
+        self.log.end(event);
+
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::{plan_waves, all_caches};
+
+    #[test]
+    fn waves_come_after_their_prerequisites() {
+        let waves = plan_waves(&[]).unwrap();
+        assert_eq!(waves.iter().map(|wave| wave.len()).sum::<usize>(), all_caches().len());
+
+        let mut built: BTreeSet<&str> = BTreeSet::new();
+        for wave in waves {
+            for name in wave.iter() {
+                let collection = all_caches().into_iter().find(|collection| collection.name == *name).unwrap();
+                assert!(collection.prerequisites.iter().all(|prerequisite| built.contains(prerequisite)),
+                        "{} is built before its prerequisites", name);
+            }
+            built.extend(wave);
+        }
+    }
+
+    #[test]
+    fn waves_include_prerequisites() {
+        let waves = plan_waves(&["project_commit_count".to_owned()]).unwrap();
+        assert_eq!(waves, vec![vec!["commits", "project_heads"], vec!["project_commits"], vec!["project_commit_count"]]);
+
+        assert!(plan_waves(&["no_such_cache".to_owned()]).is_err());
+    }
+}
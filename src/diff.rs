@@ -0,0 +1,272 @@
+use std::fmt::Write;
+
+/* Lines of context around each hunk of a unified diff. */
+const CONTEXT: usize = 3;
+
+/** One step of turning the old lines into the new ones: a line that stays (at its index in the
+    old and in the new lines), a line of the old that is deleted, or a line of the new that is
+    inserted. */
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/** The differences between two texts, line by line.
+
+    Lines are compared along with their line endings, so a last line without a newline differs
+    from the same line with one, as it does for `diff`. The edits are a shortest edit script,
+    found with Myers' algorithm in linear space, so even long files with many changes are cheap
+    to diff in terms of memory.
+ */
+pub struct LineDiff<'a> {
+    old: Vec<&'a str>,
+    new: Vec<&'a str>,
+    edits: Vec<Edit>,
+}
+
+impl<'a> LineDiff<'a> {
+    pub fn new(old: &'a str, new: &'a str) -> Self {
+        let old = split_lines(old);
+        let new = split_lines(new);
+        let mut edits = Vec::with_capacity(old.len().max(new.len()));
+        diff(&old, 0, old.len(), &new, 0, new.len(), &mut edits);
+        LineDiff { old, new, edits }
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+    pub fn lines_added(&self) -> usize {
+        self.edits.iter().filter(|edit| matches!(edit, Edit::Insert(_))).count()
+    }
+    pub fn lines_deleted(&self) -> usize {
+        self.edits.iter().filter(|edit| matches!(edit, Edit::Delete(_))).count()
+    }
+
+    /** The diff in the unified format, with three lines of context, under the given file names
+        (eg. `a/src/main.rs` and `b/src/main.rs`, or `/dev/null` for a file that does not exist).
+        Identical texts have an empty diff. */
+    pub fn unified(&self, old_name: &str, new_name: &str) -> String {
+        let mut output = String::new();
+        let hunks = self.hunks();
+        if hunks.is_empty() {
+            return output
+        }
+        writeln!(output, "--- {}", old_name).unwrap();
+        writeln!(output, "+++ {}", new_name).unwrap();
+        for hunk in hunks {
+            let edits = &self.edits[hunk.0..hunk.1];
+            let (old_start, new_start) = self.position(hunk.0);
+            let old_count = edits.iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+            let new_count = edits.iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+            writeln!(output, "@@ -{} +{} @@", range(old_start, old_count), range(new_start, new_count)).unwrap();
+            for edit in edits {
+                let (marker, line) = match *edit {
+                    Edit::Equal(index, _) => (' ', self.old[index]),
+                    Edit::Delete(index) => ('-', self.old[index]),
+                    Edit::Insert(index) => ('+', self.new[index]),
+                };
+                output.push(marker);
+                output.push_str(line);
+                if !line.ends_with('\n') {
+                    output.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+        output
+    }
+
+    /* Ranges of edits that make up hunks: each change with its context, merging changes whose
+       contexts touch. */
+    fn hunks(&self) -> Vec<(usize, usize)> {
+        let mut hunks: Vec<(usize, usize)> = Vec::new();
+        for (index, edit) in self.edits.iter().enumerate() {
+            if let Edit::Equal(_, _) = edit {
+                continue
+            }
+            let start = index.saturating_sub(CONTEXT);
+            let end = (index + 1 + CONTEXT).min(self.edits.len());
+            match hunks.last_mut() {
+                Some(last) if last.1 >= start => last.1 = end,
+                _ => hunks.push((start, end)),
+            }
+        }
+        hunks
+    }
+
+    /* The number of old and new lines before the edit at the given index. */
+    fn position(&self, index: usize) -> (usize, usize) {
+        self.edits[..index].iter().fold((0, 0), |(old, new), edit| match edit {
+            Edit::Equal(_, _) => (old + 1, new + 1),
+            Edit::Delete(_) => (old + 1, new),
+            Edit::Insert(_) => (old, new + 1),
+        })
+    }
+}
+
+/* A hunk header range: the first line (counted from 1) and the number of lines, or the line
+   before an empty range. */
+fn range(lines_before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", lines_before),
+        1 => format!("{}", lines_before + 1),
+        count => format!("{},{}", lines_before + 1, count),
+    }
+}
+
+/* Lines along with their line endings. */
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (index, _) in text.match_indices('\n') {
+        lines.push(&text[start..index + 1]);
+        start = index + 1;
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/* Appends the edits that turn old[old_start..old_end] into new[new_start..new_end]. The common
+   prefix and suffix are taken off first, and what remains is split at the middle snake. */
+fn diff(old: &[&str], mut old_start: usize, mut old_end: usize,
+        new: &[&str], mut new_start: usize, mut new_end: usize, edits: &mut Vec<Edit>) {
+    while old_start < old_end && new_start < new_end && old[old_start] == new[new_start] {
+        edits.push(Edit::Equal(old_start, new_start));
+        old_start += 1;
+        new_start += 1;
+    }
+    let mut suffix = 0;
+    while old_start < old_end && new_start < new_end && old[old_end - 1] == new[new_end - 1] {
+        old_end -= 1;
+        new_end -= 1;
+        suffix += 1;
+    }
+
+    if old_start == old_end {
+        edits.extend((new_start..new_end).map(Edit::Insert));
+    } else if new_start == new_end {
+        edits.extend((old_start..old_end).map(Edit::Delete));
+    } else {
+        let (x, y, u, v) = middle_snake(&old[old_start..old_end], &new[new_start..new_end]);
+        diff(old, old_start, old_start + x, new, new_start, new_start + y, edits);
+        edits.extend((0..u - x).map(|offset| Edit::Equal(old_start + x + offset, new_start + y + offset)));
+        diff(old, old_start + u, old_end, new, new_start + v, new_end, edits);
+    }
+
+    edits.extend((0..suffix).map(|offset| Edit::Equal(old_end + offset, new_end + offset)));
+}
+
+/* Finds the snake in the middle of a shortest edit script, going forward from the start and
+   backward from the end at the same time until the two meet. Returns where the snake starts
+   and ends, in both texts. */
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize, usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let max = ((n + m + 1) / 2) as usize;
+    let offset = max as isize + 1;
+    let mut forward = vec![0isize; 2 * max + 3];
+    let mut backward = vec![0isize; 2 * max + 3];
+
+    for d in 0..=(max as isize) {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[(offset + k - 1) as usize] < forward[(offset + k + 1) as usize]) {
+                forward[(offset + k + 1) as usize]
+            } else {
+                forward[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[(offset + k) as usize] = x;
+            let c = delta - k;
+            if delta % 2 != 0 && c >= -(d - 1) && c <= d - 1 && x + backward[(offset + c) as usize] >= n {
+                return (x0 as usize, y0 as usize, x as usize, y as usize)
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let mut x = if c == -d || (c != d && backward[(offset + c - 1) as usize] < backward[(offset + c + 1) as usize]) {
+                backward[(offset + c + 1) as usize]
+            } else {
+                backward[(offset + c - 1) as usize] + 1
+            };
+            let mut y = x - c;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[(offset + c) as usize] = x;
+            let k = delta - c;
+            if delta % 2 == 0 && k >= -d && k <= d && x + forward[(offset + k) as usize] >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - x0) as usize, (m - y0) as usize)
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diffs_lines() {
+        let diff = LineDiff::new("a\nb\nc\nd\n", "a\nc\nd\ne\n");
+        assert_eq!((diff.lines_added(), diff.lines_deleted()), (1, 1));
+        assert_eq!(diff.unified("a/f", "b/f"), "--- a/f\n+++ b/f\n@@ -1,4 +1,4 @@\n a\n-b\n c\n d\n+e\n");
+
+        let diff = LineDiff::new("", "x\ny");
+        assert_eq!((diff.lines_added(), diff.lines_deleted()), (2, 0));
+        assert_eq!(diff.unified("/dev/null", "b/f"), "--- /dev/null\n+++ b/f\n@@ -0,0 +1,2 @@\n+x\n+y\n\\ No newline at end of file\n");
+
+        let old: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        let new: String = (1..=20).filter(|line| *line != 19)
+            .map(|line| if line == 2 { "two\n".to_owned() } else { format!("{}\n", line) }).collect();
+        let diff = LineDiff::new(&old, &new);
+        assert_eq!((diff.lines_added(), diff.lines_deleted()), (1, 2));
+        assert_eq!(diff.unified("a", "b"), "--- a\n+++ b\n@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n@@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n");
+
+        assert_eq!(LineDiff::new("same\n", "same\n").unified("a", "b"), "");
+    }
+
+    #[test]
+    fn finds_shortest_edit_scripts() {
+        let mut seed = 42u64;
+        let mut random = move |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        for _ in 0..2000 {
+            let old: Vec<String> = (0..random(12)).map(|_| format!("{}\n", random(4))).collect();
+            let new: Vec<String> = (0..random(12)).map(|_| format!("{}\n", random(4))).collect();
+            let (old, new) = (old.concat(), new.concat());
+            let diff = LineDiff::new(&old, &new);
+
+            let mut rebuilt = String::new();
+            for edit in diff.edits() {
+                match *edit {
+                    Edit::Equal(i, j) => { assert_eq!(diff.old[i], diff.new[j]); rebuilt.push_str(diff.new[j]) }
+                    Edit::Insert(j) => rebuilt.push_str(diff.new[j]),
+                    Edit::Delete(_) => {}
+                }
+            }
+            assert_eq!(rebuilt, new);
+
+            let (a, b) = (split_lines(&old), split_lines(&new));
+            let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+                }
+            }
+            assert_eq!(diff.lines_added() + diff.lines_deleted(), a.len() + b.len() - 2 * lcs[0][0]);
+        }
+    }
+}
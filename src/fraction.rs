@@ -2,6 +2,8 @@ use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use std::fmt::{Display, Formatter};
 
+use crate::ordf64::OrdF64;
+
 pub struct Fraction<N> { numerator: N, denominator: usize }
 impl<N> Fraction<N> {
     pub fn new(numerator: N, denominator: usize) -> Self {
@@ -109,31 +111,88 @@ impl<N> Display for Fraction<N> where N: Display {
     }
 }
 
-impl Fraction<usize> { pub fn as_f64(&self) -> f64 { self.numerator as f64 / self.denominator as f64 } }
-
-// FIXME other types
-impl Ord for Fraction<usize> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        (self.numerator * other.denominator).cmp(&(other.numerator * self.denominator))
+/* Fractions are compared by cross-multiplying in a wider type, so that eg. 1/2 and 2/4 are equal,
+   and hashed in lowest terms, so that equal fractions end up in the same group. Fractions with a
+   zero denominator (eg. a `Ratio` of projects without commits) are all equal to each other and less
+   than every other fraction, the way OrdF64 treats NaN. */
+macro_rules! impl_fraction_ord {
+    ($($type:ty as $wider:ty),+) => {
+        $(
+        impl Fraction<$type> {
+            pub fn as_f64(&self) -> f64 { self.numerator as f64 / self.denominator as f64 }
+            fn lowest_terms(&self) -> ($wider, $wider) {
+                let numerator = self.numerator as $wider;
+                let denominator = self.denominator as $wider;
+                if denominator == 0 { return (0, 0) }
+                let (mut a, mut b) = (denominator, numerator.rem_euclid(denominator));
+                while b != 0 { let rest = a.rem_euclid(b); a = b; b = rest; }
+                (numerator / a, denominator / a)
+            }
+        }
+        impl Ord for Fraction<$type> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                match (self.denominator == 0, other.denominator == 0) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                    (false, false) => (self.numerator as $wider * other.denominator as $wider)
+                        .cmp(&(other.numerator as $wider * self.denominator as $wider)),
+                }
+            }
+        }
+        impl PartialOrd for Fraction<$type> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+        }
+        impl PartialEq for Fraction<$type> {
+            fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+        }
+        impl Eq for Fraction<$type> {}
+        impl Hash for Fraction<$type> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.lowest_terms().hash(state)
+            }
+        }
+        // So that eg. AtLeast(Mean(project::Stars), 2.5) works.
+        impl PartialEq<f64> for Fraction<$type> {
+            fn eq(&self, other: &f64) -> bool { self.partial_cmp(other) == Some(Ordering::Equal) }
+        }
+        impl PartialOrd<f64> for Fraction<$type> {
+            fn partial_cmp(&self, other: &f64) -> Option<Ordering> {
+                let value = if self.denominator == 0 { f64::NAN } else { self.as_f64() };
+                OrdF64::from(value).partial_cmp(other)
+            }
+        }
+        )+
     }
 }
 
-impl PartialOrd for Fraction<usize> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        (self.numerator * other.denominator).partial_cmp(&(other.numerator * self.denominator))
+// u128 and i128 have nothing wider to be multiplied in, so their fractions are not ordered.
+impl_fraction_ord!(usize as u128, u64 as u128, u32 as u128, u16 as u128, u8 as u128,
+                   isize as i128, i64 as i128, i32 as i128, i16 as i128, i8 as i128);
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashSet};
+    use crate::fraction::Fraction;
+
+    #[test]
+    fn zero_denominators_come_before_everything_else() {
+        let mut fractions = vec![Fraction::new(1usize, 2), Fraction::new(3, 0), Fraction::new(0, 5), Fraction::new(0, 0)];
+        fractions.sort();
+        assert_eq!(fractions.iter().map(|fraction| fraction.to_string()).collect::<Vec<_>>(),
+                   vec!["3/0", "0/0", "0/5", "1/2"]);
+        assert!(Fraction::new(3usize, 0) == Fraction::new(0usize, 0));
+        assert!(Fraction::new(3usize, 0) < Fraction::new(0usize, 1));
+        assert!(Fraction::new(3usize, 0) < 0.0);
+        assert!(Fraction::new(3usize, 0) != f64::INFINITY);
+        assert!(Fraction::new(1usize, 2) == 0.5);
     }
-}
 
-impl PartialEq for Fraction<usize> {
-    fn eq(&self, other: &Self) -> bool {
-        (self.numerator * other.denominator).eq(&(other.numerator * self.denominator))
+    #[test]
+    fn equal_fractions_hash_alike() {
+        let fractions = vec![Fraction::new(1usize, 2), Fraction::new(2, 4), Fraction::new(3, 0), Fraction::new(0, 0), Fraction::new(0, 7)];
+        assert_eq!(fractions.iter().cloned().collect::<HashSet<_>>().len(), 3);
+        assert_eq!(fractions.iter().cloned().collect::<BTreeSet<_>>().len(), 3);
+        assert!(fractions.into_iter().collect::<HashSet<_>>() == vec![Fraction::new(0usize, 0), Fraction::new(0, 1), Fraction::new(1, 2)].into_iter().collect());
     }
 }
-
-impl Eq for Fraction<usize> {}
-
-impl Hash for Fraction<usize> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        (self.numerator, self.denominator).hash(state)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::objects::Language;
+
+/* The registry used unless another one is given with `Database::with_language_registry`. */
+static DEFAULT_REGISTRY: &str = include_str!("languages.json");
+
+/* Shebangs and modelines are looked for this far into a file, and heuristics run over this much. */
+const HEADER_LINES: usize = 5;
+const MAX_CONTENTS: usize = 64 * 1024;
+
+/* The default registry, loaded the first time it is needed and shared between threads. */
+static DEFAULT: OnceLock<LanguageRegistry> = OnceLock::new();
+
+#[derive(Deserialize)]
+struct RegistrySpec {
+    languages: Vec<LanguageSpec>,
+    #[serde(default)]
+    heuristics: Vec<HeuristicSpec>,
+}
+
+#[derive(Deserialize)]
+struct LanguageSpec {
+    name: String,
+    #[serde(default)] extensions: Vec<String>,
+    #[serde(default)] filenames: Vec<String>,
+    #[serde(default)] interpreters: Vec<String>,
+    #[serde(default)] modes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HeuristicSpec {
+    extensions: Vec<String>,
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+struct RuleSpec {
+    language: String,
+    pattern: String,
+}
+
+/** Works out the language of a file from its name and, if that is not enough, from its contents.
+
+    The registry is read from a JSON file (see `src/languages.json` for the one used by default),
+    which lists languages by name, each with:
+
+    - `extensions`: eg. `"py"`. An extension belongs to the first language that lists it.
+    - `filenames`: whole file names, eg. `"Makefile"` or `"Dockerfile"`.
+    - `interpreters`: the programs named in a shebang, eg. `"python3"` in `#!/usr/bin/env python3`.
+    - `modes`: the names used by Emacs and Vim modelines, eg. `"perl"` in `# -*- mode: perl -*-`.
+
+    The file also lists `heuristics` for extensions that are shared by several languages, like
+    `.h`, `.m`, `.pl`, `.t` and `.inc`: each one is a list of rules, and the file is in the language
+    of the first rule whose regular expression matches its contents. If none match, or the contents
+    are not known, the extension goes to the language that lists it.
+
+    A file is recognized by its name first, then by a modeline or a shebang, and then by its
+    extension. Only the files whose name is not enough (ones with no extension or with an extension
+    that has heuristics) need their contents looked at. Files with extensions that are not in the
+    registry are `Language::Other`, and files with neither an extension nor a recognizable name or
+    contents have no language.
+
+    Languages that are not variants of `Language` become `Language::Named` ones, so the file can
+    add languages as well as describe them. Heuristics can only pick languages that the file lists.
+ */
+pub struct LanguageRegistry {
+    extensions: HashMap<String, Language>,
+    filenames: HashMap<String, Language>,
+    interpreters: HashMap<String, Language>,
+    modes: HashMap<String, Language>,
+    heuristics: HashMap<String, Vec<(Language, Regex)>>,
+    fingerprint: u64,
+}
+
+impl LanguageRegistry {
+    pub fn from_file<P>(path: P) -> Result<Self> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read language registry from {:?}", path))?;
+        Self::from_json(&json)
+            .with_context(|| format!("Cannot load language registry from {:?}", path))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let spec: RegistrySpec = serde_json::from_str(json)?;
+        let mut registry = LanguageRegistry {
+            extensions: HashMap::new(),
+            filenames: HashMap::new(),
+            interpreters: HashMap::new(),
+            modes: HashMap::new(),
+            heuristics: HashMap::new(),
+            fingerprint: fingerprint(json),
+        };
+
+        let mut languages = Vec::new();
+        for language_spec in spec.languages {
+            let language = Language::named(&language_spec.name)?;
+            languages.push(language);
+            for extension in language_spec.extensions {
+                registry.extensions.entry(extension).or_insert(language);
+            }
+            for filename in language_spec.filenames {
+                registry.filenames.entry(filename).or_insert(language);
+            }
+            for interpreter in language_spec.interpreters {
+                registry.interpreters.entry(interpreter).or_insert(language);
+            }
+            for mode in language_spec.modes {
+                registry.modes.entry(mode.to_lowercase()).or_insert(language);
+            }
+        }
+
+        for heuristic_spec in spec.heuristics {
+            let mut rules = Vec::new();
+            for rule in heuristic_spec.rules {
+                let pattern = Regex::new(&rule.pattern)
+                    .with_context(|| format!("Invalid pattern for {}: {}", rule.language, rule.pattern))?;
+                let language = Language::named(&rule.language)?;
+                if !languages.contains(&language) {
+                    bail!("Heuristic for {} picks {}, which is not one of the languages", heuristic_spec.extensions.join(", "), rule.language)
+                }
+                rules.push((language, pattern));
+            }
+            for extension in heuristic_spec.extensions {
+                registry.heuristics.entry(extension).or_insert_with(Vec::new).extend(rules.iter().cloned());
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /** Calls `f` with the default registry. The default registry is loaded once per program. */
+    pub fn with_default<F, R>(f: F) -> R where F: FnOnce(&LanguageRegistry) -> R {
+        f(DEFAULT.get_or_init(LanguageRegistry::default))
+    }
+
+    /** Changes whenever the registry file does, so that caches of languages can tell when they are
+        stale. */
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /** Whether `detect` could say something different about this path given the file's contents. */
+    pub fn needs_contents(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        if self.by_filename(path).is_some() {
+            return false
+        }
+        match extension(path) {
+            Some(extension) => self.heuristics_for(extension).is_some(),
+            None => true,
+        }
+    }
+
+    /** The language of the file at `path`, looking at its `contents` if they are given and needed. */
+    pub fn detect(&self, path: &str, contents: Option<&[u8]>) -> Option<Language> {
+        let path = Path::new(path);
+        if let Some(language) = self.by_filename(path) {
+            return Some(language)
+        }
+
+        let extension = extension(path);
+        let contents = contents
+            .filter(|_| extension.map_or(true, |extension| self.heuristics_for(extension).is_some()))
+            .map(|contents| String::from_utf8_lossy(&contents[..contents.len().min(MAX_CONTENTS)]));
+
+        if let Some(contents) = contents.as_ref() {
+            if let Some(language) = self.by_modeline(contents).or_else(|| self.by_shebang(contents)) {
+                return Some(language)
+            }
+        }
+
+        let extension = extension?;
+        if let (Some(contents), Some(rules)) = (contents.as_ref(), self.heuristics_for(extension)) {
+            if let Some((language, _)) = rules.iter().find(|(_, pattern)| pattern.is_match(contents)) {
+                return Some(*language)
+            }
+        }
+        self.extensions.get(extension)
+            .or_else(|| self.extensions.get(&extension.to_lowercase()))
+            .copied()
+            .or(Some(Language::Other))
+    }
+
+    fn by_filename(&self, path: &Path) -> Option<Language> {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| self.filenames.get(name))
+            .copied()
+    }
+
+    fn heuristics_for(&self, extension: &str) -> Option<&Vec<(Language, Regex)>> {
+        self.heuristics.get(extension)
+    }
+
+    /* Eg. `#!/bin/sh`, `#!/usr/bin/env python3`, or `#!/usr/bin/env -S perl -w`. Versions are
+       ignored if the interpreter is not known with them, eg. `python3.8` is `python`. */
+    fn by_shebang(&self, contents: &str) -> Option<Language> {
+        let line = contents.lines().next()?.strip_prefix("#!")?;
+        let mut words = line.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        }
+        self.interpreters.get(program).copied().or_else(|| {
+            let unversioned = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+            self.interpreters.get(unversioned).copied()
+        })
+    }
+
+    /* Emacs modelines on the first (or second, after a shebang) line, eg. `-*- mode: ruby -*-` or
+       `-*- ruby -*-`, and Vim modelines in the first or last few lines, eg. `vim: set ft=perl:` or
+       `vi: syntax=python`. */
+    fn by_modeline(&self, contents: &str) -> Option<Language> {
+        let lines: Vec<&str> = contents.lines().collect();
+        let head = lines.iter().take(2);
+        for line in head {
+            if let Some(mode) = emacs_mode(line) {
+                if let Some(language) = self.modes.get(&mode.to_lowercase()) {
+                    return Some(*language)
+                }
+            }
+        }
+        let tail = lines.iter().rev().take(HEADER_LINES);
+        for line in lines.iter().take(HEADER_LINES).chain(tail) {
+            if let Some(mode) = vim_mode(line) {
+                if let Some(language) = self.modes.get(&mode.to_lowercase()) {
+                    return Some(*language)
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Default for LanguageRegistry {
+    fn default() -> Self {
+        LanguageRegistry::from_json(DEFAULT_REGISTRY).expect("The built-in language registry is broken")
+    }
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+fn emacs_mode(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let inside = line[start..end].trim();
+    if !inside.contains(':') {
+        return Some(inside)
+    }
+    inside.split(';').find_map(|variable| {
+        let mut parts = variable.splitn(2, ':');
+        let name = parts.next()?.trim();
+        let value = parts.next()?.trim();
+        if name.eq_ignore_ascii_case("mode") { Some(value) } else { None }
+    })
+}
+
+fn vim_mode(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.find(marker)
+            .filter(|position| line[..*position].chars().last().map_or(true, char::is_whitespace))
+            .map(|position| position + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|setting| {
+            let mut parts = setting.splitn(2, '=');
+            match (parts.next()?, parts.next()?) {
+                ("ft", value) | ("filetype", value) | ("syntax", value) | ("syn", value) => Some(value),
+                _ => None,
+            }
+        })
+}
+
+/* FNV-1a, the same as for cache headers, so that it does not change between compiler versions. */
+fn fingerprint(json: &str) -> u64 {
+    json.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_languages() {
+        let registry = LanguageRegistry::default();
+        assert_eq!(registry.detect("src/main.rs", None), Some(Language::Rust));
+        assert_eq!(registry.detect("Makefile", None), Some(Language::Makefile));
+        assert_eq!(registry.detect("docker/Dockerfile", None), Some(Language::Dockerfile));
+        assert_eq!(registry.detect("image.png", None), Some(Language::Other));
+        assert_eq!(registry.detect("bin/run", None), None);
+        assert_eq!(registry.detect("bin/run", Some(b"#!/usr/bin/env python3.8\nprint(1)\n")), Some(Language::Python));
+        assert_eq!(registry.detect("bin/run", Some(b"#!/bin/bash\necho\n")), Some(Language::Shell));
+        assert_eq!(registry.detect("bin/run", Some(b"# -*- mode: ruby -*-\nputs 1\n")), Some(Language::Ruby));
+        assert_eq!(registry.detect("bin/run", Some(b"print 1\n# vim: set ft=perl:\n")), Some(Language::Perl));
+        assert_eq!(registry.detect("include/list.h", None), Some(Language::C));
+        assert_eq!(registry.detect("include/list.h", Some(b"@interface List : NSObject\n@end\n")), Some(Language::ObjectiveC));
+        assert_eq!(registry.detect("include/list.h", Some(b"namespace list {\ntemplate <typename T> class List;\n}\n")), Some(Language::Cpp));
+        assert_eq!(registry.detect("family.pl", Some(b"parent(tom, bob).\nancestor(X, Y) :- parent(X, Y).\n")), Some(Language::Prolog));
+        assert_eq!(registry.detect("script.pl", Some(b"use strict;\nmy $x = 1;\n")), Some(Language::Perl));
+        assert_eq!(registry.detect("config.inc", Some(b"<?php\n$x = 1;\n")), Some(Language::PHP));
+        assert!(registry.needs_contents("include/list.h"));
+        assert!(registry.needs_contents("bin/run"));
+        assert!(!registry.needs_contents("src/main.rs"));
+        assert!(!registry.needs_contents("Makefile"));
+    }
+
+    #[test]
+    fn registries_add_languages() {
+        let registry = LanguageRegistry::from_json(r#"{
+            "languages": [
+                { "name": "Zig", "extensions": ["zig"] },
+                { "name": "C", "extensions": ["h"] }
+            ],
+            "heuristics": [ { "extensions": ["h"], "rules": [ { "language": "Zig", "pattern": "@import" } ] } ]
+        }"#).unwrap();
+        let zig = registry.detect("src/main.zig", None).unwrap();
+        assert_eq!(zig, Language::named("zig").unwrap());
+        assert_eq!(zig.to_string(), "Zig");
+        assert_eq!(registry.detect("src/main.h", Some(b"const std = @import(\"std\");\n")), Some(zig));
+        assert_eq!(registry.detect("src/main.h", None), Some(Language::C));
+
+        let bytes = serde_cbor::to_vec(&zig).unwrap();
+        assert_eq!(serde_cbor::from_slice::<Language>(&bytes).unwrap(), zig);
+
+        let unlisted = LanguageRegistry::from_json(r#"{
+            "languages": [ { "name": "C", "extensions": ["h"] } ],
+            "heuristics": [ { "extensions": ["h"], "rules": [ { "language": "Zgi", "pattern": "@import" } ] } ]
+        }"#);
+        assert!(unlisted.is_err());
+    }
+
+    #[test]
+    fn named_languages_are_ordered_by_name() {
+        let nim = Language::named("Nim").unwrap();
+        let haxe = Language::named("Haxe").unwrap();
+        let odin = Language::named("Odin").unwrap();
+        let mut languages = vec![odin, Language::Other, nim, Language::Rust, haxe];
+        languages.sort();
+        assert_eq!(languages, vec![Language::Rust, haxe, nim, odin, Language::Other]);
+    }
+
+    #[test]
+    fn paths_use_the_default_registry() {
+        let path = crate::objects::Path::new(crate::objects::PathId(0), "src/main.rs".to_owned());
+        assert_eq!(path.language(), Some(Language::Rust));
+        let path = crate::objects::Path::new(crate::objects::PathId(1), "bin/run".to_owned());
+        assert_eq!(path.language(), None);
+    }
+}
@@ -3,6 +3,9 @@
              pub mod database;
              pub mod fraction;
              pub mod ordf64;
+             pub mod languages;
+             pub mod lines;
+             pub mod diff;
              pub mod weights_and_measures;
 #[macro_use] pub mod log;
              pub mod csv;
@@ -19,6 +22,7 @@
 #[cfg(test)] mod testing;
 #[macro_use] pub mod utils;
              pub mod commandline;
+             pub mod query;
 
 #[macro_use] extern crate mashup;
 
@@ -36,13 +40,12 @@ pub use commandline::*;
 // Fraction vs f64
 // more comprehensive unit tests
 // flat_map select
-// explore parallelism
 // prefiltering
 // dump metadata, also make raw metadata accessible from objects::Project
 // TODO rename Users to Contributors
 
 use std::fs::{remove_file, remove_dir_all, read_dir, metadata};
-use std::iter::{Sum, FromIterator};
+use std::iter::FromIterator;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::collections::*;
@@ -52,19 +55,24 @@ use std::fmt::Display;
 
 use itertools::Itertools;
 use rand_pcg::Pcg64Mcg;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use rand::seq::IteratorRandom;
 use chrono::{NaiveDateTime, DateTime, Utc};
 use anyhow::*;
+use rayon::prelude::*;
 
 use parasite;
 use parasite::{StoreKind};
 
 use crate::attrib::*;
 use crate::fraction::*;
+use crate::ordf64::OrdF64;
 use crate::database::Database;
 use crate::log::{Log, Verbosity};
-use crate::database::source::{Source, MERGED_SUBSTORE_DIR_NAME};
+use crate::database::source::Source;
+use crate::database::repository::RepositorySource;
+use crate::languages::LanguageRegistry;
+use crate::database::datastore::MERGED_SUBSTORE_DIR_NAME;
 
 pub type Timestamp = i64; // Epoch
 pub type Percentage = u8; // Positive integer value 0-100.
@@ -270,7 +278,7 @@ impl std::convert::From<String> for Store {
     ($($t:tt)+) => { stores!($($t)+) }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CacheDir {
     root_dir: PathBuf,
     savepoint: Timestamp,
@@ -295,6 +303,36 @@ impl CacheDir {
     pub fn as_string(&self) -> String {
         self.as_path().to_str().unwrap().to_owned()
     }
+    pub fn savepoint(&self) -> Timestamp {
+        self.savepoint
+    }
+    pub fn substores(&self) -> &Vec<Store> {
+        &self.substores
+    }
+    /** The cache directory for the same substores at a different savepoint. */
+    pub fn at_savepoint(&self, savepoint: Timestamp) -> Self {
+        CacheDir { root_dir: self.root_dir.clone(), savepoint, substores: self.substores.clone() }
+    }
+    /** Savepoints earlier than this one that have cache directories for the same substores,
+        from the oldest to the most recent. */
+    pub fn earlier_savepoints(&self) -> Vec<Timestamp> {
+        let mut stores_dir = self.as_path();
+        stores_dir.pop();
+        let entries = match read_dir(&stores_dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries.flat_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .flat_map(|entry| {
+                entry.file_name().to_str()
+                    .and_then(|name| NaiveDateTime::parse_from_str(name, "%Y-%m-%d-%H-%M-%S").ok())
+                    .map(|date_time| date_time.timestamp())
+            })
+            .filter(|savepoint| *savepoint < self.savepoint)
+            .sorted()
+            .collect()
+    }
 }
 
 pub struct Djanco;
@@ -333,6 +371,13 @@ impl Djanco {
         let source = Source::new(dataset_path, cache_dir.as_string(), savepoint, substores)?;
         Ok(Database::new(source, cache_dir, log))
     }
+    /** Serves queries from any source of data rather than a datastore, eg. from an `InMemorySource`
+        wrapped with `Source::from_backend`. The caches go into a directory for the savepoint of the
+        source under `cache_path`. */
+    pub fn from_source<Sc>(source: Source, cache_path: Sc, log: Log) -> Database where Sc: Into<String> {
+        let cache_dir = CacheDir::from(cache_path, source.savepoint(), vec![]);
+        Database::new(source, cache_dir, log)
+    }
     pub fn from_store<Sd>(dataset_path: Sd, savepoint: Timestamp, substores: Vec<Store>, preclean: bool, preclean_merged_substores: bool) -> Result<Database> where Sd: Into<String> {
         let dataset_path = dataset_path.into();
         let cache_path = env::var("DJANCO_CACHE_PATH").unwrap_or_else(|_| {
@@ -373,28 +418,46 @@ impl Djanco {
     //         config.preclean_merged_substores
     //     )
     // }
+    /** Opens the database the command line options describe.
+
+        If any local repositories are given (`--local-repository`), they are read with
+        `RepositorySource` instead of the dataset, and the dataset path and substores are ignored.
+     */
     pub fn from_config(options: &Configuration, savepoint: Timestamp, substores: Vec<Store>, log: Log) -> Result<Database> {
-        Djanco::from_full_spec(
-            options.dataset_path_as_str(), 
-            options.cache_path_as_str(), 
-            savepoint, 
-            substores, 
-            log, 
-            options.preclean_cache, 
-            options.preclean_merged_substores
-        )
+        let database = if options.local_repositories.is_empty() {
+            Djanco::from_full_spec(
+                options.dataset_path_as_str(),
+                options.cache_path_as_str(),
+                savepoint,
+                substores,
+                log,
+                options.preclean_cache,
+                options.preclean_merged_substores
+            )?
+        } else {
+            let source = RepositorySource::open(&options.local_repositories)?;
+            Djanco::from_source(Source::from_backend(source, savepoint), options.cache_path_as_str(), log)
+        }.with_cache_format(options.cache_format);
+        let database = match options.memory_budget_in_bytes() {
+            Some(bytes) => database.with_memory_budget(bytes),
+            None => database,
+        };
+        Ok(match options.languages_path.as_ref() {
+            Some(path) => database.with_language_registry(LanguageRegistry::from_file(path)?),
+            None => database,
+        })
     }
 }
 
 macro_rules! impl_attribute_definition {
-    [$object:ty, $attribute:ident ()] => {
+    [$cost:ident, $object:ty, $attribute:ident ()] => {
         #[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)] pub struct $attribute;
-        impl Attribute for $attribute { type Object = $object; }
+        impl Attribute for $attribute { type Object = $object; fn cost(&self) -> Cost { Cost::$cost } }
     };
-    [$object:ty, $attribute:ident ( $($arg_type:ty),+ ) ] => {
+    [$cost:ident, $object:ty, $attribute:ident ( $($arg_type:ty),+ ) ] => {
         #[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)] 
         pub struct $attribute($(pub $arg_type,)+);
-        impl Attribute for $attribute { type Object = $object; }
+        impl Attribute for $attribute { type Object = $object; fn cost(&self) -> Cost { Cost::$cost } }
     };
 }
 
@@ -465,6 +528,20 @@ macro_rules! impl_attribute_getter {
             }
         }
     };
+    [? $object:ty, $attribute:ident ( $($parameter:ty),* ), f64, $getter:ident] => {
+        impl<'a> Getter<'a> for $attribute {
+            type IntoItem = Option<crate::ordf64::OrdF64>;
+            fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+                call_n!(object, $getter, &self, $($parameter),*).map(crate::ordf64::OrdF64::from)
+            }
+        }
+        impl<'a> OptionGetter<'a> for $attribute {
+            type IntoItem = crate::ordf64::OrdF64;
+            fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+                call_n!(object, $getter, &self, $($parameter),*).map(crate::ordf64::OrdF64::from)
+            }
+        }
+    };
     [? $object:ty, $attribute:ident ( $($parameter:ty),* ), $small_type:ty, $getter:ident] => { 
         impl<'a> Getter<'a> for $attribute {
             type IntoItem = Option<$small_type>;
@@ -566,6 +643,7 @@ macro_rules! impl_attribute_filter {
             fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                 self.get(item_with_data)
             }
+            fn cost(&self) -> Cost { Attribute::cost(self) }
         }
     };
     [? $object:ty, $attribute:ident] => {
@@ -574,95 +652,111 @@ macro_rules! impl_attribute_filter {
             fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                 self.get(item_with_data).unwrap_or(false)
             }
+            fn cost(&self) -> Cost { Attribute::cost(self) }
         }
     }
 }
 
+/* Attributes marked `expensive` come out of derived caches, the rest are read straight from the source. */
 macro_rules! impl_attribute {
-    [! $object:ty, $attribute:ident ] => { 
-        impl_attribute_definition![$object, $attribute()];
+    [expensive $($rest:tt)*] => {
+        impl_attribute![@(Expensive) $($rest)*];
+    };
+    [@($cost:ident) ! $object:ty, $attribute:ident ] => { 
+        impl_attribute_definition![$cost, $object, $attribute()];
         impl_attribute_getter![! $object, $attribute()];
     };        
-    [!+ $object:ty, $attribute:ident] => { 
-        impl_attribute_definition![$object, $attribute()];
+    [@($cost:ident) !+ $object:ty, $attribute:ident] => { 
+        impl_attribute_definition![$cost, $object, $attribute()];
         impl_attribute_getter![!+ $object, $attribute()];
     };
-    [! $object:ty, $attribute:ident, bool, $getter:ident] => { 
-        impl_attribute![! $object, $attribute(), bool, $getter];
+    [@($cost:ident) ! $object:ty, $attribute:ident, bool, $getter:ident] => { 
+        impl_attribute![@($cost) ! $object, $attribute(), bool, $getter];
     };
-    [! $object:ty, $attribute:ident ($($parameter:ty),*), bool, $getter:ident ] => { 
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ! $object:ty, $attribute:ident ($($parameter:ty),*), bool, $getter:ident ] => { 
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![! $object, $attribute($($parameter),*), bool, $getter ];
         impl_attribute_filter![! $object, $attribute];
     };
-    [! $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => { 
-        impl_attribute![! $object, $attribute(), $small_type, $getter];
+    [@($cost:ident) ! $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => { 
+        impl_attribute![@($cost) ! $object, $attribute(), $small_type, $getter];
     };
-    [! $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => { 
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ! $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => { 
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![! $object, $attribute($($parameter),*), $small_type, $getter];
     };
-    [!+ $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => { 
-        impl_attribute![!+ $object, $attribute(), $small_type, $getter];
+    [@($cost:ident) !+ $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => { 
+        impl_attribute![@($cost) !+ $object, $attribute(), $small_type, $getter];
     };
-    [!+ $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => { 
-        impl_attribute_definition![$object, $attribute($($parameter),*)]; 
+    [@($cost:ident) !+ $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => { 
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)]; 
         impl_attribute_getter![!+ $object, $attribute($($parameter),*), $small_type, $getter];
     };
-    [? $object:ty, $attribute:ident, bool, $getter:ident] => {
-        impl_attribute![? $object, $attribute(), bool, $getter];
+    [@($cost:ident) ? $object:ty, $attribute:ident, bool, $getter:ident] => {
+        impl_attribute![@($cost) ? $object, $attribute(), bool, $getter];
     };
-    [? $object:ty, $attribute:ident ($($parameter:ty),*), bool, $getter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ? $object:ty, $attribute:ident ($($parameter:ty),*), bool, $getter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![? $object, $attribute($($parameter),*), bool, $getter];
         impl_attribute_filter![? $object, $attribute];
     };
-    [? $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => {
-        impl_attribute![? $object, $attribute(), $small_type, $getter];
+    /* Floats are not Ord, so f64 attributes are OrdF64s instead, and can be sorted and grouped. */
+    [@($cost:ident) ? $object:ty, $attribute:ident, f64, $getter:ident] => {
+        impl_attribute![@($cost) ? $object, $attribute(), f64, $getter];
     };
-    [? $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ? $object:ty, $attribute:ident ($($parameter:ty),*), f64, $getter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
+        impl_attribute_getter![? $object, $attribute($($parameter),*), f64, $getter];
+    };
+    [@($cost:ident) ? $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => {
+        impl_attribute![@($cost) ? $object, $attribute(), $small_type, $getter];
+    };
+    [@($cost:ident) ? $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![? $object, $attribute($($parameter),*), $small_type, $getter];
     };
-    [?+ $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => {
-        impl_attribute![?+ $object, $attribute(), $small_type, $getter];
+    [@($cost:ident) ?+ $object:ty, $attribute:ident, $small_type:ty, $getter:ident] => {
+        impl_attribute![@($cost) ?+ $object, $attribute(), $small_type, $getter];
     };
-    [?+ $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ?+ $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![?+ $object, $attribute($($parameter),*), $small_type, $getter];
     };
-    [!.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute![!.. $object, $attribute(), $small_type, $getter, $counter];
+    [@($cost:ident) !.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute![@($cost) !.. $object, $attribute(), $small_type, $getter, $counter];
     };
-    [!.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) !.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![! $object, $attribute($($parameter),*), Vec<$small_type>, $getter];
         impl_attribute_count![! $object, $attribute($($parameter),*), $counter];
     };
-    [!+.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute![!+.. $object, $attribute(), $small_type, $getter, $counter];
+    [@($cost:ident) !+.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute![@($cost) !+.. $object, $attribute(), $small_type, $getter, $counter];
     };
-    [!+.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) !+.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![!+.. $object, $attribute($($parameter),*), $small_type, $getter];
         impl_attribute_count![! $object, $attribute($($parameter),*), $counter];
     };
-    [?.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute![?.. $object, $attribute(), $small_type, $getter, $counter];
+    [@($cost:ident) ?.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute![@($cost) ?.. $object, $attribute(), $small_type, $getter, $counter];
     };
-    [?.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ?.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![? $object, $attribute($($parameter),*), Vec<$small_type>, $getter];
         impl_attribute_count![? $object, $attribute($($parameter),*), $counter];
     };
-    [?+.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute![?+.. $object, $attribute(), $small_type, $getter, $counter];
+    [@($cost:ident) ?+.. $object:ty, $attribute:ident, $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute![@($cost) ?+.. $object, $attribute(), $small_type, $getter, $counter];
     };
-    [?+.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
-        impl_attribute_definition![$object, $attribute($($parameter),*)];
+    [@($cost:ident) ?+.. $object:ty, $attribute:ident ($($parameter:ty),*), $small_type:ty, $getter:ident, $counter:ident] => {
+        impl_attribute_definition![$cost, $object, $attribute($($parameter),*)];
         impl_attribute_getter![?+.. $object, $attribute($($parameter),*), $small_type, $getter];
         impl_attribute_count![? $object, $attribute($($parameter),*), $counter];
     };
+    [$($rest:tt)*] => {
+        impl_attribute![@(Cheap) $($rest)*];
+    };
 }
 
 pub mod project {
@@ -909,7 +1003,7 @@ pub mod project {
     /*
      * Retrieves the lifespan of the project calculated as the duration between the earliest and most recent commit.
      */
-    impl_attribute![?     objects::Project, Age, time::Duration, lifetime];
+    impl_attribute![expensive ?     objects::Project, Age, time::Duration, lifetime];
 
     /*
      * Retrieves the heads of this project.
@@ -920,71 +1014,71 @@ pub mod project {
     /*
      * Returns the IDs of all the commits in all the branches of this project.
      */
-    impl_attribute![?..   objects::Project, CommitIds, objects::CommitId, commit_ids, commit_count];
+    impl_attribute![expensive ?..   objects::Project, CommitIds, objects::CommitId, commit_ids, commit_count];
 
     /*
      * Returns the IDs of all the users who authored any commit in any of the branches of this project.
      */
-    impl_attribute![?..   objects::Project, AuthorIds, objects::UserId, author_ids, author_count];
+    impl_attribute![expensive ?..   objects::Project, AuthorIds, objects::UserId, author_ids, author_count];
 
     /*
      * Returns the IDs of all the users who committed any commit in any of the branches of this project.
      */
-    impl_attribute![?..   objects::Project, CommitterIds, objects::UserId, committer_ids, committer_count];
+    impl_attribute![expensive ?..   objects::Project, CommitterIds, objects::UserId, committer_ids, committer_count];
 
     /*
      * Returns the IDs of all the users who authored or committed anything in any of the branches of this project.
      */
-    impl_attribute![?..   objects::Project, UserIds, objects::UserId, user_ids, user_count];
+    impl_attribute![expensive ?..   objects::Project, UserIds, objects::UserId, user_ids, user_count];
 
     /*
      * Returns the IDs in of all the file paths constituting any of the branches of this project.
      */
-    impl_attribute![?..   objects::Project, PathIds, objects::PathId, path_ids, path_count];
+    impl_attribute![expensive ?..   objects::Project, PathIds, objects::PathId, path_ids, path_count];
 
     /*
      * Returns the IDs in of all the file contents constituting any of the branches of this project.
      */
-    impl_attribute![?..   objects::Project, SnapshotIds, objects::SnapshotId, snapshot_ids, snapshot_count];
+    impl_attribute![expensive ?..   objects::Project, SnapshotIds, objects::SnapshotId, snapshot_ids, snapshot_count];
 
     /*
      * Returns all the commits in all the branches of this project.
      */
-    impl_attribute![?+..  objects::Project, Commits, objects::Commit, commits_with_data, commit_count];
+    impl_attribute![expensive ?+..  objects::Project, Commits, objects::Commit, commits_with_data, commit_count];
 
     /*
      * Returns all the users who authored any commit in any of the branches of this project.
      */
-    impl_attribute![?+..  objects::Project, Authors, objects::User, authors_with_data, author_count];
+    impl_attribute![expensive ?+..  objects::Project, Authors, objects::User, authors_with_data, author_count];
 
     /*
      * Returns all the users who committed any commit in any of the branches of this project.
      */
-    impl_attribute![?+..  objects::Project, Committers, objects::User, committers_with_data, committer_count];
+    impl_attribute![expensive ?+..  objects::Project, Committers, objects::User, committers_with_data, committer_count];
 
     /*
      * Returns all the users who authored or committed anything in any of the branches of this project.
      */
-    impl_attribute![?+..  objects::Project, Users, objects::User, users_with_data, user_count];
+    impl_attribute![expensive ?+..  objects::Project, Users, objects::User, users_with_data, user_count];
 
     /*
      * Returns the IDs in of all the file paths constituting any of the branches of this project.
      */
-    impl_attribute![?+..  objects::Project, Paths, objects::Path, paths_with_data, path_count];
+    impl_attribute![expensive ?+..  objects::Project, Paths, objects::Path, paths_with_data, path_count];
 
     /*
      * Returns the IDs in of all the file contents constituting any of the branches of this project.
       
        Only files for which the contents is in the database are counted. 
      */
-    impl_attribute![?+..  objects::Project, Snapshots, objects::Snapshot, snapshots_with_data, snapshot_count];
+    impl_attribute![expensive ?+..  objects::Project, Snapshots, objects::Snapshot, snapshots_with_data, snapshot_count];
 
     /*
      * For each project, it goes through the commits, sorts them according to their timestamp
      * and then find the time passed between 2 commits (deltas) that are adjacents in terms of time.
      * Finally, returns the max delta found.
     */
-    impl_attribute![?    objects::Project, MaxCommitDelta, i64, max_commit_delta];
+    impl_attribute![expensive ?    objects::Project, MaxCommitDelta, i64, max_commit_delta];
 
 
     /*
@@ -992,43 +1086,53 @@ pub mod project {
      * and then find the time passed between 2 commits (deltas) that are adjacents in terms of time.
      * Finally, returns the average of these deltas.
     */
-    impl_attribute![?    objects::Project, AvgCommitDelta, i64, avg_commit_delta];
+    impl_attribute![expensive ?    objects::Project, AvgCommitDelta, i64, avg_commit_delta];
 
     /*
      * Returns the time in seconds. It is the time passed between the last commit and the last time
      * parasite updated a given project. 
     */
-    impl_attribute![?    objects::Project, TimeSinceLastCommit, i64, time_since_last_commit];
+    impl_attribute![expensive ?    objects::Project, TimeSinceLastCommit, i64, time_since_last_commit];
 
     /*
      * Returns the time in seconds. It is the time passed between the first commit and the last time
      * parasite updated a given project.
     */
-    impl_attribute![?    objects::Project, TimeSinceFirstCommit, i64, time_since_first_commit];
+    impl_attribute![expensive ?    objects::Project, TimeSinceFirstCommit, i64, time_since_first_commit];
 
     /*
      * If MaxCommitDelta is less than TimeSinceLastCommit then it returns true.
     */
-    impl_attribute![?    objects::Project, IsAbandoned, bool, is_abandoned];
+    impl_attribute![expensive ?    objects::Project, IsAbandoned, bool, is_abandoned];
 
     /*
      * Reconstructs the main branch to get the latest snapshots and return
      * the number of lines of code associated with a project.
     */
-    impl_attribute![?    objects::Project, Locs, usize, project_locs];
+    impl_attribute![expensive ?    objects::Project, Locs, usize, project_locs];
+
+    /*
+     * Like Locs, but lines of code, comments, and blank lines are counted apart, going by the
+     * comment syntax of the language of each file. Binary files are left out. CommentDensity is
+     * the ratio of lines of comments to lines of code and comments.
+    */
+    impl_attribute![expensive ?    objects::Project, CodeLines, usize, code_lines];
+    impl_attribute![expensive ?    objects::Project, CommentLines, usize, comment_lines];
+    impl_attribute![expensive ?    objects::Project, BlankLines, usize, blank_lines];
+    impl_attribute![expensive ?    objects::Project, CommentDensity, f64, comment_density];
 
     /*
      * Return the max developer experience that we can find among all the Authors
      * of a given project.
     */
-    impl_attribute![?    objects::Project, MaxExperience, i32, project_max_experience];
+    impl_attribute![expensive ?    objects::Project, MaxExperience, i32, project_max_experience];
 
     /*
      * Return a single number. Let DE be a number that describes some developer's experience,
      * DC the number of commits of a given developer, and PC the total sum of commits of a given project.
      * Then for a given project, x,  we sum DE_x*DC_x/PC. 
     */
-    impl_attribute![?    objects::Project, ProjectExperience, f64, project_experience];
+    impl_attribute![expensive ?    objects::Project, ProjectExperience, f64, project_experience];
     
     /*
      * Calculates the percentage of duplicated code by first looking at the changes of files
@@ -1038,21 +1142,21 @@ pub mod project {
      * file is considered cloned.
      * Returns a number between 0 and 1. It is a percetange of duplicated code 
     */
-    impl_attribute![?    objects::Project, DuplicatedCode, f64, duplicated_code];
+    impl_attribute![expensive ?    objects::Project, DuplicatedCode, f64, duplicated_code];
 
     /*
      * Calculates the number of changes each author added to the project. 
      * Returns a list of authors with the number of changes they did. 
      * The list is sorted by the number of changes in desceding order.
      */
-    impl_attribute![?..   objects::Project, ChangeContributions, (objects::User, usize), change_contributions, author_count];
+    impl_attribute![expensive ?..   objects::Project, ChangeContributions, (objects::User, usize), change_contributions, author_count];
 
     /*
      * Calculates the number of commits each user authored in the project. 
      * Returns a list of authors with the number of committs they are responsible for.
      * The list is sorted by the number of commits in desceding order.
      */
-    impl_attribute![?..   objects::Project, CommitContributions, (objects::User, usize), commit_contributions, author_count];
+    impl_attribute![expensive ?..   objects::Project, CommitContributions, (objects::User, usize), commit_contributions, author_count];
 
     /*
      * Calculates the percentage of commits successive users authored in the project.
@@ -1064,7 +1168,7 @@ pub mod project {
      * The list is sorted so that [0] represents the contribution of 1 user, and 
      * [i] represents the cumulative contribution of i-1 users.
      */
-    impl_attribute![?..   objects::Project, CummulativeCommitContributions, Percentage, cumulative_commit_contributions, author_count];
+    impl_attribute![expensive ?..   objects::Project, CummulativeCommitContributions, Percentage, cumulative_commit_contributions, author_count];
 
     /*
      * Calculates the percentage of commits successive authors added to the project. 
@@ -1076,7 +1180,7 @@ pub mod project {
      * The list is sorted so that [0] represents the contribution of 1 user, and 
      * [i] represents the cumulative contribution of i-1 users.
      */
-    impl_attribute![?..   objects::Project, CummulativeChangeContributions, Percentage, cumulative_change_contributions, author_count];
+    impl_attribute![expensive ?..   objects::Project, CummulativeChangeContributions, Percentage, cumulative_change_contributions, author_count];
 
     /*
      * Calculates the (minimum) number of authors responsible for N% commits. 
@@ -1094,7 +1198,7 @@ pub mod project {
      * Since this is parameterized, this attribute is not cached. 
      * CommitContributions is cached.
      */
-    impl_attribute![?+..  objects::Project, AuthorsContributingCommits(Percentage), objects::User, authors_contributing_commits_with_data, authors_contributing_commits_count];
+    impl_attribute![expensive ?+..  objects::Project, AuthorsContributingCommits(Percentage), objects::User, authors_contributing_commits_with_data, authors_contributing_commits_count];
 
     /*
      * Calculates the (minimum) number of authors responsible for N% changes. 
@@ -1112,28 +1216,28 @@ pub mod project {
      * Since this is parameterized, this attribute is not cached. 
      * CommitContributions is cached.
      */
-    impl_attribute![?+..  objects::Project, AuthorsContributingChanges(Percentage), objects::User, authors_contributing_changes_with_data, authors_contributing_changes_count];
+    impl_attribute![expensive ?+..  objects::Project, AuthorsContributingChanges(Percentage), objects::User, authors_contributing_changes_with_data, authors_contributing_changes_count];
 
     /* Number of snapshots in the project that only ever exist in the project.
     
        Takes only the snapshots for which we have data into account. 
      */
-    impl_attribute![?     objects::Project, UniqueFiles, usize, unique_files];
+    impl_attribute![expensive ?     objects::Project, UniqueFiles, usize, unique_files];
     /* Number of snapshots in the project where the project is the original, i.e. the oldest occurence of the particular snapshot in the dataset. 
 
        Takes only the snapshots for which we have data into account. 
      */
-    impl_attribute![?     objects::Project, OriginalFiles, usize, original_files];
+    impl_attribute![expensive ?     objects::Project, OriginalFiles, usize, original_files];
     /* The impact of the project. 
      
         Sum of impact of its snapshots, where 0 is added for clones, 1 for unique files and the number of projects using a snapshot for original snapshots.
      */
-    impl_attribute![?     objects::Project, Impact, usize, impact];
+    impl_attribute![expensive ?     objects::Project, Impact, usize, impact];
     /* Number of unique files in the project. 
 
        TODO the current version does not provide information about deletions and so there is no way we can reconstruct only active files. At this moment this metric simply returns the number of unique paths throughout the project's history.
      */
-    impl_attribute![?     objects::Project, Files, usize, files];
+    impl_attribute![expensive ?     objects::Project, Files, usize, files];
 
     /* Languages of the project. 
 
@@ -1141,27 +1245,27 @@ pub mod project {
 
        TODO for now, this is only done using file extensions. We might want to do this using real contents analysis. 
      */
-    impl_attribute![?..   objects::Project, Languages, objects::Language, languages, languages_count];
+    impl_attribute![expensive ?..   objects::Project, Languages, objects::Language, languages, languages_count];
     /* Language composition of the project. 
 
        For each language used in the project returns the number of snapshots throught the project history ordered by descending number. 
 
        TODO for now, this is only done using file extensions. We might want to do this using real contents analysis. 
      */
-    impl_attribute![?..   objects::Project, LanguageComposition, (objects::Language, usize), language_composition, languages_count];
+    impl_attribute![expensive ?..   objects::Project, LanguageComposition, (objects::Language, usize), language_composition, languages_count];
     /* Shorthand for the major language. 
      
         This is the language with most changes in the project history. 
      */
-    impl_attribute![?     objects::Project, MajorLanguage, objects::Language, major_language];
+    impl_attribute![expensive ?     objects::Project, MajorLanguage, objects::Language, major_language];
     /* The ratio of the changes to the major languages vs. all changes in the project. 
      
        We are only counting changes to languages, i.e. where we keep snapshots. 
      */
-    impl_attribute![?     objects::Project, MajorLanguageRatio, f64, major_language_ratio];
+    impl_attribute![expensive ?     objects::Project, MajorLanguageRatio, f64, major_language_ratio];
     /* Number of changes to the major language. 
      */
-    impl_attribute![?     objects::Project, MajorLanguageChanges, usize, major_language_changes];
+    impl_attribute![expensive ?     objects::Project, MajorLanguageChanges, usize, major_language_changes];
 
     /* 
      * A project is `valid` if it was downloaded correctly in the most recent download attempt.
@@ -1172,13 +1276,13 @@ pub mod project {
 
        For simplicity we assume a project is a fork if it is younger *and* if it shares at least one commit by hash. 
      */
-    impl_attribute![?..   objects::Project, AllForks, objects::ProjectId, all_forks, all_forks_count];
+    impl_attribute![expensive ?..   objects::Project, AllForks, objects::ProjectId, all_forks, all_forks_count];
 
     /* For each branch returns the head tree.
      
        The tree is a mapping from path ids to snapshot ids. All paths are considered. Deleted files are not displayed.
      */
-    impl_attribute![?..   objects::Project, HeadTrees, (String, Vec<(objects::PathId, objects::SnapshotId)>), head_trees, head_trees_count];
+    impl_attribute![expensive ?..   objects::Project, HeadTrees, (String, Vec<(objects::PathId, objects::SnapshotId)>), head_trees, head_trees_count];
 }
 
 pub mod commit {
@@ -1219,12 +1323,48 @@ pub mod commit {
 
        This is pretty much the reverse of the project-commits mapping.
      */
-    impl_attribute![?..  objects::Commit, Projects, objects::Project, projects, projects_count];
+    impl_attribute![expensive ?..  objects::Commit, Projects, objects::Project, projects, projects_count];
 
     /*
      * The list of all languages in a commit.
      */
-    impl_attribute![?..  objects::Commit, Languages, objects::Language, languages, languages_count];
+    impl_attribute![expensive ?..  objects::Commit, Languages, objects::Language, languages, languages_count];
+
+    /*
+     * Lines added and deleted by the commit, against its first parent, and the two together.
+     * Commits that change a binary file or a file whose contents are not known have none, and so
+     * do commits descended (along first parents) from a commit that is not in the dataset.
+     */
+    impl_attribute![expensive ?    objects::Commit, LinesAdded, usize, lines_added];
+    impl_attribute![expensive ?    objects::Commit, LinesDeleted, usize, lines_deleted];
+    impl_attribute![expensive ?    objects::Commit, Churn, usize, churn];
+}
+
+/* Attributes of a whole group of objects, for `having` and `aggregate`. */
+pub mod group {
+    use crate::objects;
+    use crate::attrib::*;
+
+    // These describe groups rather than any one object, so they are not attributes: there is no
+    // object to get them from, only a group.
+
+    /** The key the objects in a group share. */
+    #[derive(Debug, Clone, Copy)] pub struct Key;
+    /** The number of objects in a group. */
+    #[derive(Debug, Clone, Copy)] pub struct Size;
+
+    impl<'a, K, T> GroupGetter<'a, K, T> for Key where K: Clone {
+        type IntoItem = K;
+        fn get_group(&self, key: &K, _members: &Vec<objects::ItemWithData<'a, T>>) -> Option<Self::IntoItem> {
+            Some(key.clone())
+        }
+    }
+    impl<'a, K, T> GroupGetter<'a, K, T> for Size {
+        type IntoItem = usize;
+        fn get_group(&self, _key: &K, members: &Vec<objects::ItemWithData<'a, T>>) -> Option<Self::IntoItem> {
+            Some(members.len())
+        }
+    }
 }
 
 pub mod head {
@@ -1248,6 +1388,9 @@ pub mod change {
     impl_attribute![?   objects::Change, SnapshotId, objects::SnapshotId, snapshot_id];
     impl_attribute![?+  objects::Change, Path, objects::Path, path_with_data];
     impl_attribute![?+  objects::Change, Snapshot, objects::Snapshot, snapshot_with_data];
+    impl_attribute![?   objects::Change, LinesAdded, usize, lines_added];
+    impl_attribute![?   objects::Change, LinesDeleted, usize, lines_deleted];
+    impl_attribute![?   objects::Change, Diff, String, diff];
 }
 
 pub mod user {
@@ -1259,14 +1402,14 @@ pub mod user {
     impl_attribute![!    objects::User, Raw];
     impl_attribute![!    objects::User, Id, objects::UserId, id];
     impl_attribute![!    objects::User, Email, String, email];
-    impl_attribute![?    objects::User, AuthorExperience, time::Duration, author_experience];
-    impl_attribute![?    objects::User, CommitterExperience, time::Duration, committer_experience];
-    impl_attribute![?    objects::User, Experience, time::Duration, experience];
-    impl_attribute![?..  objects::User, AuthoredCommitIds, objects::CommitId, authored_commit_ids, authored_commit_count];
-    impl_attribute![?..  objects::User, CommittedCommitIds, objects::CommitId, committed_commit_ids, committed_commit_count];
-    impl_attribute![?+.. objects::User, AuthoredCommits, objects::Commit, authored_commits_with_data, authored_commit_count];
-    impl_attribute![?+.. objects::User, CommittedCommits, objects::Commit, committed_commits_with_data, committed_commit_count];
-    impl_attribute![?    objects::User, DeveloperExperience, i32, developer_experience];
+    impl_attribute![expensive ?    objects::User, AuthorExperience, time::Duration, author_experience];
+    impl_attribute![expensive ?    objects::User, CommitterExperience, time::Duration, committer_experience];
+    impl_attribute![expensive ?    objects::User, Experience, time::Duration, experience];
+    impl_attribute![expensive ?..  objects::User, AuthoredCommitIds, objects::CommitId, authored_commit_ids, authored_commit_count];
+    impl_attribute![expensive ?..  objects::User, CommittedCommitIds, objects::CommitId, committed_commit_ids, committed_commit_count];
+    impl_attribute![expensive ?+.. objects::User, AuthoredCommits, objects::Commit, authored_commits_with_data, authored_commit_count];
+    impl_attribute![expensive ?+.. objects::User, CommittedCommits, objects::Commit, committed_commits_with_data, committed_commit_count];
+    impl_attribute![expensive ?    objects::User, DeveloperExperience, i32, developer_experience];
 }
 
 pub mod path {
@@ -1277,7 +1420,7 @@ pub mod path {
     impl_attribute![!   objects::Path, Raw];
     impl_attribute![!   objects::Path, Id, objects::PathId, id];
     impl_attribute![!   objects::Path, Location, String, location];
-    impl_attribute![?   objects::Path, Language, objects::Language, language];
+    impl_attribute![expensive ?   objects::Path, Language, objects::Language, language];
 }
 
 pub mod snapshot {
@@ -1290,6 +1433,10 @@ pub mod snapshot {
     impl_attribute![!   objects::Snapshot, Bytes, Vec<u8>, raw_contents_owned];
     impl_attribute![!   objects::Snapshot, Contents, String, contents_owned];
     impl_attribute![?   objects::Snapshot, Loc, usize, snapshot_locs];
+    impl_attribute![expensive ?   objects::Snapshot, CodeLines, usize, code_lines];
+    impl_attribute![expensive ?   objects::Snapshot, CommentLines, usize, comment_lines];
+    impl_attribute![expensive ?   objects::Snapshot, BlankLines, usize, blank_lines];
+    impl_attribute![expensive ?   objects::Snapshot, CommentDensity, f64, comment_density];
     impl_attribute![!   objects::Snapshot, HasContents, bool, snapshot_has_contents];
 
     /* Number of projects in the database that contain the snapshot (or did in the past). 
@@ -1298,7 +1445,7 @@ pub mod snapshot {
 
        Contains indformation about *all* snapshots, not just those for which we have contents stored as well. 
      */
-    impl_attribute![!   objects::Snapshot, NumProjects, usize, unique_projects];
+    impl_attribute![expensive !   objects::Snapshot, NumProjects, usize, unique_projects];
 
     /* The oldest project in which we have seen the snapshot appear. 
 
@@ -1306,7 +1453,7 @@ pub mod snapshot {
 
        Contains indformation about *all* snapshots, not just those for which we have contents stored as well. 
      */
-    impl_attribute![!   objects::Snapshot, OriginalProject, objects::ProjectId, original_project];
+    impl_attribute![expensive !   objects::Snapshot, OriginalProject, objects::ProjectId, original_project];
 }
 
 pub trait AttributeIterator<'a, T>: Sized + Iterator<Item=objects::ItemWithData<'a, T>> {
@@ -1336,12 +1483,59 @@ pub trait AttributeIterator<'a, T>: Sized + Iterator<Item=objects::ItemWithData<
         vector.into_iter()
     }
 
+    /** Sorts by several keys, eg. `sort_by_keys((Ascending(project::Language), Descending(project::Stars)))`.
+
+        Objects that are equal on all the keys are ordered by their ids, so the order is the same
+        from run to run whatever order the objects came in.
+     */
+    fn sort_by_keys<K>(self, keys: K)
+                       -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where K: SortKey<'a, T>, T: objects::Identifiable {
+        sort_by_keys(self, &keys).into_iter()
+    }
+
+    /** The same as `sort_by(attribute).sample(Top(k))`, but only ever keeps k items in memory. */
+    fn top_by<A: 'a, I>(self, attribute: A, k: usize)
+                        -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: Sort<'a, T, I>, I: Ord {
+        let mut heap: BinaryHeap<std::cmp::Reverse<Ranked<I, objects::ItemWithData<'a, T>>>> = BinaryHeap::with_capacity(k + 1);
+        for (position, item) in self.enumerate().take_while(|_| k > 0) {
+            heap.push(std::cmp::Reverse(Ranked { key: attribute.get(&item), position, item }));
+            if heap.len() > k {
+                heap.pop(); // the lowest key, or the earliest item among equal lowest keys
+            }
+        }
+        let vector: Vec<objects::ItemWithData<'a, T>> =
+            heap.into_sorted_vec().into_iter().map(|std::cmp::Reverse(ranked)| ranked.item).collect();
+        vector.into_iter()
+    }
+
+    /** The same as `sort_with_direction(sort::Direction::Ascending, attribute).sample(Top(k))`,
+        but only ever keeps k items in memory. */
+    fn bottom_by<A: 'a, I>(self, attribute: A, k: usize)
+                           -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: Sort<'a, T, I>, I: Ord {
+        let mut heap: BinaryHeap<Ranked<I, objects::ItemWithData<'a, T>>> = BinaryHeap::with_capacity(k + 1);
+        for (position, item) in self.enumerate().take_while(|_| k > 0) {
+            heap.push(Ranked { key: attribute.get(&item), position, item });
+            if heap.len() > k {
+                heap.pop(); // the highest key, or the latest item among equal highest keys
+            }
+        }
+        let vector: Vec<objects::ItemWithData<'a, T>> =
+            heap.into_sorted_vec().into_iter().map(|ranked| ranked.item).collect();
+        vector.into_iter()
+    }
+
     fn sample<S>(self, sampler: S)
                  -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
         where S: Sampler<'a, T> {
         sampler.sample(self).into_iter()
     }
 
+    /** Groups the objects by the value of an attribute. The objects in each group keep their
+        order, but the groups come out in no particular order, which can differ from one run to
+        the next, so they have to be sorted by key where the output has to be reproducible. */
     fn group_by<A, K>(self, attribute: A)
                       -> std::collections::hash_map::IntoIter<K, Vec<objects::ItemWithData<'a, T>>>
         where A: Group<'a, T, K>, K: Hash + Eq {
@@ -1351,12 +1545,220 @@ pub trait AttributeIterator<'a, T>: Sized + Iterator<Item=objects::ItemWithData<
         }).into_group_map().into_iter()
     }
 
+    /** Pairs each object with every object among `others` that it shares a value with, eg.
+        `join_on(project::AuthorIds, database.users(), user::Id)` pairs each project with each of
+        its authors. The pairs come out in the order of this iterator, and for each of its objects,
+        in the order of `others`. Objects without a value are left out.
+     */
+    fn join_on<A, B, U, R, K>(self, attribute: A, others: R, other_attribute: B)
+                              -> std::vec::IntoIter<(objects::ItemWithData<'a, T>, objects::ItemWithData<'a, U>)>
+        where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: JoinKeys<Key=K>,
+              B: OptionGetter<'a> + Attribute<Object=U>, B::IntoItem: JoinKeys<Key=K>,
+              R: IntoIterator<Item=objects::ItemWithData<'a, U>>, K: Hash + Eq, T: Clone, U: Clone {
+        let others: Vec<objects::ItemWithData<'a, U>> = others.into_iter().collect();
+        let index = join_index(&others, &other_attribute);
+        let vector: Vec<(objects::ItemWithData<'a, T>, objects::ItemWithData<'a, U>)> = self.flat_map(|item_with_data| {
+            let mut matches: Vec<usize> = attribute.get_opt(&item_with_data)
+                .map_or(Vec::new(), |keys| keys.into_join_keys())
+                .iter()
+                .flat_map(|key| index.get(key).into_iter().flatten().copied())
+                .collect();
+            matches.sort();
+            matches.dedup();
+            matches.into_iter()
+                .map(|position| (item_with_data.clone(), others[position].clone()))
+                .collect::<Vec<_>>()
+        }).collect();
+        vector.into_iter()
+    }
+
+    /** Keeps the objects that share a value with at least one of `others`, eg.
+        `semi_join(commit::PathIds, database.paths().filter_by(...), path::Id)`. */
+    fn semi_join<A, B, U, R, K>(self, attribute: A, others: R, other_attribute: B)
+                                -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: JoinKeys<Key=K>,
+              B: OptionGetter<'a> + Attribute<Object=U>, B::IntoItem: JoinKeys<Key=K>,
+              R: IntoIterator<Item=objects::ItemWithData<'a, U>>, K: Hash + Eq {
+        let keys = join_keys(others, &other_attribute);
+        let vector: Vec<objects::ItemWithData<'a, T>> = self
+            .filter(|item_with_data| shares_join_key(&attribute, item_with_data, &keys))
+            .collect();
+        vector.into_iter()
+    }
+
+    /** Keeps the objects that share no value with any of `others`, including those without a value. */
+    fn anti_join<A, B, U, R, K>(self, attribute: A, others: R, other_attribute: B)
+                                -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: JoinKeys<Key=K>,
+              B: OptionGetter<'a> + Attribute<Object=U>, B::IntoItem: JoinKeys<Key=K>,
+              R: IntoIterator<Item=objects::ItemWithData<'a, U>>, K: Hash + Eq {
+        let keys = join_keys(others, &other_attribute);
+        let vector: Vec<objects::ItemWithData<'a, T>> = self
+            .filter(|item_with_data| !shares_join_key(&attribute, item_with_data, &keys))
+            .collect();
+        vector.into_iter()
+    }
+
+    /* Parallel variants of the operators above.
+
+       Each of these collects the iterator and then evaluates the attribute for every item on a
+       thread pool. The resulting items come out in the same order as the sequential operators
+       would produce them, so a `Random` sampler with a fixed seed picks the same items whether
+       the preceding steps ran in parallel or not.
+     */
+    fn par_filter_by<A>(self, attribute: A)
+                        -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: Filter<'a, Item=T> + Sync, T: Send {
+        let vector: Vec<objects::ItemWithData<'a, T>> = Vec::from_iter(self);
+        let vector: Vec<objects::ItemWithData<'a, T>> = vector.into_par_iter()
+            .filter(|item_with_data| attribute.accept(item_with_data))
+            .collect();
+        vector.into_iter()
+    }
+
+    fn par_map_into<A, Tb>(self, attribute: A)
+                           -> std::vec::IntoIter<Tb>
+        where A: Select<'a, T, Tb> + Sync, T: Send, Tb: Send {
+        let vector: Vec<objects::ItemWithData<'a, T>> = Vec::from_iter(self);
+        let vector: Vec<Tb> = vector.into_par_iter()
+            .map(|item_with_data| attribute.select(&item_with_data))
+            .collect();
+        vector.into_iter()
+    }
+
+    fn par_sort_by<A: 'a, I>(self, attribute: A)
+                             -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: Sort<'a, T, I> + Sync, I: Ord + Send, T: Send {
+        self.par_sort_with_direction(sort::Direction::Descending, attribute)
+    }
+
+    fn par_sort_with_direction<A: 'a, I>(self, direction: sort::Direction, attribute: A)
+                                         -> std::vec::IntoIter<objects::ItemWithData<'a, T>>
+        where A: Sort<'a, T, I> + Sync, I: Ord + Send, T: Send {
+        let vector: Vec<objects::ItemWithData<'a, T>> = Vec::from_iter(self);
+        let mut keyed: Vec<(I, objects::ItemWithData<'a, T>)> = vector.into_par_iter()
+            .map(|item_with_data| (attribute.get(&item_with_data), item_with_data))
+            .collect();
+        keyed.par_sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b)); // stable, same as Sort::sort
+        if direction == sort::Direction::Descending {
+            keyed.reverse()
+        }
+        let vector: Vec<objects::ItemWithData<'a, T>> =
+            keyed.into_iter().map(|(_, item_with_data)| item_with_data).collect();
+        vector.into_iter()
+    }
+
+    /** The objects in each group are in the same order as with `group_by`, and the groups, as
+        with `group_by`, in no particular order. */
+    fn par_group_by<A, K>(self, attribute: A)
+                          -> std::collections::hash_map::IntoIter<K, Vec<objects::ItemWithData<'a, T>>>
+        where A: Group<'a, T, K> + Sync, K: Hash + Eq + Send, T: Send {
+        let vector: Vec<objects::ItemWithData<'a, T>> = Vec::from_iter(self);
+        let keyed: Vec<(K, objects::ItemWithData<'a, T>)> = vector.into_par_iter()
+            .map(|item_with_data| (attribute.select_key(&item_with_data), item_with_data))
+            .collect();
+        keyed.into_iter().into_group_map().into_iter()
+    }
+
     // TODO drop options
 }
 
 impl<'a, T, I> AttributeIterator<'a, T> for I
     where I: Sized + Iterator<Item=objects::ItemWithData<'a, T>> {}
 
+fn sort_by_keys<'a, I, K, T>(iter: I, keys: &K) -> Vec<objects::ItemWithData<'a, T>>
+    where I: Iterator<Item=objects::ItemWithData<'a, T>>, K: SortKey<'a, T>, T: objects::Identifiable {
+    let mut keyed: Vec<(K::Key, T::Identity, objects::ItemWithData<'a, T>)> = iter
+        .map(|item_with_data| (keys.key(&item_with_data), item_with_data.item.id(), item_with_data))
+        .collect();
+    keyed.sort_by(|(key_a, id_a, _), (key_b, id_b, _)| key_a.cmp(key_b).then(id_a.cmp(id_b)));
+    keyed.into_iter().map(|(_, _, item_with_data)| item_with_data).collect()
+}
+
+/** A key for `sort_by_keys`: an attribute, the direction to sort it in, and where objects without
+    a value go, eg. `By(project::Stars, sort::Direction::Descending, sort::Missing::First)`. */
+#[derive(Debug, Clone, Copy)] pub struct By<A: Attribute>(pub A, pub sort::Direction, pub sort::Missing);
+/** A key for `sort_by_keys` in ascending order, with objects without a value last. */
+#[derive(Debug, Clone, Copy)] pub struct Ascending<A: Attribute>(pub A);
+/** A key for `sort_by_keys` in descending order, with objects without a value last. */
+#[derive(Debug, Clone, Copy)] pub struct Descending<A: Attribute>(pub A);
+
+impl<'a, A, I, T> SortKey<'a, T> for By<A> where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=I>, I: Ord {
+    type Key = SortValue<I>;
+    fn key(&self, object: &objects::ItemWithData<'a, T>) -> Self::Key {
+        SortValue::new(self.0.get_opt(object), self.1, self.2)
+    }
+}
+macro_rules! impl_sort_key {
+    ($name:ident, $direction:expr, $missing:expr) => {
+        impl<'a, A, I, T> SortKey<'a, T> for $name<A> where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=I>, I: Ord {
+            type Key = SortValue<I>;
+            fn key(&self, object: &objects::ItemWithData<'a, T>) -> Self::Key {
+                SortValue::new(self.0.get_opt(object), $direction, $missing)
+            }
+        }
+    }
+}
+impl_sort_key!(Ascending, sort::Direction::Ascending, sort::Missing::Last);
+impl_sort_key!(Descending, sort::Direction::Descending, sort::Missing::Last);
+
+/* An item along with the key it is ranked by and its position in the iterator, so that `top_by` and
+   `bottom_by` break ties between equal keys the same way a stable sort does. */
+struct Ranked<K, X> { key: K, position: usize, item: X }
+impl<K, X> PartialEq for Ranked<K, X> where K: Ord {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == std::cmp::Ordering::Equal }
+}
+impl<K, X> Eq for Ranked<K, X> where K: Ord {}
+impl<K, X> PartialOrd for Ranked<K, X> where K: Ord {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) }
+}
+impl<K, X> Ord for Ranked<K, X> where K: Ord {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key.cmp(&other.key).then(self.position.cmp(&other.position))
+    }
+}
+
+macro_rules! impl_join_key {
+    ($($type:ty),+) => {
+        $(impl JoinKeys for $type {
+            type Key = $type;
+            fn into_join_keys(self) -> Vec<Self::Key> { vec![self] }
+        })+
+    }
+}
+impl_join_key!(objects::ProjectId, objects::CommitId, objects::UserId, objects::PathId, objects::SnapshotId);
+impl_join_key!(String, u64, usize);
+impl<K> JoinKeys for Vec<K> where K: Hash + Eq {
+    type Key = K;
+    fn into_join_keys(self) -> Vec<Self::Key> { self }
+}
+
+/* Where each join key occurs among the objects on the right-hand side of a join. */
+fn join_index<'a, B, U, K>(others: &Vec<objects::ItemWithData<'a, U>>, attribute: &B) -> HashMap<K, Vec<usize>>
+    where B: OptionGetter<'a> + Attribute<Object=U>, B::IntoItem: JoinKeys<Key=K>, K: Hash + Eq {
+    let mut index: HashMap<K, Vec<usize>> = HashMap::new();
+    for (position, other) in others.iter().enumerate() {
+        for key in attribute.get_opt(other).map_or(Vec::new(), |keys| keys.into_join_keys()) {
+            index.entry(key).or_insert_with(Vec::new).push(position);
+        }
+    }
+    index
+}
+
+fn join_keys<'a, B, U, R, K>(others: R, attribute: &B) -> HashSet<K>
+    where B: OptionGetter<'a> + Attribute<Object=U>, B::IntoItem: JoinKeys<Key=K>,
+          R: IntoIterator<Item=objects::ItemWithData<'a, U>>, K: Hash + Eq {
+    others.into_iter()
+        .flat_map(|other| attribute.get_opt(&other).map_or(Vec::new(), |keys| keys.into_join_keys()))
+        .collect()
+}
+
+fn shares_join_key<'a, A, T, K>(attribute: &A, item_with_data: &objects::ItemWithData<'a, T>, keys: &HashSet<K>) -> bool
+    where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: JoinKeys<Key=K>, K: Hash + Eq {
+    attribute.get_opt(item_with_data)
+        .map_or(false, |values| values.into_join_keys().iter().any(|key| keys.contains(key)))
+}
+
 pub trait AttributeGroupIterator<'a, K, T>: Sized + Iterator<Item=(K, Vec<objects::ItemWithData<'a, T>>)> {
     fn filter_by<A>(self, attribute: A)
                     -> AttributeGroupFilterIter<Self, A>
@@ -1377,6 +1779,15 @@ pub trait AttributeGroupIterator<'a, K, T>: Sized + Iterator<Item=(K, Vec<object
         self.sort_with_direction(sort::Direction::Descending, attribute)
     }
 
+    /** Sorts the objects within each group by several keys, like `AttributeIterator::sort_by_keys`. */
+    fn sort_by_keys<S>(self, keys: S)
+                       -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)>
+        where S: SortKey<'a, T>, T: objects::Identifiable {
+        let vector: Vec<(K, Vec<objects::ItemWithData<'a, T>>)> =
+            self.map(|(key, vector)| (key, sort_by_keys(vector.into_iter(), &keys))).collect();
+        vector.into_iter()
+    }
+
     fn sort_with_direction<A: 'a, I>(self, direction: sort::Direction, attribute: A)
                                      -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)>
         where A: Sort<'a, T, I>, I: Ord {
@@ -1387,7 +1798,7 @@ pub trait AttributeGroupIterator<'a, K, T>: Sized + Iterator<Item=(K, Vec<object
             }).collect();
         vector.into_iter()
     }
-    // TODO sort_key, sort_key_by, sort_key_with, sort_values, sort_values_by, sort_values_with
+    // TODO sort_key_by, sort_key_with, sort_values, sort_values_by, sort_values_with
 
     fn sample<S>(self, sampler: S)
                  -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)>
@@ -1400,6 +1811,42 @@ pub trait AttributeGroupIterator<'a, K, T>: Sized + Iterator<Item=(K, Vec<object
     }
     // TODO sample_key
 
+    /** Keeps only the groups that pass a filter over the whole group, eg. `having(AtLeast(group::Size, 10))`.
+        Groups for which the filtered value is missing do not pass. */
+    fn having<F>(self, filter: F)
+                 -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)>
+        where F: GroupFilter<'a, K, T> {
+        let vector: Vec<(K, Vec<objects::ItemWithData<'a, T>>)> =
+            self.filter(|(key, vector)| filter.accept_group(key, vector)).collect();
+        vector.into_iter()
+    }
+
+    /** Orders the groups by their keys, ascending, rather than in whatever order `group_by` made them. */
+    fn sort_keys(self) -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)> where K: Ord {
+        self.sort_keys_with_direction(sort::Direction::Ascending)
+    }
+
+    fn sort_keys_with_direction(self, direction: sort::Direction)
+                                -> std::vec::IntoIter<(K, Vec<objects::ItemWithData<'a, T>>)> where K: Ord {
+        let mut vector: Vec<(K, Vec<objects::ItemWithData<'a, T>>)> = self.collect();
+        vector.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+        if direction == sort::Direction::Descending {
+            vector.reverse()
+        }
+        vector.into_iter()
+    }
+
+    /** Turns each group into one row, eg. `aggregate(Select!(group::Key, group::Size, Mean(project::Stars)))`.
+
+        Groups for which there is nothing to aggregate are left out. A `Select!` always produces a
+        row, with missing values for the parts that could not be worked out.
+     */
+    fn aggregate<G>(self, aggregate: G) -> std::vec::IntoIter<G::IntoItem> where G: GroupGetter<'a, K, T> {
+        let vector: Vec<G::IntoItem> =
+            self.flat_map(|(key, vector)| aggregate.get_group(&key, &vector)).collect();
+        vector.into_iter()
+    }
+
     fn ungroup(self) -> std::vec::IntoIter<objects::ItemWithData<'a, T>> {
         let vector: Vec<objects::ItemWithData<'a, T>> =
             self.flat_map(|(_, vector)| vector).collect();
@@ -1413,37 +1860,51 @@ impl<'a, K, T, I> AttributeGroupIterator<'a, K, T> for I
 
 macro_rules! impl_comparison {
         ($name:ident, $trait_limit:ident, $comparator:ident, $default:expr) => {
-            pub struct $name<A, N>(pub A, pub N) where A: Attribute; // + OptionGetter<'a, IntoItem=N>;
-            impl<'a, A, N, T> Filter<'a> for $name<A, N> where A: OptionGetter<'a, IntoItem=N> + Attribute<Object=T>, N: $trait_limit {
+            pub struct $name<A, V>(pub A, pub V); // A is an Attribute, or a GroupGetter like group::Size
+            impl<'a, A, N, V, T> Filter<'a> for $name<A, V> where A: OptionGetter<'a, IntoItem=N> + Attribute<Object=T>, N: $trait_limit<V> {
                 type Item = T;
                 fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                     self.0.get_opt(item_with_data).map_or($default, |n| n.$comparator(&self.1))
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
+            }
+            // Unlike objects with a missing value, groups whose value is missing (eg. the mean of a
+            // group in which no member has a value) never pass, whatever the comparison.
+            impl<'a, A, N, V, K, T> GroupFilter<'a, K, T> for $name<A, V> where A: GroupGetter<'a, K, T, IntoItem=N>, N: $trait_limit<V> {
+                fn accept_group(&self, key: &K, members: &Vec<objects::ItemWithData<'a, T>>) -> bool {
+                    self.0.get_group(key, members).map_or(false, |n| n.$comparator(&self.1))
+                }
             }
         }
     }
 
+/* The value compared against can be of a different type than the attribute, as long as the two
+   can be compared, eg. AtLeast(project::MajorLanguageRatio, 0.5) compares an OrdF64 to an f64. */
 impl_comparison!(LessThan, PartialOrd, lt, false);
 impl_comparison!(AtMost,   PartialOrd, le, false);
-impl_comparison!(Equal,    Eq,         eq, false);
+impl_comparison!(Equal,    PartialEq,  eq, false);
 impl_comparison!(AtLeast,  PartialOrd, ge, true);
 impl_comparison!(MoreThan, PartialOrd, gt, true);
 
 macro_rules! impl_binary {
-        ($name:ident, $comparator:expr) => {
+        ($name:ident, $operator:tt) => {
             pub struct $name<A, B>(pub A, pub B); // where A: Attribute, B: Attribute;
             impl<'a, A, B, T> Filter<'a> for $name<A, B> where A: Filter<'a, Item=T>, B: Filter<'a, Item=T> {
                 type Item = T;
                 fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
-                    $comparator(self.0.accept(item_with_data),
-                                self.1.accept(item_with_data))
+                    if self.1.cost() < self.0.cost() {
+                        self.1.accept(item_with_data) $operator self.0.accept(item_with_data)
+                    } else {
+                        self.0.accept(item_with_data) $operator self.1.accept(item_with_data)
+                    }
                 }
+                fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), self.1.cost()) }
             }
         }
     }
 
-impl_binary!(And, |a, b| a && b); // TODO Effectively does not short circuit.
-impl_binary!(Or,  |a, b| a || b);
+impl_binary!(And, &&);
+impl_binary!(Or,  ||);
 
 macro_rules! impl_unary {
         ($name:ident, $comparator:expr) => {
@@ -1453,12 +1914,96 @@ macro_rules! impl_unary {
                 fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                     $comparator(self.0.accept(item_with_data))
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
         }
     }
 
 impl_unary!(Not,  |a: bool| !a);
 
+/** A filter along with a hint of how much work it is, eg. for a `Derived` filter that does a lot of
+    work, `WithCost(Derived::new(|project| ...), Cost::Expensive)`. */
+pub struct WithCost<A>(pub A, pub Cost);
+impl<'a, A, T> Filter<'a> for WithCost<A> where A: Filter<'a, Item=T> {
+    type Item = T;
+    fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
+        self.0.accept(item_with_data)
+    }
+    fn cost(&self) -> Cost { self.1 }
+}
+
+/** A tuple (or a vector) of filters of the same kind of object, for `AllOf`, `AnyOf`, and `NoneOf`. */
+pub trait Filters<'a> {
+    type Item;
+    fn costs(&self) -> Vec<Cost>;
+    fn accept_nth(&self, n: usize, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool;
+}
+
+macro_rules! impl_filters_for_tuple {
+    ($($filter:ident -> $n:tt),+) => {
+        impl<'a, T, $($filter,)+> Filters<'a> for ($($filter,)+) where $($filter: Filter<'a, Item=T>,)+ {
+            type Item = T;
+            fn costs(&self) -> Vec<Cost> { vec![$(self.$n.cost(),)+] }
+            fn accept_nth(&self, n: usize, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
+                match n {
+                    $($n => self.$n.accept(item_with_data),)+
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+impl_filters_for_tuple!(Fa -> 0);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2, Fd -> 3);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2, Fd -> 3, Fe -> 4);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2, Fd -> 3, Fe -> 4, Ff -> 5);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2, Fd -> 3, Fe -> 4, Ff -> 5, Fg -> 6);
+impl_filters_for_tuple!(Fa -> 0, Fb -> 1, Fc -> 2, Fd -> 3, Fe -> 4, Ff -> 5, Fg -> 6, Fh -> 7);
+
+impl<'a, F, T> Filters<'a> for Vec<F> where F: Filter<'a, Item=T> {
+    type Item = T;
+    fn costs(&self) -> Vec<Cost> { self.iter().map(|filter| filter.cost()).collect() }
+    fn accept_nth(&self, n: usize, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
+        self[n].accept(item_with_data)
+    }
+}
+
+macro_rules! impl_variadic_filter {
+    ($(#[$meta:meta])* $name:ident, $method:ident, $result:expr) => {
+        $(#[$meta])*
+        pub struct $name<F> { filters: F, order: Vec<usize>, cost: Cost }
+        #[allow(non_snake_case)]
+        pub fn $name<'a, F>(filters: F) -> $name<F> where F: Filters<'a> {
+            let costs = filters.costs();
+            let mut order: Vec<usize> = (0..costs.len()).collect();
+            order.sort_by_key(|n| costs[*n]); // stable, so filters of the same cost keep their order
+            let cost = costs.into_iter().max().unwrap_or_default();
+            $name { filters, order, cost }
+        }
+        impl<'a, F, T> Filter<'a> for $name<F> where F: Filters<'a, Item=T> {
+            type Item = T;
+            fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
+                $result(self.order.iter().$method(|n| self.filters.accept_nth(*n, item_with_data)))
+            }
+            fn cost(&self) -> Cost { self.cost }
+        }
+    }
+}
+impl_variadic_filter!(
+    /** Accepts objects that pass all of the filters in a tuple (or a vector), eg.
+        `AllOf((Equal(project::IsFork, false), AtLeast(project::Stars, 10), AtLeast(Count(project::Commits), 100)))`.
+        The filters are ordered by cost once, when the `AllOf` is made, and then cheaper filters are
+        tried first, and filters of the same cost in order. */
+    AllOf, all, |accepted: bool| accepted);
+impl_variadic_filter!(
+    /** Accepts objects that pass at least one of the filters in a tuple (or a vector). */
+    AnyOf, any, |accepted: bool| accepted);
+impl_variadic_filter!(
+    /** Accepts objects that pass none of the filters in a tuple (or a vector). */
+    NoneOf, any, |accepted: bool| !accepted);
+
 macro_rules! impl_existential {
         ($name:ident, $method:ident) => {
             pub struct $name<A>(pub A) where A: Attribute; // + OptionGetter<'a>;
@@ -1467,6 +2012,7 @@ macro_rules! impl_existential {
                 fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                     self.0.get_opt(item_with_data).$method()
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
         }
     }
@@ -1480,6 +2026,7 @@ impl<'a, A, T> Filter<'a> for Same<'a, A> where A: OptionGetter<'a, IntoItem=Str
     fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
         self.0.get_opt(item_with_data).map_or(false, |e| e.as_str() == self.1)
     }
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 pub struct Contains<'a, A>(pub A, pub &'a str) where A: OptionGetter<'a>;
@@ -1488,6 +2035,7 @@ impl<'a, A, T> Filter<'a> for Contains<'a, A> where A: OptionGetter<'a, IntoItem
     fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
         self.0.get_opt(item_with_data).map_or(false, |e| e.contains(self.1))
     }
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 #[macro_export] macro_rules! regex { ($str:expr) => { regex::Regex::new($str).unwrap() }}
@@ -1497,6 +2045,7 @@ impl<'a, A, T> Filter<'a> for  Matches<A> where A: OptionGetter<'a, IntoItem=Str
     fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
         self.0.get_opt(item_with_data).map_or(false, |e| self.1.is_match(&e))
     }
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 macro_rules! impl_collection_membership {
@@ -1509,6 +2058,7 @@ macro_rules! impl_collection_membership {
                 fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
                     self.0.get_opt(item_with_data).map_or(false, |e| self.1.contains(&e))
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
             impl<'a, A, T, I> Filter<'a> for AnyIn<A, $collection_type<I>>
                 where A: OptionGetter<'a, IntoItem=Vec<I>>,
@@ -1520,6 +2070,7 @@ macro_rules! impl_collection_membership {
                         vector.iter().any(|e| self.1.contains(e))
                     })
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
             impl<'a, A, T, I> Filter<'a> for AllIn<A, $collection_type<I>>
                 where A: OptionGetter<'a, IntoItem=Vec<I>>,
@@ -1531,6 +2082,7 @@ macro_rules! impl_collection_membership {
                         vector.iter().all(|e| self.1.contains(e))
                     })
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
             impl<'a, A, T, I> Filter<'a> for AnyWithin<A, $collection_type<I>>
             where A: OptionGetter<'a, IntoItem=Vec<I>>,
@@ -1544,6 +2096,7 @@ macro_rules! impl_collection_membership {
                     vector_ref.map_or(false, |vector| vector.contains(e))
                 })
             }
+            fn cost(&self) -> Cost { self.0.cost() }
         }
             impl<'a, A, T, I> Filter<'a> for AllWithin<A, $collection_type<I>>
                 where A: OptionGetter<'a, IntoItem=Vec<I>>,
@@ -1557,6 +2110,7 @@ macro_rules! impl_collection_membership {
                         vector_ref.map_or(false, |vector| vector.contains(e))
                     })
                 }
+                fn cost(&self) -> Cost { self.0.cost() }
             }
         }
     }
@@ -1582,6 +2136,7 @@ where A: OptionGetter<'a, IntoItem=Vec<I>>,
             e.contains(&self.1)
         })
     }
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)] pub struct Top(pub usize);
@@ -1609,6 +2164,103 @@ impl<'a, T> Sampler<'a, T> for Random {
     }
 }
 
+/** Samples the given number of objects at random, like `Random`, but keeps them in the order they came in.
+
+    Uses reservoir sampling with geometric skips (Li's algorithm L), so it only keeps the sample in
+    memory and draws random numbers only for the objects that make it into the reservoir, which
+    makes it cheap on long iterators such as all commits.
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)] pub struct Reservoir(pub usize, pub Seed);
+impl<'a, T> Sampler<'a, T> for Reservoir {
+    fn sample<I>(&self, iter: I) -> Vec<objects::ItemWithData<'a, T>>
+        where I: Iterator<Item=objects::ItemWithData<'a, T>> {
+
+        let k = self.0;
+        let mut iter = iter.enumerate();
+        let mut reservoir: Vec<(usize, objects::ItemWithData<'a, T>)> = iter.by_ref().take(k).collect();
+
+        if reservoir.len() == k && k > 0 {
+            let mut rng = Pcg64Mcg::from_seed(self.1.to_be_bytes());
+            let mut uniform = || loop { let u: f64 = rng.gen(); if u > 0f64 { return u } }; // (0,1)
+            let mut w = (uniform().ln() / k as f64).exp();
+            loop {
+                let skip = (uniform().ln() / (1f64 - w).ln()).floor() as usize;
+                match iter.nth(skip) {
+                    Some(item) => {
+                        let index = (uniform() * k as f64) as usize;
+                        reservoir[index.min(k - 1)] = item;
+                        w *= (uniform().ln() / k as f64).exp();
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        reservoir.sort_by_key(|(position, _)| *position);
+        reservoir.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/** Samples up to the given number of objects from each stratum, ie. from each group of objects that
+    have the same value of an attribute, eg. `Stratified(project::Language, 100, Seed(42))`.
+
+    Strata are sampled in the order in which they first occur, all from a single random number
+    generator, so the sample is the same for the same seed and the same objects in the same order.
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)] pub struct Stratified<A: Attribute>(pub A, pub usize, pub Seed);
+impl<'a, A, K, T> Sampler<'a, T> for Stratified<A>
+    where A: Attribute<Object=T> + Getter<'a, IntoItem=K>, K: Hash + Eq {
+    fn sample<I>(&self, iter: I) -> Vec<objects::ItemWithData<'a, T>>
+        where I: Iterator<Item=objects::ItemWithData<'a, T>> {
+
+        let mut strata: Vec<Vec<objects::ItemWithData<'a, T>>> = Vec::new();
+        let mut stratum_indices: HashMap<K, usize> = HashMap::new();
+        for item in iter {
+            let key = self.0.get(&item);
+            let index = *stratum_indices.entry(key).or_insert_with(|| {
+                strata.push(Vec::new());
+                strata.len() - 1
+            });
+            strata[index].push(item);
+        }
+
+        let mut rng = Pcg64Mcg::from_seed(self.2.to_be_bytes());
+        strata.into_iter()
+            .flat_map(|stratum| stratum.into_iter().choose_multiple(&mut rng, self.1))
+            .collect()
+    }
+}
+
+/** Samples objects at random with probability proportional to an attribute, without replacement,
+    eg. `Weighted(Count(project::Commits), 100, Seed(42))`.
+
+    Objects whose weight is missing or not positive are never picked. The sampled objects keep
+    the order they came in, and the sample is the same for the same seed and the same objects in
+    the same order.
+ */
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)] pub struct Weighted<A: Attribute>(pub A, pub usize, pub Seed);
+impl<'a, A, N, T> Sampler<'a, T> for Weighted<A>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric {
+    fn sample<I>(&self, iter: I) -> Vec<objects::ItemWithData<'a, T>>
+        where I: Iterator<Item=objects::ItemWithData<'a, T>> {
+
+        // Efraimidis and Spirakis: pick the objects with the largest u^(1/weight), u uniform in (0,1).
+        let mut rng = Pcg64Mcg::from_seed(self.2.to_be_bytes());
+        let mut keyed: Vec<(f64, usize, objects::ItemWithData<'a, T>)> = iter.enumerate()
+            .filter_map(|(position, item)| {
+                let weight = self.0.get_opt(&item).map_or(0f64, |weight| weight.to_f64());
+                let u: f64 = rng.gen();
+                if weight > 0f64 { Some((u.powf(1f64 / weight), position, item)) } else { None }
+            })
+            .collect();
+
+        keyed.sort_by(|(key_a, _, _), (key_b, _, _)| key_b.partial_cmp(key_a).unwrap());
+        keyed.truncate(self.1);
+        keyed.sort_by_key(|(_, position, _)| *position);
+        keyed.into_iter().map(|(_, _, item)| item).collect()
+    }
+}
+
 pub trait SimilarityCriterion<'a> {
     type Item;
     type IntoItem;
@@ -1668,6 +2320,7 @@ impl<'a, T, S, C> Sampler<'a, T> for Distinct<S, C> where S: Sampler<'a, T>, C:
 pub struct Length<A: Attribute>(pub A);
 impl<A, T> Attribute for Length<A> where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 impl<'a, A, T> Getter<'a> for Length<A> where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=String> {
     type IntoItem = Option<usize>;
@@ -1682,9 +2335,191 @@ impl<'a, A, T> OptionGetter<'a> for Length<A> where A: Attribute<Object=T> + Opt
     }
 }
 
+/** An attribute worked out by a closure, for metrics that are not built in, eg.
+    `Derived::new(|project: &ItemWithData<Project>| project.commit_count().map(|commits| commits / 10))`.
+
+    It works for any kind of object, and can be used wherever built-in attributes can: to filter
+    (if the closure returns a `bool`), to sort, to group, to count (if it returns a `Vec`), and in
+    `Select!`. Objects for which the closure returns `None` are treated like objects without a
+    value for a built-in attribute.
+ */
+pub struct Derived<T, R, F> { function: F, types: PhantomData<fn(&T) -> R> }
+impl<T, R, F> Derived<T, R, F> where F: for<'a, 'b> Fn(&'b objects::ItemWithData<'a, T>) -> Option<R> {
+    pub fn new(function: F) -> Self {
+        Derived { function, types: PhantomData }
+    }
+}
+impl<T, R, F> Clone for Derived<T, R, F> where F: Clone {
+    fn clone(&self) -> Self {
+        Derived { function: self.function.clone(), types: PhantomData }
+    }
+}
+impl<T, R, F> Attribute for Derived<T, R, F> {
+    type Object = T;
+}
+impl<'a, T, R, F> OptionGetter<'a> for Derived<T, R, F> where F: Fn(&objects::ItemWithData<'a, T>) -> Option<R> {
+    type IntoItem = R;
+    fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        (self.function)(object)
+    }
+}
+impl<'a, T, R, F> Getter<'a> for Derived<T, R, F> where F: Fn(&objects::ItemWithData<'a, T>) -> Option<R> {
+    type IntoItem = Option<R>;
+    fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        (self.function)(object)
+    }
+}
+impl<'a, T, F> Filter<'a> for Derived<T, bool, F> where F: Fn(&objects::ItemWithData<'a, T>) -> Option<bool> {
+    type Item = T;
+    fn accept(&self, item_with_data: &objects::ItemWithData<'a, Self::Item>) -> bool {
+        (self.function)(item_with_data).unwrap_or(false)
+    }
+}
+impl<'a, T, I, F> OptionCountable<'a> for Derived<T, Vec<I>, F> where F: Fn(&objects::ItemWithData<'a, T>) -> Option<Vec<I>> {
+    fn count(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<usize> {
+        (self.function)(object).map(|vector| vector.len())
+    }
+}
+
+/* Arithmetic on numeric attributes.
+
+   Results are `OrdF64`s, so they can be sorted, grouped, and selected like any other attribute. A
+   result is missing if any of the values it is worked out from is missing, or if it is not a finite
+   number, eg. after dividing by zero or taking the logarithm of zero. Durations count as seconds;
+   `Years` turns them into years.
+ */
+fn finite(n: f64) -> Option<OrdF64> {
+    if n.is_finite() { Some(OrdF64::from(n)) } else { None }
+}
+
+macro_rules! impl_arithmetic_binary {
+    ($name:ident, |$a:ident, $b:ident| $operation:expr) => {
+        pub struct $name<A, B>(pub A, pub B);
+        impl<A, B, T> Attribute for $name<A, B> where A: Attribute<Object=T>, B: Attribute<Object=T> {
+            type Object = T;
+            fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), self.1.cost()) }
+        }
+        impl<'a, A, B, T, Na, Nb> OptionGetter<'a> for $name<A, B>
+            where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=Na>,
+                  B: Attribute<Object=T> + OptionGetter<'a, IntoItem=Nb>,
+                  Na: Numeric, Nb: Numeric {
+            type IntoItem = OrdF64;
+            fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+                let $a = self.0.get_opt(object)?.to_f64();
+                let $b = self.1.get_opt(object)?.to_f64();
+                finite($operation)
+            }
+        }
+        impl<'a, A, B, T, Na, Nb> Getter<'a> for $name<A, B>
+            where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=Na>,
+                  B: Attribute<Object=T> + OptionGetter<'a, IntoItem=Nb>,
+                  Na: Numeric, Nb: Numeric {
+            type IntoItem = Option<OrdF64>;
+            fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+                self.get_opt(object)
+            }
+        }
+    }
+}
+impl_arithmetic_binary!(Add, |a, b| a + b);
+impl_arithmetic_binary!(Sub, |a, b| a - b);
+impl_arithmetic_binary!(Mul, |a, b| a * b);
+impl_arithmetic_binary!(Div, |a, b| a / b);
+
+macro_rules! impl_arithmetic_unary {
+    ($name:ident, |$this:ident, $n:ident| $operation:expr) => {
+        impl<A, T> Attribute for $name<A> where A: Attribute<Object=T> {
+            type Object = T;
+            fn cost(&self) -> Cost { self.0.cost() }
+        }
+        impl<'a, A, T, N> OptionGetter<'a> for $name<A>
+            where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric {
+            type IntoItem = OrdF64;
+            fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+                let $this = self;
+                let $n = self.0.get_opt(object)?.to_f64();
+                finite($operation)
+            }
+        }
+        impl<'a, A, T, N> Getter<'a> for $name<A>
+            where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric {
+            type IntoItem = Option<OrdF64>;
+            fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+                self.get_opt(object)
+            }
+        }
+    }
+}
+/** The absolute value of a numeric attribute. */
+pub struct Abs<A>(pub A);
+/** The logarithm of a numeric attribute in a given base, eg. `Logarithm(project::Stars, 10.0)`.
+    (Not `Log`, which is the logger.) */
+pub struct Logarithm<A>(pub A, pub f64);
+/** A numeric attribute, kept between a minimum and a maximum, eg. `Clamp(project::Stars, 0.0, 1000.0)`. */
+pub struct Clamp<A>(pub A, pub f64, pub f64);
+/** A duration in years, eg. `Div(project::Stars, Years(project::Age))` is stars per year. */
+pub struct Years<A>(pub A);
+/** A numeric attribute rounded to a number of decimal places, eg. to group projects by
+    `Round(project::MajorLanguageRatio, 1)`. A negative number of places rounds to tens, hundreds,
+    and so on. For bins of other widths, see `Bucket`. */
+pub struct Round<A>(pub A, pub i32);
+impl_arithmetic_unary!(Abs, |_this, n| n.abs());
+impl_arithmetic_unary!(Logarithm, |this, n| n.log(this.1));
+impl_arithmetic_unary!(Clamp, |this, n| n.max(this.1).min(this.2));
+impl_arithmetic_unary!(Years, |_this, n| n / time::Duration::from_years(1).as_seconds() as f64);
+impl_arithmetic_unary!(Round, |this, n| { let scale = 10f64.powi(this.1); (n * scale).round() / scale });
+
+/** An attribute, or a default value for objects that do not have one, eg. `Coalesce(project::Stars, 0)`. */
+pub struct Coalesce<A, N>(pub A, pub N);
+impl<A, N, T> Attribute for Coalesce<A, N> where A: Attribute<Object=T> {
+    type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
+}
+impl<'a, A, N, T> OptionGetter<'a> for Coalesce<A, N> where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Clone {
+    type IntoItem = N;
+    fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        Some(self.get(object))
+    }
+}
+impl<'a, A, N, T> Getter<'a> for Coalesce<A, N> where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Clone {
+    type IntoItem = N;
+    fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        self.0.get_opt(object).unwrap_or_else(|| self.1.clone())
+    }
+}
+
+/** One attribute for objects that pass a filter, and another for those that do not, eg.
+    `If(project::IsFork, Count(project::Commits), project::Stars)`. Both have to be of the same type. */
+pub struct If<F, A, B>(pub F, pub A, pub B);
+impl<F, A, B, T> Attribute for If<F, A, B>
+    where F: for<'a> Filter<'a, Item=T>, A: Attribute<Object=T>, B: Attribute<Object=T> {
+    type Object = T;
+    /* The condition is checked for every object, so it counts as much as either branch. */
+    fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), std::cmp::max(self.1.cost(), self.2.cost())) }
+}
+impl<'a, F, A, B, T, N> OptionGetter<'a> for If<F, A, B>
+    where F: Filter<'a, Item=T>,
+          A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>,
+          B: Attribute<Object=T> + OptionGetter<'a, IntoItem=N> {
+    type IntoItem = N;
+    fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        if self.0.accept(object) { self.1.get_opt(object) } else { self.2.get_opt(object) }
+    }
+}
+impl<'a, F, A, B, T, N> Getter<'a> for If<F, A, B>
+    where F: Filter<'a, Item=T>,
+          A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>,
+          B: Attribute<Object=T> + OptionGetter<'a, IntoItem=N> {
+    type IntoItem = Option<N>;
+    fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        self.get_opt(object)
+    }
+}
+
 pub struct Count<A: Attribute>(pub A);
 impl<A, T> Attribute for Count<A> where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 impl<'a, A, T> Getter<'a> for Count<A> where A: Attribute<Object=T> + OptionCountable<'a> {
     type IntoItem = usize;
@@ -1699,12 +2534,23 @@ impl<'a, A, T> OptionGetter<'a> for Count<A> where A: Attribute<Object=T> + Opti
     }
 }
 
+/** A number that statistics and binning can be calculated over as a float. */
+pub trait Numeric { fn to_f64(&self) -> f64; }
+macro_rules! impl_numeric {
+    ($($type:ty),+) => { $(impl Numeric for $type { fn to_f64(&self) -> f64 { *self as f64 } })+ }
+}
+impl_numeric!(usize, u128, u64, u32, u16, u8, isize, i128, i64, i32, i16, i8, f64, f32);
+impl Numeric for Fraction<usize> { fn to_f64(&self) -> f64 { self.as_f64() } }
+impl Numeric for OrdF64 { fn to_f64(&self) -> f64 { self.as_f64() } }
+impl Numeric for crate::time::Duration { fn to_f64(&self) -> f64 { self.as_seconds() as f64 } }
+
 trait CalculateStat<N, T>{ fn calculate(vector: Vec<N>) -> T; }
 macro_rules! impl_calculator {
         ($name:ident -> $result:ty where N: $($requirements:path),+; $calculate:item) => {
             pub struct $name<A: Attribute>(pub A);
             impl<A, T> Attribute for $name<A> where A: Attribute<Object=T> {
                 type Object = T;
+                fn cost(&self) -> Cost { self.0.cost() }
             }
             impl<'a, A, N, T> Getter<'a> for $name<A>
                 where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=Vec<N>>, N: $($requirements +)+ {
@@ -1723,6 +2569,14 @@ macro_rules! impl_calculator {
             impl<A, N, T> CalculateStat<N, Option<$result>> for $name<A> where A: Attribute<Object=T>, N: $($requirements +)+  {
                 $calculate
             }
+            impl<'a, A, N, K, T> GroupGetter<'a, K, T> for $name<A>
+                where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: $($requirements +)+ {
+                type IntoItem = $result;
+                fn get_group(&self, _key: &K, members: &Vec<objects::ItemWithData<'a, T>>) -> Option<Self::IntoItem> {
+                    let values: Vec<N> = members.iter().flat_map(|member| self.0.get_opt(member)).collect();
+                    Self::calculate(values)
+                }
+            }
         }
     }
 
@@ -1743,7 +2597,7 @@ impl_calculator!(Max -> N where N: Ord, Clone;
 impl_calculator!(MinMax -> (N, N) where N: Ord, Clone;
         fn calculate(vector: Vec<N>) -> Option<(N,N)> { vector.into_iter().minmax().into_option() }
     );
-impl_calculator!(Mean -> Fraction<N> where N: Sum;
+impl_calculator!(Mean -> Fraction<N> where N: std::iter::Sum;
         fn calculate(vector: Vec<N>) -> Option<Fraction<N>> {
             let length = vector.len();
             let sum = vector.into_iter().sum::<N>();
@@ -1754,7 +2608,7 @@ impl_calculator!(Mean -> Fraction<N> where N: Sum;
             }
         }
     );
-impl_calculator!(Median -> Fraction<N> where N: Ord, Clone, Sum;
+impl_calculator!(Median -> Fraction<N> where N: Ord, Clone, std::iter::Sum;
         fn calculate(mut items: Vec<N>) -> Option<Fraction<N>> {
             items.sort();
             let length = items.len();
@@ -1775,125 +2629,307 @@ impl_calculator!(Median -> Fraction<N> where N: Ord, Clone, Sum;
             }
         }
     );
-//
-// type BinKey = Option<i64>;
-// pub trait BinningFunction {
-//     type From;
-//     fn calculate_key(&self, value: Self::From) -> BinKey;
-//     fn calculate_limits(&self, key: BinKey) -> (Self::From, Self::From);
-//     fn bin(value: Self::From) -> Bin<Self> {
-//         Bin { value, binning_function: PhantomData }
-//     }
-// }
-//
-// pub struct Bin<F: BinningFunction> { value: F::From, binning_function: PhantomData<F> }
-// impl<F> Bin<F> where F: BinningFunction {
-//     // pub fn from(value: F::From) -> Self {
-//     //
-//     // }
-// }
-//
-// impl<F, T> std::convert::From<T> for Bin<F> where F: BinningFunction<From=T>, T: Div /*TODO*/ {
-//     fn from(value: T) -> Self {
-//         Bin { value, binning_function: PhantomData }
-//     }
-// }
-//
-// #[derive(Clone, Debug)]
-// pub struct Interval(pub usize);
-// impl BinningFunction for Interval {
-//     type From = f64;
-//     fn calculate_key(&self, value: Self::From) -> BinKey {
-//
-//         let bin = (value / self.0 as f64).to_i64();
-//
-//     }
-//     fn calculate_limits(&self, key: BinKey) -> (Self::From, Self::From) {
-//
-//     }
-// }
+impl_calculator!(Total -> N where N: std::iter::Sum;
+        fn calculate(vector: Vec<N>) -> Option<N> { Some(vector.into_iter().sum()) }
+    );
+impl_calculator!(Mode -> N where N: Ord, Clone;
+        fn calculate(vector: Vec<N>) -> Option<N> { mode(vector) }
+    );
 
-// impl<T, F> Display for Bin<T, F> where F: BinningFunction<T> {
-//     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-//         write!(f, "{}..{}", self.binning_function.minimum(), self.binning_function.maximum())
-//     }
-// }
+// Variants of the above for floats. Results are OrdF64s, so that they can be sorted and grouped.
+impl_calculator!(MinF64 -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { sorted_f64(vector).first().copied().map(OrdF64::from) }
+    );
+impl_calculator!(MaxF64 -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { sorted_f64(vector).last().copied().map(OrdF64::from) }
+    );
+impl_calculator!(MinMaxF64 -> (OrdF64, OrdF64) where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<(OrdF64, OrdF64)> {
+            let sorted = sorted_f64(vector);
+            sorted.first().copied().zip(sorted.last().copied()).map(|(min, max)| (OrdF64::from(min), OrdF64::from(max)))
+        }
+    );
+impl_calculator!(MeanF64 -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { mean_f64(&sorted_f64(vector)).map(OrdF64::from) }
+    );
+impl_calculator!(MedianF64 -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { percentile_of_sorted(&sorted_f64(vector), 50f64).map(OrdF64::from) }
+    );
+impl_calculator!(ModeF64 -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> {
+            mode(sorted_f64(vector).into_iter().map(OrdF64::from).collect())
+        }
+    );
 
-// pub trait BinningFunction<T>: Clone {
-//     type Into;
-//     fn bin(&self, value: T) -> Bin<Self::Into, Self>;
-//
-//     fn minimum(&self, bin: Self::Into) -> T;
-//     fn maximum(&self, bin: Self::Into) -> T;
-//     // fn minimum(bin: Bin<Self::Into, Self>) -> T {
-//     //     bin.binning_function.minimum_value(bin.bin)
-//     // }
-//     // fn maximum(bin: Bin<Self::Into, Self>) -> T {
-//     //     bin.binning_function.minimum_value(bin.bin)
-//     // }
-// }
+// These are calculated over floats, whatever the numbers are.
+impl_calculator!(Variance -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { variance_f64(&sorted_f64(vector)).map(OrdF64::from) }
+    );
+impl_calculator!(StdDev -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> { variance_f64(&sorted_f64(vector)).map(|variance| OrdF64::from(variance.sqrt())) }
+    );
+impl_calculator!(IQR -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> {
+            let sorted = sorted_f64(vector);
+            percentile_of_sorted(&sorted, 75f64).zip(percentile_of_sorted(&sorted, 25f64)).map(|(q3, q1)| OrdF64::from(q3 - q1))
+        }
+    );
+impl_calculator!(Gini -> OrdF64 where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<OrdF64> {
+            let sorted = sorted_f64(vector);
+            let length = sorted.len() as f64;
+            let sum: f64 = sorted.iter().sum();
+            if sorted.is_empty() || sum == 0f64 {
+                return None
+            }
+            let weighted: f64 = sorted.iter().enumerate()
+                .map(|(index, value)| (2f64 * (index as f64 + 1f64) - length - 1f64) * value)
+                .sum();
+            Some(OrdF64::from(weighted / (length * sum)))
+        }
+    );
+impl_calculator!(Describe -> Description where N: Numeric;
+        fn calculate(vector: Vec<N>) -> Option<Description> {
+            let sorted = sorted_f64(vector);
+            Some(Description {
+                count: sorted.len(),
+                mean: mean_f64(&sorted)?,
+                std: variance_f64(&sorted).map(|variance| variance.sqrt()),
+                min: *sorted.first()?,
+                q1: percentile_of_sorted(&sorted, 25f64)?,
+                median: percentile_of_sorted(&sorted, 50f64)?,
+                q3: percentile_of_sorted(&sorted, 75f64)?,
+                max: *sorted.last()?,
+            })
+        }
+    );
 
-// macro_rules! impl_interval_binning_function {
-//     ($from:tt -> $to:tt) => {
-//         impl BinningFunction for Interval<usize> {
-//             type From = $from;
-//             type Into = $to;
-//             fn bin(&self, value: Self::From) -> Bin<Self> {
-//                 let binning_function = self.clone();
-//                 let bin = value / self.0;
-//                 Bin { value , bin, binning_function }
-//             }
-//         }
-//     }
-// }
+/** The percentile of a vector of numbers, eg. `Percentile(FromEach(project::Commits, commit::MessageLength), 90)`.
 
-// pub trait DivInto<T> {
-//     fn div_by(&self, other: &T) -> T;
-//     fn convert_into(&self) -> T;
-//     fn convert_from(other: &T) -> Self;
-// }
-//
-// impl<T> BinningFunction<T> for Interval<i64> where T: DivInto<i64> {
-//     type Into = i64;
-//     fn bin(&self, value: T) -> Bin<T, Self> {
-//         let binning_function = self.clone();
-//         let bin = value.div_by(&self.0);
-//         Bin { value , bin, binning_function }
-//     }
-//     fn minimum(&self, bin: Self::Into) -> T {
-//         T::convert_from(&(bin * self.0))
-//     }
-//     fn maximum(&self, bin: Self::Into) -> T {
-//         T::convert_from(&(((bin + 1) * self.0) - 1))
-//     }
-// }
+    Percentiles between two of the numbers are interpolated linearly, the same as R and numpy do
+    by default.
+ */
+pub struct Percentile<A: Attribute, P>(pub A, pub P);
+impl<A, P, T> Attribute for Percentile<A, P> where A: Attribute<Object=T> {
+    type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
+}
+impl<'a, A, P, N, T> Getter<'a> for Percentile<A, P>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=Vec<N>>, N: Numeric, P: Numeric {
+    type IntoItem = Option<OrdF64>;
+    fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        self.get_opt(object)
+    }
+}
+impl<'a, A, P, N, T> OptionGetter<'a> for Percentile<A, P>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=Vec<N>>, N: Numeric, P: Numeric {
+    type IntoItem = OrdF64;
+    fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        self.0.get_opt(object).and_then(|vector| percentile_of_sorted(&sorted_f64(vector), self.1.to_f64())).map(OrdF64::from)
+    }
+}
+impl<'a, A, P, N, K, T> GroupGetter<'a, K, T> for Percentile<A, P>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric, P: Numeric {
+    type IntoItem = OrdF64;
+    fn get_group(&self, _key: &K, members: &Vec<objects::ItemWithData<'a, T>>) -> Option<Self::IntoItem> {
+        let values: Vec<N> = members.iter().flat_map(|member| self.0.get_opt(member)).collect();
+        percentile_of_sorted(&sorted_f64(values), self.1.to_f64()).map(OrdF64::from)
+    }
+}
 
-//         100
-// 0 ->      0  0..99         (n) * N..( (n+1) * N )-1
-// 10->      0  0..99
-// 100->     1  100..199
-// 101->     1  100..199
-// 1000     10  1000..1099
-// -1 ->     0
+/** Summary statistics of a vector of numbers, as calculated by `Describe`.
 
+    The standard deviation is missing when there is only one number. Quartiles are interpolated
+    the same way as by `Percentile`.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Description {
+    pub count: usize,
+    pub mean: f64,
+    pub std: Option<f64>,
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+}
+impl Description {
+    pub fn as_tuple(&self) -> (usize, f64, Option<f64>, f64, f64, f64, f64, f64) {
+        (self.count, self.mean, self.std, self.min, self.q1, self.median, self.q3, self.max)
+    }
+}
+
+/* Numbers as floats in ascending order, as OrdF64 orders them, leaving out NaNs, which would
+   otherwise sort before everything and skew every statistic. All float calculators start here. */
+fn sorted_f64<N>(vector: Vec<N>) -> Vec<f64> where N: Numeric {
+    let mut sorted: Vec<f64> = vector.iter().map(|n| n.to_f64()).filter(|n| !n.is_nan()).collect();
+    sorted.sort_by_key(|n| OrdF64::from(*n));
+    sorted
+}
+fn mean_f64(vector: &[f64]) -> Option<f64> {
+    if vector.is_empty() { None } else { Some(vector.iter().sum::<f64>() / vector.len() as f64) }
+}
+/* Sample variance, ie. divided by n - 1. */
+fn variance_f64(vector: &[f64]) -> Option<f64> {
+    if vector.len() < 2 {
+        return None
+    }
+    let mean = mean_f64(vector)?;
+    Some(vector.iter().map(|n| (n - mean) * (n - mean)).sum::<f64>() / (vector.len() - 1) as f64)
+}
+fn percentile_of_sorted(sorted: &[f64], percentile: f64) -> Option<f64> {
+    if sorted.is_empty() || !(0f64..=100f64).contains(&percentile) {
+        return None
+    }
+    let position = (sorted.len() - 1) as f64 * percentile / 100f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    Some(sorted[below] + (position - below as f64) * (sorted[above] - sorted[below]))
+}
+/* The most common item, and the smallest one if several are equally common. */
+fn mode<N>(vector: Vec<N>) -> Option<N> where N: Ord {
+    let mut counts: BTreeMap<N, usize> = BTreeMap::new();
+    for item in vector {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+    let highest = counts.values().max().copied()?;
+    counts.into_iter().find(|(_, count)| *count == highest).map(|(item, _)| item)
+}
+
+/** A range of values that `Bucket` puts objects into.
+
+    The lower bound is inclusive and the upper bound exclusive. A bound is missing if the bin is
+    open on that side. Bins are numbered in the order of their values, and bins made by the same
+    binning function are compared, ordered, and hashed by their numbers alone.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Bin { index: i64, lower: Option<f64>, upper: Option<f64> }
+impl Bin {
+    pub fn new(index: i64, lower: Option<f64>, upper: Option<f64>) -> Self { Bin { index, lower, upper } }
+    pub fn index(&self) -> i64 { self.index }
+    pub fn lower(&self) -> Option<f64> { self.lower }
+    pub fn upper(&self) -> Option<f64> { self.upper }
+    pub fn contains(&self, value: f64) -> bool {
+        self.lower.map_or(true, |lower| lower <= value) && self.upper.map_or(true, |upper| value < upper)
+    }
+}
+impl PartialEq for Bin { fn eq(&self, other: &Self) -> bool { self.index == other.index } }
+impl Eq for Bin {}
+impl PartialOrd for Bin { fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) } }
+impl Ord for Bin { fn cmp(&self, other: &Self) -> std::cmp::Ordering { self.index.cmp(&other.index) } }
+impl Hash for Bin { fn hash<H: Hasher>(&self, state: &mut H) { self.index.hash(state) } }
+impl Display for Bin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bound = |bound: Option<f64>| bound.map_or(String::new(), |bound| bound.to_string());
+        write!(f, "{}..{}", bound(self.lower), bound(self.upper))
+    }
+}
+
+pub trait BinningFunction {
+    /** Finds the bin of a value, which is never NaN. */
+    fn bin(&self, value: f64) -> Bin;
+    /** NaN is not in any bin, so objects whose value is NaN are left out of buckets, like the ones
+        without a value. */
+    fn try_bin(&self, value: f64) -> Option<Bin> {
+        if value.is_nan() { None } else { Some(self.bin(value)) }
+    }
+}
+
+/** Bins of the same width: `Interval(100)` puts 0 to 99 into `0..100`, 100 to 199 into `100..200`,
+    and so on, with negative values going into `-100..0` and below. */
+#[derive(Clone, Copy, Debug)] pub struct Interval<N>(pub N);
+impl<N> BinningFunction for Interval<N> where N: Numeric {
+    fn bin(&self, value: f64) -> Bin {
+        let width = self.0.to_f64();
+        assert!(width > 0f64, "Interval should be positive, but it is {}.", width);
+        let index = (value / width).floor() as i64;
+        Bin::new(index, Some(index as f64 * width), Some((index + 1) as f64 * width))
+    }
+}
+
+/** Bins that grow by a factor: `Logarithmic(10)` puts 1 to 9 into `1..10`, 10 to 99 into `10..100`,
+    and so on. Anything smaller than 1, including zero, goes into `..1`. */
+#[derive(Clone, Copy, Debug)] pub struct Logarithmic<N>(pub N);
+impl<N> BinningFunction for Logarithmic<N> where N: Numeric {
+    fn bin(&self, value: f64) -> Bin {
+        let base = self.0.to_f64();
+        assert!(base > 1f64, "Logarithm base should be greater than 1, but it is {}.", base);
+        if !(value >= 1f64) {
+            return Bin::new(0, None, Some(1f64))
+        }
+        let mut exponent = value.log(base).floor() as i32;
+        // log is not exact, so 1000 can come out as 2.9999999999999996 in base 10
+        while base.powi(exponent + 1) <= value { exponent += 1 }
+        while base.powi(exponent) > value { exponent -= 1 }
+        Bin::new(exponent as i64 + 1, Some(base.powi(exponent)), Some(base.powi(exponent + 1)))
+    }
+}
+
+/** Bins that each hold about as many of the given values: their boundaries are quantiles.
+
+    The boundaries come from the data, so they are worked out from the values first, eg.
+    `Quantiles::of(database.projects(), Count(project::Commits), 4)` makes quartiles, which can then
+    be used as `Bucket(Count(project::Commits), quartiles)`. Values equal to a boundary go into the
+    bin above it. If many values are equal, there are fewer bins than asked for.
+ */
+#[derive(Clone, Debug)] pub struct Quantiles { boundaries: Vec<f64> }
+impl Quantiles {
+    pub fn from_values<I, N>(values: I, count: usize) -> Self where I: IntoIterator<Item=N>, N: Numeric {
+        let values = sorted_f64(values.into_iter().collect::<Vec<N>>());
+        let mut boundaries: Vec<f64> = (1..count)
+            .flat_map(|k| values.get(k * values.len() / count).copied())
+            .collect();
+        boundaries.dedup();
+        if let Some(minimum) = values.first() {
+            boundaries.retain(|boundary| boundary > minimum) // otherwise the first bin is empty
+        }
+        Quantiles { boundaries }
+    }
+    pub fn of<'a, I, A, N, T>(objects: I, attribute: A, count: usize) -> Self
+        where I: IntoIterator<Item=objects::ItemWithData<'a, T>>,
+              A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>,
+              N: Numeric, T: 'a {
+        Self::from_values(objects.into_iter().flat_map(|object| attribute.get_opt(&object)), count)
+    }
+    pub fn with_boundaries(boundaries: Vec<f64>) -> Self {
+        let mut boundaries = sorted_f64(boundaries);
+        boundaries.dedup();
+        Quantiles { boundaries }
+    }
+    pub fn boundaries(&self) -> &Vec<f64> { &self.boundaries }
+}
+impl BinningFunction for Quantiles {
+    fn bin(&self, value: f64) -> Bin {
+        let index = self.boundaries.iter().take_while(|boundary| **boundary <= value).count();
+        let lower = index.checked_sub(1).map(|previous| self.boundaries[previous]);
+        let upper = self.boundaries.get(index).copied();
+        Bin::new(index as i64, lower, upper)
+    }
+}
+
+/** Puts objects into bins by the value of an attribute, eg. `Bucket(Count(project::Commits), Interval(1000))`.
+
+    Bins work as keys in `group_by`, so `group_by(Bucket(...))` followed by counting each group
+    makes a histogram. Objects whose value is missing or NaN are not in any bin.
+ */
 pub struct Bucket<A: Attribute, F>(pub A, pub F);
 impl<A, F, T> Attribute for Bucket<A, F> where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
+}
+impl<'a, A, F, T, N> OptionGetter<'a> for Bucket<A, F>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric, F: BinningFunction {
+    type IntoItem = Bin;
+    fn get_opt(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        self.0.get_opt(object).and_then(|value| self.1.try_bin(value.to_f64()))
+    }
+}
+impl<'a, A, F, T, N> Getter<'a> for Bucket<A, F>
+    where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=N>, N: Numeric, F: BinningFunction {
+    type IntoItem = Option<Bin>;
+    fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        self.0.get_opt(object).and_then(|value| self.1.try_bin(value.to_f64()))
+    }
 }
-// impl<'a, A, F, T, I> OptionGetter<'a> for Bucket<A, F>
-//     where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=I>, F: BinningFunction<I> {
-//     type IntoItem = Bin<I, F>;
-//     fn get_opt(&self, object: &ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
-//         self.0.get_opt(object).map(|item| self.1.bin(item))
-//     }
-// }
-// impl<'a, A, F, T, I> Getter<'a> for Bucket<A, F>
-//     where A: Attribute<Object=T> + OptionGetter<'a, IntoItem=I>, F: BinningFunction<I> {
-//     type IntoItem = Option<Bin<I, F>>;
-//     fn get(&self, object: &ItemWithData<'a, Self::Object>) -> Self::IntoItem {
-//         self.0.get_opt(object).map(|item| self.1.bin(item))
-//     }
-// }
 
 pub struct Ratio<A: Attribute<Object=T>, P: Attribute<Object=T>, T>(pub A, pub P);
 impl<A, P, T> Attribute for Ratio<A, P, T>
@@ -1901,6 +2937,7 @@ impl<A, P, T> Attribute for Ratio<A, P, T>
           P: Attribute<Object=T> {
 
     type Object = T;
+    fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), self.1.cost()) }
 }
 impl<'a, A, P, T> OptionGetter<'a> for Ratio<A, P, T>
     where A: Attribute<Object=T> + OptionCountable<'a>,
@@ -1932,6 +2969,7 @@ pub struct From<O: Attribute, A: Attribute> (pub O, pub A);
 impl<'a, O, A, T, I> Attribute for From<O, A>
     where O: Attribute<Object=T>, A: Attribute<Object=I> {
     type Object = T;
+    fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), self.1.cost()) }
 }
 
 impl<'a, O, A, T, I, E> Getter<'a> for From<O, A>
@@ -1959,6 +2997,7 @@ impl<'a, O, A, T> Attribute for FromEach<O, A>
     where O: Attribute<Object=T> /*+ OptionGetter<'a, IntoItem=Vec<I>>)*/, A: Attribute {
     //<Object=I>*/ {
     type Object = T;
+    fn cost(&self) -> Cost { std::cmp::max(self.0.cost(), self.1.cost()) }
 }
 
 impl<'a, O, A, T, I, E> Getter<'a> for FromEach<O, A>
@@ -1989,6 +3028,7 @@ pub struct FromEachIf<A: Attribute, P> (pub A, pub P);
 impl<'a, A, P, T> Attribute for FromEachIf<A, P>
     where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 impl<'a, A, P, T, I> OptionGetter<'a> for FromEachIf<A, P>
@@ -2037,10 +3077,11 @@ impl<'a, A, P, T, I> OptionCountable<'a> for FromEachIf<A, P>
 
 macro_rules! impl_select {
         ($n:ident, $($ti:ident -> $i:tt),+) => {
-            pub struct $n<$($ti: Attribute,)+> ($(pub $ti,)+);
+            pub struct $n<$($ti,)+> ($(pub $ti,)+);
             impl<T, $($ti,)+> Attribute for $n<$($ti,)+>
                 where $($ti: Attribute<Object=T>,)+ {
                 type Object = T;
+                fn cost(&self) -> Cost { [$(self.$i.cost(),)+].iter().copied().max().unwrap_or_default() }
             }
             impl<'a, T, $($ti,)+> OptionGetter<'a> for $n<$($ti,)+>
                 where $($ti: Attribute<Object=T> + OptionGetter<'a>,)+ {
@@ -2057,6 +3098,13 @@ macro_rules! impl_select {
                     ($(self.$i.get(object),)+)
                 }
             }
+            impl<'a, K, T, $($ti,)+> GroupGetter<'a, K, T> for $n<$($ti,)+>
+                where $($ti: GroupGetter<'a, K, T>,)+ {
+                type IntoItem = ($(Option<<$ti as GroupGetter<'a, K, T>>::IntoItem>,)+);
+                fn get_group(&self, key: &K, members: &Vec<objects::ItemWithData<'a, T>>) -> Option<Self::IntoItem> {
+                    Some(($(self.$i.get_group(key, members),)+))
+                }
+            }
         }
     }
 
@@ -2145,6 +3193,7 @@ pub struct FormatDate<A: Attribute> (pub Date, pub A);
 
 impl<'a, A, T> Attribute for FormatDate<A> where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.1.cost() }
 }
 
 impl<'a, A, T> OptionGetter<'a> for FormatDate<A>
@@ -2227,6 +3276,7 @@ impl<A> FormatDuration<A> where A: Attribute {
 
 impl<'a, A, T> Attribute for FormatDuration<A> where A: Attribute<Object=T> {
     type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
 }
 
 impl<'a, A, T> OptionGetter<'a> for FormatDuration<A>
@@ -2243,4 +3293,493 @@ impl<'a, A, T> Getter<'a> for FormatDuration<A>
     fn get(&self, object: &objects::ItemWithData<'a, Self::Object>) -> Self::IntoItem {
         self.0.get_opt(object).map(|duration| Self::to_fuzzy_duration(duration))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Bin, BinningFunction, Interval, Logarithmic, Quantiles};
+    use crate::{CalculateStat, Mean, Median, Total, Mode, Variance, StdDev, IQR, Gini, Describe};
+    use crate::{MedianF64, ModeF64, OrdF64, project};
+    use crate::{percentile_of_sorted, sorted_f64};
+    use crate::{AttributeIterator, AttributeGroupIterator, Count, Seed, Stratified, Weighted};
+    use crate::{Top, Reservoir, commit};
+    use crate::{By, Ascending, Descending};
+    use crate::{AtLeast, AtMost, MoreThan, Equal, Select2, Select3, group};
+    use crate::{Derived, Div, Coalesce, If, Not};
+    use crate::objects::{Language, UserId};
+    use crate::user;
+    use crate::attrib::sort;
+    use crate::attrib::{Attribute, Getter, OptionGetter, Filter, Cost};
+    use crate::{AllOf, AnyOf, NoneOf, And, Exists};
+    use crate::objects::{ProjectId, CommitId, Project, ItemWithData};
+    use crate::database::Database;
+    use crate::database::memory::InMemorySource;
+    use crate::database::source::CommitBasics;
+    use crate::database::testing::{self, TestDatabase};
+
+    // Calculators only need an attribute for their type, so any one does.
+    macro_rules! calculate {
+        ($calculator:ident, $values:expr) => { $calculator::<project::Commits>::calculate($values) }
+    }
+
+    fn bounds(bin: Bin) -> (i64, Option<f64>, Option<f64>) {
+        (bin.index(), bin.lower(), bin.upper())
+    }
+
+    /* The stars, language, and number of commits of each project, in the order of their ids. */
+    const PROJECTS: [(Option<usize>, Option<&str>, usize); 6] = [
+        (Some(10), Some("Rust"),   3),
+        (Some(30), Some("Rust"),   1),
+        (Some(30), Some("Python"), 2),
+        (None,     Some("Python"), 4),
+        (Some(5),  None,           0),
+        (Some(10), Some("Rust"),   2),
+    ];
+
+    /* Commits are by the first user, except in project 1, which only the second user commits to,
+       and in project 2, which both do. The third user commits nothing. */
+    fn database() -> TestDatabase {
+        let mut source = InMemorySource::new();
+        let users = vec![source.add_user("author@example.com"), source.add_user("other@example.com"), source.add_user("idle@example.com")];
+        for (index, (stars, language, commits)) in PROJECTS.iter().enumerate() {
+            let project = source.add_project(format!("https://github.com/example/{}.git", index));
+            source.set_project_metadata(project, serde_json::json!({ "stargazers_count": stars, "language": language }));
+            let mut head = None;
+            for position in 0..*commits {
+                let time = (index * 10 + position) as i64;
+                let user = match index { 1 => users[1], 2 => users[position % 2], _ => users[0] };
+                head = Some(source.add_commit(format!("{}-{}", index, position), CommitBasics {
+                    message: "x".repeat(position + 1),
+                    ..testing::commit(user, time, head.into_iter().collect())
+                }));
+            }
+            if let Some(head) = head {
+                source.set_head(project, "master", head);
+            }
+        }
+        testing::database(source).open()
+    }
+
+    fn ids<'a, I>(projects: I) -> Vec<ProjectId> where I: Iterator<Item=crate::objects::ItemWithData<'a, crate::objects::Project>> {
+        projects.map(|project| project.id()).collect()
+    }
+
+    #[test]
+    fn interval_bin_edges() {
+        assert_eq!(bounds(Interval(100).bin(0f64)), (0, Some(0f64), Some(100f64)));
+        assert_eq!(bounds(Interval(100).bin(99.9)), (0, Some(0f64), Some(100f64)));
+        assert_eq!(bounds(Interval(100).bin(100f64)), (1, Some(100f64), Some(200f64)));
+        assert_eq!(bounds(Interval(100).bin(-0.1)), (-1, Some(-100f64), Some(0f64)));
+        assert_eq!(bounds(Interval(100).bin(-100f64)), (-1, Some(-100f64), Some(0f64)));
+        assert!(Interval(100).try_bin(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn logarithmic_bin_edges() {
+        assert_eq!(bounds(Logarithmic(10).bin(0f64)), (0, None, Some(1f64)));
+        assert_eq!(bounds(Logarithmic(10).bin(0.99)), (0, None, Some(1f64)));
+        assert_eq!(bounds(Logarithmic(10).bin(1f64)), (1, Some(1f64), Some(10f64)));
+        assert_eq!(bounds(Logarithmic(10).bin(9.99)), (1, Some(1f64), Some(10f64)));
+        assert_eq!(bounds(Logarithmic(10).bin(10f64)), (2, Some(10f64), Some(100f64)));
+        assert_eq!(bounds(Logarithmic(10).bin(1000f64)), (4, Some(1000f64), Some(10000f64)));
+        assert!(Logarithmic(10).try_bin(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn quantile_bin_edges() {
+        let quartiles = Quantiles::from_values(1..=8, 4);
+        assert_eq!(quartiles.boundaries(), &vec![3f64, 5f64, 7f64]);
+        assert_eq!(bounds(quartiles.bin(1f64)), (0, None, Some(3f64)));
+        assert_eq!(bounds(quartiles.bin(2.99)), (0, None, Some(3f64)));
+        assert_eq!(bounds(quartiles.bin(3f64)), (1, Some(3f64), Some(5f64)));
+        assert_eq!(bounds(quartiles.bin(7f64)), (3, Some(7f64), None));
+        assert_eq!(bounds(quartiles.bin(100f64)), (3, Some(7f64), None));
+        assert!(quartiles.try_bin(f64::NAN).is_none());
+    }
+
+    #[test]
+    fn quantiles_ignore_nan_and_repeated_values() {
+        assert_eq!(Quantiles::from_values(vec![f64::NAN, 1f64, 2f64, f64::NAN], 2).boundaries(), &vec![2f64]);
+        assert_eq!(Quantiles::from_values(vec![1, 1, 1, 2, 3, 4, 5, 6], 4).boundaries(), &vec![3f64, 5f64]);
+        assert_eq!(Quantiles::with_boundaries(vec![5f64, f64::NAN, 1f64, 5f64]).boundaries(), &vec![1f64, 5f64]);
+        assert!(Quantiles::from_values(Vec::<f64>::new(), 4).boundaries().is_empty());
+    }
+
+    #[test]
+    fn statistics_of_nothing() {
+        let nothing: Vec<usize> = vec![];
+        assert!(calculate!(Mean, nothing.clone()).is_none());
+        assert!(calculate!(Median, nothing.clone()).is_none());
+        assert_eq!(calculate!(Total, nothing.clone()), Some(0));
+        assert_eq!(calculate!(Mode, nothing.clone()), None);
+        assert_eq!(calculate!(Variance, nothing.clone()), None);
+        assert_eq!(calculate!(StdDev, nothing.clone()), None);
+        assert_eq!(calculate!(IQR, nothing.clone()), None);
+        assert_eq!(calculate!(Gini, nothing.clone()), None);
+        assert_eq!(calculate!(MedianF64, nothing.clone()), None);
+        assert_eq!(calculate!(Describe, nothing.clone()), None);
+        assert_eq!(percentile_of_sorted(&[], 50f64), None);
+    }
+
+    #[test]
+    fn statistics_of_one_number() {
+        assert_eq!(calculate!(Mean, vec![5usize]).map(|mean| mean.as_f64()), Some(5f64));
+        assert_eq!(calculate!(Median, vec![5usize]).map(|median| median.as_f64()), Some(5f64));
+        assert_eq!(calculate!(Total, vec![5usize]), Some(5));
+        assert_eq!(calculate!(Mode, vec![5usize]), Some(5));
+        assert_eq!(calculate!(Variance, vec![5usize]), None);
+        assert_eq!(calculate!(StdDev, vec![5usize]), None);
+        assert_eq!(calculate!(IQR, vec![5usize]), Some(OrdF64::from(0f64)));
+        assert_eq!(calculate!(Gini, vec![5usize]), Some(OrdF64::from(0f64)));
+        assert_eq!(percentile_of_sorted(&[5f64], 90f64), Some(5f64));
+    }
+
+    #[test]
+    fn medians_of_odd_and_even_counts() {
+        assert_eq!(calculate!(Median, vec![3usize, 1, 2]).map(|median| median.as_f64()), Some(2f64));
+        assert_eq!(calculate!(Median, vec![4usize, 1, 3, 2]).map(|median| median.as_f64()), Some(2.5));
+        assert_eq!(calculate!(MedianF64, vec![3f64, 1f64, 2f64]), Some(OrdF64::from(2f64)));
+        assert_eq!(calculate!(MedianF64, vec![4f64, 1f64, 3f64, 2f64]), Some(OrdF64::from(2.5)));
+    }
+
+    #[test]
+    fn spread_of_numbers() {
+        assert_eq!(calculate!(Variance, vec![1usize, 3]), Some(OrdF64::from(2f64)));
+        assert_eq!(calculate!(StdDev, vec![1usize, 3]), Some(OrdF64::from(2f64.sqrt())));
+        assert_eq!(calculate!(IQR, vec![5usize, 4, 3, 2, 1]), Some(OrdF64::from(2f64)));
+        assert_eq!(percentile_of_sorted(&[1f64, 2f64, 3f64, 4f64, 5f64], 62.5), Some(3.5));
+    }
+
+    #[test]
+    fn gini_of_equal_and_unequal_numbers() {
+        assert_eq!(calculate!(Gini, vec![7usize, 7, 7, 7]), Some(OrdF64::from(0f64)));
+        assert_eq!(calculate!(Gini, vec![0usize, 0, 0, 1]), Some(OrdF64::from(0.75)));
+        assert_eq!(calculate!(Gini, vec![0usize, 0]), None);
+    }
+
+    #[test]
+    fn float_statistics_leave_out_nan() {
+        assert_eq!(sorted_f64(vec![2f64, f64::NAN, -1f64]), vec![-1f64, 2f64]);
+        assert_eq!(calculate!(ModeF64, vec![f64::NAN, f64::NAN, 1f64]), Some(OrdF64::from(1f64)));
+        assert_eq!(calculate!(MedianF64, vec![f64::NAN, f64::NAN, 1f64]), Some(OrdF64::from(1f64)));
+        assert_eq!(calculate!(Variance, vec![f64::NAN, 1f64, 3f64]), Some(OrdF64::from(2f64)));
+    }
+
+    #[test]
+    fn stratified_samples_are_reproducible() {
+        let database = database();
+        let sample = ids(database.projects().sample(Stratified(project::Stars, 1, Seed(42))));
+        assert_eq!(sample, ids(database.projects().sample(Stratified(project::Stars, 1, Seed(42)))));
+
+        // One project from each of the strata 10, 30, missing, and 5, in the order they first occur.
+        let stars: Vec<Option<usize>> = sample.iter().map(|id| PROJECTS[id.0 as usize].0).collect();
+        assert_eq!(stars, vec![Some(10), Some(30), None, Some(5)]);
+        assert_eq!(database.projects().sample(Stratified(project::Stars, 10, Seed(42))).count(), PROJECTS.len());
+    }
+
+    #[test]
+    fn weighted_samples_are_reproducible() {
+        let database = database();
+        let sample = ids(database.projects().sample(Weighted(Count(project::Commits), 3, Seed(42))));
+        assert_eq!(sample, ids(database.projects().sample(Weighted(Count(project::Commits), 3, Seed(42)))));
+        assert_eq!(sample.len(), 3);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]), "the sample keeps the order of the projects");
+
+        // The project without commits weighs nothing, so it is never picked.
+        let everything = ids(database.projects().sample(Weighted(Count(project::Commits), 10, Seed(42))));
+        assert_eq!(everything, vec![ProjectId(0), ProjectId(1), ProjectId(2), ProjectId(3), ProjectId(5)]);
+    }
+
+    #[test]
+    fn samplers_sample_each_group() {
+        let database = database();
+        let sample = || -> Vec<(Option<usize>, Vec<ProjectId>)> {
+            let mut groups: Vec<(Option<usize>, Vec<ProjectId>)> = database.projects()
+                .group_by(project::Stars)
+                .sample(Weighted(Count(project::Commits), 1, Seed(42)))
+                .map(|(stars, projects)| (stars, ids(projects.into_iter())))
+                .collect();
+            groups.sort();
+            groups
+        };
+        let groups = sample();
+        assert_eq!(groups, sample());
+        let sizes: Vec<(Option<usize>, usize)> = groups.iter().map(|(stars, projects)| (*stars, projects.len())).collect();
+        assert_eq!(sizes, vec![(None, 1), (Some(5), 0), (Some(10), 1), (Some(30), 1)]);
+    }
+
+    #[test]
+    fn top_and_bottom_break_ties_like_sorting() {
+        let database = database();
+        // Projects 0 and 5 have 10 stars, and 1 and 2 have 30.
+        for k in 0..=PROJECTS.len() + 1 {
+            assert_eq!(ids(database.projects().top_by(project::Stars, k)),
+                       ids(database.projects().sort_by(project::Stars).sample(Top(k))), "top {}", k);
+            assert_eq!(ids(database.projects().bottom_by(project::Stars, k)),
+                       ids(database.projects().sort_with_direction(sort::Direction::Ascending, project::Stars).sample(Top(k))), "bottom {}", k);
+        }
+        assert_eq!(ids(database.projects().top_by(project::Stars, 3)), vec![ProjectId(2), ProjectId(1), ProjectId(5)]);
+        assert_eq!(ids(database.projects().bottom_by(project::Stars, 3)), vec![ProjectId(3), ProjectId(4), ProjectId(0)]);
+    }
+
+    #[test]
+    fn reservoir_samples_have_the_requested_size() {
+        let database = database();
+        let all: Vec<CommitId> = database.commits().map(|commit| commit.id()).collect();
+        assert_eq!(all.len(), 12);
+
+        let sample = |k: usize, seed: u128| -> Vec<CommitId> {
+            database.commits().sample(Reservoir(k, Seed(seed))).map(|commit| commit.id()).collect()
+        };
+        for k in 0..=all.len() + 1 {
+            let picked = sample(k, 42);
+            assert_eq!(picked.len(), k.min(all.len()), "size {}", k);
+            assert_eq!(picked, sample(k, 42), "size {}", k);
+            assert!(picked.windows(2).all(|pair| pair[0] < pair[1]), "the sample keeps the order of the commits");
+        }
+        assert_eq!(sample(all.len(), 7), all);
+        assert_eq!(database.commits().filter_by(crate::AtLeast(commit::MessageLength, 4)).sample(Reservoir(3, Seed(42))).count(), 1);
+    }
+
+    #[test]
+    fn sorting_by_keys_breaks_ties_by_id() {
+        let database = database();
+        let keys = || (Ascending(project::Language), Descending(project::Stars));
+        // Python before Rust, and the project without a language last; 0 and 5 are tied on both keys.
+        let expected = vec![ProjectId(2), ProjectId(3), ProjectId(1), ProjectId(0), ProjectId(5), ProjectId(4)];
+        assert_eq!(ids(database.projects().sort_by_keys(keys())), expected);
+
+        let reversed: Vec<_> = database.projects().collect::<Vec<_>>().into_iter().rev().collect();
+        assert_eq!(ids(reversed.into_iter().sort_by_keys(keys())), expected);
+    }
+
+    #[test]
+    fn sorting_by_keys_places_missing_values() {
+        let database = database();
+        let sorted = |key: By<project::Stars>| ids(database.projects().sort_by_keys(key));
+        // Project 3 has no stars.
+        assert_eq!(sorted(By(project::Stars, sort::Direction::Ascending, sort::Missing::First)),
+                   vec![ProjectId(3), ProjectId(4), ProjectId(0), ProjectId(5), ProjectId(1), ProjectId(2)]);
+        assert_eq!(sorted(By(project::Stars, sort::Direction::Ascending, sort::Missing::Last)),
+                   vec![ProjectId(4), ProjectId(0), ProjectId(5), ProjectId(1), ProjectId(2), ProjectId(3)]);
+        assert_eq!(sorted(By(project::Stars, sort::Direction::Descending, sort::Missing::First)),
+                   vec![ProjectId(3), ProjectId(1), ProjectId(2), ProjectId(0), ProjectId(5), ProjectId(4)]);
+        assert_eq!(sorted(By(project::Stars, sort::Direction::Descending, sort::Missing::Last)),
+                   vec![ProjectId(1), ProjectId(2), ProjectId(0), ProjectId(5), ProjectId(4), ProjectId(3)]);
+    }
+
+    #[test]
+    fn sorting_groups_by_keys() {
+        let database = database();
+        let groups: Vec<(Option<crate::objects::Language>, Vec<ProjectId>)> = database.projects()
+            .group_by(project::Language)
+            .sort_keys()
+            .sort_by_keys(Descending(project::Stars))
+            .map(|(language, projects)| (language, ids(projects.into_iter())))
+            .collect();
+        assert_eq!(groups, vec![
+            (None, vec![ProjectId(4)]),
+            (Some(crate::objects::Language::Python), vec![ProjectId(2), ProjectId(3)]),
+            (Some(crate::objects::Language::Rust), vec![ProjectId(1), ProjectId(0), ProjectId(5)]),
+        ]);
+    }
+
+    #[test]
+    fn having_filters_whole_groups() {
+        let database = database();
+        let languages = |groups: Vec<(Option<Language>, Vec<crate::objects::ItemWithData<crate::objects::Project>>)>| {
+            groups.into_iter().map(|(language, _)| language).collect::<Vec<Option<Language>>>()
+        };
+        assert_eq!(languages(database.projects().group_by(project::Language).having(AtLeast(group::Size, 2)).sort_keys().collect()),
+                   vec![Some(Language::Python), Some(Language::Rust)]);
+        assert_eq!(languages(database.projects().group_by(project::Language).having(Equal(group::Key, Some(Language::Rust))).collect()),
+                   vec![Some(Language::Rust)]);
+        // Python has a mean of 30 stars, because the project without stars is left out; Rust has 50/3.
+        assert_eq!(languages(database.projects().group_by(project::Language).having(AtLeast(Mean(project::Stars), 20f64)).collect()),
+                   vec![Some(Language::Python)]);
+
+        // The group of projects without stars has no mean, so it passes no comparison.
+        let stars = |groups: Vec<(Option<usize>, Vec<crate::objects::ItemWithData<crate::objects::Project>>)>| {
+            groups.into_iter().map(|(stars, _)| stars).collect::<Vec<Option<usize>>>()
+        };
+        assert_eq!(stars(database.projects().group_by(project::Stars).having(AtLeast(Mean(project::Stars), 0f64)).sort_keys().collect()),
+                   vec![Some(5), Some(10), Some(30)]);
+        assert_eq!(stars(database.projects().group_by(project::Stars).having(MoreThan(Mean(project::Stars), 0f64)).sort_keys().collect()),
+                   vec![Some(5), Some(10), Some(30)]);
+        assert_eq!(stars(database.projects().group_by(project::Stars).having(AtMost(Mean(project::Stars), 100f64)).sort_keys().collect()),
+                   vec![Some(5), Some(10), Some(30)]);
+    }
+
+    #[test]
+    fn aggregate_makes_a_row_per_group() {
+        let database = database();
+        let rows: Vec<(Option<Option<Language>>, Option<usize>, Option<usize>)> = database.projects()
+            .group_by(project::Language)
+            .sort_keys()
+            .aggregate(Select!(group::Key, group::Size, Total(project::Stars)))
+            .collect();
+        assert_eq!(rows, vec![
+            (Some(None), Some(1), Some(5)),
+            (Some(Some(Language::Python)), Some(2), Some(30)),
+            (Some(Some(Language::Rust)), Some(3), Some(50)),
+        ]);
+
+        // The group of projects without stars has no mean, so it has no row.
+        let means: Vec<f64> = database.projects()
+            .group_by(project::Stars)
+            .sort_keys()
+            .aggregate(Mean(project::Stars))
+            .map(|mean| mean.as_f64())
+            .collect();
+        assert_eq!(means, vec![5f64, 10f64, 30f64]);
+    }
+
+    #[test]
+    fn join_pairs_objects_that_share_values() {
+        let database = database();
+        let pairs: Vec<(ProjectId, UserId)> = database.projects()
+            .join_on(project::AuthorIds, database.users(), user::Id)
+            .map(|(project, user)| (project.id(), user.id()))
+            .collect();
+        assert_eq!(pairs, vec![
+            (ProjectId(0), UserId(0)),
+            (ProjectId(1), UserId(1)),
+            (ProjectId(2), UserId(0)), (ProjectId(2), UserId(1)),
+            (ProjectId(3), UserId(0)),
+            (ProjectId(5), UserId(0)),
+        ]);
+
+        let commits: Vec<CommitId> = database.commits()
+            .join_on(commit::AuthorId, database.users().filter_by(Equal(user::Email, "other@example.com".to_owned())), user::Id)
+            .map(|(commit, _)| commit.id())
+            .collect();
+        assert_eq!(commits, vec![CommitId(3), CommitId(5)]);
+    }
+
+    #[test]
+    fn semi_and_anti_joins_split_objects() {
+        let database = database();
+        let others = || database.users().filter_by(Equal(user::Email, "other@example.com".to_owned()));
+        assert_eq!(ids(database.projects().semi_join(project::AuthorIds, others(), user::Id)),
+                   vec![ProjectId(1), ProjectId(2)]);
+        // Project 4 has no authors, so it shares nothing with anyone.
+        assert_eq!(ids(database.projects().anti_join(project::AuthorIds, others(), user::Id)),
+                   vec![ProjectId(0), ProjectId(3), ProjectId(4), ProjectId(5)]);
+        assert!(database.projects().semi_join(project::AuthorIds, std::iter::empty(), user::Id).next().is_none());
+        assert_eq!(database.projects().anti_join(project::AuthorIds, std::iter::empty(), user::Id).count(), PROJECTS.len());
+    }
+
+    /* An expensive attribute that must never be looked at, because a cheaper filter settles things first. */
+    #[derive(Clone, Copy)] struct Untouchable;
+    impl Attribute for Untouchable {
+        type Object = Project;
+        fn cost(&self) -> Cost { Cost::Expensive }
+    }
+    impl<'a> OptionGetter<'a> for Untouchable {
+        type IntoItem = usize;
+        fn get_opt(&self, _object: &ItemWithData<'a, Project>) -> Option<usize> {
+            panic!("the expensive filter was tried before the cheap one")
+        }
+    }
+
+    #[test]
+    fn costs_come_from_caches_and_pass_through_filters() {
+        assert_eq!(Attribute::cost(&project::Stars), Cost::Cheap);
+        assert_eq!(Attribute::cost(&project::Commits), Cost::Expensive);
+        assert_eq!(Attribute::cost(&Count(project::Commits)), Cost::Expensive);
+        assert_eq!(Attribute::cost(&commit::Message), Cost::Cheap);
+        assert_eq!(Attribute::cost(&commit::Churn), Cost::Expensive);
+        assert_eq!(Filter::cost(&Exists(project::Stars)), Cost::Cheap);
+        assert_eq!(Filter::cost(&AtLeast(Count(project::Commits), 1)), Cost::Expensive);
+        assert_eq!(Filter::cost(&AllOf((Exists(project::Stars), AtLeast(Untouchable, 1)))), Cost::Expensive);
+        assert_eq!(Attribute::cost(&If(Exists(project::Stars), project::Stars, project::Watchers)), Cost::Cheap);
+        assert_eq!(Attribute::cost(&If(Exists(project::Stars), project::Stars, Count(project::Commits))), Cost::Expensive);
+        assert_eq!(Attribute::cost(&If(AtLeast(Count(project::Commits), 1), project::Stars, project::Watchers)), Cost::Expensive);
+    }
+
+    #[test]
+    fn cheaper_filters_short_circuit_expensive_ones() {
+        let database = database();
+        // No project has 1000 stars, and every project has at least none (or no stars at all).
+        assert!(database.projects().filter_by(And(AtLeast(Untouchable, 1), Equal(project::Stars, 1000))).next().is_none());
+        assert!(database.projects().filter_by(AllOf((AtLeast(Untouchable, 1), Equal(project::Stars, 1000)))).next().is_none());
+        assert_eq!(database.projects().filter_by(AnyOf((AtLeast(Untouchable, 1), AtLeast(project::Stars, 0)))).count(), PROJECTS.len());
+        assert!(database.projects().filter_by(NoneOf((AtLeast(Untouchable, 1), AtLeast(project::Stars, 0)))).next().is_none());
+    }
+
+    #[test]
+    fn derived_attributes_work_like_built_in_ones() {
+        let database = database();
+        let stars = || Derived::new(|project: &ItemWithData<Project>| project.star_count());
+
+        let popular = Derived::new(|project: &ItemWithData<Project>| project.star_count().map(|stars| stars >= 10));
+        assert_eq!(ids(database.projects().filter_by(popular)), vec![ProjectId(0), ProjectId(1), ProjectId(2), ProjectId(5)]);
+        assert_eq!(ids(database.projects().filter_by(AtLeast(stars(), 10))),
+                   ids(database.projects().filter_by(AtLeast(project::Stars, 10))));
+
+        assert_eq!(ids(database.projects().sort_by(stars())), ids(database.projects().sort_by(project::Stars)));
+
+        let groups = |groups: Vec<(Option<usize>, Vec<ItemWithData<Project>>)>| -> Vec<(Option<usize>, Vec<ProjectId>)> {
+            groups.into_iter().map(|(key, projects)| (key, ids(projects.into_iter()))).collect()
+        };
+        assert_eq!(groups(database.projects().group_by(stars()).sort_keys().collect()),
+                   groups(database.projects().group_by(project::Stars).sort_keys().collect()));
+
+        let doubled = Derived::new(|project: &ItemWithData<Project>| project.star_count().map(|stars| stars * 2));
+        let rows: Vec<(ProjectId, Option<usize>)> = database.projects().map_into(Select!(project::Id, doubled)).collect();
+        assert_eq!(rows, vec![
+            (ProjectId(0), Some(20)), (ProjectId(1), Some(60)), (ProjectId(2), Some(60)),
+            (ProjectId(3), None), (ProjectId(4), Some(10)), (ProjectId(5), Some(20)),
+        ]);
+    }
+
+    #[test]
+    fn combinators_propagate_missing_values() {
+        let database = database();
+        // Project 4 has no commits, and whether that is a count of none or no count at all is up to
+        // the cache, so it is left out wherever commits are counted.
+        fn values<'a, A>(database: &'a Database, attribute: A) -> Vec<(ProjectId, Option<OrdF64>)>
+            where A: Getter<'a, IntoItem=Option<OrdF64>> + Attribute<Object=Project> {
+            database.projects()
+                .filter(|project| project.id() != ProjectId(4))
+                .map_into(Select!(project::Id, attribute))
+                .collect()
+        }
+        let ratio = |a: f64, b: f64| Some(OrdF64::from(a / b));
+
+        // Project 3 has no stars, so it has no stars per commit, nor commits per star.
+        assert_eq!(values(&database, Div(project::Stars, Count(project::Commits))), vec![
+            (ProjectId(0), ratio(10.0, 3.0)), (ProjectId(1), ratio(30.0, 1.0)), (ProjectId(2), ratio(30.0, 2.0)),
+            (ProjectId(3), None), (ProjectId(5), ratio(10.0, 2.0)),
+        ]);
+        assert_eq!(values(&database, Div(Count(project::Commits), project::Stars)), vec![
+            (ProjectId(0), ratio(3.0, 10.0)), (ProjectId(1), ratio(1.0, 30.0)), (ProjectId(2), ratio(2.0, 30.0)),
+            (ProjectId(3), None), (ProjectId(5), ratio(2.0, 10.0)),
+        ]);
+        // Dividing by zero is missing too, rather than infinite.
+        assert_eq!(database.projects().filter(|project| project.id() == ProjectId(4))
+                       .map_into(Div(project::Stars, Coalesce(Count(project::Commits), 0))).collect::<Vec<_>>(),
+                   vec![None]);
+
+        let stars: Vec<usize> = database.projects().map_into(Coalesce(project::Stars, 0)).collect();
+        assert_eq!(stars, vec![10, 30, 30, 0, 5, 10]);
+
+        // If picks one attribute or the other, and a missing value in the one it picks stays missing.
+        let picked: Vec<(ProjectId, Option<usize>)> = database.projects()
+            .filter(|project| project.id() != ProjectId(4))
+            .map_into(Select!(project::Id, If(Exists(project::Stars), project::Stars, Count(project::Commits))))
+            .collect();
+        assert_eq!(picked, vec![
+            (ProjectId(0), Some(10)), (ProjectId(1), Some(30)), (ProjectId(2), Some(30)),
+            (ProjectId(3), Some(4)), (ProjectId(5), Some(10)),
+        ]);
+        let picked: Vec<(ProjectId, Option<usize>)> = database.projects()
+            .filter(|project| project.id() != ProjectId(4))
+            .map_into(Select!(project::Id, If(Not(Exists(project::Stars)), project::Stars, Count(project::Commits))))
+            .collect();
+        assert_eq!(picked, vec![
+            (ProjectId(0), Some(3)), (ProjectId(1), Some(1)), (ProjectId(2), Some(2)),
+            (ProjectId(3), None), (ProjectId(5), Some(2)),
+        ]);
+    }
+}
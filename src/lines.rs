@@ -0,0 +1,195 @@
+use std::iter::Sum;
+use std::ops::Add;
+
+use serde::{Serialize, Deserialize};
+
+/* Files with a NUL byte this far in are taken to be binary. */
+const BINARY_PROBE: usize = 8 * 1024;
+
+/** How comments are written in a language: markers that start a comment running to the end of the
+    line, and pairs of markers that open and close a block comment. Block comments that can contain
+    other block comments are nested. Comment markers inside strings are not comments, so the syntax
+    also has the quotes that strings (or character literals) are written between: double quotes
+    unless given others `with_quotes`. */
+#[derive(Clone, Copy, Debug)]
+pub struct CommentSyntax {
+    pub line: &'static [&'static str],
+    pub block: &'static [(&'static str, &'static str)],
+    pub nested: bool,
+    pub quotes: &'static [char],
+}
+
+impl CommentSyntax {
+    /** For languages whose comments are not known: every line that is not blank is code. */
+    pub const NONE: CommentSyntax = CommentSyntax { line: &[], block: &[], nested: false, quotes: &[] };
+
+    pub const fn new(line: &'static [&'static str], block: &'static [(&'static str, &'static str)]) -> Self {
+        CommentSyntax { line, block, nested: false, quotes: &['"'] }
+    }
+    pub const fn nested(line: &'static [&'static str], block: &'static [(&'static str, &'static str)]) -> Self {
+        CommentSyntax { line, block, nested: true, quotes: &['"'] }
+    }
+    pub const fn with_quotes(self, quotes: &'static [char]) -> Self {
+        CommentSyntax { quotes, ..self }
+    }
+}
+
+/** The lines of a file, split into lines of code, comments, and blank lines.
+
+    A line is code if it has anything other than whitespace outside of comments, a comment if it
+    has nothing but comments, and blank otherwise (including blank lines inside block comments).
+    Comment markers inside strings are not taken for comments, but strings are assumed not to
+    continue onto the next line.
+ */
+#[derive(Clone, Copy, Default, Hash, Eq, PartialEq, Serialize, Deserialize, Debug)]
+pub struct LineCounts {
+    pub code: usize,
+    pub comment: usize,
+    pub blank: usize,
+}
+
+impl LineCounts {
+    /** Counts the lines of the contents of a file written in a language with the given comment
+        syntax. Binary files have no lines. */
+    pub fn count(contents: &[u8], syntax: CommentSyntax) -> Option<Self> {
+        if is_binary(contents) {
+            return None
+        }
+        let contents = String::from_utf8_lossy(contents);
+        let mut counts = LineCounts::default();
+        let mut open: Option<(&str, &str, usize)> = None; // the block comment the line starts in and its depth
+
+        for line in contents.lines() {
+            let (mut code, mut comment) = (false, false);
+            let mut string: Option<char> = None; // the quote the string the line is in ends with
+            let mut rest = line;
+            while let Some(c) = rest.chars().next() {
+                if let Some((opener, closer, depth)) = open {
+                    if rest.starts_with(closer) {
+                        comment = true;
+                        open = if depth > 1 { Some((opener, closer, depth - 1)) } else { None };
+                        rest = &rest[closer.len()..];
+                        continue
+                    }
+                    if syntax.nested && rest.starts_with(opener) {
+                        open = Some((opener, closer, depth + 1));
+                        rest = &rest[opener.len()..];
+                        continue
+                    }
+                    comment = comment || !c.is_whitespace();
+                } else if string.is_some() {
+                    if c == '\\' {
+                        rest = &rest[c.len_utf8()..];
+                        if let Some(escaped) = rest.chars().next() {
+                            rest = &rest[escaped.len_utf8()..];
+                        }
+                        continue
+                    }
+                    string = string.filter(|quote| *quote != c);
+                } else if let Some(&(opener, closer)) = syntax.block.iter().find(|(opener, _)| rest.starts_with(opener)) {
+                    comment = true;
+                    open = Some((opener, closer, 1));
+                    rest = &rest[opener.len()..];
+                    continue
+                } else if syntax.line.iter().any(|marker| rest.starts_with(marker)) {
+                    comment = true;
+                    break
+                } else if !c.is_whitespace() {
+                    code = true;
+                    string = Some(c).filter(|c| syntax.quotes.contains(c));
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+
+            if code {
+                counts.code += 1
+            } else if comment {
+                counts.comment += 1
+            } else {
+                counts.blank += 1
+            }
+        }
+        Some(counts)
+    }
+
+    pub fn total(&self) -> usize {
+        self.code + self.comment + self.blank
+    }
+
+    /** The ratio of lines of comments to lines of code and comments, if there are any. */
+    pub fn comment_density(&self) -> Option<f64> {
+        match self.code + self.comment {
+            0 => None,
+            lines => Some(self.comment as f64 / lines as f64),
+        }
+    }
+}
+
+impl Add for LineCounts {
+    type Output = LineCounts;
+    fn add(self, other: Self) -> Self::Output {
+        LineCounts {
+            code: self.code + other.code,
+            comment: self.comment + other.comment,
+            blank: self.blank + other.blank,
+        }
+    }
+}
+
+impl<'a> Sum<&'a LineCounts> for LineCounts {
+    fn sum<I>(iter: I) -> Self where I: Iterator<Item=&'a LineCounts> {
+        iter.fold(LineCounts::default(), |sum, counts| sum + *counts)
+    }
+}
+
+pub(crate) fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_PROBE)].contains(&0u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::objects::Language;
+
+    fn count(contents: &str, language: Language) -> (usize, usize, usize) {
+        let counts = LineCounts::count(contents.as_bytes(), language.comment_syntax()).unwrap();
+        (counts.code, counts.comment, counts.blank)
+    }
+
+    #[test]
+    fn counts_lines() {
+        let rust = "// header\n\nfn main() { /* inline */\n    /* one\n       /* nested */\n\n     still */\n    println!(\"// not a comment\"); // trailing\n}\n";
+        assert_eq!(count(rust, Language::Rust), (3, 4, 2));
+
+        let c = "/* a /* b */\nint x; /* c\n*/\n";
+        assert_eq!(count(c, Language::C), (1, 2, 0));
+
+        let python = "#!/usr/bin/env python\n# comment\n\nx = \"#\" # comment\n  \t\n";
+        assert_eq!(count(python, Language::Python), (1, 2, 2));
+
+        let lua = "--[[ block\n]] x = 1\n-- line\n";
+        assert_eq!(count(lua, Language::Lua), (1, 2, 0));
+
+        let javascript = "var open = '/*'; // comment\nvar quote = '\"'; /* comment */\nvar escaped = 'it\\'s /*';\n";
+        assert_eq!(count(javascript, Language::JavaScript), (3, 0, 0));
+
+        let python = "x = '#' # comment\ny = \"it's\" # comment\n";
+        assert_eq!(count(python, Language::Python), (2, 0, 0));
+
+        let sql = "select '--', 'it''s' from t; -- comment\n-- comment\n";
+        assert_eq!(count(sql, Language::SQL), (1, 1, 0));
+
+        // Lifetimes do not start strings.
+        let rust = "fn f(x: &'static str) {} /* comment\n   comment\n*/\n";
+        assert_eq!(count(rust, Language::Rust), (1, 2, 0));
+
+        let unknown = "# not a comment\n\nx\n";
+        assert_eq!(count(unknown, Language::Other), (2, 0, 1));
+
+        assert_eq!(LineCounts::count(b"\x00\x01binary\n", CommentSyntax::NONE), None);
+        assert_eq!(LineCounts::count(b"", CommentSyntax::NONE), Some(LineCounts::default()));
+
+        assert_eq!(LineCounts { code: 3, comment: 1, blank: 5 }.comment_density(), Some(0.25));
+        assert_eq!(LineCounts { code: 0, comment: 0, blank: 5 }.comment_density(), None);
+    }
+}
@@ -1,12 +1,11 @@
 use std::fmt::Display;
 
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use std::rc::Rc;
 use crate::weights_and_measures::{Weighed, Weights, BigNumbers};
 
 pub struct Log {
-    log: Rc<RefCell<InnerLog>>
+    log: Arc<Mutex<InnerLog>>
 }
 
 impl Clone for Log {
@@ -17,16 +16,16 @@ impl Clone for Log {
 
 impl Log {
     pub fn new(level: Verbosity) -> Self {
-        Log { log: Rc::new(RefCell::new(InnerLog::new(level))) }
+        Log { log: Arc::new(Mutex::new(InnerLog::new(level))) }
     }
     // fn log(&self, event: &Event) {
     //     self.log.as_ref().borrow().log(event)
     // }
     pub fn start<S>(&self, level: Verbosity, event: S) -> Event where S: Display {
-        self.log.as_ref().borrow().start(level, event)
+        self.log.lock().unwrap().start(level, event)
     }
     pub fn end(&self, event: Event) {
-        self.log.as_ref().borrow().end(event)
+        self.log.lock().unwrap().end(event)
     }
 }
 
@@ -53,7 +52,7 @@ impl Verbosity {
 #[derive(Clone, Debug)] /*Serialize, Deserialize*/
 struct InnerLog {
     level: Verbosity,
-    sequence: RefCell<Vec<Event>>,
+    sequence: Vec<Event>,
 }
 
 // macro_rules! log {
@@ -66,24 +65,24 @@ struct InnerLog {
 
 impl InnerLog {
     pub fn new(level: Verbosity) -> Self {
-        InnerLog { level, sequence: RefCell::new(vec![]) }
+        InnerLog { level, sequence: vec![] }
     }
     fn log(&self, event: &Event) {
         if event.should_log(&self.level) {
             eprintln!("{}", event.message());
         }
     }
-    pub fn start<S>(&self, level: Verbosity, event: S) -> Event where S: Display {
-        let sequence_number = self.sequence.borrow().len() + 1;
+    pub fn start<S>(&mut self, level: Verbosity, event: S) -> Event where S: Display {
+        let sequence_number = self.sequence.len() + 1;
         let event = Event::new(sequence_number, level, event);
-        self.sequence.borrow_mut().push(event.clone());
+        self.sequence.push(event.clone());
         self.log(&event);
         event
     }
-    pub fn end(&self, mut event: Event) {
+    pub fn end(&mut self, mut event: Event) {
         event.done();
         self.log(&event);
-        self.sequence.borrow_mut().push(event);
+        self.sequence.push(event);
     }
 }
 
@@ -5,6 +5,7 @@ use std::borrow::Cow;
 use std::io::Write;
 use std::fs::{File, create_dir_all};
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use bstr::ByteSlice;
 use itertools::Itertools;
@@ -14,14 +15,21 @@ use crate::database::Database;
 use crate::time::Duration;
 use crate::iterators::*;
 use crate::weights_and_measures::Weighed;
+use crate::lines::{CommentSyntax, LineCounts};
+use crate::languages::LanguageRegistry;
 use crate::{Timestamp, Store, Percentage};
 
 #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize, Debug)]
 pub enum Language {
     ASM, ASP, ActionScript, C, Cpp, CSharp, CoffeeScript, Lisp, Cobol, CSS, Clojure, D, Eiffel,
     Elixir, Elm, Erlang, FSharp, Fortran, Go, Groovy, HTML, Haskell, Java, JavaScript, Julia,
-    Kotlin, Lua, ObjectiveC, OCaml, PHP, Pascal, Python, Perl, /*Prolog,*/ R, Racket, Ruby, Rust,
+    Kotlin, Lua, ObjectiveC, OCaml, PHP, Pascal, Python, Perl, R, Racket, Ruby, Rust,
     Scala, SQL, Scheme, Swift, TypeScript, VisualBasic,
+    // recognized by the language registry, eg. by file name or by contents
+    Shell, PowerShell, Makefile, CMake, Dockerfile, Prolog, Raku, ObjectiveCpp, MATLAB, Mathematica,
+    Mercury, Dart, TeX, Vue,
+    // languages that only a language registry knows about, see `Language::named`
+    Named(LanguageName),
     // special category for languages we do not yet recognize and anything we do not know
     Other
 }
@@ -62,8 +70,8 @@ impl Language {
             "php" => Some(Language::PHP),
             "pascal" => Some(Language::Pascal),
             "python" => Some(Language::Python),
-            "perl" | "perl 6" | "perl6" => Some(Language::Perl),
-            //"prolog" => Some(Language::Prolog),
+            "perl" => Some(Language::Perl),
+            "prolog" => Some(Language::Prolog),
             "r" => Some(Language::R),
             "racket" => Some(Language::Racket),
             "ruby" => Some(Language::Ruby),
@@ -74,72 +82,83 @@ impl Language {
             "swift" => Some(Language::Swift),
             "typescript" => Some(Language::TypeScript),
             "visual basic" | "visual basic .net" => Some(Language::VisualBasic),
+            "shell" => Some(Language::Shell),
+            "powershell" => Some(Language::PowerShell),
+            "makefile" => Some(Language::Makefile),
+            "cmake" => Some(Language::CMake),
+            "dockerfile" => Some(Language::Dockerfile),
+            "raku" | "perl 6" | "perl6" => Some(Language::Raku),
+            "objective-c++" | "objective c++" | "objectivec++" => Some(Language::ObjectiveCpp),
+            "matlab" => Some(Language::MATLAB),
+            "mathematica" => Some(Language::Mathematica),
+            "mercury" => Some(Language::Mercury),
+            "dart" => Some(Language::Dart),
+            "tex" => Some(Language::TeX),
+            "vue" => Some(Language::Vue),
             _ => None,
         }
     }
 
-    fn from_path(path: &str) -> Option<Self> {
-        std::path::Path::new(path).extension().map(|extension| {
-            extension.to_str().map(|extension| Language::from_extension(extension))
-        }).flatten().flatten()
-    }
-
-    /** Returns the language associated with given extension. 
-     
-        If the extension is *not* recognized, returns Language::Other. If the extension is recognized as not belonging to a source code file (say images, etc.) should return None. 
-
-        TODO implement the above?
-     */
-    fn from_extension(extension: &str) -> Option<Self> {
-        match extension {
-            "c" | "h"                                               => Some(Language::C),
-            "C" | "cc" | "cpp" | "cxx" | "c++" | "hpp"              => Some(Language::Cpp),
-            "m" | "mm" | "M"                                        => Some(Language::ObjectiveC),
-            "go"                                                    => Some(Language::Go),
-            "java"                                                  => Some(Language::Java),
-            "coffee" | "litcoffee"                                  => Some(Language::CoffeeScript),
-            "js" | "mjs"                                            => Some(Language::JavaScript),
-            "ts" | "tsx"                                            => Some(Language::TypeScript),
-            "rb"                                                    => Some(Language::Ruby),
-            "rs"                                                    => Some(Language::Rust),
-            "py" | "pyi" | "pyc" | "pyd" | "pyo" | "pyw" | "pyz"    => Some(Language::Python),
-            "plx" | "pl" | "pm" | "xs" | "t" | "pod"                => Some(Language::Perl),
-            "clj" | "cljs" | "cljc" | "edn"                         => Some(Language::Clojure),
-            "erl" | "hrl"                                           => Some(Language::Erlang),
-            "hs" | "lhs"                                            => Some(Language::Haskell),
-            "scala" | "sc"                                          => Some(Language::Scala),
-            "r" | "rscript"                                         => Some(Language::R),
-            "php" | "phtml" | "php3" | "php4" | "php5" |
-            "php7" | "phps" | "php-s" | "pht" | "phar"              => Some(Language::PHP),
-            "vb"                                                    => Some(Language::VisualBasic),
-            // also: bas, cls, ctl, ctx, dca, ddf,dep, dob, dox, dsr, dsx, dws, frm, frx, log, oca,
-            //       pag, pgx, res, tbl, vbg, vbl, vbp, vbr, vbw, vbz, wct, apparently
-            "swift"                                                 => Some(Language::Swift),
-            "scm" | "ss" | "sls" | "sps" | "sld"                    => Some(Language::Scheme),
-            "rkt"                                                   => Some(Language::Racket),
-            "sql" | "pls" | "pks"                                   => Some(Language::SQL),
-            //"pl" | "pro"                                            => Some(Language::Prolog), // conflict
-            "pp" | "pas" | "inc"                                    => Some(Language::Pascal),
-            "ml" | "mli"                                            => Some(Language::OCaml),
-            "lua"                                                   => Some(Language::Lua),
-            "kt" | "kts"                                            => Some(Language::Kotlin),
-            "jl"                                                    => Some(Language::Julia),
-            "html" | "htm"                                          => Some(Language::HTML),
-            "groovy" | "gvy" | "gy" | "gsh"                         => Some(Language::Groovy),
-            "f90" | "for" | "f"                                     => Some(Language::Fortran),
-            "fs" | "fsi"                                            => Some(Language::FSharp),
-            "elm"                                                   => Some(Language::Elm),
-            "ex" | "exs"                                            => Some(Language::Elixir),
-            "e"                                                     => Some(Language::Eiffel),
-            "d"                                                     => Some(Language::D),
-            "css"                                                   => Some(Language::CSS),
-            "cbl" | "cob" | "cpy"                                   => Some(Language::Cobol),
-            "lisp" | "lsp" | "l" | "cl" | "fasl"                    => Some(Language::Lisp),
-            "cs"                                                    => Some(Language::CSharp),
-            "as" | "swf"                                            => Some(Language::ActionScript),
-            "asp"                                                   => Some(Language::ASP),
-            "asm" | "s"                                             => Some(Language::ASM),
-            _                                                       => Some(Language::Other),
+    /** The language with this name: the variant `from_str` finds for it, or otherwise a `Named`
+        language, so that a language registry can add languages that are not variants. A name is
+        the same `Named` language wherever it comes from, ignoring case, for as long as the program runs. */
+    pub fn named(name: &str) -> anyhow::Result<Self> {
+        if let Some(language) = Language::from_str(name) {
+            return Ok(language)
+        }
+        let mut names = LANGUAGE_NAMES.lock().unwrap();
+        if let Some(index) = names.iter().position(|known| known.eq_ignore_ascii_case(name)) {
+            return Ok(Language::Named(LanguageName(index as u16)))
+        }
+        if names.len() > u16::MAX as usize {
+            anyhow::bail!("Too many languages, cannot add {}", name)
+        }
+        names.push(name.to_owned());
+        Ok(Language::Named(LanguageName((names.len() - 1) as u16)))
+    }
+
+    /** How comments are written in this language, for telling comments from code when counting lines. */
+    pub fn comment_syntax(&self) -> CommentSyntax {
+        // Single quotes are quotes too where they start strings or character literals, but not in
+        // Rust (lifetimes), Scala (symbols), the Lisps (quoting), Haskell and the MLs (primes and
+        // type variables), MATLAB (transposes), or Visual Basic (comments).
+        const QUOTES: &[char] = &['"', '\''];
+        const C: CommentSyntax = CommentSyntax::new(&["//"], &[("/*", "*/")]).with_quotes(QUOTES);
+        const C_NESTED: CommentSyntax = CommentSyntax::nested(&["//"], &[("/*", "*/")]);
+        const HASH: CommentSyntax = CommentSyntax::new(&["#"], &[]).with_quotes(QUOTES);
+        const ML: CommentSyntax = CommentSyntax::nested(&[], &[("(*", "*)")]);
+        match self {
+            Language::C | Language::Cpp | Language::CSharp | Language::Go | Language::Groovy
+            | Language::Java | Language::JavaScript | Language::ObjectiveC | Language::ObjectiveCpp
+            | Language::TypeScript | Language::ActionScript => C,
+            Language::Rust | Language::Scala | Language::Swift => C_NESTED,
+            Language::Kotlin | Language::Dart => C_NESTED.with_quotes(QUOTES),
+            Language::D => CommentSyntax::nested(&["//"], &[("/*", "*/"), ("/+", "+/")]).with_quotes(QUOTES),
+            Language::PHP => CommentSyntax::new(&["//", "#"], &[("/*", "*/")]).with_quotes(QUOTES),
+            Language::CSS => CommentSyntax::new(&[], &[("/*", "*/")]).with_quotes(QUOTES),
+            Language::Python | Language::Perl | Language::Ruby | Language::R | Language::Shell
+            | Language::Makefile | Language::Dockerfile | Language::Elixir | Language::Raku => HASH,
+            Language::PowerShell => CommentSyntax::new(&["#"], &[("<#", "#>")]).with_quotes(QUOTES),
+            Language::CMake => CommentSyntax::new(&["#"], &[("#[[", "]]")]),
+            Language::Julia => CommentSyntax::nested(&["#"], &[("#=", "=#")]).with_quotes(QUOTES),
+            Language::CoffeeScript => CommentSyntax::new(&["#"], &[("###", "###")]).with_quotes(QUOTES),
+            Language::SQL => CommentSyntax::new(&["--"], &[("/*", "*/")]).with_quotes(QUOTES),
+            Language::Haskell | Language::Elm => CommentSyntax::nested(&["--"], &[("{-", "-}")]),
+            Language::Lua => CommentSyntax::new(&["--"], &[("--[[", "]]")]).with_quotes(QUOTES),
+            Language::Eiffel => CommentSyntax::new(&["--"], &[]).with_quotes(QUOTES),
+            Language::Lisp | Language::Racket | Language::Scheme => CommentSyntax::nested(&[";"], &[("#|", "|#")]),
+            Language::Clojure | Language::ASM => CommentSyntax::new(&[";"], &[]),
+            Language::TeX | Language::MATLAB | Language::Mercury => CommentSyntax::new(&["%"], &[]),
+            Language::Erlang => CommentSyntax::new(&["%"], &[]).with_quotes(QUOTES),
+            Language::Prolog => CommentSyntax::new(&["%"], &[("/*", "*/")]).with_quotes(QUOTES),
+            Language::OCaml | Language::Mathematica => ML,
+            Language::FSharp => CommentSyntax::nested(&["//"], &[("(*", "*)")]),
+            Language::Pascal => CommentSyntax::new(&["//"], &[("{", "}"), ("(*", "*)")]).with_quotes(&['\'']),
+            Language::VisualBasic | Language::ASP => CommentSyntax::new(&["'"], &[]),
+            Language::Fortran => CommentSyntax::new(&["!"], &[]).with_quotes(QUOTES),
+            Language::Cobol => CommentSyntax::new(&["*>"], &[]).with_quotes(QUOTES),
+            Language::HTML | Language::Vue => CommentSyntax::new(&[], &[("<!--", "-->")]),
+            Language::Named(_) | Language::Other => CommentSyntax::NONE,
         }
     }
 }
@@ -147,6 +166,7 @@ impl Language {
 impl Display for Language {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let string = match self {
+            Language::Named(name) => return Display::fmt(name, f),
             Language::ASM => "Assembly",
             Language::C => "C",
             Language::Cpp => "C++",
@@ -185,19 +205,76 @@ impl Display for Language {
             Language::Lua => "Lua",
             Language::OCaml => "OCaml",
             Language::Pascal => "Pascal",
-            //Language::Prolog => "Prolog",
+            Language::Prolog => "Prolog",
             Language::R => "R",
             Language::Racket => "Racket",
             Language::SQL => "SQL",
             Language::Scheme => "Scheme",
             Language::Swift => "Swift",
             Language::VisualBasic => "Visual Basic",
+            Language::Shell => "Shell",
+            Language::PowerShell => "PowerShell",
+            Language::Makefile => "Makefile",
+            Language::CMake => "CMake",
+            Language::Dockerfile => "Dockerfile",
+            Language::Raku => "Raku",
+            Language::ObjectiveCpp => "Objective-C++",
+            Language::MATLAB => "MATLAB",
+            Language::Mathematica => "Mathematica",
+            Language::Mercury => "Mercury",
+            Language::Dart => "Dart",
+            Language::TeX => "TeX",
+            Language::Vue => "Vue",
             Language::Other => "Other"
         };
         f.write_str(string)
     }
 }
 
+/* The names of `Language::Named` languages, by their index. */
+static LANGUAGE_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/** The name of a `Language::Named` language, kept as its index among the names seen so far.
+    Named languages are ordered, stored, and printed by name rather than by index, since indices
+    depend on the order in which names were first seen. */
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
+pub struct LanguageName(u16);
+
+impl Ord for LanguageName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other { return Ordering::Equal }
+        let names = LANGUAGE_NAMES.lock().unwrap();
+        names[self.0 as usize].cmp(&names[other.0 as usize])
+    }
+}
+
+impl PartialOrd for LanguageName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Display for LanguageName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&LANGUAGE_NAMES.lock().unwrap()[self.0 as usize])
+    }
+}
+
+impl Serialize for LanguageName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LanguageName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+        use serde::de::Error;
+        let name = String::deserialize(deserializer)?;
+        match Language::named(&name).map_err(D::Error::custom)? {
+            Language::Named(name) => Ok(name),
+            language => Err(D::Error::custom(format!("{} is not a named language", language))),
+        }
+    }
+}
+
 /**== Object IDs ================================================================================**/
 #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize, Debug)] pub struct ProjectId(pub u64);
 #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize, Debug)] pub struct CommitId(pub u64);
@@ -442,6 +519,11 @@ impl Project {
     pub fn time_since_first_commit      (&self, store: &Database)    -> Option<i64>          { store.project_time_since_first_commit(&self.id)}
     pub fn is_abandoned      (&self, store: &Database)    -> Option<bool>                   { store.project_is_abandoned(&self.id)                    }
     pub fn project_locs      (&self, store: &Database)    -> Option<usize>                  { store.project_locs(&self.id)                    }
+    pub fn line_counts      (&self, store: &Database)    -> Option<LineCounts>             { store.project_line_counts(&self.id)             }
+    pub fn code_lines       (&self, store: &Database)    -> Option<usize>                  { self.line_counts(store).map(|lines| lines.code)    }
+    pub fn comment_lines    (&self, store: &Database)    -> Option<usize>                  { self.line_counts(store).map(|lines| lines.comment) }
+    pub fn blank_lines      (&self, store: &Database)    -> Option<usize>                  { self.line_counts(store).map(|lines| lines.blank)   }
+    pub fn comment_density  (&self, store: &Database)    -> Option<f64>                    { self.line_counts(store).and_then(|lines| lines.comment_density()) }
     pub fn duplicated_code      (&self, store: &Database)    -> Option<f64>                 { store.project_duplicated_code(&self.id)                 }
     pub fn is_valid      (&self, store: &Database)    -> Option<bool>                       { store.project_is_valid(&self.id)                        }
     pub fn all_forks        (&self, store: &Database) -> Option<Vec<ProjectId>>             { store.project_all_forks(&self.id)               }
@@ -483,17 +565,27 @@ impl From<(String, CommitId)> for Head {
 /** Contains the path id and snapshot id of the change.
  
     If the file has been deleted as part of the change, snapshot is None. Otherwise snapshot is the snapshot id. 
+    Changes retrieved from a commit also know the commit, which is what their line counts and diffs are
+    taken against (its first parent).
  */
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Change {
     pub(crate) path: PathId,
     //pub(crate) hash: u64, // TODO could change into HeadId
     pub(crate) snapshot: Option<SnapshotId>,
+    #[serde(default)]
+    pub(crate) commit: Option<CommitId>,
 }
 
 impl Change {
     pub fn new(path: PathId, /*hash: u64,*/ snapshot: Option<SnapshotId>) -> Self {
-        Change { path, snapshot }
+        Change { path, snapshot, commit: None }
+    }
+    pub(crate) fn in_commit(self, commit: CommitId) -> Self {
+        Change { commit: Some(commit), ..self }
+    }
+    pub fn commit_id(&self) -> Option<CommitId> {
+        self.commit.clone()
     }
     pub fn snapshot_id(&self) -> Option<SnapshotId> {
         self.snapshot.clone()
@@ -503,6 +595,22 @@ impl Change {
     }
     pub fn snapshot(&self, store: &Database) -> Option<Snapshot> { self.snapshot.map(|id| store.snapshot(&id)).flatten() }
     pub fn path(&self, store: &Database) -> Option<Path> { store.path(&self.path) }
+
+    pub fn lines_added(&self, store: &Database) -> Option<usize> {
+        self.line_changes(store).map(|(added, _)| added)
+    }
+    pub fn lines_deleted(&self, store: &Database) -> Option<usize> {
+        self.line_changes(store).map(|(_, deleted)| deleted)
+    }
+    fn line_changes(&self, store: &Database) -> Option<(usize, usize)> {
+        store.commit_line_changes(&self.commit?)?.into_iter()
+            .find(|(path_id, _, _)| *path_id == self.path)
+            .map(|(_, added, deleted)| (added, deleted))
+    }
+    /** The unified diff of the file against its version in the first parent of the commit. */
+    pub fn diff(&self, store: &Database) -> Option<String> {
+        store.change_diff(&self.commit?, &self.path)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -600,6 +708,16 @@ impl Commit {
     pub fn languages_count(& self, store: &Database) -> Option<usize> {
         store.commit_languages_count(&self.id)
     }
+
+    pub fn lines_added(&self, store: &Database) -> Option<usize> {
+        store.commit_line_changes(&self.id).map(|changes| changes.iter().map(|(_, added, _)| added).sum())
+    }
+    pub fn lines_deleted(&self, store: &Database) -> Option<usize> {
+        store.commit_line_changes(&self.id).map(|changes| changes.iter().map(|(_, _, deleted)| deleted).sum())
+    }
+    pub fn churn(&self, store: &Database) -> Option<usize> {
+        store.commit_line_changes(&self.id).map(|changes| changes.iter().map(|(_, added, deleted)| added + deleted).sum())
+    }
 }
 
 impl Identifiable for Commit {
@@ -628,7 +746,13 @@ pub struct Path { pub(crate) id: PathId, pub(crate) location: String }
 impl Path {
     pub fn new(id: PathId, location: String) -> Self { Path { id, location } }
     pub fn location(&self) -> String { self.location.to_string() }
-    pub fn language(&self) -> Option<Language> { Language::from_path(self.location.as_str()) }
+    /** The language of the file as the default language registry sees it from its name alone. */
+    pub fn language(&self) -> Option<Language> {
+        LanguageRegistry::with_default(|registry| registry.detect(&self.location, None))
+    }
+    /** The language of the file as the database's language registry sees it, from its name and
+        contents if need be. */
+    pub fn language_in(&self, store: &Database) -> Option<Language> { store.path_language(&self.id) }
 }
 impl Identifiable for Path {
     type Identity = PathId;
@@ -691,6 +815,11 @@ impl Snapshot {
 
     // FIXME add hashes
     pub fn snapshot_locs      (&self, store: &Database)    -> Option<usize>                   { store.snapshot_locs(&self.id)                   }
+    pub fn line_counts        (&self, store: &Database)    -> Option<LineCounts>              { store.snapshot_line_counts(&self.id)            }
+    pub fn code_lines         (&self, store: &Database)    -> Option<usize>                   { self.line_counts(store).map(|lines| lines.code)    }
+    pub fn comment_lines      (&self, store: &Database)    -> Option<usize>                   { self.line_counts(store).map(|lines| lines.comment) }
+    pub fn blank_lines        (&self, store: &Database)    -> Option<usize>                   { self.line_counts(store).map(|lines| lines.blank)   }
+    pub fn comment_density    (&self, store: &Database)    -> Option<f64>                     { self.line_counts(store).and_then(|lines| lines.comment_density()) }
 
     pub fn snapshot_has_contents(&self, store: &Database) -> bool {
         store.snapshot_has_contents(&self.id)
@@ -849,6 +978,11 @@ impl<'a> ItemWithData<'a, Project> {
     pub fn time_since_first_commit (&self) -> Option<i64>                 { self.item.time_since_first_commit(&self.data) }
     pub fn is_abandoned (&self)        -> Option<bool>                    { self.item.is_abandoned(&self.data) }
     pub fn project_locs (&self)        -> Option<usize>                   { self.item.project_locs(&self.data) }
+    pub fn line_counts (&self)         -> Option<LineCounts>              { self.item.line_counts(&self.data) }
+    pub fn code_lines (&self)          -> Option<usize>                   { self.item.code_lines(&self.data) }
+    pub fn comment_lines (&self)       -> Option<usize>                   { self.item.comment_lines(&self.data) }
+    pub fn blank_lines (&self)         -> Option<usize>                   { self.item.blank_lines(&self.data) }
+    pub fn comment_density (&self)     -> Option<f64>                     { self.item.comment_density(&self.data) }
     pub fn duplicated_code (&self)        -> Option<f64>                  { self.item.duplicated_code(&self.data) }
     pub fn substore   (&self)    -> Option<Store>                         { self.item.substore(&self.data)     }
     pub fn is_valid   (&self)    -> Option<bool>                          { self.item.is_valid(&self.data)     }
@@ -947,6 +1081,11 @@ impl<'a> ItemWithData<'a, Snapshot> {
     pub fn contents_owned(&self) -> String { self.item.contents_owned() }
     pub fn contains(&self, needle: &str) -> bool { self.item.contains(needle) }
     pub fn snapshot_locs (&self)        -> Option<usize>                    { self.item.snapshot_locs(&self.data) }
+    pub fn line_counts (&self)          -> Option<LineCounts>               { self.item.line_counts(&self.data) }
+    pub fn code_lines (&self)           -> Option<usize>                    { self.item.code_lines(&self.data) }
+    pub fn comment_lines (&self)        -> Option<usize>                    { self.item.comment_lines(&self.data) }
+    pub fn blank_lines (&self)          -> Option<usize>                    { self.item.blank_lines(&self.data) }
+    pub fn comment_density (&self)      -> Option<f64>                      { self.item.comment_density(&self.data) }
     pub fn snapshot_has_contents(&self) -> bool { self.item.snapshot_has_contents(&self.data) }
     pub fn unique_projects(&self) -> usize { self.item.unique_projects(&self.data) }
     pub fn original_project(&self) -> ProjectId { self.item.original_project(&self.data) }
@@ -1024,12 +1163,15 @@ impl<'a> ItemWithData<'a, Commit> {
     pub fn projects_count(& self) -> Option<usize> { self.item.projects_count(& self.data) }
     pub fn languages(&self) -> Option<Vec<Language>> { self.item.languages(&self.data) }
     pub fn languages_count(& self) -> Option<usize> { self.item.languages_count(& self.data) }
+    pub fn lines_added(&self) -> Option<usize> { self.item.lines_added(&self.data) }
+    pub fn lines_deleted(&self) -> Option<usize> { self.item.lines_deleted(&self.data) }
+    pub fn churn(&self) -> Option<usize> { self.item.churn(&self.data) }
 
 }
 impl<'a> ItemWithData<'a, Path> {
     pub fn id      (&self) -> PathId           { self.item.id()       }
     pub fn location(&self) -> String           { self.item.location() }
-    pub fn language(&self) -> Option<Language> { self.item.language() }
+    pub fn language(&self) -> Option<Language> { self.item.language_in(&self.data) }
 }
 
 impl<'a> ItemWithData<'a, Head> {
@@ -1048,6 +1190,10 @@ impl<'a> ItemWithData<'a, Change> {
     pub fn snapshot_id(&self) -> Option<SnapshotId> { self.item.snapshot_id() }
     pub fn path(&self) -> Option<Path> { self.item.path(&self.data) }
     pub fn snapshot(&self) -> Option<Snapshot> { self.item.snapshot(&self.data) }
+    pub fn commit_id(&self) -> Option<CommitId> { self.item.commit_id() }
+    pub fn lines_added(&self) -> Option<usize> { self.item.lines_added(&self.data) }
+    pub fn lines_deleted(&self) -> Option<usize> { self.item.lines_deleted(&self.data) }
+    pub fn diff(&self) -> Option<String> { self.item.diff(&self.data) }
 
     pub fn path_with_data<'b> (&'b self) -> Option<ItemWithData<'a, Path>> {
         self.item.path(self.data).attach_data_to_inner(self.data)
@@ -1,31 +1,54 @@
 use std::cmp::Ordering;
-use std::f64::*;
 use std::hash::{Hash, Hasher};
 
-#[derive(PartialEq, PartialOrd, Copy, Clone)]
+/** An `f64` with a total order, so that floats can be sorted, grouped, and compared like any other
+    attribute. NaN is equal to itself and less than every other number, including negative
+    infinity. Negative and positive zero are equal. */
+#[derive(Copy, Clone)]
 pub struct OrdF64(f64);
 
 impl OrdF64 {
     pub fn as_f64(&self) -> f64 { self.0 }
 }
 
+impl std::fmt::Display for OrdF64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.0) }
+}
+impl std::fmt::Debug for OrdF64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{:?}", self.0) }
+}
+
 impl Into<f64> for OrdF64 { fn into(self)    -> f64  { self.0    } }
 impl From<f64> for OrdF64 { fn from(n: f64)  -> Self { OrdF64(n) } }
 
 impl Eq for OrdF64 {}
 
+impl PartialEq for OrdF64 {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl PartialOrd for OrdF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
 impl Ord for OrdF64 {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.0 == INFINITY && other.0 == INFINITY { return Ordering::Equal }
-        if self.0 == NEG_INFINITY && other.0 == NEG_INFINITY { return Ordering::Equal }
-        if self.0 == NAN && other.0 == NAN { return Ordering::Equal }
+        match (self.0.is_nan(), other.0.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => self.0.partial_cmp(&other.0).unwrap(), // Only NaN is unordered.
+        }
+    }
+}
 
-        if self.0 == INFINITY { return Ordering::Greater }
-        if self.0 == NEG_INFINITY { return Ordering::Less }
-        if self.0 == NAN { return Ordering::Less }
+// So that float attributes can be compared against plain numbers, eg. AtLeast(..., 0.5).
+impl PartialEq<f64> for OrdF64 {
+    fn eq(&self, other: &f64) -> bool { *self == OrdF64(*other) }
+}
 
-        return self.0.partial_cmp(&other.0).unwrap()
-    }
+impl PartialOrd<f64> for OrdF64 {
+    fn partial_cmp(&self, other: &f64) -> Option<Ordering> { self.partial_cmp(&OrdF64(*other)) }
 }
 
 impl OrdF64 {
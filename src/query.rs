@@ -0,0 +1,834 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::*;
+use regex::Regex;
+
+use crate::attrib::*;
+use crate::csv::{CSV, CSVItem, StringConvenience};
+use crate::database::Database;
+use crate::objects;
+use crate::objects::ItemWithData;
+use crate::ordf64::OrdF64;
+use crate::{project, commit, user, path, snapshot};
+use crate::{AttributeIterator, By, Count, Top, Random, Seed};
+use crate::{LessThan, AtMost, Equal, AtLeast, MoreThan, And, Or, Not, Exists, Matches};
+
+/*
+ * A small textual query language, for writing queries without writing (and compiling) Rust, eg.
+ *
+ *     projects | filter stars >= 100 and language = "Rust" | sort commits desc | sample random 50 seed 42 | select id, url, stars
+ *
+ * A query starts with the kind of objects it is about: `projects`, `commits`, `users`, `paths`, or
+ * `snapshots`. Then come any number of stages, separated by `|`:
+ *
+ *   - `filter <condition>` keeps the objects that meet the condition,
+ *   - `sort <attribute> [asc|desc], ...` sorts by one or more attributes, ascending unless `desc`
+ *     is given, with objects without a value last and ties broken by id,
+ *   - `sample top <n>` keeps the first n objects, and `sample random <n> [seed <n>]` n random ones,
+ *   - `select <attribute>, ...` writes out those attributes rather than whole objects; it has to be
+ *     the last stage.
+ *
+ * Conditions compare an attribute to a number, a string, `true`, or `false` with `<`, `<=`, `=`,
+ * `!=`, `>=`, or `>`, as long as the attribute has values of that kind, look into strings with
+ * `<attribute> contains "..."` and `<attribute> matches "<regex>"`, check whether an attribute has
+ * a value at all with `exists <attribute>` and `missing <attribute>`, and are combined with `and`,
+ * `or`, `not`, and parentheses. Objects without a value never pass a comparison, whichever way it
+ * goes. Numbers are decimal, with an optional sign, fraction, and exponent (`42`, `-1.5`, `.5`,
+ * `1e3`, `2.5E-2`), and strings are in double quotes, with `\` escaping the next character.
+ *
+ * Attributes are those from the `project`, `commit`, `user`, `path`, and `snapshot` modules, in
+ * snake case: `project::Stars` is `stars` and `commit::MessageLength` is `message_length`. Those that
+ * are lists, like `project::Commits`, stand for their length. Durations are in seconds, timestamps
+ * are UNIX timestamps, and languages are strings like "Rust" or "C++". `#` starts a comment.
+ *
+ * Each stage is compiled into the same filters, sort keys, samplers, and selections that a query
+ * written in Rust would use.
+ */
+
+/** A value of an attribute, as far as the query language is concerned.
+
+    Values of different kinds are ordered too, booleans before numbers before strings, but all
+    values of one attribute are of the same kind, and queries that compare them to a value of
+    another kind are rejected.
+ */
+#[derive(Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Value {
+    Bool(bool),
+    Number(OrdF64),
+    Text(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind { Bool, Number, Text }
+
+impl Value {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Value::Bool(_) => Kind::Bool,
+            Value::Number(_) => Kind::Number,
+            Value::Text(_) => Kind::Text,
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Bool => write!(f, "a boolean"),
+            Kind::Number => write!(f, "a number"),
+            Kind::Text => write!(f, "a string"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.as_f64().fract() == 0.0 && n.as_f64().abs() < 1e15 => write!(f, "{}", n.as_f64() as i64),
+            Value::Number(n) => write!(f, "{}", n.as_f64()),
+            Value::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+pub trait IntoValue { const KIND: Kind; fn into_value(self) -> Value; }
+macro_rules! impl_into_value {
+    ($variant:ident: $($type:ty => |$x:ident| $convert:expr),+) => {
+        $(impl IntoValue for $type {
+            const KIND: Kind = Kind::$variant;
+            fn into_value(self) -> Value { let $x = self; Value::$variant($convert) }
+        })+
+    }
+}
+impl_into_value!(Bool: bool => |b| b);
+impl_into_value!(Number: usize => |n| OrdF64::from(n as f64), u64 => |n| OrdF64::from(n as f64),
+                         i64 => |n| OrdF64::from(n as f64), i32 => |n| OrdF64::from(n as f64),
+                         f64 => |n| OrdF64::from(n), OrdF64 => |n| n,
+                         crate::time::Duration => |d| OrdF64::from(d.as_seconds() as f64),
+                         objects::ProjectId => |id| OrdF64::from(id.0 as f64),
+                         objects::CommitId => |id| OrdF64::from(id.0 as f64),
+                         objects::UserId => |id| OrdF64::from(id.0 as f64),
+                         objects::PathId => |id| OrdF64::from(id.0 as f64),
+                         objects::SnapshotId => |id| OrdF64::from(id.0 as f64));
+impl_into_value!(Text: String => |s| s, objects::Language => |language| language.to_string());
+
+/** An attribute looked up by name, which yields `Value`s of one kind. */
+pub struct Field<T> {
+    name: String,
+    kind: Kind,
+    cost: Cost,
+    getter: for<'a, 'b> fn(&'b ItemWithData<'a, T>) -> Option<Value>,
+}
+impl<T> Field<T> {
+    pub fn new(name: &str, kind: Kind, cost: Cost, getter: for<'a, 'b> fn(&'b ItemWithData<'a, T>) -> Option<Value>) -> Self {
+        Field { name: name.to_owned(), kind, cost, getter }
+    }
+    pub fn name(&self) -> &str { &self.name }
+    pub fn kind(&self) -> Kind { self.kind }
+}
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self { Field { name: self.name.clone(), kind: self.kind, cost: self.cost, getter: self.getter } }
+}
+impl<T> Attribute for Field<T> {
+    type Object = T;
+    fn cost(&self) -> Cost { self.cost }
+}
+impl<'a, T> OptionGetter<'a> for Field<T> {
+    type IntoItem = Value;
+    fn get_opt(&self, object: &ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        (self.getter)(object)
+    }
+}
+impl<'a, T> Getter<'a> for Field<T> {
+    type IntoItem = Option<Value>;
+    fn get(&self, object: &ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        self.get_opt(object)
+    }
+}
+
+/* A field as a string, for `Matches`. */
+struct AsText<T>(Field<T>);
+impl<T> Attribute for AsText<T> {
+    type Object = T;
+    fn cost(&self) -> Cost { self.0.cost() }
+}
+impl<'a, T> OptionGetter<'a> for AsText<T> {
+    type IntoItem = String;
+    fn get_opt(&self, object: &ItemWithData<'a, Self::Object>) -> Option<Self::IntoItem> {
+        self.0.get_opt(object).map(|value| value.to_string())
+    }
+}
+
+/* The fields in a `select` stage. */
+struct Fields<T>(Vec<Field<T>>);
+impl<T> Attribute for Fields<T> {
+    type Object = T;
+    fn cost(&self) -> Cost { self.0.iter().map(|field| field.cost()).max().unwrap_or_default() }
+}
+impl<'a, T> Getter<'a> for Fields<T> {
+    type IntoItem = Row;
+    fn get(&self, object: &ItemWithData<'a, Self::Object>) -> Self::IntoItem {
+        Row(self.0.iter().map(|field| field.get_opt(object)).collect())
+    }
+}
+
+/** One row of the result of a query that ends with `select`. */
+pub struct Row(pub Vec<Option<Value>>);
+impl CSVItem for Row {
+    fn column_headers() -> Vec<&'static str> { vec![] } // the names of the selected attributes
+    fn row(&self) -> Vec<String> {
+        self.0.iter().map(|value| match value {
+            Some(Value::Text(s)) => s.escape_quotes().quoted(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }).collect()
+    }
+}
+
+fn value_of<'a, A, T>(attribute: A, object: &ItemWithData<'a, T>) -> Option<Value>
+    where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: IntoValue {
+    attribute.get_opt(object).map(|value| value.into_value())
+}
+
+/* The kind of the values an attribute yields, worked out from its type alone. */
+fn kind_of<'a, A, T>(_attribute: A) -> Kind
+    where A: OptionGetter<'a> + Attribute<Object=T>, A::IntoItem: IntoValue {
+    <A::IntoItem as IntoValue>::KIND
+}
+
+/** Objects that queries can be about, along with the names of their attributes. */
+pub trait Queryable: Sized + Clone + objects::Identifiable {
+    const NAME: &'static str;
+    fn field(name: &str) -> Option<Field<Self>>;
+    fn objects<'a>(database: &'a Database) -> Box<dyn Iterator<Item=ItemWithData<'a, Self>> + 'a>;
+}
+
+macro_rules! impl_queryable {
+    ($object:ty, $name:expr, $iterator:ident; $($field:literal => $attribute:expr),+ $(,)?) => {
+        impl Queryable for $object {
+            const NAME: &'static str = $name;
+            fn field(name: &str) -> Option<Field<Self>> {
+                match name {
+                    $($field => Some(Field::new($field, kind_of($attribute), Attribute::cost(&$attribute), |object| value_of($attribute, object))),)+
+                    _ => None,
+                }
+            }
+            fn objects<'a>(database: &'a Database) -> Box<dyn Iterator<Item=ItemWithData<'a, Self>> + 'a> {
+                Box::new(database.$iterator())
+            }
+        }
+    }
+}
+
+impl_queryable!(objects::Project, "projects", projects;
+    "id" => project::Id, "url" => project::URL,
+    "issues" => project::Issues, "buggy_issues" => project::BuggyIssues,
+    "all_issues" => project::AllIssues, "open_issues" => project::OpenIssues,
+    "is_fork" => project::IsFork, "is_archived" => project::IsArchived, "is_disabled" => project::IsDisabled,
+    "stars" => project::Stars, "watchers" => project::Watchers, "subscribers" => project::Subscribers,
+    "size" => project::Size, "forks" => project::Forks, "license" => project::License,
+    "language" => project::Language, "description" => project::Description, "homepage" => project::Homepage,
+    "has_issues" => project::HasIssues, "has_downloads" => project::HasDownloads,
+    "has_wiki" => project::HasWiki, "has_pages" => project::HasPages,
+    "created" => project::Created, "updated" => project::Updated, "pushed" => project::Pushed,
+    "default_branch" => project::DefaultBranch, "age" => project::Age,
+    "heads" => Count(project::Heads), "commits" => Count(project::CommitIds),
+    "authors" => Count(project::AuthorIds), "committers" => Count(project::CommitterIds),
+    "users" => Count(project::UserIds), "paths" => Count(project::PathIds),
+    "snapshots" => Count(project::SnapshotIds),
+    "max_commit_delta" => project::MaxCommitDelta, "avg_commit_delta" => project::AvgCommitDelta,
+    "time_since_last_commit" => project::TimeSinceLastCommit,
+    "time_since_first_commit" => project::TimeSinceFirstCommit,
+    "is_abandoned" => project::IsAbandoned, "locs" => project::Locs,
+    "code_lines" => project::CodeLines, "comment_lines" => project::CommentLines,
+    "blank_lines" => project::BlankLines, "comment_density" => project::CommentDensity,
+    "max_experience" => project::MaxExperience, "project_experience" => project::ProjectExperience,
+    "duplicated_code" => project::DuplicatedCode,
+    "unique_files" => project::UniqueFiles, "original_files" => project::OriginalFiles,
+    "impact" => project::Impact, "files" => project::Files,
+    "languages" => Count(project::Languages), "major_language" => project::MajorLanguage,
+    "major_language_ratio" => project::MajorLanguageRatio,
+    "major_language_changes" => project::MajorLanguageChanges,
+    "is_valid" => project::IsValid, "all_forks" => Count(project::AllForks),
+);
+
+impl_queryable!(objects::Commit, "commits", commits;
+    "id" => commit::Id, "committer_id" => commit::CommitterId, "author_id" => commit::AuthorId,
+    "hash" => commit::Hash, "message" => commit::Message, "message_length" => commit::MessageLength,
+    "authored_timestamp" => commit::AuthoredTimestamp, "committed_timestamp" => commit::CommittedTimestamp,
+    "changes" => Count(commit::Changes), "paths" => Count(commit::PathIds),
+    "snapshots" => Count(commit::SnapshotIds), "parents" => Count(commit::ParentIds),
+    "projects" => Count(commit::Projects), "languages" => Count(commit::Languages),
+    "lines_added" => commit::LinesAdded, "lines_deleted" => commit::LinesDeleted, "churn" => commit::Churn,
+);
+
+impl_queryable!(objects::User, "users", users;
+    "id" => user::Id, "email" => user::Email,
+    "author_experience" => user::AuthorExperience, "committer_experience" => user::CommitterExperience,
+    "experience" => user::Experience, "developer_experience" => user::DeveloperExperience,
+    "authored_commits" => Count(user::AuthoredCommitIds), "committed_commits" => Count(user::CommittedCommitIds),
+);
+
+impl_queryable!(objects::Path, "paths", paths;
+    "id" => path::Id, "location" => path::Location, "language" => path::Language,
+);
+
+impl_queryable!(objects::Snapshot, "snapshots", snapshots_with_data;
+    "id" => snapshot::Id, "contents" => snapshot::Contents, "loc" => snapshot::Loc,
+    "code_lines" => snapshot::CodeLines, "comment_lines" => snapshot::CommentLines,
+    "blank_lines" => snapshot::BlankLines, "comment_density" => snapshot::CommentDensity,
+    "has_contents" => snapshot::HasContents, "num_projects" => snapshot::NumProjects,
+    "original_project" => snapshot::OriginalProject,
+);
+
+/** A parsed query. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub objects: Objects,
+    pub stages: Vec<Stage>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Objects { Projects, Commits, Users, Paths, Snapshots }
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stage {
+    Filter(Condition),
+    Sort(Vec<(String, sort::Direction)>),
+    Sample(Sample),
+    Select(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sample { Top(usize), Random(usize, u128) }
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Compare(String, Comparison, Literal),
+    Contains(String, String),
+    Matches(String, String),
+    Exists(String),
+    Missing(String),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison { LessThan, AtMost, Equal, NotEqual, AtLeast, MoreThan }
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal { Bool(bool), Number(f64), Text(String) }
+
+impl Literal {
+    fn into_value(self) -> Value {
+        match self {
+            Literal::Bool(b) => Value::Bool(b),
+            Literal::Number(n) => Value::Number(OrdF64::from(n)),
+            Literal::Text(s) => Value::Text(s),
+        }
+    }
+}
+
+/*== Parsing ====================================================================================*/
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token { Word(String), Number(f64), Text(String), Symbol(&'static str) }
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Text(s) => write!(f, "\"{}\"", s),
+            Token::Symbol(symbol) => write!(f, "`{}`", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&'static str; 10] = ["<=", ">=", "!=", "<", ">", "=", "|", ",", "(", ")"];
+
+/* Whether the characters start with a number: a digit, or a dot followed by one, after an
+   optional minus sign. */
+fn starts_number(characters: &[char]) -> bool {
+    let unsigned = match characters.first() {
+        Some('-') => &characters[1..],
+        _ => characters,
+    };
+    match unsigned {
+        [digit, ..] if digit.is_ascii_digit() => true,
+        ['.', digit, ..] => digit.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+/* Splits a query into tokens, along with the line and column where each of them starts. */
+fn tokenize(source: &str) -> Result<Vec<(Token, usize, usize)>> {
+    let mut tokens = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let characters: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < characters.len() {
+            let (line_number, column) = (line_index + 1, i + 1);
+            let c = characters[i];
+            if c == '#' {
+                break
+            } else if c.is_whitespace() {
+                i += 1;
+            } else if c == '"' {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match characters.get(i) {
+                        None => bail!("Unterminated string starting at line {}, column {}", line_number, column),
+                        Some('"') => { i += 1; break }
+                        Some('\\') if i + 1 < characters.len() => { text.push(characters[i + 1]); i += 2 }
+                        Some(c) => { text.push(*c); i += 1 }
+                    }
+                }
+                tokens.push((Token::Text(text), line_number, column));
+            } else if starts_number(&characters[i..]) {
+                let start = i;
+                if c == '-' {
+                    i += 1;
+                }
+                while i < characters.len() && (characters[i].is_ascii_digit() || characters[i] == '.') {
+                    i += 1;
+                }
+                // An exponent, as long as there are digits after the `e` and its sign.
+                if i < characters.len() && (characters[i] == 'e' || characters[i] == 'E') {
+                    let sign = matches!(characters.get(i + 1), Some('+') | Some('-')) as usize;
+                    if characters.get(i + 1 + sign).map_or(false, |c| c.is_ascii_digit()) {
+                        i += 1 + sign;
+                        while i < characters.len() && characters[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let number: String = characters[start..i].iter().collect();
+                let number = f64::from_str(&number)
+                    .with_context(|| format!("Invalid number `{}` at line {}, column {}", number, line_number, column))?;
+                tokens.push((Token::Number(number), line_number, column));
+            } else if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < characters.len() && (characters[i].is_alphanumeric() || characters[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Word(characters[start..i].iter().collect()), line_number, column));
+            } else {
+                let rest: String = characters[i..].iter().take(2).collect();
+                let symbol = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol))
+                    .with_context(|| format!("Unexpected `{}` at line {}, column {}", c, line_number, column))?;
+                tokens.push((Token::Symbol(*symbol), line_number, column));
+                i += symbol.len();
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser { tokens: Vec<(Token, usize, usize)>, position: usize }
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _, _)| token)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        match self.tokens.get(self.position) {
+            Some((token, line, column)) =>
+                bail!("Expected {} but found {} at line {}, column {}", expected, token, line, column),
+            None => bail!("Expected {} but the query ended", expected),
+        }
+    }
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        if self.is_word(word) { self.position += 1; Ok(()) } else { self.unexpected(&format!("`{}`", word)) }
+    }
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.is_symbol(symbol) { self.position += 1; Ok(()) } else { self.unexpected(&format!("`{}`", symbol)) }
+    }
+    fn attribute(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(word)) => { let word = word.clone(); self.position += 1; Ok(word) }
+            _ => self.unexpected("an attribute"),
+        }
+    }
+    fn count(&mut self) -> Result<usize> {
+        match self.peek() {
+            Some(Token::Number(n)) if *n >= 0.0 && n.fract() == 0.0 => { let n = *n as usize; self.position += 1; Ok(n) }
+            _ => self.unexpected("a whole number"),
+        }
+    }
+    fn string(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Text(s)) => { let s = s.clone(); self.position += 1; Ok(s) }
+            _ => self.unexpected("a string"),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        let objects = match self.peek() {
+            Some(Token::Word(word)) if word == "projects" => Objects::Projects,
+            Some(Token::Word(word)) if word == "commits" => Objects::Commits,
+            Some(Token::Word(word)) if word == "users" => Objects::Users,
+            Some(Token::Word(word)) if word == "paths" => Objects::Paths,
+            Some(Token::Word(word)) if word == "snapshots" => Objects::Snapshots,
+            _ => return self.unexpected("`projects`, `commits`, `users`, `paths`, or `snapshots`"),
+        };
+        self.position += 1;
+
+        let mut stages = Vec::new();
+        while self.peek().is_some() {
+            if let Some(Stage::Select(_)) = stages.last() {
+                return self.unexpected("the end of the query after `select`");
+            }
+            self.expect_symbol("|")?;
+            stages.push(self.stage()?);
+        }
+        Ok(Query { objects, stages })
+    }
+
+    fn stage(&mut self) -> Result<Stage> {
+        match self.next() {
+            Some(Token::Word(word)) if word == "filter" => Ok(Stage::Filter(self.condition()?)),
+            Some(Token::Word(word)) if word == "sort" => {
+                let mut keys = Vec::new();
+                loop {
+                    let attribute = self.attribute()?;
+                    let direction = if self.is_word("desc") {
+                        self.position += 1; sort::Direction::Descending
+                    } else {
+                        if self.is_word("asc") { self.position += 1 }
+                        sort::Direction::Ascending
+                    };
+                    keys.push((attribute, direction));
+                    if !self.is_symbol(",") { break }
+                    self.position += 1;
+                }
+                Ok(Stage::Sort(keys))
+            }
+            Some(Token::Word(word)) if word == "sample" => {
+                if self.is_word("top") {
+                    self.position += 1;
+                    Ok(Stage::Sample(Sample::Top(self.count()?)))
+                } else if self.is_word("random") {
+                    self.position += 1;
+                    let count = self.count()?;
+                    let seed = if self.is_word("seed") { self.position += 1; self.count()? as u128 } else { 0 };
+                    Ok(Stage::Sample(Sample::Random(count, seed)))
+                } else {
+                    self.unexpected("`top` or `random`")
+                }
+            }
+            Some(Token::Word(word)) if word == "select" => {
+                let mut attributes = vec![self.attribute()?];
+                while self.is_symbol(",") {
+                    self.position += 1;
+                    attributes.push(self.attribute()?);
+                }
+                Ok(Stage::Select(attributes))
+            }
+            _ => { self.position -= 1; self.unexpected("`filter`, `sort`, `sample`, or `select`") }
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let mut condition = self.conjunction()?;
+        while self.is_word("or") {
+            self.position += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+        }
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Condition> {
+        let mut condition = self.negation()?;
+        while self.is_word("and") {
+            self.position += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.negation()?));
+        }
+        Ok(condition)
+    }
+
+    fn negation(&mut self) -> Result<Condition> {
+        if self.is_word("not") {
+            self.position += 1;
+            return Ok(Condition::Not(Box::new(self.negation()?)))
+        }
+        if self.is_symbol("(") {
+            self.position += 1;
+            let condition = self.condition()?;
+            self.expect_symbol(")")?;
+            return Ok(condition)
+        }
+        if self.is_word("exists") {
+            self.position += 1;
+            return Ok(Condition::Exists(self.attribute()?))
+        }
+        if self.is_word("missing") {
+            self.position += 1;
+            return Ok(Condition::Missing(self.attribute()?))
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Condition> {
+        let attribute = self.attribute()?;
+        if self.is_word("contains") {
+            self.position += 1;
+            return Ok(Condition::Contains(attribute, self.string()?))
+        }
+        if self.is_word("matches") {
+            self.position += 1;
+            return Ok(Condition::Matches(attribute, self.string()?))
+        }
+        let comparison = match self.peek() {
+            Some(Token::Symbol("<")) => Comparison::LessThan,
+            Some(Token::Symbol("<=")) => Comparison::AtMost,
+            Some(Token::Symbol("=")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol(">=")) => Comparison::AtLeast,
+            Some(Token::Symbol(">")) => Comparison::MoreThan,
+            _ => return self.unexpected("a comparison, `contains`, or `matches`"),
+        };
+        self.position += 1;
+        let literal = match self.next() {
+            Some(Token::Number(n)) => Literal::Number(n),
+            Some(Token::Text(s)) => Literal::Text(s),
+            Some(Token::Word(word)) if word == "true" => Literal::Bool(true),
+            Some(Token::Word(word)) if word == "false" => Literal::Bool(false),
+            _ => { self.position -= 1; return self.unexpected("a number, a string, `true`, or `false`") }
+        };
+        Ok(Condition::Compare(attribute, comparison, literal))
+    }
+}
+
+impl Query {
+    pub fn parse(source: &str) -> Result<Query> {
+        Parser { tokens: tokenize(source)?, position: 0 }.query()
+    }
+
+    /** Checks that all the attributes in the query exist, that they are compared to values of the
+        right kind, and that all the regular expressions compile, without running it. */
+    pub fn check(&self) -> Result<()> {
+        match self.objects {
+            Objects::Projects => compile::<objects::Project>(&self.stages).map(|_| ()),
+            Objects::Commits => compile::<objects::Commit>(&self.stages).map(|_| ()),
+            Objects::Users => compile::<objects::User>(&self.stages).map(|_| ()),
+            Objects::Paths => compile::<objects::Path>(&self.stages).map(|_| ()),
+            Objects::Snapshots => compile::<objects::Snapshot>(&self.stages).map(|_| ()),
+        }
+    }
+
+    /** Runs the query and writes its results to a CSV file. */
+    pub fn execute_to_csv(&self, database: &Database, location: impl Into<String>) -> Result<()> {
+        let location = location.into();
+        match self.objects {
+            Objects::Projects => run::<objects::Project>(database, &self.stages, location),
+            Objects::Commits => run::<objects::Commit>(database, &self.stages, location),
+            Objects::Users => run::<objects::User>(database, &self.stages, location),
+            Objects::Paths => run::<objects::Path>(database, &self.stages, location),
+            Objects::Snapshots => run::<objects::Snapshot>(database, &self.stages, location),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+    fn from_str(source: &str) -> Result<Self> { Query::parse(source) }
+}
+
+/*== Running ====================================================================================*/
+
+enum Plan<'a, T> {
+    Filter(Box<dyn Filter<'a, Item=T> + 'a>),
+    Sort(Vec<By<Field<T>>>),
+    Sample(Sample),
+    Select(Fields<T>),
+}
+
+fn field<T>(name: &str) -> Result<Field<T>> where T: Queryable {
+    T::field(name).with_context(|| format!("There is no attribute `{}` of {}", name, T::NAME))
+}
+
+fn compile<'a, T>(stages: &Vec<Stage>) -> Result<Vec<Plan<'a, T>>> where T: Queryable + 'a {
+    stages.iter().map(|stage| Ok(match stage {
+        Stage::Filter(condition) => Plan::Filter(compile_condition(condition)?),
+        Stage::Sort(keys) => Plan::Sort(keys.iter()
+            .map(|(name, direction)| Ok(By(field(name)?, *direction, sort::Missing::Last)))
+            .collect::<Result<Vec<_>>>()?),
+        Stage::Sample(sample) => Plan::Sample(*sample),
+        Stage::Select(names) => Plan::Select(Fields(names.iter()
+            .map(|name| field(name))
+            .collect::<Result<Vec<_>>>()?)),
+    })).collect()
+}
+
+fn compile_condition<'a, T>(condition: &Condition) -> Result<Box<dyn Filter<'a, Item=T> + 'a>> where T: Queryable + 'a {
+    let filter: Box<dyn Filter<'a, Item=T> + 'a> = match condition {
+        Condition::Compare(name, comparison, literal) => {
+            let (field, value) = (field::<T>(name)?, literal.clone().into_value());
+            if field.kind() != value.kind() {
+                bail!("`{}` is {}, so it cannot be compared to {}", name, field.kind(), value.kind())
+            }
+            // `>=`, `>`, and `!=` would otherwise let objects without a value through.
+            let exists = Exists(field.clone());
+            match comparison {
+                Comparison::LessThan => Box::new(LessThan(field, value)),
+                Comparison::AtMost => Box::new(AtMost(field, value)),
+                Comparison::Equal => Box::new(Equal(field, value)),
+                Comparison::NotEqual => Box::new(And(exists, Not(Equal(field, value)))),
+                Comparison::AtLeast => Box::new(And(exists, AtLeast(field, value))),
+                Comparison::MoreThan => Box::new(And(exists, MoreThan(field, value))),
+            }
+        }
+        Condition::Contains(name, string) =>
+            Box::new(Matches(AsText(field(name)?), Regex::new(&regex::escape(string))?)),
+        Condition::Matches(name, pattern) =>
+            Box::new(Matches(AsText(field(name)?), Regex::new(pattern)
+                .with_context(|| format!("Invalid regular expression \"{}\"", pattern))?)),
+        Condition::Exists(name) => Box::new(Exists(field::<T>(name)?)),
+        Condition::Missing(name) => Box::new(Not(Exists(field::<T>(name)?))),
+        Condition::And(left, right) => Box::new(And(compile_condition(left)?, compile_condition(right)?)),
+        Condition::Or(left, right) => Box::new(Or(compile_condition(left)?, compile_condition(right)?)),
+        Condition::Not(condition) => Box::new(Not(compile_condition(condition)?)),
+    };
+    Ok(filter)
+}
+
+fn run<'a, T>(database: &'a Database, stages: &Vec<Stage>, location: String) -> Result<()>
+    where T: Queryable + 'a, ItemWithData<'a, T>: CSVItem {
+    let mut objects: Box<dyn Iterator<Item=ItemWithData<'a, T>> + 'a> = T::objects(database);
+    for plan in compile::<T>(stages)? {
+        objects = match plan {
+            Plan::Filter(filter) => Box::new(objects.filter_by(filter)),
+            Plan::Sort(keys) => Box::new(objects.sort_by_keys(keys)),
+            Plan::Sample(Sample::Top(n)) => Box::new(objects.sample(Top(n))),
+            Plan::Sample(Sample::Random(n, seed)) => Box::new(objects.sample(Random(n, Seed(seed)))),
+            Plan::Select(fields) => {
+                let names: Vec<String> = fields.0.iter().map(|field| field.name().to_owned()).collect();
+                let headers: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                objects.map_into(fields).into_csv_with_headers(headers, location)?;
+                return Ok(())
+            }
+        }
+    }
+    objects.into_csv(location)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attrib::sort::Direction;
+    use crate::database::memory::InMemorySource;
+    use crate::database::testing::{self, commit};
+    use super::*;
+
+    #[test]
+    fn parses_query() {
+        let query = Query::parse(r#"
+            projects # all of them
+              | filter stars >= 100 and (language = "Rust" or not exists license)
+              | sort commits desc, id
+              | sample random 50 seed 42
+              | select id, url, stars
+        "#).unwrap();
+
+        assert_eq!(query, Query {
+            objects: Objects::Projects,
+            stages: vec![
+                Stage::Filter(Condition::And(
+                    Box::new(Condition::Compare("stars".to_owned(), Comparison::AtLeast, Literal::Number(100.0))),
+                    Box::new(Condition::Or(
+                        Box::new(Condition::Compare("language".to_owned(), Comparison::Equal, Literal::Text("Rust".to_owned()))),
+                        Box::new(Condition::Not(Box::new(Condition::Exists("license".to_owned())))))))),
+                Stage::Sort(vec![("commits".to_owned(), Direction::Descending), ("id".to_owned(), Direction::Ascending)]),
+                Stage::Sample(Sample::Random(50, 42)),
+                Stage::Select(vec!["id".to_owned(), "url".to_owned(), "stars".to_owned()]),
+            ]
+        });
+        query.check().unwrap();
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = Query::parse("projects | filter stars >= | select id").unwrap_err();
+        assert_eq!(error.to_string(), "Expected a number, a string, `true`, or `false` but found `|` at line 1, column 28");
+
+        let error = Query::parse("projects | select id | sort stars").unwrap_err();
+        assert_eq!(error.to_string(), "Expected the end of the query after `select` but found `|` at line 1, column 22");
+
+        let error = Query::parse("users | filter stars > 5").unwrap().check().unwrap_err();
+        assert_eq!(error.to_string(), "There is no attribute `stars` of users");
+
+        let error = Query::parse("projects | filter stars >= \"many\"").unwrap().check().unwrap_err();
+        assert_eq!(error.to_string(), "`stars` is a number, so it cannot be compared to a string");
+
+        let error = Query::parse("projects | filter not (language = true)").unwrap().check().unwrap_err();
+        assert_eq!(error.to_string(), "`language` is a string, so it cannot be compared to a boolean");
+
+        let error = Query::parse("projects | filter stars >= 1.2.3").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid number `1.2.3` at line 1, column 28");
+
+        let error = Query::parse("projects | filter stars >= \"many").unwrap_err();
+        assert_eq!(error.to_string(), "Unterminated string starting at line 1, column 28");
+
+        let error = Query::parse("projects | filter stars >= 5 ; select id").unwrap_err();
+        assert_eq!(error.to_string(), "Unexpected `;` at line 1, column 30");
+
+        let error = Query::parse("projects | sample top -1").unwrap_err();
+        assert_eq!(error.to_string(), "Expected a whole number but found `-1` at line 1, column 23");
+    }
+
+    #[test]
+    fn reads_numbers() {
+        let numbers = |source: &str| -> Vec<Token> {
+            tokenize(source).unwrap().into_iter().map(|(token, _, _)| token).collect()
+        };
+        assert_eq!(numbers("42 -1.5 .5 -.25 1e3 2.5E-2 1e+2"),
+                   vec![Token::Number(42.0), Token::Number(-1.5), Token::Number(0.5), Token::Number(-0.25),
+                        Token::Number(1000.0), Token::Number(0.025), Token::Number(100.0)]);
+        // Without digits after it, an `e` is not an exponent.
+        assert_eq!(numbers("1e"), vec![Token::Number(1.0), Token::Word("e".to_owned())]);
+        assert_eq!(numbers("stars>=1e3"), vec![Token::Word("stars".to_owned()), Token::Symbol(">="), Token::Number(1000.0)]);
+    }
+
+    #[test]
+    fn runs_query() {
+        let mut source = InMemorySource::new();
+        let user = source.add_user("author@example.com");
+        for (index, stars) in vec![Some(10), None, Some(30), Some(20)].into_iter().enumerate() {
+            let project = source.add_project(format!("https://github.com/example/{}.git", index));
+            source.set_project_metadata(project, serde_json::json!({ "stargazers_count": stars }));
+            let commit = source.add_commit(format!("{}", index), commit(user, 100, vec![]));
+            source.set_head(project, "master", commit);
+        }
+        let database = testing::database(source).open();
+
+        let output = tempfile::tempdir().unwrap();
+        let run = |query: &str| -> String {
+            let location = output.path().join("output.csv");
+            Query::parse(query).unwrap().execute_to_csv(&database, location.to_str().unwrap()).unwrap();
+            std::fs::read_to_string(location).unwrap()
+        };
+        // Project 1 has no stars, so it passes none of these.
+        assert_eq!(run("projects | filter stars >= 0 | sort stars desc | select id, stars"), "id,stars\n2,30\n3,20\n0,10\n");
+        assert_eq!(run("projects | filter stars > 10 | select id"), "id\n2\n3\n");
+        assert_eq!(run("projects | filter stars != 10 | select id"), "id\n2\n3\n");
+        assert_eq!(run("projects | filter stars < 20 or missing stars | select id"), "id\n0\n1\n");
+        assert_eq!(run("projects | filter not stars >= 20 | sort id desc | sample top 2 | select id, url"),
+                   "id,url\n1,\"https://github.com/example/1.git\"\n0,\"https://github.com/example/0.git\"\n");
+    }
+}
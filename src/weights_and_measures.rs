@@ -2,6 +2,7 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 use crate::objects::*;
 use crate::Store;
+use crate::lines::LineCounts;
 
 const QUADRILLION: (usize, &'static str) = (TRILLION.0 * 1000, "Q");
 const TRILLION:    (usize, &'static str) = (BILLION.0  * 1000, "T");
@@ -76,6 +77,7 @@ quick_impl_countable!(Snapshot);
 quick_impl_countable!(Head);
 quick_impl_countable!(Change);
 quick_impl_countable!(Language);
+quick_impl_countable!(LineCounts);
 quick_impl_countable!(Store);
 
 quick_impl_countable!(UserId);
@@ -144,6 +146,7 @@ quick_impl_weighed!(PathId);
 quick_impl_weighed!(SnapshotId);
 // quick_impl_weighed!(Change);
 quick_impl_weighed!(Language);
+quick_impl_weighed!(LineCounts);
 quick_impl_weighed!(Store);
 
 macro_rules! quick_impl_weighed_static_collection {